@@ -1,11 +1,17 @@
 from typing import (
+    AbstractSet,
+    Any,
+    Callable,
+    Generic,
     ItemsView,
     Iterable,
     Iterator,
     KeysView,
+    Literal,
     Mapping,
     TypeVar,
     ValuesView,
+    overload,
 )
 
 _T = TypeVar("_T")
@@ -13,6 +19,8 @@ _KT_co = TypeVar("_KT_co", covariant=True)
 _VT_co = TypeVar("_VT_co", covariant=True)
 _KU_co = TypeVar("_KU_co", covariant=True)
 _VU_co = TypeVar("_VU_co", covariant=True)
+_R = TypeVar("_R", bound="Record")
+_E = TypeVar("_E")
 
 class HashTrieMap(Mapping[_KT_co, _VT_co]):
     def __init__(
@@ -23,11 +31,58 @@ class HashTrieMap(Mapping[_KT_co, _VT_co]):
     def __getitem__(self, key: _KT_co) -> _VT_co: ...
     def __iter__(self) -> Iterator[_KT_co]: ...
     def __len__(self) -> int: ...
+    def __or__(
+        self, other: Mapping[_KU_co, _VU_co]
+    ) -> HashTrieMap[_KT_co | _KU_co, _VT_co | _VU_co]: ...
+    def __ror__(
+        self, other: Mapping[_KU_co, _VU_co]
+    ) -> HashTrieMap[_KT_co | _KU_co, _VT_co | _VU_co]: ...
     def discard(self, key: _KT_co) -> HashTrieMap[_KT_co, _VT_co]: ...
+    def with_default(
+        self,
+        factory: Callable[[], _VT_co],
+    ) -> DefaultHashTrieMap[_KT_co, _VT_co]: ...
+    def evolver(self) -> HashTrieMapEvolver[_KT_co, _VT_co]: ...
+    @overload
+    def mutate(self) -> HashTrieMapEvolver[_KT_co, _VT_co]: ...
+    @overload
+    def mutate(self, f: Callable[[HashTrieMapEvolver[_KT_co, _VT_co]], object]) -> HashTrieMap[_KT_co, _VT_co]: ...
+    def flatten(self, sep: str = ".") -> HashTrieMap[str, Any]: ...
     def items(self) -> ItemsView[_KT_co, _VT_co]: ...
     def keys(self) -> KeysView[_KT_co]: ...
+    def sorted_items(
+        self,
+        key: Callable[[_KT_co], Any] | None = None,
+        reverse: bool = False,
+    ) -> list[tuple[_KT_co, _VT_co]]: ...
+    def sorted_keys(
+        self,
+        key: Callable[[_KT_co], Any] | None = None,
+        reverse: bool = False,
+    ) -> list[_KT_co]: ...
+    def keys_set(self) -> HashTrieSet[_KT_co]: ...
     def values(self) -> ValuesView[_VT_co]: ...
     def remove(self, key: _KT_co) -> HashTrieMap[_KT_co, _VT_co]: ...
+    def insert_many(
+        self,
+        items: Iterable[tuple[_KU_co, _VU_co]],
+    ) -> HashTrieMap[_KT_co | _KU_co, _VT_co | _VU_co]: ...
+    def remove_many(self, keys: Iterable[_KT_co]) -> HashTrieMap[_KT_co, _VT_co]: ...
+    def try_remove(self, key: _KT_co) -> tuple[HashTrieMap[_KT_co, _VT_co], bool]: ...
+    @overload
+    def pop(self, key: _KT_co) -> tuple[_VT_co, HashTrieMap[_KT_co, _VT_co]]: ...
+    @overload
+    def pop(
+        self, key: _KT_co, default: _T
+    ) -> tuple[_VT_co | _T, HashTrieMap[_KT_co, _VT_co]]: ...
+    def insert_if_missing(
+        self, key: _KT_co, val: _VT_co
+    ) -> tuple[_VT_co, HashTrieMap[_KT_co, _VT_co]]: ...
+    def popitem(self) -> tuple[_KT_co, _VT_co, HashTrieMap[_KT_co, _VT_co]]: ...
+    def resolve_pointer(self, pointer: str) -> Any: ...
+    def apply_json_patch(self, patch: Iterable[Mapping[str, Any]]) -> Any: ...
+    def to_json_patch(self, other: Any) -> list[dict[str, Any]]: ...
+    def unflatten(self, sep: str = ".") -> HashTrieMap[Any, Any]: ...
     def insert(
         self,
         key: _KT_co,
@@ -37,41 +92,653 @@ class HashTrieMap(Mapping[_KT_co, _VT_co]):
         self,
         *args: Mapping[_KU_co, _VU_co] | Iterable[tuple[_KU_co, _VU_co]],
     ) -> HashTrieMap[_KT_co | _KU_co, _VT_co | _VU_co]: ...
+    def reduce(
+        self,
+        f: Callable[[_T, _KT_co, _VT_co], _T],
+        initial: _T,
+    ) -> _T: ...
+    @overload
+    def invert(self, *, collect: Literal[False] = False) -> HashTrieMap[Any, _KT_co]: ...
+    @overload
+    def invert(
+        self,
+        *,
+        collect: Literal[True],
+    ) -> HashTrieMap[Any, HashTrieSet[_KT_co]]: ...
+    def map_values(self, f: Callable[[_VT_co], _T]) -> HashTrieMap[_KT_co, _T]: ...
+    def map_keys(
+        self,
+        f: Callable[[_KT_co], _KU_co],
+        combine: Callable[[_VT_co, _VT_co], _VT_co] | None = None,
+    ) -> HashTrieMap[_KU_co, _VT_co]: ...
+    def select(self, keys: Iterable[_KT_co]) -> HashTrieMap[_KT_co, _VT_co]: ...
+    def omit(self, keys: Iterable[_KT_co]) -> HashTrieMap[_KT_co, _VT_co]: ...
+    def get_in(self, path: Iterable[Any], default: Any = None) -> Any: ...
+    def set_in(self, path: Iterable[Any], value: Any) -> HashTrieMap[Any, Any]: ...
+    def delete_in(self, path: Iterable[Any]) -> HashTrieMap[Any, Any]: ...
+    def partition(
+        self,
+        predicate: Callable[[_KT_co, _VT_co], object],
+    ) -> tuple[HashTrieMap[_KT_co, _VT_co], HashTrieMap[_KT_co, _VT_co]]: ...
+    def merge_deep(
+        self,
+        other: Mapping[_KU_co, _VU_co] | Iterable[tuple[_KU_co, _VU_co]],
+        on_conflict: Callable[[Any, Any], Any] | None = None,
+    ) -> HashTrieMap[_KT_co | _KU_co, Any]: ...
+    def update_with(
+        self,
+        f: Callable[[_VT_co, _VU_co], _T],
+        *args: Mapping[_KU_co, _VU_co] | Iterable[tuple[_KU_co, _VU_co]],
+    ) -> HashTrieMap[_KT_co | _KU_co, _VT_co | _VU_co | _T]: ...
     @classmethod
     def convert(
         cls,
         value: Mapping[_KT_co, _VT_co] | Iterable[tuple[_KT_co, _VT_co]],
+        *,
+        deep: bool = False,
     ) -> HashTrieMap[_KT_co, _VT_co]: ...
     @classmethod
+    def group_by(
+        cls,
+        key: Callable[[_T], _KT_co],
+        iterable: Iterable[_T],
+    ) -> HashTrieMap[_KT_co, List[_T]]: ...
+    @overload
+    @classmethod
     def fromkeys(
         cls,
         keys: Iterable[_KT_co],
         value: _VT_co = None,
+        *,
+        factory: Literal[False] = False,
+    ) -> HashTrieMap[_KT_co, _VT_co]: ...
+    @overload
+    @classmethod
+    def fromkeys(
+        cls,
+        keys: Iterable[_KT_co],
+        value: Callable[[], _VT_co],
+        *,
+        factory: Literal[True],
     ) -> HashTrieMap[_KT_co, _VT_co]: ...
 
+class HashTrieMapEvolver(Generic[_KT_co, _VT_co]):
+    def __enter__(self: _E) -> _E: ...
+    def __exit__(self, *args: object) -> bool: ...
+    def __contains__(self, key: object) -> bool: ...
+    def __getitem__(self, key: _KT_co) -> _VT_co: ...
+    def __setitem__(self, key: _KT_co, val: _VT_co) -> None: ...
+    def __delitem__(self, key: _KT_co) -> None: ...
+    def __len__(self) -> int: ...
+    def persistent(self) -> HashTrieMap[_KT_co, _VT_co]: ...
+    @property
+    def result(self) -> HashTrieMap[_KT_co, _VT_co]: ...
+
 class HashTrieSet(frozenset[_T]):
     def __init__(self, value: Iterable[_T] = ()): ...
     def __iter__(self) -> Iterator[_T]: ...
     def __len__(self) -> int: ...
+    def __and__(self, other: AbstractSet[object]) -> HashTrieSet[_T]: ...
+    def __or__(self, other: AbstractSet[_T]) -> HashTrieSet[_T]: ...
+    def __sub__(self, other: AbstractSet[object]) -> HashTrieSet[_T]: ...
+    def __xor__(self, other: AbstractSet[_T]) -> HashTrieSet[_T]: ...
+    def __rand__(self, other: AbstractSet[object]) -> HashTrieSet[_T]: ...
+    def __ror__(self, other: AbstractSet[_T]) -> HashTrieSet[_T]: ...
+    def __rsub__(self, other: AbstractSet[_T]) -> HashTrieSet[_T]: ...
+    def __rxor__(self, other: AbstractSet[_T]) -> HashTrieSet[_T]: ...
     def discard(self, value: _T) -> HashTrieSet[_T]: ...
+    @classmethod
+    def convert(
+        cls,
+        value: Iterable[_T],
+        *,
+        deep: bool = False,
+    ) -> HashTrieSet[_T]: ...
     def remove(self, value: _T) -> HashTrieSet[_T]: ...
+    def remove_many(self, values: Iterable[_T]) -> HashTrieSet[_T]: ...
+    def to_frozenset(self) -> frozenset[_T]: ...
     def insert(self, value: _T) -> HashTrieSet[_T]: ...
     def update(self, *args: Iterable[_T]) -> HashTrieSet[_T]: ...
+    def difference(self, *others: Iterable[object]) -> HashTrieSet[_T]: ...
+    def intersection(self, *others: Iterable[object]) -> HashTrieSet[_T]: ...
+    def symmetric_difference(self, *others: Iterable[_T]) -> HashTrieSet[_T]: ...
+    def union(self, *others: Iterable[_T]) -> HashTrieSet[_T]: ...
+    def map(self, f: Callable[[_T], _KU_co]) -> HashTrieSet[_KU_co]: ...
+    def filter(self, predicate: Callable[[_T], object]) -> HashTrieSet[_T]: ...
+    def partition(
+        self,
+        predicate: Callable[[_T], object],
+    ) -> tuple[HashTrieSet[_T], HashTrieSet[_T]]: ...
+    def evolver(self) -> HashTrieSetEvolver[_T]: ...
+    @overload
+    def mutate(self) -> HashTrieSetEvolver[_T]: ...
+    @overload
+    def mutate(self, f: Callable[[HashTrieSetEvolver[_T]], object]) -> HashTrieSet[_T]: ...
+
+class HashTrieSetEvolver(Generic[_T]):
+    def __enter__(self: _E) -> _E: ...
+    def __exit__(self, *args: object) -> bool: ...
+    def __contains__(self, value: object) -> bool: ...
+    def __len__(self) -> int: ...
+    def add(self, value: _T) -> None: ...
+    def discard(self, value: _T) -> None: ...
+    def remove(self, value: _T) -> None: ...
+    def persistent(self) -> HashTrieSet[_T]: ...
+    @property
+    def result(self) -> HashTrieSet[_T]: ...
+
+class DefaultHashTrieMap(Mapping[_KT_co, _VT_co]):
+    def __init__(
+        self,
+        factory: Callable[[], _VT_co],
+        value: Mapping[_KT_co, _VT_co] | Iterable[tuple[_KT_co, _VT_co]] = {},
+        **kwds: Mapping[_KT_co, _VT_co],
+    ): ...
+    def __getitem__(self, key: _KT_co) -> _VT_co: ...
+    def __iter__(self) -> Iterator[_KT_co]: ...
+    def __len__(self) -> int: ...
+    @property
+    def factory(self) -> Callable[[], _VT_co]: ...
+    def add_default(self, key: _KT_co) -> DefaultHashTrieMap[_KT_co, _VT_co]: ...
+    def discard(self, key: _KT_co) -> DefaultHashTrieMap[_KT_co, _VT_co]: ...
+    def remove(self, key: _KT_co) -> DefaultHashTrieMap[_KT_co, _VT_co]: ...
+    def insert(
+        self,
+        key: _KT_co,
+        val: _VT_co,
+    ) -> DefaultHashTrieMap[_KT_co, _VT_co]: ...
+    def update(
+        self,
+        *args: Mapping[_KT_co, _VT_co] | Iterable[tuple[_KT_co, _VT_co]],
+    ) -> DefaultHashTrieMap[_KT_co, _VT_co]: ...
+    def keys(self) -> KeysView[_KT_co]: ...
+    def values(self) -> ValuesView[_VT_co]: ...
+    def items(self) -> ItemsView[_KT_co, _VT_co]: ...
+
+class CheckedHashTrieMap(HashTrieMap[_KT_co, _VT_co]):
+    def __init__(
+        self,
+        value: Mapping[_KT_co, _VT_co] | Iterable[tuple[_KT_co, _VT_co]] = {},
+        key_type: type | tuple[type, ...] | Callable[[Any], bool] | None = None,
+        value_type: type | tuple[type, ...] | Callable[[Any], bool] | None = None,
+        **kwds: Mapping[_KT_co, _VT_co],
+    ): ...
+    @property
+    def key_type(self) -> type | tuple[type, ...] | Callable[[Any], bool] | None: ...
+    @property
+    def value_type(self) -> type | tuple[type, ...] | Callable[[Any], bool] | None: ...
+    def discard(self, key: _KT_co) -> CheckedHashTrieMap[_KT_co, _VT_co]: ...
+    def remove(self, key: _KT_co) -> CheckedHashTrieMap[_KT_co, _VT_co]: ...
+    def insert(
+        self,
+        key: _KT_co,
+        val: _VT_co,
+    ) -> CheckedHashTrieMap[_KT_co, _VT_co]: ...
+    def update(
+        self,
+        *args: Mapping[_KU_co, _VU_co] | Iterable[tuple[_KU_co, _VU_co]],
+    ) -> CheckedHashTrieMap[_KT_co | _KU_co, _VT_co | _VU_co]: ...
+
+class CheckedHashTrieSet(frozenset[_T]):
+    def __init__(
+        self,
+        value: Iterable[_T] = (),
+        value_type: type | tuple[type, ...] | Callable[[Any], bool] | None = None,
+    ): ...
+    @property
+    def value_type(self) -> type | tuple[type, ...] | Callable[[Any], bool] | None: ...
+    def __iter__(self) -> Iterator[_T]: ...
+    def __len__(self) -> int: ...
+    def discard(self, value: _T) -> CheckedHashTrieSet[_T]: ...
+    def remove(self, value: _T) -> CheckedHashTrieSet[_T]: ...
+    def insert(self, value: _T) -> CheckedHashTrieSet[_T]: ...
+    def update(self, *args: Iterable[_T]) -> CheckedHashTrieSet[_T]: ...
 
 class List(Iterable[_T]):
     def __init__(self, value: Iterable[_T] = (), *more: _T): ...
     def __iter__(self) -> Iterator[_T]: ...
+    def __reversed__(self) -> Iterator[_T]: ...
     def __len__(self) -> int: ...
+    def index(self, value: object, start: int = 0, stop: int | None = None) -> int: ...
+    def count(self, value: object) -> int: ...
     def push_front(self, value: _T) -> List[_T]: ...
+    def push_back(self, value: _T) -> List[_T]: ...
+    def extend(self, iterable: Iterable[_T]) -> List[_T]: ...
+    def extend_front(self, iterable: Iterable[_T]) -> List[_T]: ...
+    def insert(self, index: int, value: _T) -> List[_T]: ...
+    def delete(self, index: int) -> List[_T]: ...
+    def remove(self, value: _T) -> List[_T]: ...
+    def take(self, n: int) -> List[_T]: ...
+    def drop(self, n: int) -> List[_T]: ...
+    def split_at(self, n: int) -> tuple[List[_T], List[_T]]: ...
+    def zip(self, other: Iterable[_KU_co]) -> List[tuple[_T, _KU_co]]: ...
+    def map(self, f: Callable[[_T], _KU_co]) -> List[_KU_co]: ...
+    def filter(self, predicate: Callable[[_T], object]) -> List[_T]: ...
+    def reduce(self, f: Callable[[_KU_co, _T], _KU_co], initial: _KU_co) -> _KU_co: ...
+    def unique(self) -> List[_T]: ...
+    def sort(
+        self,
+        *,
+        key: Callable[[_T], Any] | None = None,
+        reverse: bool = False,
+    ) -> List[_T]: ...
+    @classmethod
+    def convert(cls, value: Iterable[_T], *, deep: bool = False) -> List[_T]: ...
     def drop_first(self) -> List[_T]: ...
+    @staticmethod
+    def builder() -> ListBuilder[Any]: ...
+    @overload
+    def mutate(self) -> ListBuilder[_T]: ...
+    @overload
+    def mutate(self, f: Callable[[ListBuilder[_T]], object]) -> List[_T]: ...
+    def resolve_pointer(self, pointer: str) -> Any: ...
+
+class ListBuilder(Generic[_T]):
+    def __enter__(self: _E) -> _E: ...
+    def __exit__(self, *args: object) -> bool: ...
+    def __len__(self) -> int: ...
+    def append(self, value: _T) -> None: ...
+    def build(self) -> List[_T]: ...
+    @property
+    def result(self) -> List[_T]: ...
+
+class ListZipper(Generic[_T]):
+    def __init__(self, list: Iterable[_T] = ()): ...
+    def __len__(self) -> int: ...
+    @property
+    def focus(self) -> _T: ...
+    @property
+    def index(self) -> int: ...
+    def left(self) -> ListZipper[_T]: ...
+    def right(self) -> ListZipper[_T]: ...
+    def set(self, value: _T) -> ListZipper[_T]: ...
+    def insert(self, value: _T) -> ListZipper[_T]: ...
+    def delete(self) -> ListZipper[_T]: ...
+    def to_list(self) -> List[_T]: ...
 
 class Queue(Iterable[_T]):
     def __init__(self, value: Iterable[_T] = (), *more: _T): ...
     def __iter__(self) -> Iterator[_T]: ...
+    def __contains__(self, value: object) -> bool: ...
+    def __getitem__(self, index: int) -> _T: ...
+    def __reversed__(self) -> Iterator[_T]: ...
     def __len__(self) -> int: ...
+    def index(self, value: object, start: int = 0, stop: int | None = None) -> int: ...
+    def count(self, value: object) -> int: ...
     def enqueue(self, value: _T) -> Queue[_T]: ...
     def dequeue(self, value: _T) -> Queue[_T]: ...
+    def pop(self) -> tuple[_T, Queue[_T]]: ...
     @property
     def is_empty(self) -> _T: ...
     @property
     def peek(self) -> _T: ...
+    def peek_or(self, default: _KU_co) -> _T | _KU_co: ...
+    @staticmethod
+    def builder() -> QueueBuilder[Any]: ...
+    @overload
+    def mutate(self) -> QueueBuilder[_T]: ...
+    @overload
+    def mutate(self, f: Callable[[QueueBuilder[_T]], object]) -> Queue[_T]: ...
+
+class QueueBuilder(Generic[_T]):
+    def __enter__(self: _E) -> _E: ...
+    def __exit__(self, *args: object) -> bool: ...
+    def __len__(self) -> int: ...
+    def enqueue(self, value: _T) -> None: ...
+    def build(self) -> Queue[_T]: ...
+    @property
+    def result(self) -> Queue[_T]: ...
+
+class SortedMap(Mapping[_KT_co, _VT_co]):
+    def __init__(
+        self,
+        value: Mapping[_KT_co, _VT_co] | Iterable[tuple[_KT_co, _VT_co]] = {},
+        *,
+        key: Callable[[_KT_co], Any] | None = None,
+        **kwds: Mapping[_KT_co, _VT_co],
+    ): ...
+    def __getitem__(self, key: _KT_co) -> _VT_co: ...
+    def __iter__(self) -> Iterator[_KT_co]: ...
+    def __reversed__(self) -> Iterator[_KT_co]: ...
+    def __len__(self) -> int: ...
+    @property
+    def key(self) -> Callable[[_KT_co], Any] | None: ...
+    def discard(self, key: _KT_co) -> SortedMap[_KT_co, _VT_co]: ...
+    def first_item(self) -> tuple[_KT_co, _VT_co]: ...
+    def last_item(self) -> tuple[_KT_co, _VT_co]: ...
+    def floor_key(self, key: _KT_co) -> _KT_co | None: ...
+    def ceiling_key(self, key: _KT_co) -> _KT_co | None: ...
+    def nth(self, index: int) -> _KT_co: ...
+    def index_of(self, key: _KT_co) -> int: ...
+    def irange(
+        self,
+        min: _KT_co | None = None,
+        max: _KT_co | None = None,
+        inclusive: tuple[bool, bool] = (True, True),
+        reverse: bool = False,
+    ) -> Iterator[_KT_co]: ...
+    def submap(
+        self,
+        min: _KT_co | None = None,
+        max: _KT_co | None = None,
+        inclusive: tuple[bool, bool] = (True, True),
+    ) -> SortedMap[_KT_co, _VT_co]: ...
+    def items(self) -> ItemsView[_KT_co, _VT_co]: ...
+    def keys(self) -> KeysView[_KT_co]: ...
+    def values(self) -> ValuesView[_VT_co]: ...
+    def remove(self, key: _KT_co) -> SortedMap[_KT_co, _VT_co]: ...
+    def insert(
+        self,
+        key: _KT_co,
+        val: _VT_co,
+    ) -> SortedMap[_KT_co, _VT_co]: ...
+    def merge(
+        self,
+        other: SortedMap[_KT_co, _VT_co],
+        resolve: Callable[[_VT_co, _VT_co], _VT_co] | None = None,
+    ) -> SortedMap[_KT_co, _VT_co]: ...
+    def update(
+        self,
+        *args: Mapping[_KU_co, _VU_co] | Iterable[tuple[_KU_co, _VU_co]],
+    ) -> SortedMap[_KT_co | _KU_co, _VT_co | _VU_co]: ...
+    @overload
+    def mutate(self) -> SortedMapEvolver[_KT_co, _VT_co]: ...
+    @overload
+    def mutate(self, f: Callable[[SortedMapEvolver[_KT_co, _VT_co]], object]) -> SortedMap[_KT_co, _VT_co]: ...
+
+class SortedMapEvolver(Generic[_KT_co, _VT_co]):
+    def __enter__(self: _E) -> _E: ...
+    def __exit__(self, *args: object) -> bool: ...
+    def __contains__(self, key: object) -> bool: ...
+    def __getitem__(self, key: _KT_co) -> _VT_co: ...
+    def __setitem__(self, key: _KT_co, val: _VT_co) -> None: ...
+    def __delitem__(self, key: _KT_co) -> None: ...
+    def __len__(self) -> int: ...
+    def persistent(self) -> SortedMap[_KT_co, _VT_co]: ...
+    @property
+    def result(self) -> SortedMap[_KT_co, _VT_co]: ...
+
+class SortedSet(frozenset[_T]):
+    def __init__(
+        self,
+        value: Iterable[_T] = (),
+        *,
+        key: Callable[[_T], Any] | None = None,
+    ): ...
+    def __iter__(self) -> Iterator[_T]: ...
+    def __reversed__(self) -> Iterator[_T]: ...
+    def __len__(self) -> int: ...
+    @property
+    def key(self) -> Callable[[_T], Any] | None: ...
+    @property
+    def first(self) -> _T: ...
+    @property
+    def last(self) -> _T: ...
+    def nsmallest(self, k: int) -> list[_T]: ...
+    def nlargest(self, k: int) -> list[_T]: ...
+    def irange(
+        self,
+        min: _T | None = None,
+        max: _T | None = None,
+        inclusive: tuple[bool, bool] = (True, True),
+        reverse: bool = False,
+    ) -> Iterator[_T]: ...
+    def discard(self, value: _T) -> SortedSet[_T]: ...
+    def remove(self, value: _T) -> SortedSet[_T]: ...
+    def insert(self, value: _T) -> SortedSet[_T]: ...
+    def update(self, *args: Iterable[_T]) -> SortedSet[_T]: ...
+    @overload
+    def mutate(self) -> SortedSetEvolver[_T]: ...
+    @overload
+    def mutate(self, f: Callable[[SortedSetEvolver[_T]], object]) -> SortedSet[_T]: ...
+
+class SortedSetEvolver(Generic[_T]):
+    def __enter__(self: _E) -> _E: ...
+    def __exit__(self, *args: object) -> bool: ...
+    def __contains__(self, value: object) -> bool: ...
+    def __len__(self) -> int: ...
+    def add(self, value: _T) -> None: ...
+    def discard(self, value: _T) -> None: ...
+    def remove(self, value: _T) -> None: ...
+    def persistent(self) -> SortedSet[_T]: ...
+    @property
+    def result(self) -> SortedSet[_T]: ...
+
+class IntSet(frozenset[int]):
+    def __init__(self, value: Iterable[int] = ()): ...
+    @classmethod
+    def range(cls, start: int, stop: int, step: int = 1) -> IntSet: ...
+    def __iter__(self) -> Iterator[int]: ...
+    def __len__(self) -> int: ...
+    def __and__(self, other: AbstractSet[int]) -> IntSet: ...
+    def __or__(self, other: AbstractSet[int]) -> IntSet: ...
+    def __sub__(self, other: AbstractSet[int]) -> IntSet: ...
+    def __xor__(self, other: AbstractSet[int]) -> IntSet: ...
+    def __rand__(self, other: AbstractSet[int]) -> IntSet: ...
+    def __ror__(self, other: AbstractSet[int]) -> IntSet: ...
+    def __rsub__(self, other: AbstractSet[int]) -> IntSet: ...
+    def __rxor__(self, other: AbstractSet[int]) -> IntSet: ...
+    def difference(self, other: Iterable[int]) -> IntSet: ...
+    def intersection(self, other: Iterable[int]) -> IntSet: ...
+    def symmetric_difference(self, other: Iterable[int]) -> IntSet: ...
+    def union(self, other: Iterable[int]) -> IntSet: ...
+    def discard(self, value: int) -> IntSet: ...
+    def remove(self, value: int) -> IntSet: ...
+    def insert(self, value: int) -> IntSet: ...
+    def update(self, *args: Iterable[int]) -> IntSet: ...
+
+class Bag(Iterable[_T]):
+    def __init__(self, value: Iterable[_T] = ()): ...
+    def __contains__(self, value: object) -> bool: ...
+    def __iter__(self) -> Iterator[_T]: ...
+    def __len__(self) -> int: ...
+    def add(self, value: _T) -> Bag[_T]: ...
+    def count(self, value: _T) -> int: ...
+    def remove(self, value: _T) -> Bag[_T]: ...
+    @overload
+    def mutate(self) -> BagEvolver[_T]: ...
+    @overload
+    def mutate(self, f: Callable[[BagEvolver[_T]], object]) -> Bag[_T]: ...
+
+class BagEvolver(Generic[_T]):
+    def __enter__(self: _E) -> _E: ...
+    def __exit__(self, *args: object) -> bool: ...
+    def __contains__(self, value: object) -> bool: ...
+    def __len__(self) -> int: ...
+    def count(self, value: _T) -> int: ...
+    def add(self, value: _T) -> None: ...
+    def remove(self, value: _T) -> None: ...
+    def persistent(self) -> Bag[_T]: ...
+    @property
+    def result(self) -> Bag[_T]: ...
+
+class OrderedMap(Mapping[_KT_co, _VT_co]):
+    def __init__(
+        self,
+        value: Mapping[_KT_co, _VT_co] | Iterable[tuple[_KT_co, _VT_co]] = {},
+        **kwds: Mapping[_KT_co, _VT_co],
+    ): ...
+    def __getitem__(self, key: _KT_co) -> _VT_co: ...
+    def __iter__(self) -> Iterator[_KT_co]: ...
+    def __len__(self) -> int: ...
+    def discard(self, key: _KT_co) -> OrderedMap[_KT_co, _VT_co]: ...
+    def items(self) -> ItemsView[_KT_co, _VT_co]: ...
+    def keys(self) -> KeysView[_KT_co]: ...
+    def values(self) -> ValuesView[_VT_co]: ...
+    def remove(self, key: _KT_co) -> OrderedMap[_KT_co, _VT_co]: ...
+    def insert(
+        self,
+        key: _KT_co,
+        val: _VT_co,
+    ) -> OrderedMap[_KT_co, _VT_co]: ...
+    def update(
+        self,
+        *args: Mapping[_KU_co, _VU_co] | Iterable[tuple[_KU_co, _VU_co]],
+    ) -> OrderedMap[_KT_co | _KU_co, _VT_co | _VU_co]: ...
+    @overload
+    def mutate(self) -> OrderedMapEvolver[_KT_co, _VT_co]: ...
+    @overload
+    def mutate(self, f: Callable[[OrderedMapEvolver[_KT_co, _VT_co]], object]) -> OrderedMap[_KT_co, _VT_co]: ...
+
+class OrderedMapEvolver(Generic[_KT_co, _VT_co]):
+    def __enter__(self: _E) -> _E: ...
+    def __exit__(self, *args: object) -> bool: ...
+    def __contains__(self, key: object) -> bool: ...
+    def __getitem__(self, key: _KT_co) -> _VT_co: ...
+    def __setitem__(self, key: _KT_co, val: _VT_co) -> None: ...
+    def __delitem__(self, key: _KT_co) -> None: ...
+    def __len__(self) -> int: ...
+    def persistent(self) -> OrderedMap[_KT_co, _VT_co]: ...
+    @property
+    def result(self) -> OrderedMap[_KT_co, _VT_co]: ...
+
+class ChainMap(Mapping[_KT_co, _VT_co]):
+    def __init__(
+        self,
+        *maps: Mapping[_KT_co, _VT_co] | Iterable[tuple[_KT_co, _VT_co]],
+    ): ...
+    def __getitem__(self, key: _KT_co) -> _VT_co: ...
+    def __iter__(self) -> Iterator[_KT_co]: ...
+    def __len__(self) -> int: ...
+    @property
+    def maps(self) -> list[HashTrieMap[_KT_co, _VT_co]]: ...
+    def discard(self, key: _KT_co) -> ChainMap[_KT_co, _VT_co]: ...
+    def flatten(self) -> HashTrieMap[_KT_co, _VT_co]: ...
+    def items(self) -> ItemsView[_KT_co, _VT_co]: ...
+    def keys(self) -> KeysView[_KT_co]: ...
+    def values(self) -> ValuesView[_VT_co]: ...
+    def insert(self, key: _KT_co, val: _VT_co) -> ChainMap[_KT_co, _VT_co]: ...
+    def remove(self, key: _KT_co) -> ChainMap[_KT_co, _VT_co]: ...
+    def push_layer(
+        self,
+        map: Mapping[_KT_co, _VT_co] | Iterable[tuple[_KT_co, _VT_co]] = {},
+    ) -> ChainMap[_KT_co, _VT_co]: ...
+    def pop_layer(self) -> ChainMap[_KT_co, _VT_co]: ...
+
+class IdentityMap(Mapping[_KT_co, _VT_co]):
+    def __init__(
+        self,
+        value: Mapping[_KT_co, _VT_co] | Iterable[tuple[_KT_co, _VT_co]] = (),
+    ): ...
+    def __getitem__(self, key: _KT_co) -> _VT_co: ...
+    def __iter__(self) -> Iterator[_KT_co]: ...
+    def __len__(self) -> int: ...
+    def discard(self, key: _KT_co) -> IdentityMap[_KT_co, _VT_co]: ...
+    def items(self) -> ItemsView[_KT_co, _VT_co]: ...
+    def keys(self) -> KeysView[_KT_co]: ...
+    def values(self) -> ValuesView[_VT_co]: ...
+    def insert(self, key: _KT_co, val: _VT_co) -> IdentityMap[_KT_co, _VT_co]: ...
+    def remove(self, key: _KT_co) -> IdentityMap[_KT_co, _VT_co]: ...
+    def update(
+        self,
+        *args: Mapping[_KU_co, _VU_co] | Iterable[tuple[_KU_co, _VU_co]],
+    ) -> IdentityMap[_KT_co | _KU_co, _VT_co | _VU_co]: ...
+
+class IntervalMap(Mapping[tuple[Any, Any], _VT_co]):
+    def __init__(
+        self,
+        value: (
+            Mapping[tuple[Any, Any], _VT_co] | Iterable[tuple[tuple[Any, Any], _VT_co]]
+        ) = {},
+    ): ...
+    def __getitem__(self, key: tuple[Any, Any]) -> _VT_co: ...
+    def __iter__(self) -> Iterator[tuple[Any, Any]]: ...
+    def __len__(self) -> int: ...
+    def at(self, point: Any) -> list[tuple[tuple[Any, Any], _VT_co]]: ...
+    def overlapping(
+        self, low: Any, high: Any
+    ) -> list[tuple[tuple[Any, Any], _VT_co]]: ...
+    def discard(self, key: tuple[Any, Any]) -> IntervalMap[_VT_co]: ...
+    def items(self) -> ItemsView[tuple[Any, Any], _VT_co]: ...
+    def keys(self) -> KeysView[tuple[Any, Any]]: ...
+    def values(self) -> ValuesView[_VT_co]: ...
+    def insert(self, key: tuple[Any, Any], val: _VT_co) -> IntervalMap[_VT_co]: ...
+    def remove(self, key: tuple[Any, Any]) -> IntervalMap[_VT_co]: ...
+    def update(
+        self,
+        *args: (
+            Mapping[tuple[Any, Any], _VU_co] | Iterable[tuple[tuple[Any, Any], _VU_co]]
+        ),
+    ) -> IntervalMap[_VT_co | _VU_co]: ...
+
+class StringTrieMap(Mapping[str, _VT_co]):
+    def __init__(
+        self,
+        value: Mapping[str, _VT_co] | Iterable[tuple[str, _VT_co]] = {},
+        **kwds: _VT_co,
+    ): ...
+    def __getitem__(self, key: str) -> _VT_co: ...
+    def __iter__(self) -> Iterator[str]: ...
+    def __len__(self) -> int: ...
+    def discard(self, key: str) -> StringTrieMap[_VT_co]: ...
+    def items(self) -> ItemsView[str, _VT_co]: ...
+    def keys(self) -> KeysView[str]: ...
+    def values(self) -> ValuesView[_VT_co]: ...
+    def keys_with_prefix(self, prefix: str) -> list[str]: ...
+    def longest_prefix_of(self, value: str) -> str | None: ...
+    def remove(self, key: str) -> StringTrieMap[_VT_co]: ...
+    def insert(self, key: str, val: _VT_co) -> StringTrieMap[_VT_co]: ...
+    def update(
+        self,
+        *args: Mapping[str, _VU_co] | Iterable[tuple[str, _VU_co]],
+    ) -> StringTrieMap[_VT_co | _VU_co]: ...
+
+class InvariantError(ValueError):
+    field: str
+    value: Any
+
+class Field:
+    def __init__(
+        self,
+        *,
+        type: type | tuple[type, ...] | None = None,
+        invariant: Callable[[Any], bool] | None = None,
+        default: Any = ...,
+    ): ...
+
+class Record:
+    _fields: Iterable[str] | Mapping[str, Field | None]
+    def __init__(self, **fields: Any): ...
+    def __getattr__(self, name: str) -> Any: ...
+    def set(self: _R, field: str, value: Any) -> _R: ...
+    def evolve(self: _R, **changes: Any) -> _R: ...
+    def to_dict(self) -> dict[str, Any]: ...
+    @classmethod
+    def from_dict(cls: type[_R], data: Mapping[str, Any]) -> _R: ...
+    def __replace__(self: _R, **changes: Any) -> _R: ...
+
+class Vector(Iterable[_T]):
+    def __init__(self, value: Iterable[_T] = (), *more: _T): ...
+    def __contains__(self, value: object) -> bool: ...
+    def __getitem__(self, index: int) -> _T: ...
+    def __iter__(self) -> Iterator[_T]: ...
+    def __len__(self) -> int: ...
+    def __reversed__(self) -> Iterator[_T]: ...
+    def index(self, value: object, start: int = 0, stop: int | None = None) -> int: ...
+    def count(self, value: object) -> int: ...
+    def set(self, index: int, value: _T) -> Vector[_T]: ...
+    def push_back(self, value: _T) -> Vector[_T]: ...
+    def drop_last(self) -> Vector[_T]: ...
+    @staticmethod
+    def builder() -> VectorBuilder[Any]: ...
+    @overload
+    def mutate(self) -> VectorBuilder[_T]: ...
+    @overload
+    def mutate(self, f: Callable[[VectorBuilder[_T]], object]) -> Vector[_T]: ...
+
+class VectorBuilder(Generic[_T]):
+    def __enter__(self: _E) -> _E: ...
+    def __exit__(self, *args: object) -> bool: ...
+    def __len__(self) -> int: ...
+    def append(self, value: _T) -> None: ...
+    def build(self) -> Vector[_T]: ...
+    @property
+    def result(self) -> Vector[_T]: ...
+
+def deep_merge(
+    *maps: Mapping[Any, Any] | Iterable[tuple[Any, Any]],
+    list_strategy: Literal["replace", "concatenate"] = "replace",
+) -> HashTrieMap[Any, Any]: ...
+def resolve_pointer(value: Any, pointer: str) -> Any: ...
+def apply_json_patch(value: Any, patch: Iterable[Mapping[str, Any]]) -> Any: ...
+def make_json_patch(old: Any, new: Any) -> list[dict[str, Any]]: ...
@@ -1,13 +1,19 @@
-use pyo3::exceptions::{PyIndexError, PyTypeError};
-use pyo3::pyclass::CompareOp;
-use pyo3::types::{PyDict, PyIterator, PyTuple, PyType};
+use archery::{ArcTK, RcK, SharedPointerKind};
+use pyo3::exceptions::{PyAttributeError, PyIndexError, PyLookupError, PyTypeError, PyValueError};
+use pyo3::pyclass::{boolean_struct::False, CompareOp};
+use pyo3::types::{PyDict, PyFrozenSet, PyList, PySequence, PySet, PyString, PyTuple, PyType};
+use pyo3::{create_exception, prelude::*, AsPyPointer, BoundObject, PyClass, PyTypeInfo};
 use pyo3::{exceptions::PyKeyError, types::PyMapping, types::PyTupleMethods};
-use pyo3::{prelude::*, AsPyPointer, BoundObject, PyTypeInfo};
 use rpds::{
     HashTrieMap, HashTrieMapSync, HashTrieSet, HashTrieSetSync, List, ListSync, Queue, QueueSync,
+    RedBlackTreeMap, RedBlackTreeMapSync, RedBlackTreeSet, RedBlackTreeSetSync, Vector, VectorSync,
 };
-use std::collections::hash_map::DefaultHasher;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{BTreeSet, HashMap};
 use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
 
 fn hash_shuffle_bits(h: usize) -> usize {
     ((h ^ 89869747) ^ (h << 16)).wrapping_mul(3644798167)
@@ -49,6 +55,9 @@ impl Eq for Key {}
 
 impl PartialEq for Key {
     fn eq(&self, other: &Self) -> bool {
+        if self.inner.is(&other.inner) {
+            return true;
+        }
         Python::with_gil(|py| {
             self.inner
                 .call_method1(py, "__eq__", (&other.inner,))
@@ -58,6 +67,63 @@ impl PartialEq for Key {
     }
 }
 
+// Like Python's own containers, elements which are the same object are
+// considered equal without calling __eq__, which also makes comparing
+// collections that share most of their structure cheap.
+fn elements_eq(e1: &PyObject, e2: &PyObject, py: Python<'_>) -> PyResult<bool> {
+    Ok(e1.is(e2) || e1.bind(py).eq(e2)?)
+}
+
+fn elements_ne(e1: &PyObject, e2: &PyObject, py: Python<'_>) -> PyResult<bool> {
+    Ok(!e1.is(e2) && e1.bind(py).ne(e2)?)
+}
+
+/// The position of the first element equal to value within [start, stop),
+/// where both bounds are interpreted like slice bounds, as list.index does.
+fn sequence_index<'a>(
+    elements: impl Iterator<Item = &'a PyObject>,
+    len: usize,
+    value: &PyObject,
+    start: isize,
+    stop: Option<isize>,
+    kind: &str,
+    py: Python<'_>,
+) -> PyResult<usize> {
+    let clamp = |bound: isize| {
+        let bound = if bound < 0 {
+            bound + len as isize
+        } else {
+            bound
+        };
+        bound.clamp(0, len as isize) as usize
+    };
+    let (start, stop) = (clamp(start), stop.map_or(len, clamp));
+    for (index, each) in elements.enumerate().take(stop).skip(start) {
+        if elements_eq(each, value, py)? {
+            return Ok(index);
+        }
+    }
+    Err(PyValueError::new_err(format!(
+        "{} is not in {}",
+        value.bind(py).repr()?,
+        kind
+    )))
+}
+
+fn sequence_count<'a>(
+    elements: impl Iterator<Item = &'a PyObject>,
+    value: &PyObject,
+    py: Python<'_>,
+) -> PyResult<usize> {
+    let mut count = 0;
+    for each in elements {
+        if elements_eq(each, value, py)? {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
 impl Key {
     fn clone_ref(&self, py: Python<'_>) -> Self {
         Key {
@@ -82,55 +148,8299 @@ impl<'source> FromPyObject<'source> for Key {
     }
 }
 
-#[repr(transparent)]
-#[pyclass(name = "HashTrieMap", module = "rpds", frozen, mapping)]
-struct HashTrieMapPy {
+thread_local! {
+    static COMPARISON_ERROR: RefCell<Option<PyErr>> = const { RefCell::new(None) };
+}
+
+// Ord can't fail, so a failed Python comparison is stashed here instead, and
+// every tree operation is followed by a call to this to surface it. Whatever
+// the operation produced is then discarded, which is safe since it's persistent.
+fn comparison_result<T>(value: T) -> PyResult<T> {
+    match COMPARISON_ERROR.with(|slot| slot.borrow_mut().take()) {
+        Some(err) => Err(err),
+        None => Ok(value),
+    }
+}
+
+// The order the given sort keys should be visited in, stable just like sorted()
+// is, including when reversed. The sorting goes through a BTreeSet rather than
+// a slice sort, which may panic if a comparison fails part way through.
+fn sorted_order(keys: Vec<PyObject>, reverse: bool) -> PyResult<Vec<usize>> {
+    let len = keys.len();
+    let mut order = BTreeSet::new();
+    for (index, inner) in keys.into_iter().enumerate() {
+        let tiebreak = match reverse {
+            true => len - index,
+            false => index,
+        };
+        order.insert((
+            SortedKey {
+                inner,
+                sort_key: None,
+            },
+            tiebreak,
+        ));
+    }
+    comparison_result(())?;
+    let indices = order.into_iter().map(|(_, tiebreak)| match reverse {
+        true => len - tiebreak,
+        false => tiebreak,
+    });
+    Ok(match reverse {
+        true => indices.rev().collect(),
+        false => indices.collect(),
+    })
+}
+
+// When the collection was given a key function, sort_key holds what it returned
+// for inner, computed once up front, and is what gets compared instead.
+#[derive(Debug)]
+struct SortedKey {
+    inner: PyObject,
+    sort_key: Option<PyObject>,
+}
+
+impl<'py> IntoPyObject<'py> for SortedKey {
+    type Target = PyAny;
+    type Output = Bound<'py, Self::Target>;
+    type Error = std::convert::Infallible;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        Ok(self.inner.into_bound(py))
+    }
+}
+
+impl Ord for SortedKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Python::with_gil(|py| {
+            self.ordered_by()
+                .bind(py)
+                .compare(other.ordered_by().bind(py))
+                .unwrap_or_else(|err| {
+                    COMPARISON_ERROR.with(|slot| {
+                        slot.borrow_mut().get_or_insert(err);
+                    });
+                    Ordering::Equal
+                })
+        })
+    }
+}
+
+impl PartialOrd for SortedKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Eq for SortedKey {}
+
+impl PartialEq for SortedKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl SortedKey {
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        SortedKey {
+            inner: self.inner.clone_ref(py),
+            sort_key: self.sort_key.as_ref().map(|k| k.clone_ref(py)),
+        }
+    }
+
+    fn ordered_by(&self) -> &PyObject {
+        self.sort_key.as_ref().unwrap_or(&self.inner)
+    }
+
+    // This key as ordered by the given key function (or naturally for None).
+    fn keyed(self, key: Option<&PyObject>, py: Python<'_>) -> PyResult<Self> {
+        let sort_key = match key {
+            Some(key) => Some(key.call1(py, (self.inner.clone_ref(py),))?),
+            None => None,
+        };
+        Ok(SortedKey {
+            inner: self.inner,
+            sort_key,
+        })
+    }
+}
+
+impl<'source> FromPyObject<'source> for SortedKey {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        Ok(SortedKey {
+            inner: ob.clone().unbind(),
+            sort_key: None,
+        })
+    }
+}
+
+// Compares and hashes by object identity, which stays stable since the key
+// holds a reference to the object for as long as it's in the map.
+#[derive(Debug)]
+struct IdentityKey {
+    inner: PyObject,
+}
+
+impl Hash for IdentityKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.inner.as_ptr() as usize);
+    }
+}
+
+impl Eq for IdentityKey {}
+
+impl PartialEq for IdentityKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.is(&other.inner)
+    }
+}
+
+impl IdentityKey {
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        IdentityKey {
+            inner: self.inner.clone_ref(py),
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for IdentityKey {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        Ok(IdentityKey {
+            inner: ob.clone().unbind(),
+        })
+    }
+}
+
+impl<'py> IntoPyObject<'py> for IdentityKey {
+    type Target = PyAny;
+    type Output = Bound<'py, Self::Target>;
+    type Error = std::convert::Infallible;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        Ok(self.inner.into_bound(py))
+    }
+}
+
+// HashTrieMap, HashTrieSet, List and Queue each have an rpds.local counterpart
+// which differs only in being backed by Rc rather than Arc, so what they have
+// in common is implemented once here, generic over the kind of pointer.
+
+fn map_repr<P: SharedPointerKind>(inner: &HashTrieMap<Key, PyObject, P>, py: Python) -> String {
+    let contents = inner.into_iter().map(|(k, v)| {
+        format!(
+            "{}: {}",
+            k.inner
+                .call_method0(py, "__repr__")
+                .and_then(|r| r.extract(py))
+                .unwrap_or("<repr error>".to_owned()),
+            v.call_method0(py, "__repr__")
+                .and_then(|r| r.extract(py))
+                .unwrap_or("<repr error>".to_owned())
+        )
+    });
+    format!(
+        "HashTrieMap({{{}}})",
+        contents.collect::<Vec<_>>().join(", ")
+    )
+}
+
+// Errors raised while comparing values propagate, rather than meaning unequal,
+// here and everywhere else collections are compared.
+fn maps_eq<P: SharedPointerKind>(
+    this: &HashTrieMap<Key, PyObject, P>,
+    that: &HashTrieMap<Key, PyObject, P>,
+    py: Python,
+) -> PyResult<bool> {
+    Ok(std::ptr::eq(this, that)
+        || (this.size() == that.size()
+            && this
+                .iter()
+                .map(|(k1, v1)| (v1, that.get(k1)))
+                .map(|(v1, v2)| v2.map_or(Ok(false), |v2| elements_eq(v1, v2, py)))
+                .find(|r| !matches!(r, Ok(true)))
+                .unwrap_or(Ok(true))?))
+}
+
+// Other mappings are compared by looking up each of our keys in them, which
+// works for any map given its (size many) entries.
+fn mapping_richcmp<'py, 'a>(
+    mut entries: impl Iterator<Item = (Bound<'py, PyAny>, &'a PyObject)>,
+    size: usize,
+    other: &Bound<'py, PyAny>,
+    op: CompareOp,
+    py: Python<'py>,
+) -> PyResult<PyObject> {
+    let Ok(other) = other.downcast::<PyMapping>() else {
+        return Ok(py.NotImplemented());
+    };
+    let mut eq = || -> PyResult<bool> {
+        if other.len()? != size {
+            return Ok(false);
+        }
+        for (k, v) in entries.by_ref() {
+            match other.get_item(k) {
+                Ok(value) => {
+                    if !elements_eq(v, &value.unbind(), py)? {
+                        return Ok(false);
+                    }
+                }
+                Err(err) if err.is_instance_of::<PyKeyError>(py) => return Ok(false),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(true)
+    };
+    match op {
+        CompareOp::Eq => eq()?
+            .into_pyobject(py)
+            .map_err(Into::into)
+            .map(BoundObject::into_any)
+            .map(BoundObject::unbind),
+        CompareOp::Ne => (!eq()?)
+            .into_pyobject(py)
+            .map_err(Into::into)
+            .map(BoundObject::into_any)
+            .map(BoundObject::unbind),
+        _ => Ok(py.NotImplemented()),
+    }
+}
+
+// Either another map of the same flavour (other) or any other Mapping.
+fn map_richcmp<P: SharedPointerKind>(
+    inner: &HashTrieMap<Key, PyObject, P>,
+    other: Option<&HashTrieMap<Key, PyObject, P>>,
+    any: &Bound<'_, PyAny>,
+    op: CompareOp,
+    py: Python<'_>,
+) -> PyResult<PyObject> {
+    let Some(other) = other else {
+        let entries = inner.iter().map(|(k, v)| (k.inner.bind(py).clone(), v));
+        return mapping_richcmp(entries, inner.size(), any, op, py);
+    };
+    let eq = maps_eq(inner, other, py)?;
+    match op {
+        CompareOp::Eq => eq
+            .into_pyobject(py)
+            .map_err(Into::into)
+            .map(BoundObject::into_any)
+            .map(BoundObject::unbind),
+        CompareOp::Ne => (!eq)
+            .into_pyobject(py)
+            .map_err(Into::into)
+            .map(BoundObject::into_any)
+            .map(BoundObject::unbind),
+        _ => Ok(py.NotImplemented()),
+    }
+}
+
+// The hash of a collection from the hashes of its size entries, in whatever order
+// they come, modified from
+// https://github.com/python/cpython/blob/d69529d31ccd1510843cfac1ab53bb8cb027541f/Objects/setobject.c#L715
+//
+// Computed on first use and then cached, which is safe since collections are frozen.
+fn unordered_hash(
+    mut hashes: impl Iterator<Item = PyResult<usize>>,
+    size: usize,
+    cache: &OnceLock<isize>,
+) -> PyResult<isize> {
+    if let Some(hash) = cache.get() {
+        return Ok(*hash);
+    }
+
+    let mut hash_val = hashes.try_fold(0, |acc: usize, x: PyResult<usize>| {
+        PyResult::<usize>::Ok(acc ^ hash_shuffle_bits(x?))
+    })?;
+
+    // factor in the number of entries in the collection
+    hash_val ^= size.wrapping_add(1).wrapping_mul(1927868237);
+
+    // dispense patterns in the hash value
+    hash_val ^= (hash_val >> 11) ^ (hash_val >> 25);
+    hash_val = hash_val.wrapping_mul(69069).wrapping_add(907133923);
+
+    Ok(*cache.get_or_init(|| hash_val as isize))
+}
+
+fn map_hash<P: SharedPointerKind>(
+    inner: &HashTrieMap<Key, PyObject, P>,
+    cache: &OnceLock<isize>,
+    py: Python,
+) -> PyResult<isize> {
+    let hashes = inner.iter().map(|(key, val)| {
+        let mut hasher = DefaultHasher::new();
+        let val_bound = val.bind(py);
+
+        let key_hash = key.hash;
+        let val_hash = val_bound.hash().map_err(|_| {
+            PyTypeError::new_err(format!(
+                "Unhashable type in HashTrieMap of key {}: {}",
+                key.inner
+                    .bind(py)
+                    .repr()
+                    .and_then(|r| r.extract())
+                    .unwrap_or("<repr> error".to_string()),
+                val_bound
+                    .repr()
+                    .and_then(|r| r.extract())
+                    .unwrap_or("<repr> error".to_string())
+            ))
+        })?;
+
+        hasher.write_isize(key_hash);
+        hasher.write_isize(val_hash);
+
+        Ok(hasher.finish() as usize)
+    });
+    unordered_hash(hashes, inner.size(), cache)
+}
+
+// The approximate size of slf, a collection of len elements of type T held in
+// nodes of type N (see shared_size).
+fn collection_size<T, N>(slf: &Bound<'_, PyAny>, len: usize) -> PyResult<usize> {
+    let base: usize = slf.get_type().getattr("__basicsize__")?.extract()?;
+    Ok(base + len * (shared_size::<T>() + shared_size::<N>()))
+}
+
+fn set_repr<P: SharedPointerKind>(inner: &HashTrieSet<Key, P>, py: Python) -> PyResult<String> {
+    let contents = inner.into_iter().map(|k| {
+        Ok(k.clone_ref(py)
+            .into_pyobject(py)?
+            .call_method0("__repr__")
+            .and_then(|r| r.extract())
+            .unwrap_or("<repr failed>".to_owned()))
+    });
+    let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
+    Ok(format!("HashTrieSet({{{}}})", contents.join(", ")))
+}
+
+// Sets of either flavour are equal to any other Set with the same elements.
+fn set_eq<P: SharedPointerKind>(
+    slf: &Bound<'_, PyAny>,
+    inner: &HashTrieSet<Key, P>,
+    other: &Bound<'_, PyAny>,
+) -> PyResult<bool> {
+    if slf.is(other) {
+        return Ok(true);
+    }
+    let abc = PyModule::import(slf.py(), "collections.abc")?;
+    if !other.is_instance(&abc.getattr("Set")?)? || other.len()? != inner.size() {
+        return Ok(false);
+    }
+    for each in other.try_iter()? {
+        if !inner.contains(&Key::extract_bound(&each?)?) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn set_hash<P: SharedPointerKind>(
+    inner: &HashTrieSet<Key, P>,
+    cache: &OnceLock<isize>,
+) -> PyResult<isize> {
+    let hashes = inner.iter().map(|k| Ok(k.hash as usize));
+    unordered_hash(hashes, inner.size(), cache)
+}
+
+fn set_difference<P: SharedPointerKind>(
+    this: &HashTrieSet<Key, P>,
+    other: &HashTrieSet<Key, P>,
+) -> HashTrieSet<Key, P> {
+    let mut inner = this.clone();
+    for value in other.iter() {
+        inner.remove_mut(value);
+    }
+    inner
+}
+
+fn set_intersection<P: SharedPointerKind>(
+    this: &HashTrieSet<Key, P>,
+    other: &HashTrieSet<Key, P>,
+    py: Python,
+) -> HashTrieSet<Key, P> {
+    let (larger, smaller) = match this.size() > other.size() {
+        true => (this, other),
+        false => (other, this),
+    };
+    let mut inner = HashTrieSet::default();
+    for value in smaller {
+        if larger.contains(value) {
+            inner.insert_mut(value.clone_ref(py));
+        }
+    }
+    inner
+}
+
+fn set_symmetric_difference<P: SharedPointerKind>(
+    this: &HashTrieSet<Key, P>,
+    other: &HashTrieSet<Key, P>,
+    py: Python,
+) -> HashTrieSet<Key, P> {
+    let (mut inner, other) = match this.size() > other.size() {
+        true => (this.clone(), other),
+        false => (other.clone(), this),
+    };
+    for value in other {
+        if inner.contains(value) {
+            inner.remove_mut(value);
+        } else {
+            inner.insert_mut(value.clone_ref(py));
+        }
+    }
+    inner
+}
+
+fn set_union<P: SharedPointerKind>(
+    this: &HashTrieSet<Key, P>,
+    other: &HashTrieSet<Key, P>,
+    py: Python,
+) -> HashTrieSet<Key, P> {
+    let (mut inner, other) = match this.size() > other.size() {
+        true => (this.clone(), other),
+        false => (other.clone(), this),
+    };
+    for value in other {
+        inner.insert_mut(value.clone_ref(py));
+    }
+    inner
+}
+
+// The persistent sequences behind List and Queue (of either flavour) and Vector.
+trait Elements {
+    fn count(&self) -> usize;
+    fn elements(&self) -> impl Iterator<Item = &PyObject>;
+}
+
+impl<P: SharedPointerKind> Elements for List<PyObject, P> {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &PyObject> {
+        self.iter()
+    }
+}
+
+impl<P: SharedPointerKind> Elements for Queue<PyObject, P> {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &PyObject> {
+        self.iter()
+    }
+}
+
+impl<P: SharedPointerKind> Elements for Vector<PyObject, P> {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &PyObject> {
+        self.iter()
+    }
+}
+
+fn sequence_repr(kind: &str, inner: &impl Elements, py: Python) -> PyResult<String> {
+    let contents = inner.elements().map(|k| {
+        Ok(k.into_pyobject(py)?
+            .call_method0("__repr__")
+            .and_then(|r| r.extract())
+            .unwrap_or("<repr failed>".to_owned()))
+    });
+    let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
+    Ok(format!("{}([{}])", kind, contents.join(", ")))
+}
+
+fn sequences_eq<S: Elements>(this: &S, that: &S, py: Python) -> PyResult<bool> {
+    Ok(std::ptr::eq(this, that)
+        || (this.count() == that.count()
+            && this
+                .elements()
+                .zip(that.elements())
+                .map(|(e1, e2)| elements_eq(e1, e2, py))
+                .find(|r| !matches!(r, Ok(true)))
+                .unwrap_or(Ok(true))?))
+}
+
+fn sequences_ne<S: Elements>(this: &S, that: &S, py: Python) -> PyResult<bool> {
+    Ok(!std::ptr::eq(this, that)
+        && (this.count() != that.count()
+            || this
+                .elements()
+                .zip(that.elements())
+                .map(|(e1, e2)| elements_ne(e1, e2, py))
+                .find(|r| !matches!(r, Ok(false)))
+                .unwrap_or(Ok(false))?))
+}
+
+fn sequence_hash(
+    kind: &str,
+    inner: &impl Elements,
+    cache: &OnceLock<u64>,
+    py: Python,
+) -> PyResult<u64> {
+    if let Some(hash) = cache.get() {
+        return Ok(*hash);
+    }
+
+    let mut hasher = DefaultHasher::new();
+
+    inner.elements().enumerate().try_for_each(|(index, each)| {
+        each.bind(py)
+            .hash()
+            .map_err(|_| {
+                PyTypeError::new_err(format!(
+                    "Unhashable type at {} element in {}: {}",
+                    index,
+                    kind,
+                    each.bind(py)
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string())
+                ))
+            })
+            .map(|x| hasher.write_isize(x))
+    })?;
+
+    Ok(*cache.get_or_init(|| hasher.finish()))
+}
+
+fn sequence_richcmp<S: Elements>(
+    this: &S,
+    that: &S,
+    op: CompareOp,
+    py: Python<'_>,
+) -> PyResult<PyObject> {
+    match op {
+        CompareOp::Eq => sequences_eq(this, that, py)?
+            .into_pyobject(py)
+            .map_err(Into::into)
+            .map(BoundObject::into_any)
+            .map(BoundObject::unbind),
+        CompareOp::Ne => sequences_ne(this, that, py)?
+            .into_pyobject(py)
+            .map_err(Into::into)
+            .map(BoundObject::into_any)
+            .map(BoundObject::unbind),
+        _ => Ok(py.NotImplemented()),
+    }
+}
+
+// Lists are built from the back, but not every iterable can be reversed, so
+// the elements are collected first.
+fn list_from_iterable<P: SharedPointerKind>(ob: &Bound<'_, PyAny>) -> PyResult<List<PyObject, P>> {
+    let mut elements = Vec::new();
+    for each in ob.try_iter()? {
+        elements.push(each?.unbind());
+    }
+    let mut ret = List::new_with_ptr_kind();
+    for each in elements.into_iter().rev() {
+        ret.push_front_mut(each);
+    }
+    Ok(ret)
+}
+
+fn list_first<P: SharedPointerKind>(inner: &List<PyObject, P>) -> PyResult<&PyObject> {
+    inner
+        .first()
+        .ok_or_else(|| PyIndexError::new_err("empty list has no first element"))
+}
+
+fn list_rest<P: SharedPointerKind>(inner: &List<PyObject, P>) -> List<PyObject, P> {
+    let mut inner = inner.clone();
+    inner.drop_first_mut();
+    inner
+}
+
+fn list_drop_first<P: SharedPointerKind>(inner: &List<PyObject, P>) -> PyResult<List<PyObject, P>> {
+    inner
+        .drop_first()
+        .ok_or_else(|| PyIndexError::new_err("empty list has no first element"))
+}
+
+fn queue_from_iterable<P: SharedPointerKind>(
+    ob: &Bound<'_, PyAny>,
+) -> PyResult<Queue<PyObject, P>> {
+    let mut ret = Queue::new_with_ptr_kind();
+    for each in ob.try_iter()? {
+        ret.enqueue_mut(each?.unbind());
+    }
+    Ok(ret)
+}
+
+fn queue_peek<P: SharedPointerKind>(inner: &Queue<PyObject, P>, py: Python) -> PyResult<PyObject> {
+    inner
+        .peek()
+        .map(|peeked| peeked.clone_ref(py))
+        .ok_or_else(|| PyIndexError::new_err("peeked an empty queue"))
+}
+
+fn queue_item<P: SharedPointerKind>(
+    inner: &Queue<PyObject, P>,
+    index: isize,
+    py: Python,
+) -> PyResult<PyObject> {
+    let len = inner.len() as isize;
+    let normalized = if index < 0 { index + len } else { index };
+    if !(0..len).contains(&normalized) {
+        return Err(PyIndexError::new_err("queue index out of range"));
+    }
+    match inner.iter().nth(normalized as usize) {
+        Some(value) => Ok(value.clone_ref(py)),
+        None => Err(PyIndexError::new_err("queue index out of range")),
+    }
+}
+
+fn queue_dequeue<P: SharedPointerKind>(inner: &Queue<PyObject, P>) -> PyResult<Queue<PyObject, P>> {
+    inner
+        .dequeue()
+        .ok_or_else(|| PyIndexError::new_err("dequeued an empty queue"))
+}
+
+fn sequence_contains(inner: &impl Elements, value: &PyObject, py: Python) -> PyResult<bool> {
+    for each in inner.elements() {
+        if elements_eq(each, value, py)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn sequence_reversed(inner: &impl Elements, py: Python) -> ReverseIterator {
+    ReverseIterator {
+        elements: inner.elements().map(|each| each.clone_ref(py)).collect(),
+    }
+}
+
+#[pyclass(name = "HashTrieMap", module = "rpds", frozen, mapping)]
+struct HashTrieMapPy {
+    inner: HashTrieMapSync<Key, PyObject>,
+    // computed on first use, which is safe since the map is frozen
+    hash: OnceLock<isize>,
+}
+
+impl From<HashTrieMapSync<Key, PyObject>> for HashTrieMapPy {
+    fn from(map: HashTrieMapSync<Key, PyObject>) -> Self {
+        HashTrieMapPy {
+            inner: map,
+            hash: OnceLock::new(),
+        }
+    }
+}
+
+// Visits the items of a mapping or an iterable of pairs, where like for dict,
+// a pair may be any two item iterable and not just a tuple.
+fn each_item(
+    ob: &Bound<'_, PyAny>,
+    mut f: impl FnMut(Key, PyObject) -> PyResult<()>,
+) -> PyResult<()> {
+    if let Ok(mapping) = ob.downcast::<PyMapping>() {
+        for each in mapping.items()?.iter() {
+            let (k, v): (Key, PyObject) = each.extract()?;
+            f(k, v)?;
+        }
+        return Ok(());
+    }
+    for (index, each) in ob.try_iter()?.enumerate() {
+        let each = each?;
+        if let Ok(pair) = each.downcast::<PyTuple>() {
+            if pair.len() == 2 {
+                let (k, v): (Key, PyObject) = pair.extract()?;
+                f(k, v)?;
+                continue;
+            }
+        }
+        let Ok(items) = each.try_iter() else {
+            return Err(PyTypeError::new_err(format!(
+                "cannot convert map update sequence element #{} to a sequence",
+                index
+            )));
+        };
+        let items = items.collect::<PyResult<Vec<_>>>()?;
+        let [k, v] = items.as_slice() else {
+            return Err(PyValueError::new_err(format!(
+                "map update sequence element #{} has length {}; 2 is required",
+                index,
+                items.len()
+            )));
+        };
+        f(Key::extract_bound(k)?, v.clone().unbind())?;
+    }
+    Ok(())
+}
+
+fn insert_items<P: SharedPointerKind>(
+    inner: &mut HashTrieMap<Key, PyObject, P>,
+    ob: &Bound<'_, PyAny>,
+) -> PyResult<()> {
+    each_item(ob, |k, v| {
+        inner.insert_mut(k, v);
+        Ok(())
+    })
+}
+
+fn key_set<P: SharedPointerKind>(
+    inner: &HashTrieMap<Key, PyObject, P>,
+    py: Python<'_>,
+) -> HashTrieSet<Key, P> {
+    let mut keys = HashTrieSet::default();
+    for key in inner.keys() {
+        keys.insert_mut(key.clone_ref(py));
+    }
+    keys
+}
+
+fn map_with_kwds<P: SharedPointerKind>(
+    mut inner: HashTrieMap<Key, PyObject, P>,
+    kwds: Option<&Bound<'_, PyDict>>,
+) -> PyResult<HashTrieMap<Key, PyObject, P>> {
+    if let Some(kwds) = kwds {
+        for (k, v) in kwds {
+            inner.insert_mut(Key::extract_bound(&k)?, v.unbind());
+        }
+    }
+    Ok(inner)
+}
+
+fn map_updated<P: SharedPointerKind>(
+    inner: &HashTrieMap<Key, PyObject, P>,
+    maps: &Bound<'_, PyTuple>,
+    kwds: Option<&Bound<'_, PyDict>>,
+) -> PyResult<HashTrieMap<Key, PyObject, P>> {
+    let mut inner = inner.clone();
+    for value in maps {
+        insert_items(&mut inner, &value)?;
+    }
+    map_with_kwds(inner, kwds)
+}
+
+fn set_updated<P: SharedPointerKind>(
+    inner: &HashTrieSet<Key, P>,
+    iterables: &Bound<'_, PyTuple>,
+) -> PyResult<HashTrieSet<Key, P>> {
+    let mut inner = inner.clone();
+    for each in iterables {
+        for value in each.try_iter()? {
+            inner.insert_mut(Key::extract_bound(&value?)?);
+        }
+    }
+    Ok(inner)
+}
+
+impl<'source> FromPyObject<'source> for HashTrieMapPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let mut ret = HashTrieMap::new_sync();
+        insert_items(&mut ret, ob)?;
+        Ok(HashTrieMapPy::from(ret))
+    }
+}
+
+impl HashTrieMapPy {
+    // Our entries ordered by their keys (or by key(k) if given).
+    fn sorted_entries(
+        &self,
+        key: Option<&Bound<'_, PyAny>>,
+        reverse: bool,
+        py: Python,
+    ) -> PyResult<Vec<(&Key, &PyObject)>> {
+        let entries: Vec<_> = self.inner.iter().collect();
+        let mut sort_keys = Vec::with_capacity(entries.len());
+        for (k, _) in entries.iter() {
+            sort_keys.push(match key {
+                Some(key) => key.call1((*k,))?.unbind(),
+                None => k.inner.clone_ref(py),
+            });
+        }
+        let order = sorted_order(sort_keys, reverse)?;
+        Ok(order.into_iter().map(|index| entries[index]).collect())
+    }
+}
+
+#[pymethods]
+impl HashTrieMapPy {
+    #[new]
+    #[pyo3(signature = (value=None, ** kwds))]
+    fn init(value: Option<HashTrieMapPy>, kwds: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let inner = value.map_or_else(HashTrieMap::new_sync, |map| map.inner);
+        Ok(HashTrieMapPy::from(map_with_kwds(inner, kwds)?))
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.inner.contains_key(&key)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> KeysIterator {
+        KeysIterator {
+            inner: slf.inner.clone(),
+        }
+    }
+
+    fn __getitem__(&self, key: Key, py: Python) -> PyResult<PyObject> {
+        match self.inner.get(&key) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        collection_size::<(Key, PyObject), (usize, u64)>(slf.as_any(), slf.get().inner.size())
+    }
+
+    fn __or__(&self, other: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let py = other.py();
+        if other.downcast::<PyMapping>().is_err() {
+            return Ok(py.NotImplemented());
+        }
+        let mut inner = self.inner.clone();
+        insert_items(&mut inner, other)?;
+        Ok(HashTrieMapPy::from(inner)
+            .into_pyobject(py)?
+            .into_any()
+            .unbind())
+    }
+
+    fn __ror__(&self, other: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let py = other.py();
+        if other.downcast::<PyMapping>().is_err() {
+            return Ok(py.NotImplemented());
+        }
+        let mut inner = HashTrieMapPy::extract_bound(other)?.inner;
+        for (k, v) in &self.inner {
+            inner.insert_mut(k.clone_ref(py), v.clone_ref(py));
+        }
+        Ok(HashTrieMapPy::from(inner)
+            .into_pyobject(py)?
+            .into_any()
+            .unbind())
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        map_repr(&self.inner, py)
+    }
+
+    fn __richcmp__<'py>(
+        &self,
+        other: &Bound<'py, PyAny>,
+        op: CompareOp,
+        py: Python<'py>,
+    ) -> PyResult<PyObject> {
+        let same = other.downcast::<HashTrieMapPy>().ok();
+        map_richcmp(&self.inner, same.map(|map| &map.get().inner), other, op, py)
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        map_hash(&self.inner, &self.hash, py)
+    }
+
+    // Pickles as a single flat tuple of alternating keys and values, which
+    // is both smaller than a list of pairs and cheaper to restore from.
+    fn __reduce__(slf: PyRef<'_, Self>) -> PyResult<(Bound<'_, PyAny>, (Bound<'_, PyTuple>,))> {
+        let py = slf.py();
+        let mut items = Vec::with_capacity(2 * slf.inner.size());
+        for (k, v) in slf.inner.iter() {
+            items.push(k.inner.clone_ref(py));
+            items.push(v.clone_ref(py));
+        }
+        let items = PyTuple::new(py, items)?;
+        Ok((
+            HashTrieMapPy::type_object(py).getattr("_from_flat_items")?,
+            (items,),
+        ))
+    }
+
+    #[staticmethod]
+    fn _from_flat_items(items: &Bound<'_, PyTuple>) -> PyResult<HashTrieMapPy> {
+        if items.len() % 2 != 0 {
+            return Err(PyValueError::new_err(
+                "expected an even number of alternating keys and values",
+            ));
+        }
+        let mut inner = HashTrieMap::new_sync();
+        let mut items = items.iter();
+        while let (Some(k), Some(v)) = (items.next(), items.next()) {
+            inner.insert_mut(Key::extract_bound(&k)?, v.unbind());
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// With deep=True, nested dicts, lists and sets are converted as well.
+    #[classmethod]
+    #[pyo3(signature = (value, *, deep=false))]
+    fn convert(
+        _cls: &Bound<'_, PyType>,
+        value: Bound<'_, PyAny>,
+        deep: bool,
+        py: Python,
+    ) -> PyResult<PyObject> {
+        if value.is_instance_of::<HashTrieMapPy>() && !deep {
+            return Ok(value.unbind());
+        }
+        let map = match deep {
+            true => HashTrieMapPy::from(convert_deep_map(&value)?),
+            false => HashTrieMapPy::extract_bound(&value)?,
+        };
+        Ok(map.into_pyobject(py)?.into_any().unbind())
+    }
+
+    /// With factory=True, val is called with no arguments to produce a fresh
+    /// value for each key rather than being shared between all of them.
+    #[classmethod]
+    #[pyo3(signature = (keys, val=None, *, factory=false))]
+    fn fromkeys(
+        _cls: &Bound<'_, PyType>,
+        keys: &Bound<'_, PyAny>,
+        val: Option<&Bound<'_, PyAny>>,
+        factory: bool,
+        py: Python,
+    ) -> PyResult<HashTrieMapPy> {
+        let mut inner = HashTrieMap::new_sync();
+        let none = py.None().into_bound(py);
+        let value = val.unwrap_or(&none);
+        if factory && !value.is_callable() {
+            return Err(PyTypeError::new_err(format!(
+                "fromkeys() factory must be callable, not {}",
+                value.repr()?
+            )));
+        }
+        for each in keys.try_iter()? {
+            let key = Key::extract_bound(&each?)?;
+            let value = match factory {
+                true => value.call0()?.unbind(),
+                false => value.clone().unbind(),
+            };
+            inner.insert_mut(key, value);
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// Bucket the items of an iterable into Lists by key(item), keeping the
+    /// order they came in.
+    #[classmethod]
+    fn group_by(
+        _cls: &Bound<'_, PyType>,
+        key: &Bound<'_, PyAny>,
+        iterable: &Bound<'_, PyAny>,
+    ) -> PyResult<HashTrieMapPy> {
+        let py = key.py();
+        let mut groups: HashMap<Key, Vec<PyObject>> = HashMap::new();
+        for each in iterable.try_iter()? {
+            let each = each?;
+            let group = Key::extract_bound(&key.call1((&each,))?)?;
+            groups.entry(group).or_default().push(each.unbind());
+        }
+        let mut inner = HashTrieMap::new_sync();
+        for (group, items) in groups {
+            let mut list = List::new_sync();
+            for each in items.into_iter().rev() {
+                list.push_front_mut(each);
+            }
+            inner.insert_mut(
+                group,
+                ListPy::from(list).into_pyobject(py)?.into_any().unbind(),
+            );
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    #[pyo3(signature = (sep="."))]
+    fn flatten(&self, sep: &str, py: Python) -> PyResult<HashTrieMapPy> {
+        let mut inner = HashTrieMap::new_sync();
+        for (k, v) in self.inner.iter() {
+            let path = k.inner.bind(py).str()?.to_string();
+            flatten_into(path, v.bind(py), sep, &mut inner)?;
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    #[pyo3(signature = (sep="."))]
+    fn unflatten(&self, sep: &str, py: Python) -> PyResult<HashTrieMapPy> {
+        if sep.is_empty() {
+            return Err(PyValueError::new_err("empty separator"));
+        }
+        let mut inner = HashTrieMap::new_sync();
+        let mut tree = HashMap::new();
+        for (k, v) in self.inner.iter() {
+            match k.inner.downcast_bound::<PyString>(py) {
+                Ok(path) => unflatten_into(&mut tree, path, sep, v.clone_ref(py))?,
+                Err(_) => inner.insert_mut(k.clone_ref(py), v.clone_ref(py)),
+            }
+        }
+        for (k, node) in tree {
+            inner.insert_mut(
+                Key::extract_bound(PyString::new(py, &k).as_any())?,
+                node.into_value(py)?,
+            );
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    fn resolve_pointer<'py>(slf: &Bound<'py, Self>, pointer: &str) -> PyResult<Bound<'py, PyAny>> {
+        resolve_pointer_in(slf.as_any(), pointer)
+    }
+
+    fn apply_json_patch<'py>(
+        slf: &Bound<'py, Self>,
+        patch: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        apply_json_patch(slf.as_any(), patch)
+    }
+
+    fn to_json_patch<'py>(
+        slf: &Bound<'py, Self>,
+        other: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyList>> {
+        make_json_patch(slf.as_any(), other)
+    }
+
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, key: Key, default: Option<PyObject>, py: Python) -> Option<PyObject> {
+        if let Some(value) = self.inner.get(&key) {
+            Some(value.clone_ref(py))
+        } else {
+            default
+        }
+    }
+
+    /// This map's items, sorted by key (or by key(k) if given).
+    #[pyo3(signature = (key=None, reverse=false))]
+    fn sorted_items(
+        &self,
+        key: Option<&Bound<'_, PyAny>>,
+        reverse: bool,
+        py: Python,
+    ) -> PyResult<Vec<(Key, PyObject)>> {
+        Ok(self
+            .sorted_entries(key, reverse, py)?
+            .into_iter()
+            .map(|(k, v)| (k.clone_ref(py), v.clone_ref(py)))
+            .collect())
+    }
+
+    /// This map's keys, sorted (by key(k) if given).
+    #[pyo3(signature = (key=None, reverse=false))]
+    fn sorted_keys(
+        &self,
+        key: Option<&Bound<'_, PyAny>>,
+        reverse: bool,
+        py: Python,
+    ) -> PyResult<Vec<Key>> {
+        Ok(self
+            .sorted_entries(key, reverse, py)?
+            .into_iter()
+            .map(|(k, _)| k.clone_ref(py))
+            .collect())
+    }
+
+    /// A HashTrieSet of this map's keys.
+    fn keys_set(&self, py: Python) -> HashTrieSetPy {
+        HashTrieSetPy::from(key_set(&self.inner, py))
+    }
+
+    fn keys(&self) -> KeysView {
+        KeysView {
+            inner: self.inner.clone(),
+        }
+    }
+
+    fn values(&self) -> ValuesView {
+        ValuesView {
+            inner: self.inner.clone(),
+        }
+    }
+
+    fn items(&self) -> ItemsView {
+        ItemsView {
+            inner: self.inner.clone(),
+        }
+    }
+
+    fn discard(&self, key: Key) -> PyResult<HashTrieMapPy> {
+        match self.inner.contains_key(&key) {
+            true => Ok(HashTrieMapPy::from(self.inner.remove(&key))),
+            false => Ok(HashTrieMapPy::from(self.inner.clone())),
+        }
+    }
+
+    fn insert(&self, key: Key, value: Bound<'_, PyAny>) -> HashTrieMapPy {
+        HashTrieMapPy::from(self.inner.insert(key, value.unbind()))
+    }
+
+    fn remove(&self, key: Key) -> PyResult<HashTrieMapPy> {
+        match self.inner.contains_key(&key) {
+            true => Ok(HashTrieMapPy::from(self.inner.remove(&key))),
+            false => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    /// Insert each of an iterable of (key, value) pairs. Unlike update, the
+    /// argument is never treated as a mapping.
+    fn insert_many(&self, items: &Bound<'_, PyAny>) -> PyResult<HashTrieMapPy> {
+        let mut inner = self.inner.clone();
+        for each in items.try_iter()? {
+            let (k, v): (Key, PyObject) = each?.extract()?;
+            inner.insert_mut(k, v);
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// Remove each of the given keys, raising a KeyError if any is missing.
+    fn remove_many(&self, keys: &Bound<'_, PyAny>) -> PyResult<HashTrieMapPy> {
+        let mut inner = self.inner.clone();
+        for each in keys.try_iter()? {
+            let key = Key::extract_bound(&each?)?;
+            if !inner.remove_mut(&key) {
+                return Err(PyKeyError::new_err(key));
+            }
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// Remove a key if present, returning the new map along with whether the
+    /// key was removed.
+    fn try_remove(&self, key: Key) -> (HashTrieMapPy, bool) {
+        let mut inner = self.inner.clone();
+        let removed = inner.remove_mut(&key);
+        (HashTrieMapPy::from(inner), removed)
+    }
+
+    #[pyo3(signature = (key, *default))]
+    fn pop(
+        &self,
+        key: Key,
+        default: &Bound<'_, PyTuple>,
+        py: Python,
+    ) -> PyResult<(PyObject, HashTrieMapPy)> {
+        if default.len() > 1 {
+            return Err(PyTypeError::new_err(format!(
+                "pop expected at most 2 arguments, got {}",
+                default.len() + 1
+            )));
+        }
+        match self.inner.get(&key) {
+            Some(value) => Ok((
+                value.clone_ref(py),
+                HashTrieMapPy::from(self.inner.remove(&key)),
+            )),
+            None => match default.get_item(0) {
+                Ok(default) => Ok((default.unbind(), HashTrieMapPy::from(self.inner.clone()))),
+                Err(_) => Err(PyKeyError::new_err(key)),
+            },
+        }
+    }
+
+    /// Like dict.setdefault, but returning the new map alongside the value.
+    fn insert_if_missing(
+        &self,
+        key: Key,
+        value: PyObject,
+        py: Python,
+    ) -> (PyObject, HashTrieMapPy) {
+        match self.inner.get(&key) {
+            Some(existing) => (
+                existing.clone_ref(py),
+                HashTrieMapPy::from(self.inner.clone()),
+            ),
+            None => (
+                value.clone_ref(py),
+                HashTrieMapPy::from(self.inner.insert(key, value)),
+            ),
+        }
+    }
+
+    fn popitem(&self, py: Python) -> PyResult<(Key, PyObject, HashTrieMapPy)> {
+        match self.inner.iter().next() {
+            Some((key, value)) => Ok((
+                key.clone_ref(py),
+                value.clone_ref(py),
+                HashTrieMapPy::from(self.inner.remove(key)),
+            )),
+            None => Err(PyKeyError::new_err("popitem(): map is empty")),
+        }
+    }
+
+    /// Fold f(accumulator, key, value) over each entry, starting from initial.
+    fn reduce(&self, f: &Bound<'_, PyAny>, initial: PyObject) -> PyResult<PyObject> {
+        let mut accumulator = initial;
+        for (k, v) in self.inner.iter() {
+            accumulator = f.call1((accumulator, k, v))?.unbind();
+        }
+        Ok(accumulator)
+    }
+
+    /// A map from each value to its key. Values shared by several keys are an
+    /// error unless collect is true, in which case each value maps to the
+    /// HashTrieSet of all of its keys.
+    #[pyo3(signature = (*, collect=false))]
+    fn invert(&self, collect: bool, py: Python) -> PyResult<HashTrieMapPy> {
+        let mut inner: HashTrieMapSync<Key, PyObject> = HashTrieMap::new_sync();
+        for (k, v) in self.inner.iter() {
+            let value = Key::extract_bound(v.bind(py))?;
+            let key = match (inner.get(&value), collect) {
+                (None, false) => k.inner.clone_ref(py),
+                (None, true) => {
+                    let keys = HashTrieSetPy::from(HashTrieSet::new_sync().insert(k.clone_ref(py)));
+                    keys.into_pyobject(py)?.into_any().unbind()
+                }
+                (Some(existing), true) => {
+                    let keys = existing.downcast_bound::<HashTrieSetPy>(py)?.get();
+                    let keys = HashTrieSetPy::from(keys.inner.insert(k.clone_ref(py)));
+                    keys.into_pyobject(py)?.into_any().unbind()
+                }
+                (Some(existing), false) => {
+                    return Err(PyValueError::new_err(format!(
+                        "cannot invert, since keys {} and {} both have value {}",
+                        existing.bind(py).repr()?,
+                        k.inner.bind(py).repr()?,
+                        v.bind(py).repr()?,
+                    )));
+                }
+            };
+            inner.insert_mut(value, key);
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// Apply a function to each value, keeping the existing keys.
+    fn map_values(&self, f: &Bound<'_, PyAny>) -> PyResult<HashTrieMapPy> {
+        let py = f.py();
+        let mut inner = HashTrieMapSync::new_sync();
+        for (k, v) in self.inner.iter() {
+            inner.insert_mut(k.clone_ref(py), f.call1((v,))?.unbind());
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// Apply a function to each key, keeping the existing values.
+    ///
+    /// Two keys mapping to the same new key is an error unless combine is
+    /// given, in which case combine(old, new) decides the value to keep.
+    #[pyo3(signature = (f, combine=None))]
+    fn map_keys(
+        &self,
+        f: &Bound<'_, PyAny>,
+        combine: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<HashTrieMapPy> {
+        let py = f.py();
+        let mut inner: HashTrieMapSync<Key, PyObject> = HashTrieMapSync::new_sync();
+        let mut originals: HashTrieMapSync<Key, PyObject> = HashTrieMapSync::new_sync();
+        for (k, v) in self.inner.iter() {
+            let new = Key::extract_bound(&f.call1((k,))?)?;
+            let value = match (inner.get(&new), combine) {
+                (None, _) => v.clone_ref(py),
+                (Some(old), Some(combine)) => combine.call1((old, v))?.unbind(),
+                (Some(_), None) => {
+                    return Err(PyValueError::new_err(format!(
+                        "map_keys(): keys {} and {} both map to {}",
+                        originals[&new].bind(py).repr()?,
+                        k.inner.bind(py).repr()?,
+                        new.inner.bind(py).repr()?,
+                    )));
+                }
+            };
+            originals.insert_mut(new.clone_ref(py), k.inner.clone_ref(py));
+            inner.insert_mut(new, value);
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// A new map containing only those of the given keys which are present.
+    fn select(&self, keys: &Bound<'_, PyAny>) -> PyResult<HashTrieMapPy> {
+        let py = keys.py();
+        let mut inner = HashTrieMapSync::new_sync();
+        for each in keys.try_iter()? {
+            let key = Key::extract_bound(&each?)?;
+            if let Some(value) = self.inner.get(&key) {
+                inner.insert_mut(key, value.clone_ref(py));
+            }
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// A new map with all of the given keys removed, ignoring missing ones.
+    fn omit(&self, keys: &Bound<'_, PyAny>) -> PyResult<HashTrieMapPy> {
+        let mut inner = self.inner.clone();
+        for each in keys.try_iter()? {
+            inner.remove_mut(&Key::extract_bound(&each?)?);
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// Look up a value by walking a path of keys or indices through nested
+    /// maps and sequences, returning default if any step along it is missing.
+    #[pyo3(signature = (path, default=None))]
+    fn get_in(
+        slf: &Bound<'_, Self>,
+        path: &Bound<'_, PyAny>,
+        default: Option<PyObject>,
+    ) -> PyResult<PyObject> {
+        let py = slf.py();
+        let mut current = slf.clone().into_any();
+        for segment in path.try_iter()? {
+            match path_child(&current, &segment?)? {
+                Some(child) => current = child,
+                None => return Ok(default.unwrap_or_else(|| py.None())),
+            }
+        }
+        Ok(current.unbind())
+    }
+
+    /// Set the value at a path of keys or indices through nested maps and
+    /// sequences, creating any missing intermediate maps along the way.
+    fn set_in(
+        slf: &Bound<'_, Self>,
+        path: &Bound<'_, PyAny>,
+        value: PyObject,
+    ) -> PyResult<HashTrieMapPy> {
+        let segments = path.try_iter()?.collect::<PyResult<Vec<_>>>()?;
+        if segments.is_empty() {
+            return Err(PyValueError::new_err("set_in() requires a non-empty path"));
+        }
+        set_in_value(Some(slf.as_any()), &segments, value)?.extract(slf.py())
+    }
+
+    /// Remove the entry at a path of keys or indices through nested maps and
+    /// sequences, sharing everything not along the path.
+    fn delete_in(slf: &Bound<'_, Self>, path: &Bound<'_, PyAny>) -> PyResult<HashTrieMapPy> {
+        let segments = path.try_iter()?.collect::<PyResult<Vec<_>>>()?;
+        if segments.is_empty() {
+            return Err(PyValueError::new_err(
+                "delete_in() requires a non-empty path",
+            ));
+        }
+        delete_in_value(slf.as_any(), &segments)?.extract(slf.py())
+    }
+
+    /// Split this map into a pair of maps, the first containing the items
+    /// for which predicate(key, value) is true and the second the rest.
+    fn partition(&self, predicate: &Bound<'_, PyAny>) -> PyResult<(HashTrieMapPy, HashTrieMapPy)> {
+        let py = predicate.py();
+        let mut matching = HashTrieMapSync::new_sync();
+        let mut rest = HashTrieMapSync::new_sync();
+        for (k, v) in self.inner.iter() {
+            let side = match predicate.call1((k, v))?.is_truthy()? {
+                true => &mut matching,
+                false => &mut rest,
+            };
+            side.insert_mut(k.clone_ref(py), v.clone_ref(py));
+        }
+        Ok((HashTrieMapPy::from(matching), HashTrieMapPy::from(rest)))
+    }
+
+    /// Recursively merge another map into this one, merging nested maps
+    /// rather than replacing them and resolving any other colliding values
+    /// with on_conflict(old, new) if given.
+    #[pyo3(signature = (other, on_conflict=None))]
+    fn merge_deep(
+        &self,
+        other: HashTrieMapPy,
+        on_conflict: Option<&Bound<'_, PyAny>>,
+        py: Python,
+    ) -> PyResult<HashTrieMapPy> {
+        let mut inner = self.inner.clone();
+        deep_merge_into(
+            &mut inner,
+            &other.inner,
+            &ListStrategy::Replace,
+            on_conflict,
+            py,
+        )?;
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// Like update, but with fn(old, new) deciding the value of any keys
+    /// which are already present.
+    #[pyo3(signature = (f, *maps))]
+    fn update_with(
+        &self,
+        f: &Bound<'_, PyAny>,
+        maps: &Bound<'_, PyTuple>,
+    ) -> PyResult<HashTrieMapPy> {
+        let mut inner = self.inner.clone();
+        for value in maps {
+            each_item(&value, |k, v| {
+                let v = match inner.get(&k) {
+                    Some(old) => f.call1((old, v))?.unbind(),
+                    None => v,
+                };
+                inner.insert_mut(k, v);
+                Ok(())
+            })?;
+        }
+        Ok(HashTrieMapPy::from(inner))
+    }
+
+    /// A DefaultHashTrieMap with these items, producing factory() for any
+    /// missing key.
+    fn with_default(&self, factory: &Bound<'_, PyAny>) -> PyResult<DefaultHashTrieMapPy> {
+        DefaultHashTrieMapPy::init(factory, Some(HashTrieMapPy::from(self.inner.clone())), None)
+    }
+
+    fn evolver(&self) -> HashTrieMapEvolver {
+        HashTrieMapEvolver {
+            inner: self.inner.clone(),
+            finished: false,
+        }
+    }
+
+    #[pyo3(signature = (f=None))]
+    fn mutate(&self, f: Option<&Bound<'_, PyAny>>, py: Python) -> PyResult<PyObject> {
+        mutate_with(self.evolver(), f, py)
+    }
+
+    #[pyo3(signature = (*maps, **kwds))]
+    fn update(
+        &self,
+        maps: &Bound<'_, PyTuple>,
+        kwds: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<HashTrieMapPy> {
+        Ok(HashTrieMapPy::from(map_updated(&self.inner, maps, kwds)?))
+    }
+}
+
+// Once a with block (or the function given to mutate) is done with a builder,
+// what it holds is the finished value, so it refuses any further changes.
+fn ensure_unfinished(finished: bool) -> PyResult<()> {
+    match finished {
+        true => Err(PyValueError::new_err(
+            "cannot mutate a builder once its with block has ended",
+        )),
+        false => Ok(()),
+    }
+}
+
+// A builder or evolver as handed out by mutate, which finishes as Persistent.
+trait Evolver: PyClass<Frozen = False> + Into<PyClassInitializer<Self>> {
+    type Persistent: PyClass + Into<PyClassInitializer<Self::Persistent>>;
+
+    fn finish(&mut self, py: Python) -> Self::Persistent;
+}
+
+// With f, the evolver is handed to it and then finished, otherwise it is
+// returned for use as a context manager.
+fn mutate_with<E: Evolver>(
+    evolver: E,
+    f: Option<&Bound<'_, PyAny>>,
+    py: Python,
+) -> PyResult<PyObject> {
+    let evolver = Bound::new(py, evolver)?;
+    match f {
+        Some(f) => {
+            f.call1((&evolver,))?;
+            let finished = evolver.borrow_mut().finish(py);
+            Ok(Py::new(py, finished)?.into_any())
+        }
+        None => Ok(evolver.into_any().unbind()),
+    }
+}
+
+// A mutable builder for batching up changes to a map, which are applied in
+// place rather than creating a new intermediate map for each one.
+#[pyclass(module = "rpds")]
+struct HashTrieMapEvolver {
+    inner: HashTrieMapSync<Key, PyObject>,
+    finished: bool,
+}
+
+impl Evolver for HashTrieMapEvolver {
+    type Persistent = HashTrieMapPy;
+
+    fn finish(&mut self, _py: Python) -> HashTrieMapPy {
+        self.finished = true;
+        self.persistent()
+    }
+}
+
+#[pymethods]
+impl HashTrieMapEvolver {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.finished |= exc_type.is_none();
+        false
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.inner.contains_key(&key)
+    }
+
+    fn __getitem__(&self, key: Key, py: Python) -> PyResult<PyObject> {
+        match self.inner.get(&key) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __setitem__(&mut self, key: Key, value: PyObject) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        self.inner.insert_mut(key, value);
+        Ok(())
+    }
+
+    fn __delitem__(&mut self, key: Key) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        match self.inner.remove_mut(&key) {
+            true => Ok(()),
+            false => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn persistent(&self) -> HashTrieMapPy {
+        HashTrieMapPy::from(self.inner.clone())
+    }
+
+    /// The map as it stands, which is final once the with block has ended.
+    #[getter]
+    fn result(&self) -> HashTrieMapPy {
+        self.persistent()
+    }
+}
+
+// Nested maps which are empty are kept as leaves, so that flattening loses no entries.
+// Two paths which flatten to the same key (e.g. "a.b" and "a" -> "b") would lose
+// one of them, so like unflatten_into we refuse rather than pick one arbitrarily.
+fn flatten_into(
+    path: String,
+    value: &Bound<'_, PyAny>,
+    sep: &str,
+    out: &mut HashTrieMapSync<Key, PyObject>,
+) -> PyResult<()> {
+    let py = value.py();
+    if let Ok(map) = value.downcast::<HashTrieMapPy>() {
+        let map = &map.get().inner;
+        if !map.is_empty() {
+            for (k, v) in map.iter() {
+                let child = format!("{}{}{}", path, sep, k.inner.bind(py).str()?);
+                flatten_into(child, v.bind(py), sep, out)?;
+            }
+            return Ok(());
+        }
+    } else if let Ok(mapping) = value.downcast::<PyMapping>() {
+        if mapping.len()? != 0 {
+            for each in mapping.items()?.iter() {
+                let (k, v): (Bound<'_, PyAny>, Bound<'_, PyAny>) = each.extract()?;
+                let child = format!("{}{}{}", path, sep, k.str()?);
+                flatten_into(child, &v, sep, out)?;
+            }
+            return Ok(());
+        }
+    }
+    let key = Key::extract_bound(PyString::new(py, &path).as_any())?;
+    if out.contains_key(&key) {
+        return Err(PyValueError::new_err(format!(
+            "duplicate key while flattening: {}",
+            key.inner.bind(py).repr()?
+        )));
+    }
+    out.insert_mut(key, value.clone().unbind());
+    Ok(())
+}
+
+enum Unflattened {
+    Leaf(PyObject),
+    Branch(HashMap<String, Unflattened>),
+}
+
+impl Unflattened {
+    fn into_value(self, py: Python<'_>) -> PyResult<PyObject> {
+        match self {
+            Unflattened::Leaf(value) => Ok(value),
+            Unflattened::Branch(children) => {
+                let mut inner = HashTrieMap::new_sync();
+                for (k, node) in children {
+                    inner.insert_mut(
+                        Key::extract_bound(PyString::new(py, &k).as_any())?,
+                        node.into_value(py)?,
+                    );
+                }
+                Ok(Py::new(py, HashTrieMapPy::from(inner))?.into_any())
+            }
+        }
+    }
+}
+
+// A key which is both a leaf and a prefix of another key (e.g. "a" and "a.b")
+// has no nested representation, so we refuse rather than silently drop one.
+fn unflatten_into(
+    tree: &mut HashMap<String, Unflattened>,
+    path: &Bound<'_, PyString>,
+    sep: &str,
+    value: PyObject,
+) -> PyResult<()> {
+    let conflict = || -> PyResult<()> {
+        Err(PyValueError::new_err(format!(
+            "conflicting key while unflattening: {}",
+            path.repr()?
+        )))
+    };
+    let full = path.to_cow()?;
+    let parts: Vec<&str> = full.split(sep).collect();
+    let (last, init) = parts.split_last().expect("split always yields a part");
+    let mut node = tree;
+    for part in init {
+        match node
+            .entry(part.to_string())
+            .or_insert_with(|| Unflattened::Branch(HashMap::new()))
+        {
+            Unflattened::Branch(children) => node = children,
+            Unflattened::Leaf(_) => return conflict(),
+        }
+    }
+    match node.entry(last.to_string()) {
+        Entry::Occupied(_) => conflict(),
+        Entry::Vacant(entry) => {
+            entry.insert(Unflattened::Leaf(value));
+            Ok(())
+        }
+    }
+}
+
+#[derive(FromPyObject)]
+struct ItemViewQuery(Key, PyObject);
+
+// The views and iterators of a HashTrieMap, and the set operand handling and
+// iterator of a HashTrieSet, for each flavour of them (see rpds.local).
+macro_rules! hash_trie_companions {
+    (
+        pyclass($($options:tt)*),
+        pointer: $pointer:ty,
+        map: $map:ident,
+        set: $set:ident,
+        views: ($keys_view:ident, $values_view:ident, $items_view:ident),
+        iterators: ($keys_iterator:ident, $values_iterator:ident, $items_iterator:ident, $set_iterator:ident) $(,)?
+    ) => {
+        impl $map {
+            // Views and their iterators pickle as the (remaining) map they came from,
+            // along with how to get back to them from it.
+            fn reduce_view<'py>(
+                inner: &HashTrieMap<Key, PyObject, $pointer>,
+                method: &str,
+                py: Python<'py>,
+            ) -> PyResult<(Bound<'py, PyAny>, ($map,))> {
+                Ok((
+                    $map::type_object(py).getattr(method)?,
+                    ($map::from(inner.clone()),),
+                ))
+            }
+
+            fn reduce_view_iterator<'py>(
+                inner: &HashTrieMap<Key, PyObject, $pointer>,
+                method: &str,
+                py: Python<'py>,
+            ) -> PyResult<(Bound<'py, PyAny>, (Bound<'py, PyAny>,))> {
+                let (method, (map,)) = $map::reduce_view(inner, method, py)?;
+                Ok((
+                    PyModule::import(py, "builtins")?.getattr("iter")?,
+                    (method.call1((map,))?,),
+                ))
+            }
+        }
+
+        #[pyclass(name = "KeysIterator", $($options)*)]
+        struct $keys_iterator {
+            inner: HashTrieMap<Key, PyObject, $pointer>,
+        }
+
+        #[pymethods]
+        impl $keys_iterator {
+            fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+                slf
+            }
+
+            fn __reduce__<'py>(
+                &self,
+                py: Python<'py>,
+            ) -> PyResult<(Bound<'py, PyAny>, (Bound<'py, PyAny>,))> {
+                $map::reduce_view_iterator(&self.inner, "keys", py)
+            }
+
+            fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<Key> {
+                let first = slf.inner.keys().next()?.clone_ref(slf.py());
+                slf.inner = slf.inner.remove(&first);
+                Some(first)
+            }
+        }
+
+        #[pyclass(name = "ValuesIterator", $($options)*)]
+        struct $values_iterator {
+            inner: HashTrieMap<Key, PyObject, $pointer>,
+        }
+
+        #[pymethods]
+        impl $values_iterator {
+            fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+                slf
+            }
+
+            fn __reduce__<'py>(
+                &self,
+                py: Python<'py>,
+            ) -> PyResult<(Bound<'py, PyAny>, (Bound<'py, PyAny>,))> {
+                $map::reduce_view_iterator(&self.inner, "values", py)
+            }
+
+            fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
+                let kv = slf.inner.iter().next()?;
+                let value = kv.1.clone_ref(slf.py());
+                slf.inner = slf.inner.remove(kv.0);
+                Some(value)
+            }
+        }
+
+        #[pyclass(name = "ItemsIterator", $($options)*)]
+        struct $items_iterator {
+            inner: HashTrieMap<Key, PyObject, $pointer>,
+        }
+
+        #[pymethods]
+        impl $items_iterator {
+            fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+                slf
+            }
+
+            fn __reduce__<'py>(
+                &self,
+                py: Python<'py>,
+            ) -> PyResult<(Bound<'py, PyAny>, (Bound<'py, PyAny>,))> {
+                $map::reduce_view_iterator(&self.inner, "items", py)
+            }
+
+            fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<(Key, PyObject)> {
+                let kv = slf.inner.iter().next()?;
+                let key = kv.0.clone_ref(slf.py());
+                let value = kv.1.clone_ref(slf.py());
+
+                slf.inner = slf.inner.remove(kv.0);
+
+                Some((key, value))
+            }
+        }
+
+        #[pyclass(name = "KeysView", $($options)*)]
+        struct $keys_view {
+            inner: HashTrieMap<Key, PyObject, $pointer>,
+        }
+
+        #[pymethods]
+        impl $keys_view {
+            /// The map this is a view of, which since maps are immutable is simply
+            /// an equal HashTrieMap sharing all of its structure.
+            #[getter]
+            fn mapping(&self) -> $map {
+                $map::from(self.inner.clone())
+            }
+
+            fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyAny>, ($map,))> {
+                $map::reduce_view(&self.inner, "keys", py)
+            }
+
+            fn __contains__(&self, key: Key) -> bool {
+                self.inner.contains_key(&key)
+            }
+
+            fn __eq__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? != slf.inner.size() {
+                    return Ok(false);
+                }
+                for each in other.try_iter()? {
+                    if !slf.inner.contains_key(&Key::extract_bound(&each?)?) {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __lt__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? <= slf.inner.size() {
+                    return Ok(false);
+                }
+
+                for each in slf.inner.keys() {
+                    if !other.contains(each.inner.clone_ref(slf.py()))? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __le__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? < slf.inner.size() {
+                    return Ok(false);
+                }
+
+                for each in slf.inner.keys() {
+                    if !other.contains(each.inner.clone_ref(slf.py()))? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __gt__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? >= slf.inner.size() {
+                    return Ok(false);
+                }
+                for each in other.try_iter()? {
+                    if !slf.inner.contains_key(&Key::extract_bound(&each?)?) {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __ge__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? > slf.inner.size() {
+                    return Ok(false);
+                }
+                for each in other.try_iter()? {
+                    if !slf.inner.contains_key(&Key::extract_bound(&each?)?) {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __iter__(slf: PyRef<'_, Self>) -> $keys_iterator {
+                $keys_iterator {
+                    inner: slf.inner.clone(),
+                }
+            }
+
+            fn __len__(slf: PyRef<'_, Self>) -> usize {
+                slf.inner.size()
+            }
+
+            fn __and__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<$set> {
+                $keys_view::intersection(slf, other)
+            }
+
+            fn __or__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                let Some(other) = $set::keys_operand(other)? else {
+                    return Ok(py.NotImplemented());
+                };
+                Ok($set::from(other.union_keys(&self.inner, py))
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind())
+            }
+
+            fn __sub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                let Some(other) = $set::keys_operand(other)? else {
+                    return Ok(py.NotImplemented());
+                };
+                Ok($set::from(other.subtracted_from_keys(&self.inner, py))
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind())
+            }
+
+            fn __xor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                let Some(other) = $set::keys_operand(other)? else {
+                    return Ok(py.NotImplemented());
+                };
+                Ok($set::from(other.symmetric_difference_keys(&self.inner, py))
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind())
+            }
+
+            fn __rand__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<$set> {
+                $keys_view::intersection(slf, other)
+            }
+
+            fn __ror__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                let Some(other) = $set::keys_operand(other)? else {
+                    return Ok(py.NotImplemented());
+                };
+                Ok($set::from(other.union_keys(&self.inner, py))
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind())
+            }
+
+            fn __rsub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                let Some(other) = $set::keys_operand(other)? else {
+                    return Ok(py.NotImplemented());
+                };
+                Ok($set::from(other.without_keys(&self.inner, py))
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind())
+            }
+
+            fn __rxor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                self.__xor__(other, py)
+            }
+
+            fn __repr__(&self, py: Python) -> PyResult<String> {
+                let contents = self.inner.into_iter().map(|(k, _)| {
+                    Ok(k.clone_ref(py)
+                        .inner
+                        .into_pyobject(py)?
+                        .call_method0("__repr__")
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr failed>".to_owned()))
+                });
+                let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
+                Ok(format!("keys_view({{{}}})", contents.join(", ")))
+            }
+
+            fn intersection(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<$set> {
+                let mut inner = HashTrieSet::default();
+                if let Ok(set) = other.downcast::<$set>() {
+                    let set = &set.borrow().inner;
+                    if set.size() < slf.inner.size() {
+                        for key in set.iter() {
+                            if slf.inner.contains_key(key) {
+                                inner.insert_mut(key.clone_ref(slf.py()));
+                            }
+                        }
+                    } else {
+                        for key in slf.inner.keys() {
+                            if set.contains(key) {
+                                inner.insert_mut(key.clone_ref(slf.py()));
+                            }
+                        }
+                    }
+                    return Ok($set::from(inner));
+                }
+                // TODO: iterate over the shorter one if it's got a length
+                for each in other.try_iter()? {
+                    let key = Key::extract_bound(&each?)?;
+                    if slf.inner.contains_key(&key) {
+                        inner.insert_mut(key);
+                    }
+                }
+                Ok($set::from(inner))
+            }
+
+            fn isdisjoint(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                if let Ok(set) = other.downcast::<$set>() {
+                    let set = &set.borrow().inner;
+                    return Ok(match set.size() < self.inner.size() {
+                        true => !set.iter().any(|key| self.inner.contains_key(key)),
+                        false => !self.inner.keys().any(|key| set.contains(key)),
+                    });
+                }
+                // other Sets can be probed instead when they're the larger side
+                let abc = PyModule::import(py, "collections.abc")?;
+                if other.is_instance(&abc.getattr("Set")?)? && other.len()? >= self.inner.size() {
+                    for key in self.inner.keys() {
+                        if other.contains(key)? {
+                            return Ok(false);
+                        }
+                    }
+                    return Ok(true);
+                }
+                for each in other.try_iter()? {
+                    if self.inner.contains_key(&Key::extract_bound(&each?)?) {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
+            fn union(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<$set> {
+                let mut inner = key_set(&self.inner, py);
+                if let Ok(set) = other.downcast::<$set>() {
+                    for key in set.borrow().inner.iter() {
+                        inner.insert_mut(key.clone_ref(py));
+                    }
+                    return Ok($set::from(inner));
+                }
+                for each in other.try_iter()? {
+                    inner.insert_mut(Key::extract_bound(&each?)?);
+                }
+                Ok($set::from(inner))
+            }
+        }
+
+        #[pyclass(name = "ValuesView", $($options)*)]
+        struct $values_view {
+            inner: HashTrieMap<Key, PyObject, $pointer>,
+        }
+
+        #[pymethods]
+        impl $values_view {
+            #[getter]
+            fn mapping(&self) -> $map {
+                $map::from(self.inner.clone())
+            }
+
+            fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyAny>, ($map,))> {
+                $map::reduce_view(&self.inner, "values", py)
+            }
+
+            fn __iter__(slf: PyRef<'_, Self>) -> $values_iterator {
+                $values_iterator {
+                    inner: slf.inner.clone(),
+                }
+            }
+
+            fn __len__(slf: PyRef<'_, Self>) -> usize {
+                slf.inner.size()
+            }
+
+            fn __repr__(&self, py: Python) -> PyResult<String> {
+                let contents = self.inner.into_iter().map(|(_, v)| {
+                    Ok(v.into_pyobject(py)?
+                        .call_method0("__repr__")
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr failed>".to_owned()))
+                });
+                let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
+                Ok(format!("values_view([{}])", contents.join(", ")))
+            }
+        }
+
+        #[pyclass(name = "ItemsView", $($options)*)]
+        struct $items_view {
+            inner: HashTrieMap<Key, PyObject, $pointer>,
+        }
+
+        impl $items_view {
+            fn pairs(&self, py: Python) -> PyResult<$set> {
+                let mut inner = HashTrieSet::default();
+                for (k, v) in self.inner.iter() {
+                    let pair = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
+                    inner.insert_mut(Key::extract_bound(&pair)?);
+                }
+                Ok($set::from(inner))
+            }
+        }
+
+        #[pymethods]
+        impl $items_view {
+            #[getter]
+            fn mapping(&self) -> $map {
+                $map::from(self.inner.clone())
+            }
+
+            fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyAny>, ($map,))> {
+                $map::reduce_view(&self.inner, "items", py)
+            }
+
+            fn __contains__(slf: PyRef<'_, Self>, item: ItemViewQuery) -> PyResult<bool> {
+                if let Some(value) = slf.inner.get(&item.0) {
+                    return item.1.bind(slf.py()).eq(value);
+                }
+
+                Ok(false)
+            }
+
+            fn __iter__(slf: PyRef<'_, Self>) -> $items_iterator {
+                $items_iterator {
+                    inner: slf.inner.clone(),
+                }
+            }
+
+            fn __len__(slf: PyRef<'_, Self>) -> usize {
+                slf.inner.size()
+            }
+
+            fn __eq__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? != slf.inner.size() {
+                    return Ok(false);
+                }
+                for (k, v) in slf.inner.iter() {
+                    if !other.contains((k.inner.clone_ref(slf.py()), v))? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __repr__(&self, py: Python) -> PyResult<String> {
+                let contents = self.inner.into_iter().map(|(k, v)| {
+                    let tuple = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
+                    Ok(format!("{:?}", tuple))
+                });
+                let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
+                Ok(format!("items_view([{}])", contents.join(", ")))
+            }
+
+            fn __lt__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? <= slf.inner.size() {
+                    return Ok(false);
+                }
+                for (k, v) in slf.inner.iter() {
+                    let pair = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
+                    // FIXME: needs to compare
+                    if !other.contains(pair)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __le__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? < slf.inner.size() {
+                    return Ok(false);
+                }
+                for (k, v) in slf.inner.iter() {
+                    let pair = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
+                    // FIXME: needs to compare
+                    if !other.contains(pair)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __gt__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? >= slf.inner.size() {
+                    return Ok(false);
+                }
+                for each in other.try_iter()? {
+                    let kv = each?;
+                    let k = kv.get_item(0)?;
+                    match slf.inner.get(&Key::extract_bound(&k)?) {
+                        Some(value) => {
+                            let pair = PyTuple::new(py, [k, value.bind(py).clone()])?;
+                            if !pair.eq(kv)? {
+                                return Ok(false);
+                            }
+                        }
+                        None => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __ge__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+                let abc = PyModule::import(py, "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Set")?)? || other.len()? > slf.inner.size() {
+                    return Ok(false);
+                }
+                for each in other.try_iter()? {
+                    let kv = each?;
+                    let k = kv.get_item(0)?;
+                    match slf.inner.get(&Key::extract_bound(&k)?) {
+                        Some(value) => {
+                            let pair = PyTuple::new(py, [k, value.bind(py).clone()])?;
+                            if !pair.eq(kv)? {
+                                return Ok(false);
+                            }
+                        }
+                        None => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+
+            fn __and__(
+                slf: PyRef<'_, Self>,
+                other: &Bound<'_, PyAny>,
+                py: Python,
+            ) -> PyResult<$set> {
+                $items_view::intersection(slf, other, py)
+            }
+
+            fn __or__(
+                slf: PyRef<'_, Self>,
+                other: &Bound<'_, PyAny>,
+                py: Python,
+            ) -> PyResult<$set> {
+                $items_view::union(slf, other, py)
+            }
+
+            fn __sub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                let Some(other) = $set::view_operand(other)? else {
+                    return Ok(py.NotImplemented());
+                };
+                let pairs = self.pairs(py)?;
+                Ok(pairs
+                    .set_difference(&other)
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind())
+            }
+
+            fn __xor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                let Some(other) = $set::view_operand(other)? else {
+                    return Ok(py.NotImplemented());
+                };
+                let pairs = self.pairs(py)?;
+                Ok(pairs
+                    .set_symmetric_difference(&other, py)
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind())
+            }
+
+            fn __rand__(
+                slf: PyRef<'_, Self>,
+                other: &Bound<'_, PyAny>,
+                py: Python,
+            ) -> PyResult<$set> {
+                $items_view::intersection(slf, other, py)
+            }
+
+            fn __ror__(
+                slf: PyRef<'_, Self>,
+                other: &Bound<'_, PyAny>,
+                py: Python,
+            ) -> PyResult<$set> {
+                $items_view::union(slf, other, py)
+            }
+
+            fn __rsub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                let Some(other) = $set::view_operand(other)? else {
+                    return Ok(py.NotImplemented());
+                };
+                let pairs = self.pairs(py)?;
+                Ok(other
+                    .set_difference(&pairs)
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind())
+            }
+
+            fn __rxor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+                self.__xor__(other, py)
+            }
+
+            fn difference(&self, other: $set, py: Python) -> PyResult<$set> {
+                Ok(self.pairs(py)?.set_difference(&other))
+            }
+
+            fn symmetric_difference(&self, other: $set, py: Python) -> PyResult<$set> {
+                Ok(self.pairs(py)?.set_symmetric_difference(&other, py))
+            }
+
+            fn intersection(
+                slf: PyRef<'_, Self>,
+                other: &Bound<'_, PyAny>,
+                py: Python,
+            ) -> PyResult<$set> {
+                // TODO: iterate over the shorter one if it's got a length
+                let mut inner = HashTrieSet::default();
+                for each in other.try_iter()? {
+                    let kv = each?;
+                    let k = kv.get_item(0)?;
+                    if let Some(value) = slf.inner.get(&Key::extract_bound(&k)?) {
+                        let pair = PyTuple::new(py, [k, value.bind(py).clone()])?;
+                        if pair.eq(kv)? {
+                            inner.insert_mut(Key::extract_bound(&pair)?);
+                        }
+                    }
+                }
+                Ok($set::from(inner))
+            }
+
+            fn union(
+                slf: PyRef<'_, Self>,
+                other: &Bound<'_, PyAny>,
+                py: Python,
+            ) -> PyResult<$set> {
+                // TODO: this is very inefficient, but again can't seem to get a HashTrieSet out of ourself
+                let mut inner = HashTrieSet::default();
+                for (k, v) in slf.inner.iter() {
+                    let pair = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
+                    inner.insert_mut(Key::extract_bound(&pair)?);
+                }
+                for each in other.try_iter()? {
+                    inner.insert_mut(Key::extract_bound(&each?)?);
+                }
+                Ok($set::from(inner))
+            }
+        }
+
+        impl $set {
+            /// The right-hand side of a set operator, which may be any Set (builtin
+            /// ones included), or None if the operator isn't supported for it.
+            fn operand(other: &Bound<'_, PyAny>) -> PyResult<Option<SetOperand<$pointer>>> {
+                if let Ok(view) = other.downcast::<$keys_view>() {
+                    return Ok(Some(SetOperand::Keys(view.borrow().inner.clone())));
+                }
+                let abc = PyModule::import(other.py(), "collections.abc")?;
+                if !other.is_instance_of::<$set>() && !other.is_instance(&abc.getattr("Set")?)? {
+                    return Ok(None);
+                }
+                other
+                    .extract()
+                    .map(|set: $set| Some(SetOperand::Set(set.inner)))
+            }
+
+            // Applies operator to self and the operand for other, if it has one.
+            fn apply_operator(
+                &self,
+                other: &Bound<'_, PyAny>,
+                operator: fn(
+                    &SetOperand<$pointer>,
+                    &HashTrieSet<Key, $pointer>,
+                    Python,
+                ) -> HashTrieSet<Key, $pointer>,
+                py: Python,
+            ) -> PyResult<PyObject> {
+                let Some(other) = $set::operand(other)? else {
+                    return Ok(py.NotImplemented());
+                };
+                Ok($set::from(operator(&other, &self.inner, py))
+                    .into_pyobject(py)?
+                    .into_any()
+                    .unbind())
+            }
+
+            /// Like operand, but also accepting any other iterable, as the operators
+            /// on map views do.
+            fn view_operand(other: &Bound<'_, PyAny>) -> PyResult<Option<$set>> {
+                if let Some(operand) = $set::operand(other)? {
+                    return Ok(Some($set::from(operand.into_set(other.py()))));
+                }
+                let abc = PyModule::import(other.py(), "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Iterable")?)? {
+                    return Ok(None);
+                }
+                other.extract().map(Some)
+            }
+
+            /// Like view_operand, but keeping map key views as their map, for the
+            /// operators on key views themselves.
+            fn keys_operand(other: &Bound<'_, PyAny>) -> PyResult<Option<SetOperand<$pointer>>> {
+                if let Some(operand) = $set::operand(other)? {
+                    return Ok(Some(operand));
+                }
+                let abc = PyModule::import(other.py(), "collections.abc")?;
+                if !other.is_instance(&abc.getattr("Iterable")?)? {
+                    return Ok(None);
+                }
+                other.extract().map(|set: $set| Some(SetOperand::Set(set.inner)))
+            }
+
+            fn set_difference(&self, other: &Self) -> $set {
+                $set::from(set_difference(&self.inner, &other.inner))
+            }
+
+            fn set_intersection(&self, other: &Self, py: Python) -> $set {
+                $set::from(set_intersection(&self.inner, &other.inner, py))
+            }
+
+            fn set_symmetric_difference(&self, other: &Self, py: Python) -> $set {
+                $set::from(set_symmetric_difference(&self.inner, &other.inner, py))
+            }
+
+            fn set_union(&self, other: &Self, py: Python) -> $set {
+                $set::from(set_union(&self.inner, &other.inner, py))
+            }
+        }
+
+        impl<'source> FromPyObject<'source> for $set {
+            fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+                if let Ok(set) = ob.downcast::<$set>() {
+                    return Ok($set::from(set.borrow().inner.clone()));
+                }
+                let mut ret = HashTrieSet::default();
+                // builtin sets can be walked directly rather than via the iterator
+                // protocol, and hashing an element whose hash is cached (str) is cheap
+                if let Ok(set) = ob.downcast::<PyFrozenSet>() {
+                    for each in set.iter() {
+                        ret.insert_mut(Key::extract_bound(&each)?);
+                    }
+                    return Ok($set::from(ret));
+                }
+                if let Ok(set) = ob.downcast::<PySet>() {
+                    for each in set.iter() {
+                        ret.insert_mut(Key::extract_bound(&each)?);
+                    }
+                    return Ok($set::from(ret));
+                }
+                for each in ob.try_iter()? {
+                    let k: Key = each?.extract()?;
+                    ret.insert_mut(k);
+                }
+                Ok($set::from(ret))
+            }
+        }
+
+        #[pyclass(name = "SetIterator", $($options)*)]
+        struct $set_iterator {
+            inner: HashTrieSet<Key, $pointer>,
+        }
+
+        #[pymethods]
+        impl $set_iterator {
+            fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+                slf
+            }
+
+            fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<Key> {
+                let first = slf.inner.iter().next()?.clone_ref(slf.py());
+                slf.inner = slf.inner.remove(&first);
+                Some(first)
+            }
+        }
+    };
+}
+
+hash_trie_companions! {
+    pyclass(module = "rpds"),
+    pointer: ArcTK,
+    map: HashTrieMapPy,
+    set: HashTrieSetPy,
+    views: (KeysView, ValuesView, ItemsView),
+    iterators: (KeysIterator, ValuesIterator, ItemsIterator, SetIterator),
+}
+
+#[pyclass(name = "HashTrieSet", module = "rpds", frozen)]
+struct HashTrieSetPy {
+    inner: HashTrieSetSync<Key>,
+    hash: OnceLock<isize>,
+}
+
+impl From<HashTrieSetSync<Key>> for HashTrieSetPy {
+    fn from(set: HashTrieSetSync<Key>) -> Self {
+        HashTrieSetPy {
+            inner: set,
+            hash: OnceLock::new(),
+        }
+    }
+}
+
+// The right-hand side of a set operator. Map key views are kept as their map,
+// so that they're queried in place rather than having their keys copied.
+enum SetOperand<P: SharedPointerKind> {
+    Set(HashTrieSet<Key, P>),
+    Keys(HashTrieMap<Key, PyObject, P>),
+}
+
+impl<P: SharedPointerKind> SetOperand<P> {
+    fn into_set(self, py: Python) -> HashTrieSet<Key, P> {
+        match self {
+            SetOperand::Set(set) => set,
+            SetOperand::Keys(keys) => key_set(&keys, py),
+        }
+    }
+
+    // set - self
+    fn subtracted_from(&self, set: &HashTrieSet<Key, P>, py: Python) -> HashTrieSet<Key, P> {
+        let keys = match self {
+            SetOperand::Set(other) => return set_difference(set, other),
+            SetOperand::Keys(keys) => keys,
+        };
+        if keys.size() < set.size() {
+            let mut inner = set.clone();
+            for key in keys.keys() {
+                inner.remove_mut(key);
+            }
+            return inner;
+        }
+        let mut inner = HashTrieSet::default();
+        for value in set.iter() {
+            if !keys.contains_key(value) {
+                inner.insert_mut(value.clone_ref(py));
+            }
+        }
+        inner
+    }
+
+    // self - set
+    fn without(&self, set: &HashTrieSet<Key, P>, py: Python) -> HashTrieSet<Key, P> {
+        let keys = match self {
+            SetOperand::Set(other) => return set_difference(other, set),
+            SetOperand::Keys(keys) => keys,
+        };
+        let mut inner = HashTrieSet::default();
+        for key in keys.keys() {
+            if !set.contains(key) {
+                inner.insert_mut(key.clone_ref(py));
+            }
+        }
+        inner
+    }
+
+    fn intersection(&self, set: &HashTrieSet<Key, P>, py: Python) -> HashTrieSet<Key, P> {
+        let keys = match self {
+            SetOperand::Set(other) => return set_intersection(set, other, py),
+            SetOperand::Keys(keys) => keys,
+        };
+        let mut inner = HashTrieSet::default();
+        if keys.size() < set.size() {
+            for key in keys.keys() {
+                if set.contains(key) {
+                    inner.insert_mut(key.clone_ref(py));
+                }
+            }
+        } else {
+            for value in set.iter() {
+                if keys.contains_key(value) {
+                    inner.insert_mut(value.clone_ref(py));
+                }
+            }
+        }
+        inner
+    }
+
+    fn symmetric_difference(&self, set: &HashTrieSet<Key, P>, py: Python) -> HashTrieSet<Key, P> {
+        let keys = match self {
+            SetOperand::Set(other) => return set_symmetric_difference(set, other, py),
+            SetOperand::Keys(keys) => keys,
+        };
+        let mut inner = set.clone();
+        for key in keys.keys() {
+            if !inner.remove_mut(key) {
+                inner.insert_mut(key.clone_ref(py));
+            }
+        }
+        inner
+    }
+
+    fn union(&self, set: &HashTrieSet<Key, P>, py: Python) -> HashTrieSet<Key, P> {
+        let keys = match self {
+            SetOperand::Set(other) => return set_union(set, other, py),
+            SetOperand::Keys(keys) => keys,
+        };
+        let mut inner = set.clone();
+        for key in keys.keys() {
+            inner.insert_mut(key.clone_ref(py));
+        }
+        inner
+    }
+
+    fn contains(&self, key: &Key) -> bool {
+        match self {
+            SetOperand::Set(set) => set.contains(key),
+            SetOperand::Keys(keys) => keys.contains_key(key),
+        }
+    }
+
+    // The operators again, but for when the left-hand side is itself the keys
+    // of a map, which are then walked in place rather than copied into a set.
+
+    // keys - self
+    fn subtracted_from_keys(
+        &self,
+        keys: &HashTrieMap<Key, PyObject, P>,
+        py: Python,
+    ) -> HashTrieSet<Key, P> {
+        let mut inner = HashTrieSet::default();
+        for key in keys.keys() {
+            if !self.contains(key) {
+                inner.insert_mut(key.clone_ref(py));
+            }
+        }
+        inner
+    }
+
+    // self - keys
+    fn without_keys(
+        &self,
+        keys: &HashTrieMap<Key, PyObject, P>,
+        py: Python,
+    ) -> HashTrieSet<Key, P> {
+        match self {
+            SetOperand::Set(set) if keys.size() < set.size() => {
+                let mut inner = set.clone();
+                for key in keys.keys() {
+                    inner.remove_mut(key);
+                }
+                inner
+            }
+            SetOperand::Set(set) => {
+                let mut inner = HashTrieSet::default();
+                for value in set.iter() {
+                    if !keys.contains_key(value) {
+                        inner.insert_mut(value.clone_ref(py));
+                    }
+                }
+                inner
+            }
+            SetOperand::Keys(other) => {
+                SetOperand::Keys(keys.clone()).subtracted_from_keys(other, py)
+            }
+        }
+    }
+
+    fn symmetric_difference_keys(
+        &self,
+        keys: &HashTrieMap<Key, PyObject, P>,
+        py: Python,
+    ) -> HashTrieSet<Key, P> {
+        match self {
+            SetOperand::Set(set) => SetOperand::Keys(keys.clone()).symmetric_difference(set, py),
+            SetOperand::Keys(other) => {
+                let mut inner = self.subtracted_from_keys(keys, py);
+                for key in other.keys() {
+                    if !keys.contains_key(key) {
+                        inner.insert_mut(key.clone_ref(py));
+                    }
+                }
+                inner
+            }
+        }
+    }
+
+    fn union_keys(&self, keys: &HashTrieMap<Key, PyObject, P>, py: Python) -> HashTrieSet<Key, P> {
+        match self {
+            SetOperand::Set(set) => SetOperand::Keys(keys.clone()).union(set, py),
+            SetOperand::Keys(other) => {
+                let mut inner = key_set(other, py);
+                for key in keys.keys() {
+                    inner.insert_mut(key.clone_ref(py));
+                }
+                inner
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl HashTrieSetPy {
+    #[new]
+    #[pyo3(signature = (value=None))]
+    fn init(value: Option<HashTrieSetPy>) -> Self {
+        if let Some(value) = value {
+            value
+        } else {
+            HashTrieSetPy::from(HashTrieSet::new_sync())
+        }
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.inner.contains(&key)
+    }
+
+    fn __and__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::intersection, py)
+    }
+
+    fn __or__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::union, py)
+    }
+
+    fn __sub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::subtracted_from, py)
+    }
+
+    fn __xor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::symmetric_difference, py)
+    }
+
+    fn __rand__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.__and__(other, py)
+    }
+
+    fn __ror__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.__or__(other, py)
+    }
+
+    fn __rsub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::without, py)
+    }
+
+    fn __rxor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.__xor__(other, py)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> SetIterator {
+        SetIterator {
+            inner: slf.inner.clone(),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        collection_size::<Key, (usize, u64)>(slf.as_any(), slf.get().inner.size())
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        set_repr(&self.inner, py)
+    }
+
+    fn __eq__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<bool> {
+        set_eq(slf.as_any(), &slf.get().inner, other)
+    }
+
+    fn __hash__(&self) -> PyResult<isize> {
+        set_hash(&self.inner, &self.hash)
+    }
+
+    fn __lt__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? <= slf.inner.size() {
+            return Ok(false);
+        }
+        for each in slf.inner.iter() {
+            if !other.contains(each.inner.clone_ref(py))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __le__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? < slf.inner.size() {
+            return Ok(false);
+        }
+        for each in slf.inner.iter() {
+            if !other.contains(each.inner.clone_ref(slf.py()))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __gt__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? >= slf.inner.size() {
+            return Ok(false);
+        }
+        for each in other.try_iter()? {
+            if !slf.inner.contains(&Key::extract_bound(&each?)?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __ge__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? > slf.inner.size() {
+            return Ok(false);
+        }
+        for each in other.try_iter()? {
+            if !slf.inner.contains(&Key::extract_bound(&each?)?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __reduce__(slf: PyRef<Self>) -> (Bound<'_, PyType>, (Vec<Key>,)) {
+        (
+            HashTrieSetPy::type_object(slf.py()),
+            (slf.inner.iter().map(|e| e.clone_ref(slf.py())).collect(),),
+        )
+    }
+
+    /// With deep=True, nested dicts, lists and sets are converted as well.
+    #[classmethod]
+    #[pyo3(signature = (value, *, deep=false))]
+    fn convert(
+        _cls: &Bound<'_, PyType>,
+        value: Bound<'_, PyAny>,
+        deep: bool,
+        py: Python,
+    ) -> PyResult<PyObject> {
+        if value.is_instance_of::<HashTrieSetPy>() && !deep {
+            return Ok(value.unbind());
+        }
+        let set = match deep {
+            true => HashTrieSetPy::from(convert_deep_set(&value)?),
+            false => HashTrieSetPy::extract_bound(&value)?,
+        };
+        Ok(set.into_pyobject(py)?.into_any().unbind())
+    }
+
+    fn insert(&self, value: Key) -> HashTrieSetPy {
+        HashTrieSetPy::from(self.inner.insert(value))
+    }
+
+    fn discard(&self, value: Key) -> PyResult<HashTrieSetPy> {
+        match self.inner.contains(&value) {
+            true => Ok(HashTrieSetPy::from(self.inner.remove(&value))),
+            false => Ok(HashTrieSetPy::from(self.inner.clone())),
+        }
+    }
+
+    fn remove(&self, value: Key) -> PyResult<HashTrieSetPy> {
+        match self.inner.contains(&value) {
+            true => Ok(HashTrieSetPy::from(self.inner.remove(&value))),
+            false => Err(PyKeyError::new_err(value)),
+        }
+    }
+
+    fn to_frozenset<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyFrozenSet>> {
+        PyFrozenSet::new(py, self.inner.iter())
+    }
+
+    /// Remove each of the given elements, raising a KeyError if any is
+    /// missing.
+    fn remove_many(&self, values: &Bound<'_, PyAny>) -> PyResult<HashTrieSetPy> {
+        let mut inner = self.inner.clone();
+        for each in values.try_iter()? {
+            let value = Key::extract_bound(&each?)?;
+            if !inner.remove_mut(&value) {
+                return Err(PyKeyError::new_err(value));
+            }
+        }
+        Ok(HashTrieSetPy::from(inner))
+    }
+
+    #[pyo3(signature = (*others))]
+    fn difference(&self, others: Vec<HashTrieSetPy>) -> HashTrieSetPy {
+        others
+            .iter()
+            .fold(HashTrieSetPy::from(self.inner.clone()), |acc, other| {
+                acc.set_difference(other)
+            })
+    }
+
+    #[pyo3(signature = (*others))]
+    fn intersection(&self, others: Vec<HashTrieSetPy>, py: Python) -> HashTrieSetPy {
+        others
+            .iter()
+            .fold(HashTrieSetPy::from(self.inner.clone()), |acc, other| {
+                acc.set_intersection(other, py)
+            })
+    }
+
+    #[pyo3(signature = (*others))]
+    fn symmetric_difference(&self, others: Vec<HashTrieSetPy>, py: Python) -> HashTrieSetPy {
+        others
+            .iter()
+            .fold(HashTrieSetPy::from(self.inner.clone()), |acc, other| {
+                acc.set_symmetric_difference(other, py)
+            })
+    }
+
+    #[pyo3(signature = (*others))]
+    fn union(&self, others: Vec<HashTrieSetPy>, py: Python) -> HashTrieSetPy {
+        others
+            .iter()
+            .fold(HashTrieSetPy::from(self.inner.clone()), |acc, other| {
+                acc.set_union(other, py)
+            })
+    }
+
+    #[pyo3(signature = (*iterables))]
+    fn update(&self, iterables: Bound<'_, PyTuple>) -> PyResult<HashTrieSetPy> {
+        Ok(HashTrieSetPy::from(set_updated(&self.inner, &iterables)?))
+    }
+
+    /// Apply a function to each element, collapsing any results which
+    /// compare equal.
+    fn map(&self, f: &Bound<'_, PyAny>) -> PyResult<HashTrieSetPy> {
+        let mut inner = HashTrieSet::new_sync();
+        for value in self.inner.iter() {
+            inner.insert_mut(Key::extract_bound(&f.call1((value,))?)?);
+        }
+        Ok(HashTrieSetPy::from(inner))
+    }
+
+    /// A new set with only the elements for which predicate is true.
+    fn filter(&self, predicate: &Bound<'_, PyAny>) -> PyResult<HashTrieSetPy> {
+        let py = predicate.py();
+        let mut inner = HashTrieSet::new_sync();
+        for value in self.inner.iter() {
+            if predicate.call1((value,))?.is_truthy()? {
+                inner.insert_mut(value.clone_ref(py));
+            }
+        }
+        Ok(HashTrieSetPy::from(inner))
+    }
+
+    /// Split this set into a pair of sets, the first containing the
+    /// elements for which predicate is true and the second the rest.
+    fn partition(&self, predicate: &Bound<'_, PyAny>) -> PyResult<(HashTrieSetPy, HashTrieSetPy)> {
+        let py = predicate.py();
+        let mut matching = HashTrieSet::new_sync();
+        let mut rest = HashTrieSet::new_sync();
+        for value in self.inner.iter() {
+            let side = match predicate.call1((value,))?.is_truthy()? {
+                true => &mut matching,
+                false => &mut rest,
+            };
+            side.insert_mut(value.clone_ref(py));
+        }
+        Ok((HashTrieSetPy::from(matching), HashTrieSetPy::from(rest)))
+    }
+
+    fn evolver(&self) -> HashTrieSetEvolver {
+        HashTrieSetEvolver {
+            inner: self.inner.clone(),
+            finished: false,
+        }
+    }
+
+    #[pyo3(signature = (f=None))]
+    fn mutate(&self, f: Option<&Bound<'_, PyAny>>, py: Python) -> PyResult<PyObject> {
+        mutate_with(self.evolver(), f, py)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct HashTrieSetEvolver {
+    inner: HashTrieSetSync<Key>,
+    finished: bool,
+}
+
+impl Evolver for HashTrieSetEvolver {
+    type Persistent = HashTrieSetPy;
+
+    fn finish(&mut self, _py: Python) -> HashTrieSetPy {
+        self.finished = true;
+        self.persistent()
+    }
+}
+
+#[pymethods]
+impl HashTrieSetEvolver {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.finished |= exc_type.is_none();
+        false
+    }
+
+    fn __contains__(&self, value: Key) -> bool {
+        self.inner.contains(&value)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn add(&mut self, value: Key) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        self.inner.insert_mut(value);
+        Ok(())
+    }
+
+    fn discard(&mut self, value: Key) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        self.inner.remove_mut(&value);
+        Ok(())
+    }
+
+    fn remove(&mut self, value: Key) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        match self.inner.remove_mut(&value) {
+            true => Ok(()),
+            false => Err(PyKeyError::new_err(value)),
+        }
+    }
+
+    fn persistent(&self) -> HashTrieSetPy {
+        HashTrieSetPy::from(self.inner.clone())
+    }
+
+    /// The set as it stands, which is final once the with block has ended.
+    #[getter]
+    fn result(&self) -> HashTrieSetPy {
+        self.persistent()
+    }
+}
+
+#[pyclass(name = "List", module = "rpds", frozen, sequence)]
+struct ListPy {
+    inner: ListSync<PyObject>,
+    hash: OnceLock<u64>,
+}
+
+impl From<ListSync<PyObject>> for ListPy {
+    fn from(elements: ListSync<PyObject>) -> Self {
+        ListPy {
+            inner: elements,
+            hash: OnceLock::new(),
+        }
+    }
+}
+
+impl ListPy {
+    fn evolver(&self, py: Python) -> ListBuilder {
+        ListBuilder {
+            elements: self.inner.iter().map(|each| each.clone_ref(py)).collect(),
+            finished: false,
+        }
+    }
+
+    /// A new list with count elements starting at index replaced by
+    /// replacement, sharing everything after them with this one.
+    fn splice(
+        &self,
+        index: usize,
+        count: usize,
+        replacement: Option<PyObject>,
+        py: Python,
+    ) -> ListPy {
+        let prefix: Vec<&PyObject> = self.inner.iter().take(index).collect();
+        let mut inner = self.inner.clone();
+        for _ in 0..index + count {
+            inner.drop_first_mut();
+        }
+        if let Some(replacement) = replacement {
+            inner.push_front_mut(replacement);
+        }
+        for each in prefix.into_iter().rev() {
+            inner.push_front_mut(each.clone_ref(py));
+        }
+        ListPy::from(inner)
+    }
+}
+
+impl<'source> FromPyObject<'source> for ListPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        if let Ok(list) = ob.downcast::<ListPy>() {
+            return Ok(ListPy::from(list.get().inner.clone()));
+        }
+        Ok(ListPy::from(list_from_iterable(ob)?))
+    }
+}
+
+#[pymethods]
+impl ListPy {
+    #[new]
+    #[pyo3(signature = (*elements))]
+    fn init(elements: &Bound<'_, PyTuple>) -> PyResult<Self> {
+        if elements.len() == 1 {
+            return elements.get_item(0)?.extract();
+        }
+        Ok(ListPy::from(list_from_iterable(elements)?))
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        collection_size::<PyObject, (usize, usize)>(slf.as_any(), slf.get().inner.len())
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        sequence_repr("List", &self.inner, py)
+    }
+
+    fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python<'_>) -> PyResult<PyObject> {
+        sequence_richcmp(&self.inner, &other.inner, op, py)
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<u64> {
+        sequence_hash("List", &self.inner, &self.hash, py)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> ListIterator {
+        ListIterator {
+            inner: slf.inner.clone(),
+        }
+    }
+
+    fn __reversed__(&self, py: Python) -> ReverseIterator {
+        sequence_reversed(&self.inner, py)
+    }
+
+    fn __reduce__(slf: PyRef<Self>) -> (Bound<'_, PyType>, (Vec<PyObject>,)) {
+        (
+            ListPy::type_object(slf.py()),
+            (slf.inner.iter().map(|e| e.clone_ref(slf.py())).collect(),),
+        )
+    }
+
+    /// With deep=True, nested dicts, lists and sets are converted as well.
+    #[classmethod]
+    #[pyo3(signature = (value, *, deep=false))]
+    fn convert(
+        _cls: &Bound<'_, PyType>,
+        value: Bound<'_, PyAny>,
+        deep: bool,
+        py: Python,
+    ) -> PyResult<PyObject> {
+        if value.is_instance_of::<ListPy>() && !deep {
+            return Ok(value.unbind());
+        }
+        let list = match deep {
+            true => ListPy::from(convert_deep_list(&value)?),
+            false => ListPy::extract_bound(&value)?,
+        };
+        Ok(list.into_pyobject(py)?.into_any().unbind())
+    }
+
+    #[getter]
+    fn first(&self) -> PyResult<&PyObject> {
+        list_first(&self.inner)
+    }
+
+    #[getter]
+    fn rest(&self) -> ListPy {
+        ListPy::from(list_rest(&self.inner))
+    }
+
+    fn resolve_pointer<'py>(slf: &Bound<'py, Self>, pointer: &str) -> PyResult<Bound<'py, PyAny>> {
+        resolve_pointer_in(slf.as_any(), pointer)
+    }
+
+    #[pyo3(signature = (value, start=0, stop=None))]
+    fn index(
+        &self,
+        value: PyObject,
+        start: isize,
+        stop: Option<isize>,
+        py: Python,
+    ) -> PyResult<usize> {
+        sequence_index(
+            self.inner.iter(),
+            self.inner.len(),
+            &value,
+            start,
+            stop,
+            "List",
+            py,
+        )
+    }
+
+    fn count(&self, value: PyObject, py: Python) -> PyResult<usize> {
+        sequence_count(self.inner.iter(), &value, py)
+    }
+
+    fn push_front(&self, other: PyObject) -> ListPy {
+        ListPy::from(self.inner.push_front(other))
+    }
+
+    /// Add an element at the end, which unlike push_front copies the whole
+    /// list and so is O(n).
+    fn push_back(&self, other: PyObject, py: Python) -> ListPy {
+        let elements: Vec<&PyObject> = self.inner.iter().collect();
+        let mut inner = List::new_sync();
+        inner.push_front_mut(other);
+        for each in elements.into_iter().rev() {
+            inner.push_front_mut(each.clone_ref(py));
+        }
+        ListPy::from(inner)
+    }
+
+    /// Insert a value before index, which like list.insert is clamped to
+    /// the bounds of the list.
+    fn insert(&self, index: isize, value: PyObject, py: Python) -> ListPy {
+        let len = self.inner.len() as isize;
+        let index = if index < 0 { index + len } else { index };
+        self.splice(index.clamp(0, len) as usize, 0, Some(value), py)
+    }
+
+    fn delete(&self, index: isize, py: Python) -> PyResult<ListPy> {
+        let len = self.inner.len() as isize;
+        let normalized = if index < 0 { index + len } else { index };
+        if !(0..len).contains(&normalized) {
+            return Err(PyIndexError::new_err("list index out of range"));
+        }
+        Ok(self.splice(normalized as usize, 1, None, py))
+    }
+
+    /// Remove the first element equal to value.
+    fn remove(&self, value: PyObject, py: Python) -> PyResult<ListPy> {
+        let index = sequence_index(
+            self.inner.iter(),
+            self.inner.len(),
+            &value,
+            0,
+            None,
+            "List",
+            py,
+        )?;
+        Ok(self.splice(index, 1, None, py))
+    }
+
+    /// A sorted copy of this list, ordered by key(element) if key is given.
+    #[pyo3(signature = (*, key=None, reverse=false))]
+    fn sort(&self, key: Option<&Bound<'_, PyAny>>, reverse: bool, py: Python) -> PyResult<ListPy> {
+        let elements: Vec<&PyObject> = self.inner.iter().collect();
+        let mut sort_keys = Vec::with_capacity(elements.len());
+        for each in elements.iter() {
+            sort_keys.push(match key {
+                Some(key) => key.call1((*each,))?.unbind(),
+                None => each.clone_ref(py),
+            });
+        }
+        let mut inner = List::new_sync();
+        for index in sorted_order(sort_keys, reverse)?.into_iter().rev() {
+            inner.push_front_mut(elements[index].clone_ref(py));
+        }
+        Ok(ListPy::from(inner))
+    }
+
+    /// The first n elements (or all of them if there are fewer).
+    fn take(&self, n: usize, py: Python) -> ListPy {
+        self.split_at(n, py).0
+    }
+
+    /// Everything after the first n elements, sharing it with this list.
+    fn drop(&self, n: usize) -> ListPy {
+        let mut inner = self.inner.clone();
+        for _ in 0..n {
+            if !inner.drop_first_mut() {
+                break;
+            }
+        }
+        ListPy::from(inner)
+    }
+
+    /// Both take(n) and drop(n) at once.
+    fn split_at(&self, n: usize, py: Python) -> (ListPy, ListPy) {
+        let prefix: Vec<&PyObject> = self.inner.iter().take(n).collect();
+        let mut inner = List::new_sync();
+        for each in prefix.into_iter().rev() {
+            inner.push_front_mut(each.clone_ref(py));
+        }
+        (ListPy::from(inner), self.drop(n))
+    }
+
+    /// A list of pairs of elements from this list and other, stopping at
+    /// the end of whichever is shorter just like zip.
+    fn zip(&self, other: &Bound<'_, PyAny>) -> PyResult<ListPy> {
+        let py = other.py();
+        let mut pairs = Vec::new();
+        for (each, theirs) in self.inner.iter().zip(other.try_iter()?) {
+            let pair = PyTuple::new(py, [each.clone_ref(py), theirs?.unbind()])?;
+            pairs.push(pair.into_any().unbind());
+        }
+        let mut inner = List::new_sync();
+        for pair in pairs.into_iter().rev() {
+            inner.push_front_mut(pair);
+        }
+        Ok(ListPy::from(inner))
+    }
+
+    /// Apply a function to each element.
+    fn map(&self, f: &Bound<'_, PyAny>) -> PyResult<ListPy> {
+        let mut results = Vec::with_capacity(self.inner.len());
+        for each in self.inner.iter() {
+            results.push(f.call1((each,))?.unbind());
+        }
+        let mut inner = List::new_sync();
+        for each in results.into_iter().rev() {
+            inner.push_front_mut(each);
+        }
+        Ok(ListPy::from(inner))
+    }
+
+    /// A new list with only the elements for which predicate is true.
+    fn filter(&self, predicate: &Bound<'_, PyAny>) -> PyResult<ListPy> {
+        let mut matching = Vec::new();
+        for each in self.inner.iter() {
+            if predicate.call1((each,))?.is_truthy()? {
+                matching.push(each);
+            }
+        }
+        let mut inner = List::new_sync();
+        for each in matching.into_iter().rev() {
+            inner.push_front_mut(each.clone_ref(predicate.py()));
+        }
+        Ok(ListPy::from(inner))
+    }
+
+    /// Fold f(accumulator, element) over the elements from first to last.
+    fn reduce(&self, f: &Bound<'_, PyAny>, initial: PyObject) -> PyResult<PyObject> {
+        let mut accumulator = initial;
+        for each in self.inner.iter() {
+            accumulator = f.call1((accumulator, each))?.unbind();
+        }
+        Ok(accumulator)
+    }
+
+    /// This list without any repeated elements, keeping the first of each.
+    fn unique(&self, py: Python) -> PyResult<ListPy> {
+        let mut seen = HashTrieSet::new_sync();
+        let mut elements = Vec::new();
+        for each in self.inner.iter() {
+            let key = Key::extract_bound(each.bind(py))?;
+            if !seen.contains(&key) {
+                seen.insert_mut(key);
+                elements.push(each);
+            }
+        }
+        let mut inner = List::new_sync();
+        for each in elements.into_iter().rev() {
+            inner.push_front_mut(each.clone_ref(py));
+        }
+        Ok(ListPy::from(inner))
+    }
+
+    /// Add the elements of an iterable at the end, in order.
+    fn extend(&self, iterable: &Bound<'_, PyAny>) -> PyResult<ListPy> {
+        let py = iterable.py();
+        let mut elements: Vec<PyObject> = self.inner.iter().map(|e| e.clone_ref(py)).collect();
+        for each in iterable.try_iter()? {
+            elements.push(each?.unbind());
+        }
+        let mut inner = List::new_sync();
+        for each in elements.into_iter().rev() {
+            inner.push_front_mut(each);
+        }
+        Ok(ListPy::from(inner))
+    }
+
+    /// Add the elements of an iterable at the front, keeping their order, so
+    /// that the first of them becomes the first element.
+    fn extend_front(&self, iterable: &Bound<'_, PyAny>) -> PyResult<ListPy> {
+        let mut elements = Vec::new();
+        for each in iterable.try_iter()? {
+            elements.push(each?.unbind());
+        }
+        let mut inner = self.inner.clone();
+        for each in elements.into_iter().rev() {
+            inner.push_front_mut(each);
+        }
+        Ok(ListPy::from(inner))
+    }
+
+    fn drop_first(&self) -> PyResult<ListPy> {
+        Ok(ListPy::from(list_drop_first(&self.inner)?))
+    }
+
+    #[staticmethod]
+    fn builder() -> ListBuilder {
+        ListBuilder {
+            elements: Vec::new(),
+            finished: false,
+        }
+    }
+
+    #[pyo3(signature = (f=None))]
+    fn mutate(&self, f: Option<&Bound<'_, PyAny>>, py: Python) -> PyResult<PyObject> {
+        mutate_with(self.evolver(py), f, py)
+    }
+}
+
+// Lists are built from the back, so elements are collected until the end.
+#[pyclass(module = "rpds")]
+struct ListBuilder {
+    elements: Vec<PyObject>,
+    finished: bool,
+}
+
+impl Evolver for ListBuilder {
+    type Persistent = ListPy;
+
+    fn finish(&mut self, py: Python) -> ListPy {
+        self.finished = true;
+        self.build(py)
+    }
+}
+
+#[pymethods]
+impl ListBuilder {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.finished |= exc_type.is_none();
+        false
+    }
+
+    fn __len__(&self) -> usize {
+        self.elements.len()
+    }
+
+    fn append(&mut self, value: PyObject) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        self.elements.push(value);
+        Ok(())
+    }
+
+    fn build(&self, py: Python) -> ListPy {
+        let mut inner = List::new_sync();
+        for each in self.elements.iter().rev() {
+            inner.push_front_mut(each.clone_ref(py));
+        }
+        ListPy::from(inner)
+    }
+
+    /// The list as it stands, which is final once the with block has ended.
+    #[getter]
+    fn result(&self, py: Python) -> ListPy {
+        self.build(py)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct ListIterator {
+    inner: ListSync<PyObject>,
+}
+
+#[pymethods]
+impl ListIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
+        let first_op = slf.inner.first()?;
+        let first = first_op.clone_ref(slf.py());
+
+        slf.inner = slf.inner.drop_first()?;
+
+        Some(first)
+    }
+}
+
+// Walking a List or Queue backwards needs its elements in hand, but holding
+// just the handles is much cheaper than building a whole reversed copy.
+#[pyclass(module = "rpds")]
+struct ReverseIterator {
+    elements: Vec<PyObject>,
+}
+
+#[pymethods]
+impl ReverseIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
+        slf.elements.pop()
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.elements.len()
+    }
+}
+
+// The focus is the first element of right, and left holds the elements before
+// it nearest first, so moving in either direction is constant time.
+#[pyclass(name = "ListZipper", module = "rpds", frozen)]
+struct ListZipperPy {
+    left: ListSync<PyObject>,
+    right: ListSync<PyObject>,
+}
+
+#[pymethods]
+impl ListZipperPy {
+    #[new]
+    #[pyo3(signature = (list=None))]
+    fn init(list: Option<ListPy>) -> Self {
+        ListZipperPy {
+            left: List::new_sync(),
+            right: list.map_or_else(List::new_sync, |list| list.inner),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.left.len() + self.right.len()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!(
+            "ListZipper({}, index={})",
+            self.to_list(py).__repr__(py)?,
+            self.left.len()
+        ))
+    }
+
+    #[getter]
+    fn focus(&self, py: Python) -> PyResult<PyObject> {
+        match self.right.first() {
+            Some(focus) => Ok(focus.clone_ref(py)),
+            None => Err(PyIndexError::new_err("empty zipper has no focus")),
+        }
+    }
+
+    #[getter]
+    fn index(&self) -> usize {
+        self.left.len()
+    }
+
+    fn left(&self, py: Python) -> PyResult<ListZipperPy> {
+        match self.left.first() {
+            Some(previous) => Ok(ListZipperPy {
+                left: self.left.drop_first().unwrap_or_default(),
+                right: self.right.push_front(previous.clone_ref(py)),
+            }),
+            None => Err(PyIndexError::new_err("zipper is already at the start")),
+        }
+    }
+
+    fn right(&self, py: Python) -> PyResult<ListZipperPy> {
+        match (self.right.first(), self.right.drop_first()) {
+            (Some(focus), Some(rest)) if !rest.is_empty() => Ok(ListZipperPy {
+                left: self.left.push_front(focus.clone_ref(py)),
+                right: rest,
+            }),
+            _ => Err(PyIndexError::new_err("zipper is already at the end")),
+        }
+    }
+
+    fn set(&self, value: PyObject) -> PyResult<ListZipperPy> {
+        match self.right.drop_first() {
+            Some(rest) => Ok(ListZipperPy {
+                left: self.left.clone(),
+                right: rest.push_front(value),
+            }),
+            None => Err(PyIndexError::new_err("empty zipper has no focus")),
+        }
+    }
+
+    /// Insert a value before the focus, which then becomes the focus.
+    fn insert(&self, value: PyObject) -> ListZipperPy {
+        ListZipperPy {
+            left: self.left.clone(),
+            right: self.right.push_front(value),
+        }
+    }
+
+    /// Delete the focus, moving the focus to the next element if there is
+    /// one and otherwise to the previous one.
+    fn delete(&self, py: Python) -> PyResult<ListZipperPy> {
+        let Some(rest) = self.right.drop_first() else {
+            return Err(PyIndexError::new_err("empty zipper has no focus"));
+        };
+        match (rest.is_empty(), self.left.first()) {
+            (true, Some(previous)) => Ok(ListZipperPy {
+                left: self.left.drop_first().unwrap_or_default(),
+                right: rest.push_front(previous.clone_ref(py)),
+            }),
+            _ => Ok(ListZipperPy {
+                left: self.left.clone(),
+                right: rest,
+            }),
+        }
+    }
+
+    fn to_list(&self, py: Python) -> ListPy {
+        let mut inner = self.right.clone();
+        for each in self.left.iter() {
+            inner.push_front_mut(each.clone_ref(py));
+        }
+        ListPy::from(inner)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct QueueIterator {
+    inner: QueueSync<PyObject>,
+}
+
+#[pymethods]
+impl QueueIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
+        let first_op = slf.inner.peek()?;
+        let first = first_op.clone_ref(slf.py());
+        slf.inner = slf.inner.dequeue()?;
+        Some(first)
+    }
+}
+
+#[pyclass(name = "Queue", module = "rpds", frozen, sequence)]
+struct QueuePy {
+    inner: QueueSync<PyObject>,
+    hash: OnceLock<u64>,
+}
+
+impl From<QueueSync<PyObject>> for QueuePy {
+    fn from(elements: QueueSync<PyObject>) -> Self {
+        QueuePy {
+            inner: elements,
+            hash: OnceLock::new(),
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for QueuePy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        Ok(QueuePy::from(queue_from_iterable(ob)?))
+    }
+}
+
+impl QueuePy {
+    fn evolver(&self, _py: Python) -> QueueBuilder {
+        QueueBuilder {
+            inner: self.inner.clone(),
+            finished: false,
+        }
+    }
+}
+
+#[pymethods]
+impl QueuePy {
+    #[new]
+    #[pyo3(signature = (*elements))]
+    fn init(elements: &Bound<'_, PyTuple>) -> PyResult<Self> {
+        if elements.len() == 1 {
+            return elements.get_item(0)?.extract();
+        }
+        Ok(QueuePy::from(queue_from_iterable(elements)?))
+    }
+
+    fn __eq__(&self, other: &Self, py: Python<'_>) -> PyResult<bool> {
+        sequences_eq(&self.inner, &other.inner, py)
+    }
+
+    fn __hash__(&self, py: Python<'_>) -> PyResult<u64> {
+        sequence_hash("Queue", &self.inner, &self.hash, py)
+    }
+
+    fn __ne__(&self, other: &Self, py: Python<'_>) -> PyResult<bool> {
+        sequences_ne(&self.inner, &other.inner, py)
+    }
+
+    fn __contains__(&self, value: PyObject, py: Python) -> PyResult<bool> {
+        sequence_contains(&self.inner, &value, py)
+    }
+
+    fn __getitem__(&self, index: isize, py: Python) -> PyResult<PyObject> {
+        queue_item(&self.inner, index, py)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> QueueIterator {
+        QueueIterator {
+            inner: slf.inner.clone(),
+        }
+    }
+
+    fn __reversed__(&self, py: Python) -> ReverseIterator {
+        sequence_reversed(&self.inner, py)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<PyObject>,)) {
+        (
+            QueuePy::type_object(slf.py()),
+            (slf.inner.iter().map(|e| e.clone_ref(slf.py())).collect(),),
+        )
+    }
+
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        collection_size::<PyObject, (usize, usize)>(slf.as_any(), slf.get().inner.len())
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        sequence_repr("Queue", &self.inner, py)
+    }
+
+    #[getter]
+    fn peek(&self, py: Python) -> PyResult<PyObject> {
+        queue_peek(&self.inner, py)
+    }
+
+    /// Like peek, but returning default rather than raising when empty
+    /// (peek itself is a property, so can't take one).
+    fn peek_or(&self, default: PyObject, py: Python) -> PyObject {
+        match self.inner.peek() {
+            Some(peeked) => peeked.clone_ref(py),
+            None => default,
+        }
+    }
+
+    #[getter]
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    #[pyo3(signature = (value, start=0, stop=None))]
+    fn index(
+        &self,
+        value: PyObject,
+        start: isize,
+        stop: Option<isize>,
+        py: Python,
+    ) -> PyResult<usize> {
+        sequence_index(
+            self.inner.iter(),
+            self.inner.len(),
+            &value,
+            start,
+            stop,
+            "Queue",
+            py,
+        )
+    }
+
+    fn count(&self, value: PyObject, py: Python) -> PyResult<usize> {
+        sequence_count(self.inner.iter(), &value, py)
+    }
+
+    fn enqueue(&self, value: Bound<'_, PyAny>) -> Self {
+        QueuePy::from(self.inner.enqueue(value.into()))
+    }
+
+    fn dequeue(&self) -> PyResult<QueuePy> {
+        Ok(QueuePy::from(queue_dequeue(&self.inner)?))
+    }
+
+    /// Dequeue, returning the dequeued value along with the new queue.
+    fn pop(&self, py: Python) -> PyResult<(PyObject, QueuePy)> {
+        match (self.inner.peek(), self.inner.dequeue()) {
+            (Some(value), Some(inner)) => Ok((value.clone_ref(py), QueuePy::from(inner))),
+            _ => Err(PyIndexError::new_err("popped an empty queue")),
+        }
+    }
+
+    #[staticmethod]
+    fn builder() -> QueueBuilder {
+        QueueBuilder {
+            inner: Queue::new_sync(),
+            finished: false,
+        }
+    }
+
+    #[pyo3(signature = (f=None))]
+    fn mutate(&self, f: Option<&Bound<'_, PyAny>>, py: Python) -> PyResult<PyObject> {
+        mutate_with(self.evolver(py), f, py)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct QueueBuilder {
+    inner: QueueSync<PyObject>,
+    finished: bool,
+}
+
+impl Evolver for QueueBuilder {
+    type Persistent = QueuePy;
+
+    fn finish(&mut self, _py: Python) -> QueuePy {
+        self.finished = true;
+        self.build()
+    }
+}
+
+#[pymethods]
+impl QueueBuilder {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.finished |= exc_type.is_none();
+        false
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn enqueue(&mut self, value: PyObject) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        self.inner.enqueue_mut(value);
+        Ok(())
+    }
+
+    fn build(&self) -> QueuePy {
+        QueuePy::from(self.inner.clone())
+    }
+
+    /// The queue as it stands, which is final once the with block has ended.
+    #[getter]
+    fn result(&self) -> QueuePy {
+        self.build()
+    }
+}
+
+#[pyclass(name = "Vector", module = "rpds", frozen, sequence)]
+struct VectorPy {
+    inner: VectorSync<PyObject>,
+    hash: OnceLock<u64>,
+}
+
+impl From<VectorSync<PyObject>> for VectorPy {
+    fn from(elements: VectorSync<PyObject>) -> Self {
+        VectorPy {
+            inner: elements,
+            hash: OnceLock::new(),
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for VectorPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let mut ret = Vector::new_sync();
+        for each in ob.try_iter()? {
+            ret.push_back_mut(each?.extract()?);
+        }
+        Ok(VectorPy::from(ret))
+    }
+}
+
+impl VectorPy {
+    fn evolver(&self, _py: Python) -> VectorBuilder {
+        VectorBuilder {
+            inner: self.inner.clone(),
+            finished: false,
+        }
+    }
+
+    fn normalize_index(&self, index: isize) -> PyResult<usize> {
+        let len = self.inner.len() as isize;
+        let normalized = if index < 0 { index + len } else { index };
+        if (0..len).contains(&normalized) {
+            Ok(normalized as usize)
+        } else {
+            Err(PyIndexError::new_err("vector index out of range"))
+        }
+    }
+}
+
+#[pymethods]
+impl VectorPy {
+    #[new]
+    #[pyo3(signature = (*elements))]
+    fn init(elements: &Bound<'_, PyTuple>) -> PyResult<Self> {
+        let mut ret: VectorPy;
+        if elements.len() == 1 {
+            ret = elements.get_item(0)?.extract()?;
+        } else {
+            ret = VectorPy::from(Vector::new_sync());
+            for each in elements {
+                ret.inner.push_back_mut(each.unbind());
+            }
+        }
+        Ok(ret)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __getitem__(&self, index: isize, py: Python) -> PyResult<PyObject> {
+        let index = self.normalize_index(index)?;
+        Ok(self.inner[index].clone_ref(py))
+    }
+
+    fn __contains__(&self, value: PyObject, py: Python) -> PyResult<bool> {
+        sequence_contains(&self.inner, &value, py)
+    }
+
+    fn __reversed__(&self, py: Python) -> ReverseIterator {
+        sequence_reversed(&self.inner, py)
+    }
+
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        collection_size::<PyObject, usize>(slf.as_any(), slf.get().inner.len())
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        sequence_repr("Vector", &self.inner, py)
+    }
+
+    fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python<'_>) -> PyResult<PyObject> {
+        sequence_richcmp(&self.inner, &other.inner, op, py)
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<u64> {
+        sequence_hash("Vector", &self.inner, &self.hash, py)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> VectorIterator {
+        VectorIterator {
+            inner: slf.inner.clone(),
+            index: 0,
+        }
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<PyObject>,)) {
+        (
+            VectorPy::type_object(slf.py()),
+            (slf.inner.iter().map(|e| e.clone_ref(slf.py())).collect(),),
+        )
+    }
+
+    fn set(&self, index: isize, value: PyObject) -> PyResult<VectorPy> {
+        let index = self.normalize_index(index)?;
+        Ok(VectorPy::from(
+            self.inner.set(index, value).expect("index was checked"),
+        ))
+    }
+
+    #[pyo3(signature = (value, start=0, stop=None))]
+    fn index(
+        &self,
+        value: PyObject,
+        start: isize,
+        stop: Option<isize>,
+        py: Python,
+    ) -> PyResult<usize> {
+        sequence_index(
+            self.inner.iter(),
+            self.inner.len(),
+            &value,
+            start,
+            stop,
+            "Vector",
+            py,
+        )
+    }
+
+    fn count(&self, value: PyObject, py: Python) -> PyResult<usize> {
+        sequence_count(self.inner.iter(), &value, py)
+    }
+
+    fn push_back(&self, value: PyObject) -> VectorPy {
+        VectorPy::from(self.inner.push_back(value))
+    }
+
+    fn drop_last(&self) -> PyResult<VectorPy> {
+        if let Some(inner) = self.inner.drop_last() {
+            Ok(VectorPy::from(inner))
+        } else {
+            Err(PyIndexError::new_err("empty vector has no last element"))
+        }
+    }
+
+    #[staticmethod]
+    fn builder() -> VectorBuilder {
+        VectorBuilder {
+            inner: Vector::new_sync(),
+            finished: false,
+        }
+    }
+
+    #[pyo3(signature = (f=None))]
+    fn mutate(&self, f: Option<&Bound<'_, PyAny>>, py: Python) -> PyResult<PyObject> {
+        mutate_with(self.evolver(py), f, py)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct VectorBuilder {
+    inner: VectorSync<PyObject>,
+    finished: bool,
+}
+
+impl Evolver for VectorBuilder {
+    type Persistent = VectorPy;
+
+    fn finish(&mut self, _py: Python) -> VectorPy {
+        self.finished = true;
+        self.build()
+    }
+}
+
+#[pymethods]
+impl VectorBuilder {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.finished |= exc_type.is_none();
+        false
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn append(&mut self, value: PyObject) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        self.inner.push_back_mut(value);
+        Ok(())
+    }
+
+    fn build(&self) -> VectorPy {
+        VectorPy::from(self.inner.clone())
+    }
+
+    /// The vector as it stands, which is final once the with block has ended.
+    #[getter]
+    fn result(&self) -> VectorPy {
+        self.build()
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct VectorIterator {
+    inner: VectorSync<PyObject>,
+    index: usize,
+}
+
+#[pymethods]
+impl VectorIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
+        let next = slf.inner.get(slf.index)?.clone_ref(slf.py());
+        slf.index += 1;
+        Some(next)
+    }
+}
+
+fn same_key_function(ours: &Option<PyObject>, theirs: &Option<PyObject>) -> bool {
+    match (ours, theirs) {
+        (None, None) => true,
+        (Some(ours), Some(theirs)) => ours.is(theirs),
+        _ => false,
+    }
+}
+
+type SortedItems = Vec<(PyObject, PyObject)>;
+
+// What pickling calls to rebuild a sorted collection, which needs to be told
+// its key function again if it had one.
+fn sorted_constructor<T: PyTypeInfo>(key: &Option<PyObject>, py: Python) -> PyResult<PyObject> {
+    let cls = T::type_object(py).into_any();
+    match key {
+        None => Ok(cls.unbind()),
+        Some(key) => {
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("key", key)?;
+            let partial = PyModule::import(py, "functools")?.getattr("partial")?;
+            Ok(partial.call((cls,), Some(&kwargs))?.unbind())
+        }
+    }
+}
+
+#[pyclass(name = "SortedMap", module = "rpds", frozen, mapping)]
+struct SortedMapPy {
+    inner: RedBlackTreeMapSync<SortedKey, PyObject>,
+    key: Option<PyObject>,
+    hash: OnceLock<isize>,
+}
+
+impl From<RedBlackTreeMapSync<SortedKey, PyObject>> for SortedMapPy {
+    fn from(map: RedBlackTreeMapSync<SortedKey, PyObject>) -> Self {
+        SortedMapPy {
+            hash: OnceLock::new(),
+            inner: map,
+            key: None,
+        }
+    }
+}
+
+// Insert the items of a mapping (or an iterable of pairs) into a sorted map
+// ordered by the given key function.
+fn insert_sorted_items(
+    map: &mut RedBlackTreeMapSync<SortedKey, PyObject>,
+    ob: &Bound<'_, PyAny>,
+    key: Option<&PyObject>,
+) -> PyResult<()> {
+    let py = ob.py();
+    let items = match ob.downcast::<PyMapping>() {
+        Ok(mapping) => mapping.items()?.into_any(),
+        Err(_) => ob.clone(),
+    };
+    for each in items.try_iter()? {
+        let (k, v): (SortedKey, PyObject) = each?.extract()?;
+        map.insert_mut(k.keyed(key, py)?, v);
+        comparison_result(())?;
+    }
+    Ok(())
+}
+
+impl<'source> FromPyObject<'source> for SortedMapPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        SortedMapPy::from_items(ob, None)
+    }
+}
+
+impl SortedMapPy {
+    fn evolver(&self, py: Python) -> SortedMapEvolver {
+        SortedMapEvolver {
+            inner: self.inner.clone(),
+            key: self.key(py),
+            finished: false,
+        }
+    }
+
+    fn from_items(ob: &Bound<'_, PyAny>, key: Option<&PyObject>) -> PyResult<Self> {
+        let py = ob.py();
+        let mut ret = RedBlackTreeMap::new_sync();
+        insert_sorted_items(&mut ret, ob, key)?;
+        Ok(SortedMapPy {
+            hash: OnceLock::new(),
+            inner: ret,
+            key: key.map(|key| key.clone_ref(py)),
+        })
+    }
+
+    // Ready a key given from Python to be looked up in (or added to) this map.
+    fn key_for(&self, key: SortedKey, py: Python) -> PyResult<SortedKey> {
+        key.keyed(self.key.as_ref(), py)
+    }
+
+    // A map ordered the same way as this one, with the given contents.
+    fn with_inner(&self, inner: RedBlackTreeMapSync<SortedKey, PyObject>, py: Python) -> Self {
+        SortedMapPy {
+            hash: OnceLock::new(),
+            inner,
+            key: self.key.as_ref().map(|key| key.clone_ref(py)),
+        }
+    }
+
+    // The entries of other, ordered the same way as this map so the two can be
+    // combined.
+    fn reordered(&self, other: &Self, py: Python) -> PyResult<Self> {
+        if same_key_function(&self.key, &other.key) {
+            return Ok(self.with_inner(other.inner.clone(), py));
+        }
+        let mut inner = RedBlackTreeMap::new_sync();
+        for (k, v) in other.inner.iter() {
+            inner.insert_mut(self.key_for(k.clone_ref(py), py)?, v.clone_ref(py));
+            comparison_result(())?;
+        }
+        Ok(self.with_inner(inner, py))
+    }
+}
+
+#[pymethods]
+impl SortedMapPy {
+    #[new]
+    #[pyo3(signature = (value=None, *, key=None, ** kwds))]
+    fn init(
+        value: Option<Bound<'_, PyAny>>,
+        key: Option<PyObject>,
+        kwds: Option<&Bound<'_, PyDict>>,
+        py: Python,
+    ) -> PyResult<Self> {
+        let mut map = match value {
+            Some(value) => SortedMapPy::from_items(&value, key.as_ref())?,
+            None => SortedMapPy {
+                hash: OnceLock::new(),
+                inner: RedBlackTreeMap::new_sync(),
+                key,
+            },
+        };
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                let k = map.key_for(SortedKey::extract_bound(&k)?, py)?;
+                map.inner.insert_mut(k, v.into());
+                comparison_result(())?;
+            }
+        }
+        Ok(map)
+    }
+
+    /// The key function the map is ordered by, if any.
+    #[getter]
+    fn key(&self, py: Python) -> Option<PyObject> {
+        self.key.as_ref().map(|key| key.clone_ref(py))
+    }
+
+    fn __contains__(&self, key: SortedKey, py: Python) -> PyResult<bool> {
+        comparison_result(self.inner.contains_key(&self.key_for(key, py)?))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> SortedKeysIterator {
+        SortedKeysIterator {
+            inner: slf.inner.clone(),
+            reverse: false,
+        }
+    }
+
+    fn __reversed__(&self) -> SortedKeysIterator {
+        SortedKeysIterator {
+            inner: self.inner.clone(),
+            reverse: true,
+        }
+    }
+
+    fn __getitem__(&self, key: SortedKey, py: Python) -> PyResult<PyObject> {
+        let key = self.key_for(key, py)?;
+        match comparison_result(self.inner.get(&key))? {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        let contents = self.inner.into_iter().map(|(k, v)| {
+            format!(
+                "{}: {}",
+                k.inner
+                    .call_method0(py, "__repr__")
+                    .and_then(|r| r.extract(py))
+                    .unwrap_or("<repr error>".to_owned()),
+                v.call_method0(py, "__repr__")
+                    .and_then(|r| r.extract(py))
+                    .unwrap_or("<repr error>".to_owned())
+            )
+        });
+        format!("SortedMap({{{}}})", contents.collect::<Vec<_>>().join(", "))
+    }
+
+    fn __richcmp__<'py>(&self, other: &Self, op: CompareOp, py: Python<'py>) -> PyResult<PyObject> {
+        let eq = || -> PyResult<bool> {
+            if std::ptr::eq(self, other) {
+                return Ok(true);
+            }
+            if self.inner.size() != other.inner.size() {
+                return Ok(false);
+            }
+            // Maps ordered differently can't be walked side by side, so each of
+            // our keys is looked up in other instead (where one it can't even be
+            // compared with isn't there).
+            if !same_key_function(&self.key, &other.key) {
+                for (k1, v1) in self.inner.iter() {
+                    let key = other.key_for(k1.clone_ref(py), py)?;
+                    let found = comparison_result(other.inner.get_key_value(&key));
+                    let Ok(Some((k2, v2))) = found else {
+                        return Ok(false);
+                    };
+                    if !elements_eq(&k1.inner, &k2.inner, py)? || !elements_eq(v1, v2, py)? {
+                        return Ok(false);
+                    }
+                }
+                return Ok(true);
+            }
+            for ((k1, v1), (k2, v2)) in self.inner.iter().zip(other.inner.iter()) {
+                if !elements_eq(&k1.inner, &k2.inner, py)? || !elements_eq(v1, v2, py)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+        match op {
+            CompareOp::Eq => eq()?
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            CompareOp::Ne => (!eq()?)
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            _ => Ok(py.NotImplemented()),
+        }
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        let hashes = self.inner.iter().map(|(key, val)| {
+            let mut hasher = DefaultHasher::new();
+            let key_bound = key.inner.bind(py);
+            let val_bound = val.bind(py);
+
+            let unhashable = |_| {
+                PyTypeError::new_err(format!(
+                    "Unhashable type in SortedMap of key {}: {}",
+                    key_bound
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string()),
+                    val_bound
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string())
+                ))
+            };
+            let key_hash = key_bound.hash().map_err(unhashable)?;
+            let val_hash = val_bound.hash().map_err(unhashable)?;
+
+            hasher.write_isize(key_hash);
+            hasher.write_isize(val_hash);
+
+            Ok(hasher.finish() as usize)
+        });
+        unordered_hash(hashes, self.inner.size(), &self.hash)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> PyResult<(PyObject, (SortedItems,))> {
+        let py = slf.py();
+        let items = slf
+            .inner
+            .iter()
+            .map(|(k, v)| (k.inner.clone_ref(py), v.clone_ref(py)))
+            .collect();
+        Ok((sorted_constructor::<Self>(&slf.key, py)?, (items,)))
+    }
+
+    #[pyo3(signature = (key, default=None))]
+    fn get(
+        &self,
+        key: SortedKey,
+        default: Option<PyObject>,
+        py: Python,
+    ) -> PyResult<Option<PyObject>> {
+        let key = self.key_for(key, py)?;
+        if let Some(value) = comparison_result(self.inner.get(&key))? {
+            Ok(Some(value.clone_ref(py)))
+        } else {
+            Ok(default)
+        }
+    }
+
+    fn first_item(&self, py: Python) -> PyResult<(PyObject, PyObject)> {
+        self.inner
+            .first()
+            .map(|(k, v)| (k.inner.clone_ref(py), v.clone_ref(py)))
+            .ok_or_else(|| PyIndexError::new_err("empty map has no first item"))
+    }
+
+    fn last_item(&self, py: Python) -> PyResult<(PyObject, PyObject)> {
+        self.inner
+            .last()
+            .map(|(k, v)| (k.inner.clone_ref(py), v.clone_ref(py)))
+            .ok_or_else(|| PyIndexError::new_err("empty map has no last item"))
+    }
+
+    /// The largest key less than or equal to key, or None if there isn't one.
+    fn floor_key(&self, key: SortedKey, py: Python) -> PyResult<Option<PyObject>> {
+        let floor = self.inner.range(..=self.key_for(key, py)?).next_back();
+        comparison_result(floor.map(|(k, _)| k.inner.clone_ref(py)))
+    }
+
+    /// The smallest key greater than or equal to key, or None if there isn't
+    /// one.
+    fn ceiling_key(&self, key: SortedKey, py: Python) -> PyResult<Option<PyObject>> {
+        let ceiling = self.inner.range(self.key_for(key, py)?..).next();
+        comparison_result(ceiling.map(|(k, _)| k.inner.clone_ref(py)))
+    }
+
+    /// The key at position index in sorted order. The tree doesn't track
+    /// subtree sizes, so this walks it and is O(n).
+    fn nth(&self, index: isize, py: Python) -> PyResult<PyObject> {
+        let len = self.inner.size() as isize;
+        let normalized = if index < 0 { index + len } else { index };
+        if !(0..len).contains(&normalized) {
+            return Err(PyIndexError::new_err("map index out of range"));
+        }
+        match self.inner.keys().nth(normalized as usize) {
+            Some(key) => Ok(key.inner.clone_ref(py)),
+            None => Err(PyIndexError::new_err("map index out of range")),
+        }
+    }
+
+    /// The position of key in sorted order, raising a KeyError if missing.
+    fn index_of(&self, key: SortedKey, py: Python) -> PyResult<usize> {
+        let key = self.key_for(key, py)?;
+        if !comparison_result(self.inner.contains_key(&key))? {
+            return Err(PyKeyError::new_err(key));
+        }
+        comparison_result(self.inner.range(..key).count())
+    }
+
+    /// The keys between min and max (either of which may be None to leave
+    /// that end unbounded), in order.
+    #[pyo3(signature = (min=None, max=None, inclusive=(true, true), reverse=false))]
+    fn irange(
+        &self,
+        min: Option<SortedKey>,
+        max: Option<SortedKey>,
+        inclusive: (bool, bool),
+        reverse: bool,
+        py: Python,
+    ) -> PyResult<SortedRangeIterator> {
+        let min = min.map(|key| self.key_for(key, py)).transpose()?;
+        let max = max.map(|key| self.key_for(key, py)).transpose()?;
+        let mut keys = Vec::new();
+        if let Some(range) = sorted_range(min, max, inclusive, py)? {
+            keys.extend(self.inner.range(range).map(|(k, _)| k.inner.clone_ref(py)));
+        }
+        comparison_result(())?;
+        if reverse {
+            keys.reverse();
+        }
+        Ok(SortedRangeIterator {
+            elements: keys.into_iter(),
+        })
+    }
+
+    /// A map of just the entries whose keys are between min and max, bounded
+    /// the same way as irange.
+    #[pyo3(signature = (min=None, max=None, inclusive=(true, true)))]
+    fn submap(
+        &self,
+        min: Option<SortedKey>,
+        max: Option<SortedKey>,
+        inclusive: (bool, bool),
+        py: Python,
+    ) -> PyResult<SortedMapPy> {
+        let min = min.map(|key| self.key_for(key, py)).transpose()?;
+        let max = max.map(|key| self.key_for(key, py)).transpose()?;
+        let mut inner = RedBlackTreeMap::new_sync();
+        if let Some(range) = sorted_range(min, max, inclusive, py)? {
+            for (k, v) in self.inner.range(range) {
+                inner.insert_mut(k.clone_ref(py), v.clone_ref(py));
+            }
+        }
+        comparison_result(self.with_inner(inner, py))
+    }
+
+    fn keys(&self, py: Python) -> SortedKeysView {
+        SortedKeysView {
+            inner: self.inner.clone(),
+            key: self.key(py),
+        }
+    }
+
+    fn values(&self) -> SortedValuesView {
+        SortedValuesView {
+            inner: self.inner.clone(),
+        }
+    }
+
+    fn items(&self, py: Python) -> SortedItemsView {
+        SortedItemsView {
+            inner: self.inner.clone(),
+            key: self.key(py),
+        }
+    }
+
+    fn discard(&self, key: SortedKey, py: Python) -> PyResult<SortedMapPy> {
+        let key = self.key_for(key, py)?;
+        match comparison_result(self.inner.contains_key(&key))? {
+            true => Ok(self.with_inner(self.inner.remove(&key), py)),
+            false => Ok(self.with_inner(self.inner.clone(), py)),
+        }
+    }
+
+    fn insert(&self, key: SortedKey, value: Bound<'_, PyAny>) -> PyResult<SortedMapPy> {
+        let py = value.py();
+        let inner = self.inner.insert(self.key_for(key, py)?, value.unbind());
+        comparison_result(self.with_inner(inner, py))
+    }
+
+    fn remove(&self, key: SortedKey, py: Python) -> PyResult<SortedMapPy> {
+        let key = self.key_for(key, py)?;
+        match comparison_result(self.inner.contains_key(&key))? {
+            true => Ok(self.with_inner(self.inner.remove(&key), py)),
+            false => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    #[pyo3(signature = (f=None))]
+    fn mutate(&self, f: Option<&Bound<'_, PyAny>>, py: Python) -> PyResult<PyObject> {
+        mutate_with(self.evolver(py), f, py)
+    }
+
+    /// Merge another sorted map into this one, with its values winning unless
+    /// resolve is given, in which case resolve(old, new) decides the value of
+    /// any keys present in both. The smaller map's entries are what get
+    /// inserted into (a copy of) the larger, which is otherwise shared.
+    #[pyo3(signature = (other, resolve=None))]
+    fn merge(
+        &self,
+        other: &Self,
+        resolve: Option<&Bound<'_, PyAny>>,
+        py: Python,
+    ) -> PyResult<SortedMapPy> {
+        let other = self.reordered(other, py)?;
+        let (mut inner, smaller, smaller_is_other) = if self.inner.size() >= other.inner.size() {
+            (self.inner.clone(), &other.inner, true)
+        } else {
+            (other.inner.clone(), &self.inner, false)
+        };
+        for (k, v) in smaller {
+            let existing = comparison_result(inner.get_key_value(k))?;
+            let (key, value) = match (existing, resolve) {
+                (None, _) => (k, v.clone_ref(py)),
+                (Some(_), None) if smaller_is_other => (k, v.clone_ref(py)),
+                (Some(_), None) => continue,
+                (Some((_, old)), Some(resolve)) if smaller_is_other => {
+                    (k, resolve.call1((old, v))?.unbind())
+                }
+                (Some((key, new)), Some(resolve)) => (key, resolve.call1((v, new))?.unbind()),
+            };
+            inner.insert_mut(key.clone_ref(py), value);
+            comparison_result(())?;
+        }
+        Ok(self.with_inner(inner, py))
+    }
+
+    #[pyo3(signature = (*maps, **kwds))]
+    fn update(
+        &self,
+        maps: &Bound<'_, PyTuple>,
+        kwds: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<SortedMapPy> {
+        let py = maps.py();
+        let mut inner = self.inner.clone();
+        for value in maps {
+            insert_sorted_items(&mut inner, &value, self.key.as_ref())?;
+        }
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                inner.insert_mut(self.key_for(k.extract()?, py)?, v.extract()?);
+                comparison_result(())?;
+            }
+        }
+        Ok(self.with_inner(inner, py))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct SortedMapEvolver {
+    inner: RedBlackTreeMapSync<SortedKey, PyObject>,
+    key: Option<PyObject>,
+    finished: bool,
+}
+
+impl Evolver for SortedMapEvolver {
+    type Persistent = SortedMapPy;
+
+    fn finish(&mut self, py: Python) -> SortedMapPy {
+        self.finished = true;
+        self.persistent(py)
+    }
+}
+
+#[pymethods]
+impl SortedMapEvolver {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.finished |= exc_type.is_none();
+        false
+    }
+
+    fn __contains__(&self, key: SortedKey, py: Python) -> PyResult<bool> {
+        let key = key.keyed(self.key.as_ref(), py)?;
+        comparison_result(self.inner.contains_key(&key))
+    }
+
+    fn __getitem__(&self, key: SortedKey, py: Python) -> PyResult<PyObject> {
+        let key = key.keyed(self.key.as_ref(), py)?;
+        match comparison_result(self.inner.get(&key))? {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    // Changes are made to a copy (which shares all but the changed path) so
+    // that a failed comparison leaves the evolver as it was.
+    fn __setitem__(&mut self, key: SortedKey, value: PyObject, py: Python) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        let key = key.keyed(self.key.as_ref(), py)?;
+        let mut inner = self.inner.clone();
+        inner.insert_mut(key, value);
+        self.inner = comparison_result(inner)?;
+        Ok(())
+    }
+
+    fn __delitem__(&mut self, key: SortedKey, py: Python) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        let key = key.keyed(self.key.as_ref(), py)?;
+        let mut inner = self.inner.clone();
+        match comparison_result(inner.remove_mut(&key))? {
+            true => {
+                self.inner = inner;
+                Ok(())
+            }
+            false => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn persistent(&self, py: Python) -> SortedMapPy {
+        SortedMapPy {
+            hash: OnceLock::new(),
+            inner: self.inner.clone(),
+            key: self.key.as_ref().map(|key| key.clone_ref(py)),
+        }
+    }
+
+    /// The map as it stands, which is final once the with block has ended.
+    #[getter]
+    fn result(&self, py: Python) -> SortedMapPy {
+        self.persistent(py)
+    }
+}
+
+// The bounds for a range query over sorted keys, or None if the range is empty
+// (which the trees panic on rather than returning nothing for).
+type SortedRange = (std::ops::Bound<SortedKey>, std::ops::Bound<SortedKey>);
+
+fn sorted_range(
+    min: Option<SortedKey>,
+    max: Option<SortedKey>,
+    inclusive: (bool, bool),
+    py: Python,
+) -> PyResult<Option<SortedRange>> {
+    if let (Some(min), Some(max)) = (&min, &max) {
+        match min
+            .ordered_by()
+            .bind(py)
+            .compare(max.ordered_by().bind(py))?
+        {
+            Ordering::Greater => return Ok(None),
+            Ordering::Equal if !(inclusive.0 && inclusive.1) => return Ok(None),
+            _ => {}
+        }
+    }
+    let bound = |key: Option<SortedKey>, inclusive: bool| match key {
+        None => std::ops::Bound::Unbounded,
+        Some(key) if inclusive => std::ops::Bound::Included(key),
+        Some(key) => std::ops::Bound::Excluded(key),
+    };
+    Ok(Some((bound(min, inclusive.0), bound(max, inclusive.1))))
+}
+
+// Range queries can't hold on to the tree iterator they come from, so their
+// results are collected up front.
+#[pyclass(module = "rpds")]
+struct SortedRangeIterator {
+    elements: std::vec::IntoIter<PyObject>,
+}
+
+#[pymethods]
+impl SortedRangeIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
+        slf.elements.next()
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.elements.len()
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct SortedKeysIterator {
+    inner: RedBlackTreeMapSync<SortedKey, PyObject>,
+    reverse: bool,
+}
+
+#[pymethods]
+impl SortedKeysIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<Option<PyObject>> {
+        let next = match slf.reverse {
+            true => slf.inner.last(),
+            false => slf.inner.first(),
+        };
+        let Some((first, _)) = next else {
+            return Ok(None);
+        };
+        let first = first.clone_ref(slf.py());
+        slf.inner.remove_mut(&first);
+        comparison_result(Some(first.inner))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct SortedValuesIterator {
+    inner: RedBlackTreeMapSync<SortedKey, PyObject>,
+    reverse: bool,
+}
+
+#[pymethods]
+impl SortedValuesIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<Option<PyObject>> {
+        let next = match slf.reverse {
+            true => slf.inner.last(),
+            false => slf.inner.first(),
+        };
+        let Some((first, value)) = next else {
+            return Ok(None);
+        };
+        let (first, value) = (first.clone_ref(slf.py()), value.clone_ref(slf.py()));
+        slf.inner.remove_mut(&first);
+        comparison_result(Some(value))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct SortedItemsIterator {
+    inner: RedBlackTreeMapSync<SortedKey, PyObject>,
+    reverse: bool,
+}
+
+#[pymethods]
+impl SortedItemsIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<Option<(PyObject, PyObject)>> {
+        let next = match slf.reverse {
+            true => slf.inner.last(),
+            false => slf.inner.first(),
+        };
+        let Some((first, value)) = next else {
+            return Ok(None);
+        };
+        let (first, value) = (first.clone_ref(slf.py()), value.clone_ref(slf.py()));
+        slf.inner.remove_mut(&first);
+        comparison_result(Some((first.inner, value)))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct SortedKeysView {
+    inner: RedBlackTreeMapSync<SortedKey, PyObject>,
+    key: Option<PyObject>,
+}
+
+#[pymethods]
+impl SortedKeysView {
+    fn __contains__(&self, key: SortedKey, py: Python) -> PyResult<bool> {
+        let key = key.keyed(self.key.as_ref(), py)?;
+        comparison_result(self.inner.contains_key(&key))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> SortedKeysIterator {
+        SortedKeysIterator {
+            inner: slf.inner.clone(),
+            reverse: false,
+        }
+    }
+
+    fn __reversed__(&self) -> SortedKeysIterator {
+        SortedKeysIterator {
+            inner: self.inner.clone(),
+            reverse: true,
+        }
+    }
+
+    fn __len__(slf: PyRef<'_, Self>) -> usize {
+        slf.inner.size()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let contents = self.inner.keys().map(|k| {
+            Ok(k.inner
+                .bind(py)
+                .repr()
+                .and_then(|r| r.extract())
+                .unwrap_or("<repr failed>".to_owned()))
+        });
+        let contents = contents.collect::<Result<Vec<String>, PyErr>>()?;
+        Ok(format!("sorted_keys_view([{}])", contents.join(", ")))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct SortedValuesView {
+    inner: RedBlackTreeMapSync<SortedKey, PyObject>,
+}
+
+#[pymethods]
+impl SortedValuesView {
+    fn __iter__(slf: PyRef<'_, Self>) -> SortedValuesIterator {
+        SortedValuesIterator {
+            inner: slf.inner.clone(),
+            reverse: false,
+        }
+    }
+
+    fn __reversed__(&self) -> SortedValuesIterator {
+        SortedValuesIterator {
+            inner: self.inner.clone(),
+            reverse: true,
+        }
+    }
+
+    fn __len__(slf: PyRef<'_, Self>) -> usize {
+        slf.inner.size()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let contents = self.inner.values().map(|v| {
+            Ok(v.bind(py)
+                .repr()
+                .and_then(|r| r.extract())
+                .unwrap_or("<repr failed>".to_owned()))
+        });
+        let contents = contents.collect::<Result<Vec<String>, PyErr>>()?;
+        Ok(format!("sorted_values_view([{}])", contents.join(", ")))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct SortedItemsView {
+    inner: RedBlackTreeMapSync<SortedKey, PyObject>,
+    key: Option<PyObject>,
+}
+
+#[pymethods]
+impl SortedItemsView {
+    fn __contains__(slf: PyRef<'_, Self>, item: (SortedKey, PyObject)) -> PyResult<bool> {
+        let key = item.0.keyed(slf.key.as_ref(), slf.py())?;
+        if let Some(value) = comparison_result(slf.inner.get(&key))? {
+            return item.1.bind(slf.py()).eq(value);
+        }
+
+        Ok(false)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> SortedItemsIterator {
+        SortedItemsIterator {
+            inner: slf.inner.clone(),
+            reverse: false,
+        }
+    }
+
+    fn __reversed__(&self) -> SortedItemsIterator {
+        SortedItemsIterator {
+            inner: self.inner.clone(),
+            reverse: true,
+        }
+    }
+
+    fn __len__(slf: PyRef<'_, Self>) -> usize {
+        slf.inner.size()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let contents = self.inner.iter().map(|(k, v)| {
+            let tuple = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
+            Ok(format!("{:?}", tuple))
+        });
+        let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
+        Ok(format!("sorted_items_view([{}])", contents.join(", ")))
+    }
+}
+
+#[pyclass(name = "SortedSet", module = "rpds", frozen)]
+struct SortedSetPy {
+    inner: RedBlackTreeSetSync<SortedKey>,
+    key: Option<PyObject>,
+    hash: OnceLock<isize>,
+}
+
+impl<'source> FromPyObject<'source> for SortedSetPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        SortedSetPy::from_iterable(ob, None)
+    }
+}
+
+impl SortedSetPy {
+    fn evolver(&self, py: Python) -> SortedSetEvolver {
+        SortedSetEvolver {
+            inner: self.inner.clone(),
+            key: self.key(py),
+            finished: false,
+        }
+    }
+
+    fn from_iterable(ob: &Bound<'_, PyAny>, key: Option<&PyObject>) -> PyResult<Self> {
+        let py = ob.py();
+        let mut ret = RedBlackTreeSet::new_sync();
+        for each in ob.try_iter()? {
+            let k: SortedKey = each?.extract()?;
+            ret.insert_mut(k.keyed(key, py)?);
+            comparison_result(())?;
+        }
+        Ok(SortedSetPy {
+            hash: OnceLock::new(),
+            inner: ret,
+            key: key.map(|key| key.clone_ref(py)),
+        })
+    }
+
+    // Ready a value given from Python to be looked up in (or added to) this set.
+    fn key_for(&self, value: SortedKey, py: Python) -> PyResult<SortedKey> {
+        value.keyed(self.key.as_ref(), py)
+    }
+
+    // A set ordered the same way as this one, with the given contents.
+    fn with_inner(&self, inner: RedBlackTreeSetSync<SortedKey>, py: Python) -> Self {
+        SortedSetPy {
+            hash: OnceLock::new(),
+            inner,
+            key: self.key.as_ref().map(|key| key.clone_ref(py)),
+        }
+    }
+
+    // The elements of other, ordered the same way as this set so the two can
+    // be combined.
+    fn reordered(&self, other: &Self, py: Python) -> PyResult<Self> {
+        if same_key_function(&self.key, &other.key) {
+            return Ok(self.with_inner(other.inner.clone(), py));
+        }
+        let mut inner = RedBlackTreeSet::new_sync();
+        for value in other.inner.iter() {
+            inner.insert_mut(self.key_for(value.clone_ref(py), py)?);
+            comparison_result(())?;
+        }
+        Ok(self.with_inner(inner, py))
+    }
+}
+
+#[pymethods]
+impl SortedSetPy {
+    #[new]
+    #[pyo3(signature = (value=None, *, key=None))]
+    fn init(value: Option<Bound<'_, PyAny>>, key: Option<PyObject>) -> PyResult<Self> {
+        match value {
+            Some(value) => SortedSetPy::from_iterable(&value, key.as_ref()),
+            None => Ok(SortedSetPy {
+                hash: OnceLock::new(),
+                inner: RedBlackTreeSet::new_sync(),
+                key,
+            }),
+        }
+    }
+
+    /// The key function the set is ordered by, if any.
+    #[getter]
+    fn key(&self, py: Python) -> Option<PyObject> {
+        self.key.as_ref().map(|key| key.clone_ref(py))
+    }
+
+    fn __contains__(&self, key: SortedKey, py: Python) -> PyResult<bool> {
+        comparison_result(self.inner.contains(&self.key_for(key, py)?))
+    }
+
+    fn __and__(&self, other: &Self, py: Python) -> PyResult<Self> {
+        self.intersection(other, py)
+    }
+
+    fn __or__(&self, other: &Self, py: Python) -> PyResult<Self> {
+        self.union(other, py)
+    }
+
+    fn __sub__(&self, other: &Self, py: Python) -> PyResult<Self> {
+        self.difference(other, py)
+    }
+
+    fn __xor__(&self, other: &Self, py: Python) -> PyResult<Self> {
+        self.symmetric_difference(other, py)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> SortedSetIterator {
+        SortedSetIterator {
+            inner: slf.inner.clone(),
+            reverse: false,
+        }
+    }
+
+    fn __reversed__(&self) -> SortedSetIterator {
+        SortedSetIterator {
+            inner: self.inner.clone(),
+            reverse: true,
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let contents = self.inner.iter().map(|k| {
+            Ok(k.inner
+                .bind(py)
+                .repr()
+                .and_then(|r| r.extract())
+                .unwrap_or("<repr failed>".to_owned()))
+        });
+        let contents = contents.collect::<Result<Vec<String>, PyErr>>()?;
+        Ok(format!("SortedSet({{{}}})", contents.join(", ")))
+    }
+
+    fn __eq__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        if slf.as_ptr() == other.as_ptr() {
+            return Ok(true);
+        }
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? != slf.inner.size() {
+            return Ok(false);
+        }
+        for each in other.try_iter()? {
+            let contained = slf.inner.contains(&slf.key_for(each?.extract()?, py)?);
+            if !comparison_result(contained).unwrap_or(false) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        let hashes = self
+            .inner
+            .iter()
+            .map(|k| k.inner.bind(py).hash().map(|h| h as usize));
+        unordered_hash(hashes, self.inner.size(), &self.hash)
+    }
+
+    fn __lt__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? <= slf.inner.size() {
+            return Ok(false);
+        }
+        for each in slf.inner.iter() {
+            if !other.contains(each.inner.clone_ref(py))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __le__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? < slf.inner.size() {
+            return Ok(false);
+        }
+        for each in slf.inner.iter() {
+            if !other.contains(each.inner.clone_ref(py))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __gt__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? >= slf.inner.size() {
+            return Ok(false);
+        }
+        for each in other.try_iter()? {
+            let value = slf.key_for(each?.extract()?, py)?;
+            if !comparison_result(slf.inner.contains(&value))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __ge__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? > slf.inner.size() {
+            return Ok(false);
+        }
+        for each in other.try_iter()? {
+            let value = slf.key_for(each?.extract()?, py)?;
+            if !comparison_result(slf.inner.contains(&value))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> PyResult<(PyObject, (Vec<PyObject>,))> {
+        let py = slf.py();
+        let elements = slf.inner.iter().map(|e| e.inner.clone_ref(py)).collect();
+        Ok((sorted_constructor::<Self>(&slf.key, py)?, (elements,)))
+    }
+
+    #[getter]
+    fn first(&self, py: Python) -> PyResult<PyObject> {
+        self.inner
+            .first()
+            .map(|k| k.inner.clone_ref(py))
+            .ok_or_else(|| PyIndexError::new_err("empty set has no first element"))
+    }
+
+    #[getter]
+    fn last(&self, py: Python) -> PyResult<PyObject> {
+        self.inner
+            .last()
+            .map(|k| k.inner.clone_ref(py))
+            .ok_or_else(|| PyIndexError::new_err("empty set has no last element"))
+    }
+
+    #[pyo3(signature = (f=None))]
+    fn mutate(&self, f: Option<&Bound<'_, PyAny>>, py: Python) -> PyResult<PyObject> {
+        mutate_with(self.evolver(py), f, py)
+    }
+
+    /// The k smallest elements, smallest first.
+    fn nsmallest(&self, k: usize, py: Python) -> Vec<PyObject> {
+        self.inner
+            .iter()
+            .take(k)
+            .map(|e| e.inner.clone_ref(py))
+            .collect()
+    }
+
+    /// The k largest elements, largest first.
+    fn nlargest(&self, k: usize, py: Python) -> Vec<PyObject> {
+        self.inner
+            .iter()
+            .rev()
+            .take(k)
+            .map(|e| e.inner.clone_ref(py))
+            .collect()
+    }
+
+    /// The elements between min and max (either of which may be None to
+    /// leave that end unbounded), in order.
+    #[pyo3(signature = (min=None, max=None, inclusive=(true, true), reverse=false))]
+    fn irange(
+        &self,
+        min: Option<SortedKey>,
+        max: Option<SortedKey>,
+        inclusive: (bool, bool),
+        reverse: bool,
+        py: Python,
+    ) -> PyResult<SortedRangeIterator> {
+        let min = min.map(|value| self.key_for(value, py)).transpose()?;
+        let max = max.map(|value| self.key_for(value, py)).transpose()?;
+        let mut elements = Vec::new();
+        if let Some(range) = sorted_range(min, max, inclusive, py)? {
+            elements.extend(self.inner.range(range).map(|e| e.inner.clone_ref(py)));
+        }
+        comparison_result(())?;
+        if reverse {
+            elements.reverse();
+        }
+        Ok(SortedRangeIterator {
+            elements: elements.into_iter(),
+        })
+    }
+
+    fn insert(&self, value: SortedKey, py: Python) -> PyResult<SortedSetPy> {
+        let inner = self.inner.insert(self.key_for(value, py)?);
+        comparison_result(self.with_inner(inner, py))
+    }
+
+    fn discard(&self, value: SortedKey, py: Python) -> PyResult<SortedSetPy> {
+        let value = self.key_for(value, py)?;
+        match comparison_result(self.inner.contains(&value))? {
+            true => Ok(self.with_inner(self.inner.remove(&value), py)),
+            false => Ok(self.with_inner(self.inner.clone(), py)),
+        }
+    }
+
+    fn remove(&self, value: SortedKey, py: Python) -> PyResult<SortedSetPy> {
+        let value = self.key_for(value, py)?;
+        match comparison_result(self.inner.contains(&value))? {
+            true => Ok(self.with_inner(self.inner.remove(&value), py)),
+            false => Err(PyKeyError::new_err(value)),
+        }
+    }
+
+    fn difference(&self, other: &Self, py: Python) -> PyResult<SortedSetPy> {
+        let other = &self.reordered(other, py)?;
+        let mut inner = self.inner.clone();
+        for value in other.inner.iter() {
+            inner.remove_mut(value);
+        }
+        comparison_result(self.with_inner(inner, py))
+    }
+
+    fn intersection(&self, other: &Self, py: Python) -> PyResult<SortedSetPy> {
+        let other = &self.reordered(other, py)?;
+        let mut inner: RedBlackTreeSetSync<SortedKey> = RedBlackTreeSet::new_sync();
+        let larger: &RedBlackTreeSetSync<SortedKey>;
+        let iter;
+        if self.inner.size() > other.inner.size() {
+            larger = &self.inner;
+            iter = other.inner.iter();
+        } else {
+            larger = &other.inner;
+            iter = self.inner.iter();
+        }
+        for value in iter {
+            if larger.contains(value) {
+                inner.insert_mut(value.clone_ref(py));
+            }
+        }
+        comparison_result(self.with_inner(inner, py))
+    }
+
+    fn symmetric_difference(&self, other: &Self, py: Python) -> PyResult<SortedSetPy> {
+        let other = &self.reordered(other, py)?;
+        let mut inner: RedBlackTreeSetSync<SortedKey>;
+        let iter;
+        if self.inner.size() > other.inner.size() {
+            inner = self.inner.clone();
+            iter = other.inner.iter();
+        } else {
+            inner = other.inner.clone();
+            iter = self.inner.iter();
+        }
+        for value in iter {
+            if !inner.remove_mut(value) {
+                inner.insert_mut(value.clone_ref(py));
+            }
+        }
+        comparison_result(self.with_inner(inner, py))
+    }
+
+    fn union(&self, other: &Self, py: Python) -> PyResult<SortedSetPy> {
+        let other = &self.reordered(other, py)?;
+        let mut inner: RedBlackTreeSetSync<SortedKey>;
+        let iter;
+        if self.inner.size() > other.inner.size() {
+            inner = self.inner.clone();
+            iter = other.inner.iter();
+        } else {
+            inner = other.inner.clone();
+            iter = self.inner.iter();
+        }
+        for value in iter {
+            inner.insert_mut(value.clone_ref(py));
+        }
+        comparison_result(self.with_inner(inner, py))
+    }
+
+    #[pyo3(signature = (*iterables))]
+    fn update(&self, iterables: Bound<'_, PyTuple>) -> PyResult<SortedSetPy> {
+        let py = iterables.py();
+        let mut inner = self.inner.clone();
+        for each in iterables {
+            let iter = each.try_iter()?;
+            for value in iter {
+                inner.insert_mut(self.key_for(value?.extract()?, py)?);
+                comparison_result(())?;
+            }
+        }
+        Ok(self.with_inner(inner, py))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct SortedSetEvolver {
+    inner: RedBlackTreeSetSync<SortedKey>,
+    key: Option<PyObject>,
+    finished: bool,
+}
+
+impl Evolver for SortedSetEvolver {
+    type Persistent = SortedSetPy;
+
+    fn finish(&mut self, py: Python) -> SortedSetPy {
+        self.finished = true;
+        self.persistent(py)
+    }
+}
+
+#[pymethods]
+impl SortedSetEvolver {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.finished |= exc_type.is_none();
+        false
+    }
+
+    fn __contains__(&self, value: SortedKey, py: Python) -> PyResult<bool> {
+        let value = value.keyed(self.key.as_ref(), py)?;
+        comparison_result(self.inner.contains(&value))
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn add(&mut self, value: SortedKey, py: Python) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        let value = value.keyed(self.key.as_ref(), py)?;
+        let mut inner = self.inner.clone();
+        inner.insert_mut(value);
+        self.inner = comparison_result(inner)?;
+        Ok(())
+    }
+
+    fn discard(&mut self, value: SortedKey, py: Python) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        let value = value.keyed(self.key.as_ref(), py)?;
+        let mut inner = self.inner.clone();
+        inner.remove_mut(&value);
+        self.inner = comparison_result(inner)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, value: SortedKey, py: Python) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        let value = value.keyed(self.key.as_ref(), py)?;
+        let mut inner = self.inner.clone();
+        match comparison_result(inner.remove_mut(&value))? {
+            true => {
+                self.inner = inner;
+                Ok(())
+            }
+            false => Err(PyKeyError::new_err(value)),
+        }
+    }
+
+    fn persistent(&self, py: Python) -> SortedSetPy {
+        SortedSetPy {
+            hash: OnceLock::new(),
+            inner: self.inner.clone(),
+            key: self.key.as_ref().map(|key| key.clone_ref(py)),
+        }
+    }
+
+    /// The set as it stands, which is final once the with block has ended.
+    #[getter]
+    fn result(&self, py: Python) -> SortedSetPy {
+        self.persistent(py)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct SortedSetIterator {
+    inner: RedBlackTreeSetSync<SortedKey>,
+    reverse: bool,
+}
+
+#[pymethods]
+impl SortedSetIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<Option<PyObject>> {
+        let next = match slf.reverse {
+            true => slf.inner.last(),
+            false => slf.inner.first(),
+        };
+        let Some(first) = next else {
+            return Ok(None);
+        };
+        let first = first.clone_ref(slf.py());
+        slf.inner.remove_mut(&first);
+        comparison_result(Some(first.inner))
+    }
+}
+
+#[pyclass(name = "Bag", module = "rpds", frozen)]
+struct BagPy {
+    inner: HashTrieMapSync<Key, usize>,
+    size: usize,
+    hash: OnceLock<isize>,
+}
+
+impl<'source> FromPyObject<'source> for BagPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let mut ret = BagPy {
+            hash: OnceLock::new(),
+            inner: HashTrieMap::new_sync(),
+            size: 0,
+        };
+        for each in ob.try_iter()? {
+            ret.add_mut(each?.extract()?);
+        }
+        Ok(ret)
+    }
+}
+
+impl BagPy {
+    fn evolver(&self, _py: Python) -> BagEvolver {
+        BagEvolver {
+            inner: self.inner.clone(),
+            size: self.size,
+            finished: false,
+        }
+    }
+
+    fn add_mut(&mut self, value: Key) {
+        let count = self.inner.get(&value).copied().unwrap_or(0);
+        self.inner.insert_mut(value, count + 1);
+        self.size += 1;
+    }
+}
+
+#[pymethods]
+impl BagPy {
+    #[new]
+    #[pyo3(signature = (value=None))]
+    fn init(value: Option<BagPy>) -> Self {
+        if let Some(value) = value {
+            value
+        } else {
+            BagPy {
+                hash: OnceLock::new(),
+                inner: HashTrieMap::new_sync(),
+                size: 0,
+            }
+        }
+    }
+
+    fn __contains__(&self, value: Key) -> bool {
+        self.inner.contains_key(&value)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> BagIterator {
+        BagIterator {
+            inner: slf.inner.clone(),
+            current: None,
+            remaining: 0,
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.size
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let mut contents = Vec::with_capacity(self.size);
+        for (k, count) in self.inner.iter() {
+            let repr = k
+                .inner
+                .bind(py)
+                .repr()
+                .and_then(|r| r.extract())
+                .unwrap_or("<repr failed>".to_owned());
+            contents.extend(std::iter::repeat_n(repr, *count));
+        }
+        Ok(format!("Bag([{}])", contents.join(", ")))
+    }
+
+    fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python<'_>) -> PyResult<PyObject> {
+        let eq = self.size == other.size
+            && self.inner.size() == other.inner.size()
+            && self
+                .inner
+                .iter()
+                .all(|(k, count)| other.inner.get(k) == Some(count));
+        match op {
+            CompareOp::Eq => eq
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            CompareOp::Ne => (!eq)
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            _ => Ok(py.NotImplemented()),
+        }
+    }
+
+    fn __hash__(&self) -> PyResult<isize> {
+        let hashes = self.inner.iter().map(|(key, count)| {
+            let mut hasher = DefaultHasher::new();
+            hasher.write_isize(key.hash);
+            hasher.write_usize(*count);
+            Ok(hasher.finish() as usize)
+        });
+        unordered_hash(hashes, self.size, &self.hash)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<Key>,)) {
+        let mut elements = Vec::with_capacity(slf.size);
+        for (k, count) in slf.inner.iter() {
+            for _ in 0..*count {
+                elements.push(k.clone_ref(slf.py()));
+            }
+        }
+        (BagPy::type_object(slf.py()), (elements,))
+    }
+
+    #[pyo3(signature = (f=None))]
+    fn mutate(&self, f: Option<&Bound<'_, PyAny>>, py: Python) -> PyResult<PyObject> {
+        mutate_with(self.evolver(py), f, py)
+    }
+
+    fn count(&self, value: Key) -> usize {
+        self.inner.get(&value).copied().unwrap_or(0)
+    }
+
+    fn add(&self, value: Key) -> BagPy {
+        let mut ret = BagPy {
+            hash: OnceLock::new(),
+            inner: self.inner.clone(),
+            size: self.size,
+        };
+        ret.add_mut(value);
+        ret
+    }
+
+    fn remove(&self, value: Key) -> PyResult<BagPy> {
+        match self.inner.get(&value) {
+            Some(1) => Ok(BagPy {
+                hash: OnceLock::new(),
+                inner: self.inner.remove(&value),
+                size: self.size - 1,
+            }),
+            Some(count) => Ok(BagPy {
+                hash: OnceLock::new(),
+                inner: self.inner.insert(value, count - 1),
+                size: self.size - 1,
+            }),
+            None => Err(PyKeyError::new_err(value)),
+        }
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct BagEvolver {
+    inner: HashTrieMapSync<Key, usize>,
+    size: usize,
+    finished: bool,
+}
+
+impl Evolver for BagEvolver {
+    type Persistent = BagPy;
+
+    fn finish(&mut self, py: Python) -> BagPy {
+        self.finished = true;
+        self.persistent(py)
+    }
+}
+
+#[pymethods]
+impl BagEvolver {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.finished |= exc_type.is_none();
+        false
+    }
+
+    fn __contains__(&self, value: Key) -> bool {
+        self.inner.contains_key(&value)
+    }
+
+    fn __len__(&self) -> usize {
+        self.size
+    }
+
+    fn count(&self, value: Key) -> usize {
+        self.inner.get(&value).copied().unwrap_or(0)
+    }
+
+    fn add(&mut self, value: Key) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        let count = self.inner.get(&value).copied().unwrap_or(0);
+        self.inner.insert_mut(value, count + 1);
+        self.size += 1;
+        Ok(())
+    }
+
+    fn remove(&mut self, value: Key) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        match self.inner.get(&value).copied() {
+            Some(1) => {
+                self.inner.remove_mut(&value);
+            }
+            Some(count) => self.inner.insert_mut(value, count - 1),
+            None => return Err(PyKeyError::new_err(value)),
+        }
+        self.size -= 1;
+        Ok(())
+    }
+
+    fn persistent(&self, _py: Python) -> BagPy {
+        BagPy {
+            hash: OnceLock::new(),
+            inner: self.inner.clone(),
+            size: self.size,
+        }
+    }
+
+    /// The bag as it stands, which is final once the with block has ended.
+    #[getter]
+    fn result(&self, py: Python) -> BagPy {
+        self.persistent(py)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct BagIterator {
+    inner: HashTrieMapSync<Key, usize>,
+    current: Option<Key>,
+    remaining: usize,
+}
+
+#[pymethods]
+impl BagIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<Key> {
+        let py = slf.py();
+        if slf.remaining == 0 {
+            let (first, count) = slf.inner.iter().next()?;
+            let (first, count) = (first.clone_ref(py), *count);
+            slf.inner.remove_mut(&first);
+            slf.current = Some(first);
+            slf.remaining = count;
+        }
+        slf.remaining -= 1;
+        slf.current.as_ref().map(|k| k.clone_ref(py))
+    }
+}
+
+#[pyclass(name = "OrderedMap", module = "rpds", frozen, mapping)]
+struct OrderedMapPy {
+    entries: HashTrieMapSync<Key, (u64, PyObject)>,
+    order: RedBlackTreeMapSync<u64, Key>,
+    next: u64,
+    hash: OnceLock<isize>,
+}
+
+impl<'source> FromPyObject<'source> for OrderedMapPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let mut ret = OrderedMapPy::new();
+        if let Ok(mapping) = ob.downcast::<PyMapping>() {
+            for each in mapping.items()?.iter() {
+                let (k, v): (Key, PyObject) = each.extract()?;
+                ret.insert_mut(k, v, ob.py());
+            }
+        } else {
+            for each in ob.try_iter()? {
+                let (k, v) = each?.extract()?;
+                ret.insert_mut(k, v, ob.py());
+            }
+        }
+        Ok(ret)
+    }
+}
+
+impl OrderedMapPy {
+    fn evolver(&self, _py: Python) -> OrderedMapEvolver {
+        OrderedMapEvolver {
+            map: self.clone_map(),
+            finished: false,
+        }
+    }
+
+    fn new() -> Self {
+        OrderedMapPy {
+            hash: OnceLock::new(),
+            entries: HashTrieMap::new_sync(),
+            order: RedBlackTreeMap::new_sync(),
+            next: 0,
+        }
+    }
+
+    fn clone_map(&self) -> Self {
+        OrderedMapPy {
+            hash: OnceLock::new(),
+            entries: self.entries.clone(),
+            order: self.order.clone(),
+            next: self.next,
+        }
+    }
+
+    // Overwriting an existing key keeps its original position, as dict does.
+    fn insert_mut(&mut self, key: Key, value: PyObject, py: Python<'_>) {
+        let seq = match self.entries.get(&key) {
+            Some((seq, _)) => *seq,
+            None => {
+                let seq = self.next;
+                self.order.insert_mut(seq, key.clone_ref(py));
+                self.next += 1;
+                seq
+            }
+        };
+        self.entries.insert_mut(key, (seq, value));
+    }
+
+    fn remove_mut(&mut self, key: &Key) -> bool {
+        match self.entries.get(key) {
+            Some((seq, _)) => {
+                self.order.remove_mut(seq);
+                self.entries.remove_mut(key)
+            }
+            None => false,
+        }
+    }
+}
+
+#[pymethods]
+impl OrderedMapPy {
+    #[new]
+    #[pyo3(signature = (value=None, ** kwds))]
+    fn init(value: Option<OrderedMapPy>, kwds: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut map = value.unwrap_or_else(OrderedMapPy::new);
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                map.insert_mut(Key::extract_bound(&k)?, v.into(), kwds.py());
+            }
+        }
+        Ok(map)
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.entries.contains_key(&key)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> OrderedKeysIterator {
+        OrderedKeysIterator {
+            order: slf.order.clone(),
+        }
+    }
+
+    fn __getitem__(&self, key: Key, py: Python) -> PyResult<PyObject> {
+        match self.entries.get(&key) {
+            Some((_, value)) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.entries.size()
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        let contents = self.order.values().map(|k| {
+            let (_, v) = &self.entries[k];
+            format!(
+                "{}: {}",
+                k.inner
+                    .call_method0(py, "__repr__")
+                    .and_then(|r| r.extract(py))
+                    .unwrap_or("<repr error>".to_owned()),
+                v.call_method0(py, "__repr__")
+                    .and_then(|r| r.extract(py))
+                    .unwrap_or("<repr error>".to_owned())
+            )
+        });
+        format!(
+            "OrderedMap({{{}}})",
+            contents.collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    fn __richcmp__<'py>(
+        &self,
+        other: &Bound<'py, PyAny>,
+        op: CompareOp,
+        py: Python<'py>,
+    ) -> PyResult<PyObject> {
+        let Ok(other) = other.downcast::<OrderedMapPy>() else {
+            let entries = self
+                .entries
+                .iter()
+                .map(|(k, (_, v))| (k.inner.bind(py).clone(), v));
+            return mapping_richcmp(entries, self.entries.size(), other, op, py);
+        };
+        let other = other.get();
+        let eq = std::ptr::eq(self, other)
+            || (self.entries.size() == other.entries.size()
+                && self
+                    .entries
+                    .iter()
+                    .map(|(k1, (_, v1))| (v1, other.entries.get(k1).map(|(_, v2)| v2)))
+                    .map(|(v1, v2)| v2.map_or(Ok(false), |v2| elements_eq(v1, v2, py)))
+                    .find(|r| !matches!(r, Ok(true)))
+                    .unwrap_or(Ok(true))?);
+        match op {
+            CompareOp::Eq => eq
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            CompareOp::Ne => (!eq)
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            _ => Ok(py.NotImplemented()),
+        }
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        let hashes = self.entries.iter().map(|(key, (_, val))| {
+            let mut hasher = DefaultHasher::new();
+            let val_bound = val.bind(py);
+
+            let key_hash = key.hash;
+            let val_hash = val_bound.hash().map_err(|_| {
+                PyTypeError::new_err(format!(
+                    "Unhashable type in OrderedMap of key {}: {}",
+                    key.inner
+                        .bind(py)
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string()),
+                    val_bound
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string())
+                ))
+            })?;
+
+            hasher.write_isize(key_hash);
+            hasher.write_isize(val_hash);
+
+            Ok(hasher.finish() as usize)
+        });
+        unordered_hash(hashes, self.entries.size(), &self.hash)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<(Key, PyObject)>,)) {
+        (
+            OrderedMapPy::type_object(slf.py()),
+            (slf.order
+                .values()
+                .map(|k| (k.clone_ref(slf.py()), slf.entries[k].1.clone_ref(slf.py())))
+                .collect(),),
+        )
+    }
+
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, key: Key, default: Option<PyObject>, py: Python) -> Option<PyObject> {
+        if let Some((_, value)) = self.entries.get(&key) {
+            Some(value.clone_ref(py))
+        } else {
+            default
+        }
+    }
+
+    fn keys(&self) -> OrderedKeysView {
+        OrderedKeysView {
+            map: self.clone_map(),
+        }
+    }
+
+    fn values(&self) -> OrderedValuesView {
+        OrderedValuesView {
+            map: self.clone_map(),
+        }
+    }
+
+    fn items(&self) -> OrderedItemsView {
+        OrderedItemsView {
+            map: self.clone_map(),
+        }
+    }
+
+    #[pyo3(signature = (f=None))]
+    fn mutate(&self, f: Option<&Bound<'_, PyAny>>, py: Python) -> PyResult<PyObject> {
+        mutate_with(self.evolver(py), f, py)
+    }
+
+    fn discard(&self, key: Key) -> OrderedMapPy {
+        let mut map = self.clone_map();
+        map.remove_mut(&key);
+        map
+    }
+
+    fn insert(&self, key: Key, value: Bound<'_, PyAny>) -> OrderedMapPy {
+        let mut map = self.clone_map();
+        let py = value.py();
+        map.insert_mut(key, value.unbind(), py);
+        map
+    }
+
+    fn remove(&self, key: Key) -> PyResult<OrderedMapPy> {
+        let mut map = self.clone_map();
+        match map.remove_mut(&key) {
+            true => Ok(map),
+            false => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    #[pyo3(signature = (*maps, **kwds))]
+    fn update(
+        &self,
+        maps: &Bound<'_, PyTuple>,
+        kwds: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<OrderedMapPy> {
+        let py = maps.py();
+        let mut map = self.clone_map();
+        for value in maps {
+            let other = OrderedMapPy::extract_bound(&value)?;
+            for k in other.order.values() {
+                map.insert_mut(k.clone_ref(py), other.entries[k].1.clone_ref(py), py);
+            }
+        }
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                map.insert_mut(Key::extract_bound(&k)?, v.extract()?, py);
+            }
+        }
+        Ok(map)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct OrderedMapEvolver {
+    map: OrderedMapPy,
+    finished: bool,
+}
+
+impl Evolver for OrderedMapEvolver {
+    type Persistent = OrderedMapPy;
+
+    fn finish(&mut self, py: Python) -> OrderedMapPy {
+        self.finished = true;
+        self.persistent(py)
+    }
+}
+
+#[pymethods]
+impl OrderedMapEvolver {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.finished |= exc_type.is_none();
+        false
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.map.entries.contains_key(&key)
+    }
+
+    fn __getitem__(&self, key: Key, py: Python) -> PyResult<PyObject> {
+        match self.map.entries.get(&key) {
+            Some((_, value)) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __setitem__(&mut self, key: Key, value: PyObject, py: Python) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        self.map.insert_mut(key, value, py);
+        Ok(())
+    }
+
+    fn __delitem__(&mut self, key: Key) -> PyResult<()> {
+        ensure_unfinished(self.finished)?;
+        match self.map.remove_mut(&key) {
+            true => Ok(()),
+            false => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.map.entries.size()
+    }
+
+    fn persistent(&self, _py: Python) -> OrderedMapPy {
+        self.map.clone_map()
+    }
+
+    /// The map as it stands, which is final once the with block has ended.
+    #[getter]
+    fn result(&self, py: Python) -> OrderedMapPy {
+        self.persistent(py)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct OrderedKeysIterator {
+    order: RedBlackTreeMapSync<u64, Key>,
+}
+
+#[pymethods]
+impl OrderedKeysIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<Key> {
+        let (seq, first) = slf.order.first()?;
+        let (seq, first) = (*seq, first.clone_ref(slf.py()));
+        slf.order.remove_mut(&seq);
+        Some(first)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct OrderedValuesIterator {
+    map: OrderedMapPy,
+}
+
+#[pymethods]
+impl OrderedValuesIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
+        let (seq, first) = slf.map.order.first()?;
+        let seq = *seq;
+        let value = slf.map.entries[first].1.clone_ref(slf.py());
+        slf.map.order.remove_mut(&seq);
+        Some(value)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct OrderedItemsIterator {
+    map: OrderedMapPy,
+}
+
+#[pymethods]
+impl OrderedItemsIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<(Key, PyObject)> {
+        let (seq, first) = slf.map.order.first()?;
+        let seq = *seq;
+        let key = first.clone_ref(slf.py());
+        let value = slf.map.entries[first].1.clone_ref(slf.py());
+        slf.map.order.remove_mut(&seq);
+        Some((key, value))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct OrderedKeysView {
+    map: OrderedMapPy,
+}
+
+#[pymethods]
+impl OrderedKeysView {
+    fn __contains__(&self, key: Key) -> bool {
+        self.map.entries.contains_key(&key)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> OrderedKeysIterator {
+        OrderedKeysIterator {
+            order: slf.map.order.clone(),
+        }
+    }
+
+    fn __len__(slf: PyRef<'_, Self>) -> usize {
+        slf.map.entries.size()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let contents = self.map.order.values().map(|k| {
+            Ok(k.inner
+                .bind(py)
+                .repr()
+                .and_then(|r| r.extract())
+                .unwrap_or("<repr failed>".to_owned()))
+        });
+        let contents = contents.collect::<Result<Vec<String>, PyErr>>()?;
+        Ok(format!("ordered_keys_view([{}])", contents.join(", ")))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct OrderedValuesView {
+    map: OrderedMapPy,
+}
+
+#[pymethods]
+impl OrderedValuesView {
+    fn __iter__(slf: PyRef<'_, Self>) -> OrderedValuesIterator {
+        OrderedValuesIterator {
+            map: slf.map.clone_map(),
+        }
+    }
+
+    fn __len__(slf: PyRef<'_, Self>) -> usize {
+        slf.map.entries.size()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let contents = self.map.order.values().map(|k| {
+            Ok(self.map.entries[k]
+                .1
+                .bind(py)
+                .repr()
+                .and_then(|r| r.extract())
+                .unwrap_or("<repr failed>".to_owned()))
+        });
+        let contents = contents.collect::<Result<Vec<String>, PyErr>>()?;
+        Ok(format!("ordered_values_view([{}])", contents.join(", ")))
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct OrderedItemsView {
+    map: OrderedMapPy,
+}
+
+#[pymethods]
+impl OrderedItemsView {
+    fn __contains__(slf: PyRef<'_, Self>, item: ItemViewQuery) -> PyResult<bool> {
+        if let Some((_, value)) = slf.map.entries.get(&item.0) {
+            return item.1.bind(slf.py()).eq(value);
+        }
+
+        Ok(false)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> OrderedItemsIterator {
+        OrderedItemsIterator {
+            map: slf.map.clone_map(),
+        }
+    }
+
+    fn __len__(slf: PyRef<'_, Self>) -> usize {
+        slf.map.entries.size()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let contents = self.map.order.values().map(|k| {
+            let value = self.map.entries[k].1.clone_ref(py);
+            let tuple = PyTuple::new(py, [k.inner.clone_ref(py), value])?;
+            Ok(format!("{:?}", tuple))
+        });
+        let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
+        Ok(format!("ordered_items_view([{}])", contents.join(", ")))
+    }
+}
+
+create_exception!(
+    rpds,
+    InvariantError,
+    PyValueError,
+    "A record field was given a value its Field spec doesn't allow."
+);
+
+// Raised with the offending field and value attached, so callers can tell
+// which one failed without parsing the message.
+fn invariant_error(
+    message: String,
+    field: &Bound<'_, PyString>,
+    value: &Bound<'_, PyAny>,
+) -> PyErr {
+    let err = InvariantError::new_err(message);
+    let instance = err.value(value.py());
+    if let Err(err) = instance
+        .setattr("field", field)
+        .and_then(|_| instance.setattr("value", value))
+    {
+        return err;
+    }
+    err
+}
+
+/// The spec for a record field, giving it a default and checking the values
+/// it may be given against a type (or tuple of types) and an invariant.
+#[pyclass(name = "Field", module = "rpds", frozen)]
+struct FieldPy {
+    r#type: Option<PyObject>,
+    invariant: Option<PyObject>,
+    default: Option<PyObject>,
+}
+
+impl FieldPy {
+    fn validate(
+        &self,
+        cls: &Bound<'_, PyType>,
+        field: &Bound<'_, PyString>,
+        value: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        let py = value.py();
+        if let Some(expected) = &self.r#type {
+            if !value.is_instance(expected.bind(py))? {
+                return Err(invariant_error(
+                    format!(
+                        "{}.{} must be an instance of {}, not {}",
+                        cls.name()?,
+                        field,
+                        expected.bind(py).repr()?,
+                        value.repr()?,
+                    ),
+                    field,
+                    value,
+                ));
+            }
+        }
+        if let Some(invariant) = &self.invariant {
+            if !invariant.bind(py).call1((value,))?.is_truthy()? {
+                return Err(invariant_error(
+                    format!(
+                        "{}.{} value {} does not satisfy its invariant",
+                        cls.name()?,
+                        field,
+                        value.repr()?,
+                    ),
+                    field,
+                    value,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl FieldPy {
+    // default is taken from kwds so that a default of None can be told apart
+    // from there being no default at all.
+    #[new]
+    #[pyo3(signature = (*, r#type=None, invariant=None, **kwds))]
+    fn init(
+        r#type: Option<PyObject>,
+        invariant: Option<PyObject>,
+        kwds: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let mut default = None;
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                if k.extract::<String>()? != "default" {
+                    return Err(PyTypeError::new_err(format!(
+                        "Field() got an unexpected keyword argument {}",
+                        k.repr()?
+                    )));
+                }
+                default = Some(v.unbind());
+            }
+        }
+        Ok(FieldPy {
+            r#type,
+            invariant,
+            default,
+        })
+    }
+}
+
+// Carries already-validated fields through `__new__` when a record derives a
+// new instance of its own (sub)class. Deliberately not exposed in the module.
+#[pyclass(module = "rpds", frozen)]
+struct RecordFields {
+    inner: HashTrieMapSync<Key, PyObject>,
+}
+
+#[pyclass(name = "Record", module = "rpds", frozen, subclass)]
+struct RecordPy {
+    inner: HashTrieMapSync<Key, PyObject>,
+    hash: OnceLock<isize>,
+}
+
+// The builtin equivalent of a value found in a record, recursively, so that
+// records (and the persistent collections within them) come out as plain
+// dicts, lists and sets.
+fn thaw(value: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let py = value.py();
+    if let Ok(record) = value.downcast::<RecordPy>() {
+        return Ok(RecordPy::to_dict(record)?.into_any().unbind());
+    }
+    if value.is_instance_of::<PyString>() {
+        return Ok(value.clone().unbind());
+    }
+    if value.is_instance_of::<PyTuple>() {
+        let thawed = value
+            .try_iter()?
+            .map(|each| thaw(&each?))
+            .collect::<PyResult<Vec<_>>>()?;
+        return Ok(PyTuple::new(py, thawed)?.into_any().unbind());
+    }
+    let abc = PyModule::import(py, "collections.abc")?;
+    if value.is_instance(&abc.getattr("Mapping")?)? {
+        let dict = PyDict::new(py);
+        for each in value.downcast::<PyMapping>()?.items()?.iter() {
+            let (k, v): (Bound<'_, PyAny>, Bound<'_, PyAny>) = each.extract()?;
+            dict.set_item(k, thaw(&v)?)?;
+        }
+        return Ok(dict.into_any().unbind());
+    }
+    if value.is_instance(&abc.getattr("Set")?)? {
+        return Ok(
+            PySet::new(py, value.try_iter()?.collect::<PyResult<Vec<_>>>()?)?
+                .into_any()
+                .unbind(),
+        );
+    }
+    if value.is_instance_of::<PyList>()
+        || value.is_instance_of::<ListPy>()
+        || value.is_instance_of::<VectorPy>()
+        || value.is_instance_of::<QueuePy>()
+    {
+        let thawed = value
+            .try_iter()?
+            .map(|each| thaw(&each?))
+            .collect::<PyResult<Vec<_>>>()?;
+        return Ok(PyList::new(py, thawed)?.into_any().unbind());
+    }
+    Ok(value.clone().unbind())
+}
+
+// The reverse of thaw for a single field, using the type its spec expects to
+// rebuild nested records and collections from plain dicts, lists and sets.
+// Anything else is left alone for the spec to accept or reject.
+fn revive<'py>(value: Bound<'py, PyAny>, spec: Option<&FieldPy>) -> PyResult<Bound<'py, PyAny>> {
+    let py = value.py();
+    let Some(expected) = spec.and_then(|spec| spec.r#type.as_ref()) else {
+        return Ok(value);
+    };
+    let Ok(expected) = expected.bind(py).downcast::<PyType>() else {
+        return Ok(value);
+    };
+    let builtin = value.is_instance_of::<PyDict>()
+        || value.is_instance_of::<PyList>()
+        || value.is_instance_of::<PySet>();
+    if !builtin || value.is_instance(expected)? {
+        return Ok(value);
+    }
+    match expected.is_subclass_of::<RecordPy>()? {
+        true => expected.call_method1("from_dict", (value,)),
+        false => expected.call1((value,)),
+    }
+}
+
+type FieldSpec<'py> = (Bound<'py, PyString>, Option<Bound<'py, FieldPy>>);
+
+impl RecordPy {
+    fn field_names<'py>(cls: &Bound<'py, PyType>) -> PyResult<Vec<Bound<'py, PyString>>> {
+        let Ok(fields) = cls.getattr("_fields") else {
+            return Err(PyTypeError::new_err(format!(
+                "{} does not declare any _fields",
+                cls.name()?
+            )));
+        };
+        fields
+            .try_iter()?
+            .map(|each| Ok(each?.downcast_into::<PyString>()?))
+            .collect()
+    }
+
+    // Each field alongside its spec, which only records declaring _fields as
+    // a mapping (of names to a Field or None) have.
+    fn field_specs<'py>(cls: &Bound<'py, PyType>) -> PyResult<Vec<FieldSpec<'py>>> {
+        let names = RecordPy::field_names(cls)?;
+        let fields = cls.getattr("_fields")?;
+        let Ok(specs) = fields.downcast::<PyMapping>() else {
+            return Ok(names.into_iter().map(|name| (name, None)).collect());
+        };
+        names
+            .into_iter()
+            .map(|name| {
+                let spec = specs.get_item(&name)?;
+                match spec.is_none() {
+                    true => Ok((name, None)),
+                    false => Ok((name, Some(spec.downcast_into::<FieldPy>()?))),
+                }
+            })
+            .collect()
+    }
+
+    fn derive<'py>(
+        slf: &Bound<'py, Self>,
+        inner: HashTrieMapSync<Key, PyObject>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let cls = slf.get_type();
+        cls.call_method1("__new__", (&cls, RecordFields { inner }))
+    }
+}
+
+#[pymethods]
+impl RecordPy {
+    #[new]
+    #[classmethod]
+    #[pyo3(signature = (*args, **kwds))]
+    fn init(
+        cls: &Bound<'_, PyType>,
+        args: &Bound<'_, PyTuple>,
+        kwds: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        if let Ok((fields,)) = args.extract::<(Bound<'_, RecordFields>,)>() {
+            return Ok(RecordPy {
+                hash: OnceLock::new(),
+                inner: fields.get().inner.clone(),
+            });
+        }
+        let name = cls.name()?;
+        if !args.is_empty() {
+            return Err(PyTypeError::new_err(format!(
+                "{} takes only keyword arguments",
+                name
+            )));
+        }
+        let fields = RecordPy::field_specs(cls)?;
+        let mut inner = HashTrieMap::new_sync();
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                let key = Key::extract_bound(&k)?;
+                if !fields
+                    .iter()
+                    .any(|(field, _)| field.as_any().eq(&k).unwrap_or(false))
+                {
+                    return Err(PyTypeError::new_err(format!(
+                        "{} got an unexpected field {}",
+                        name,
+                        k.repr()?
+                    )));
+                }
+                inner.insert_mut(key, v.unbind());
+            }
+        }
+        for (field, spec) in &fields {
+            let key = Key::extract_bound(field.as_any())?;
+            let spec = spec.as_ref().map(Bound::get);
+            let value = match (inner.get(&key), spec.and_then(|spec| spec.default.as_ref())) {
+                (Some(value), _) => value.clone_ref(cls.py()),
+                (None, Some(default)) => {
+                    inner.insert_mut(key, default.clone_ref(cls.py()));
+                    default.clone_ref(cls.py())
+                }
+                (None, None) => {
+                    return Err(PyTypeError::new_err(format!(
+                        "{} is missing field {}",
+                        name,
+                        field.repr()?
+                    )))
+                }
+            };
+            if let Some(spec) = spec {
+                spec.validate(cls, field, value.bind(cls.py()))?;
+            }
+        }
+        Ok(RecordPy {
+            inner,
+            hash: OnceLock::new(),
+        })
+    }
+
+    fn __getattr__(slf: &Bound<'_, Self>, name: Bound<'_, PyString>) -> PyResult<PyObject> {
+        match slf.get().inner.get(&Key::extract_bound(name.as_any())?) {
+            Some(value) => Ok(value.clone_ref(slf.py())),
+            None => Err(PyAttributeError::new_err(format!(
+                "{} has no field {}",
+                slf.get_type().name()?,
+                name.repr()?
+            ))),
+        }
+    }
+
+    fn __setattr__(&self, name: &str, _value: PyObject) -> PyResult<()> {
+        Err(PyAttributeError::new_err(format!(
+            "cannot assign to field {:?} of an immutable record",
+            name
+        )))
+    }
+
+    fn __delattr__(&self, name: &str) -> PyResult<()> {
+        Err(PyAttributeError::new_err(format!(
+            "cannot delete field {:?} of an immutable record",
+            name
+        )))
+    }
+
+    fn __repr__(slf: &Bound<'_, Self>) -> PyResult<String> {
+        let py = slf.py();
+        let cls = slf.get_type();
+        let contents = RecordPy::field_names(&cls)?
+            .iter()
+            .map(|field| {
+                let value = slf
+                    .get()
+                    .inner
+                    .get(&Key::extract_bound(field.as_any())?)
+                    .map(|value| value.bind(py).repr())
+                    .transpose()?;
+                Ok(match value {
+                    Some(value) => format!("{}={}", field, value),
+                    None => format!("{}=<unset>", field),
+                })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(format!("{}({})", cls.name()?, contents.join(", ")))
+    }
+
+    fn __richcmp__(
+        slf: &Bound<'_, Self>,
+        other: &Bound<'_, PyAny>,
+        op: CompareOp,
+        py: Python<'_>,
+    ) -> PyResult<PyObject> {
+        let Ok(other) = other.downcast::<RecordPy>() else {
+            return Ok(py.NotImplemented());
+        };
+        let (this, that) = (&slf.get().inner, &other.get().inner);
+        let eq = || -> PyResult<bool> {
+            if slf.is(other) {
+                return Ok(true);
+            }
+            if !slf.get_type().is(&other.get_type()) || this.size() != that.size() {
+                return Ok(false);
+            }
+            for (k, v1) in this.iter() {
+                match that.get(k) {
+                    Some(v2) if elements_eq(v1, v2, py)? => continue,
+                    _ => return Ok(false),
+                }
+            }
+            Ok(true)
+        };
+        match op {
+            CompareOp::Eq => eq()?
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            CompareOp::Ne => (!eq()?)
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            _ => Ok(py.NotImplemented()),
+        }
+    }
+
+    fn __hash__(slf: &Bound<'_, Self>) -> PyResult<isize> {
+        if let Some(hash) = slf.get().hash.get() {
+            return Ok(*hash);
+        }
+        let hash = HashTrieMapPy::from(slf.get().inner.clone()).__hash__(slf.py())?;
+        Ok(*slf.get().hash.get_or_init(|| hash))
+    }
+
+    fn __getnewargs_ex__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(Bound<'py, PyTuple>, Bound<'py, PyDict>)> {
+        let py = slf.py();
+        let kwds = PyDict::new(py);
+        for (k, v) in slf.get().inner.iter() {
+            kwds.set_item(k.inner.bind(py), v.bind(py))?;
+        }
+        Ok((PyTuple::empty(py), kwds))
+    }
+
+    fn set<'py>(
+        slf: &Bound<'py, Self>,
+        field: Key,
+        value: PyObject,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let inner = &slf.get().inner;
+        if !inner.contains_key(&field) {
+            return Err(PyAttributeError::new_err(format!(
+                "{} has no field {}",
+                slf.get_type().name()?,
+                field.inner.bind(slf.py()).repr()?
+            )));
+        }
+        let cls = slf.get_type();
+        for (name, spec) in RecordPy::field_specs(&cls)? {
+            if let Some(spec) = spec.filter(|_| name.as_any().eq(&field.inner).unwrap_or(false)) {
+                spec.get().validate(&cls, &name, value.bind(slf.py()))?;
+            }
+        }
+        RecordPy::derive(slf, inner.insert(field, value))
+    }
+
+    /// The record's fields as a dict, with any nested records and persistent
+    /// collections converted to dicts, lists and sets as well. Passing this as
+    /// the default of json.dumps makes records serializable.
+    fn to_dict<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyDict>> {
+        let py = slf.py();
+        let dict = PyDict::new(py);
+        for field in RecordPy::field_names(&slf.get_type())? {
+            if let Some(value) = slf.get().inner.get(&Key::extract_bound(field.as_any())?) {
+                dict.set_item(&field, thaw(value.bind(py))?)?;
+            }
+        }
+        Ok(dict)
+    }
+
+    /// Build a record from a mapping of its fields, such as one produced by
+    /// to_dict or json.loads, reviving nested records and collections for any
+    /// fields whose Field spec names their type.
+    #[classmethod]
+    fn from_dict<'py>(
+        cls: &Bound<'py, PyType>,
+        data: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = cls.py();
+        let specs = RecordPy::field_specs(cls)?;
+        let kwds = PyDict::new(py);
+        for each in data.downcast::<PyMapping>()?.items()?.iter() {
+            let (k, v): (Bound<'_, PyAny>, Bound<'_, PyAny>) = each.extract()?;
+            let spec = specs
+                .iter()
+                .find(|(field, _)| field.as_any().eq(&k).unwrap_or(false))
+                .and_then(|(_, spec)| spec.as_ref());
+            kwds.set_item(&k, revive(v, spec.map(Bound::get))?)?;
+        }
+        cls.call((), Some(&kwds))
+    }
+
+    /// A copy of the record with the given fields changed, sharing the rest.
+    #[pyo3(signature = (**changes))]
+    fn evolve<'py>(
+        slf: &Bound<'py, Self>,
+        changes: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let cls = slf.get_type();
+        let fields = RecordPy::field_specs(&cls)?;
+        let mut inner = slf.get().inner.clone();
+        if let Some(changes) = changes {
+            for (k, v) in changes {
+                let Some((field, spec)) = fields
+                    .iter()
+                    .find(|(field, _)| field.as_any().eq(&k).unwrap_or(false))
+                else {
+                    return Err(PyTypeError::new_err(format!(
+                        "{} got an unexpected field {}",
+                        cls.name()?,
+                        k.repr()?
+                    )));
+                };
+                if let Some(spec) = spec {
+                    spec.get().validate(&cls, field, &v)?;
+                }
+                inner.insert_mut(Key::extract_bound(&k)?, v.unbind());
+            }
+        }
+        RecordPy::derive(slf, inner)
+    }
+
+    // What copy.replace uses as of Python 3.13.
+    #[pyo3(signature = (**changes))]
+    fn __replace__<'py>(
+        slf: &Bound<'py, Self>,
+        changes: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        RecordPy::evolve(slf, changes)
+    }
+}
+
+// Despite the name, not a trie: keys are kept in a red-black tree ordered by
+// their UTF-8 bytes, so each lookup costs O(log n) string comparisons, and the
+// prefix queries below walk ranges of the tree rather than trie nodes.
+#[pyclass(name = "StringTrieMap", module = "rpds", frozen, mapping)]
+struct StringTrieMapPy {
+    inner: RedBlackTreeMapSync<String, PyObject>,
+    hash: OnceLock<isize>,
+}
+
+impl<'source> FromPyObject<'source> for StringTrieMapPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let mut ret = RedBlackTreeMap::new_sync();
+        if let Ok(mapping) = ob.downcast::<PyMapping>() {
+            for each in mapping.items()?.iter() {
+                let (k, v): (String, PyObject) = each.extract()?;
+                ret.insert_mut(k, v);
+            }
+        } else {
+            for each in ob.try_iter()? {
+                let (k, v) = each?.extract()?;
+                ret.insert_mut(k, v);
+            }
+        }
+        Ok(StringTrieMapPy {
+            inner: ret,
+            hash: OnceLock::new(),
+        })
+    }
+}
+
+#[pymethods]
+impl StringTrieMapPy {
+    #[new]
+    #[pyo3(signature = (value=None, ** kwds))]
+    fn init(value: Option<StringTrieMapPy>, kwds: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut map: StringTrieMapPy;
+        if let Some(value) = value {
+            map = value;
+        } else {
+            map = StringTrieMapPy {
+                hash: OnceLock::new(),
+                inner: RedBlackTreeMap::new_sync(),
+            };
+        }
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                map.inner.insert_mut(k.extract()?, v.into());
+            }
+        }
+        Ok(map)
+    }
+
+    fn __contains__(&self, key: &Bound<'_, PyAny>) -> bool {
+        key.extract::<&str>()
+            .is_ok_and(|key| self.inner.contains_key(key))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> StringTrieMapIterator {
+        StringTrieMapIterator {
+            inner: slf.inner.clone(),
+        }
+    }
+
+    fn __getitem__(&self, key: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        match key.extract::<&str>().ok().and_then(|k| self.inner.get(k)) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key.clone().unbind())),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        let contents = self.inner.into_iter().map(|(k, v)| {
+            format!(
+                "{}: {}",
+                PyString::new(py, k)
+                    .repr()
+                    .and_then(|r| r.extract::<String>())
+                    .unwrap_or("<repr error>".to_owned()),
+                v.call_method0(py, "__repr__")
+                    .and_then(|r| r.extract(py))
+                    .unwrap_or("<repr error>".to_owned())
+            )
+        });
+        format!(
+            "StringTrieMap({{{}}})",
+            contents.collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    fn __richcmp__<'py>(
+        &self,
+        other: &Bound<'py, PyAny>,
+        op: CompareOp,
+        py: Python<'py>,
+    ) -> PyResult<PyObject> {
+        let Ok(other) = other.downcast::<StringTrieMapPy>() else {
+            let entries = self
+                .inner
+                .iter()
+                .map(|(k, v)| (PyString::new(py, k).into_any(), v));
+            return mapping_richcmp(entries, self.inner.size(), other, op, py);
+        };
+        let other = other.get();
+        let eq = std::ptr::eq(self, other)
+            || (self.inner.size() == other.inner.size()
+                && self
+                    .inner
+                    .iter()
+                    .map(|(k1, v1)| (v1, other.inner.get(k1)))
+                    .map(|(v1, v2)| v2.map_or(Ok(false), |v2| elements_eq(v1, v2, py)))
+                    .find(|r| !matches!(r, Ok(true)))
+                    .unwrap_or(Ok(true))?);
+        match op {
+            CompareOp::Eq => eq
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            CompareOp::Ne => (!eq)
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            _ => Ok(py.NotImplemented()),
+        }
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        let hashes = self.inner.iter().map(|(key, val)| {
+            let mut hasher = DefaultHasher::new();
+            let val_hash = val.bind(py).hash().map_err(|_| {
+                PyTypeError::new_err(format!(
+                    "Unhashable type in StringTrieMap of key {:?}: {}",
+                    key,
+                    val.bind(py)
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string())
+                ))
+            })?;
+
+            key.hash(&mut hasher);
+            hasher.write_isize(val_hash);
+
+            Ok(hasher.finish() as usize)
+        });
+        unordered_hash(hashes, self.inner.size(), &self.hash)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<(String, PyObject)>,)) {
+        (
+            StringTrieMapPy::type_object(slf.py()),
+            (slf.inner
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone_ref(slf.py())))
+                .collect(),),
+        )
+    }
+
+    #[pyo3(signature = (key, default=None))]
+    fn get(
+        &self,
+        key: &Bound<'_, PyAny>,
+        default: Option<PyObject>,
+        py: Python,
+    ) -> Option<PyObject> {
+        match key.extract::<&str>().ok().and_then(|k| self.inner.get(k)) {
+            Some(value) => Some(value.clone_ref(py)),
+            None => default,
+        }
+    }
+
+    fn keys<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let abc = PyModule::import(slf.py(), "collections.abc")?;
+        abc.getattr("KeysView")?.call1((slf,))
+    }
+
+    fn values<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let abc = PyModule::import(slf.py(), "collections.abc")?;
+        abc.getattr("ValuesView")?.call1((slf,))
+    }
+
+    fn items<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let abc = PyModule::import(slf.py(), "collections.abc")?;
+        abc.getattr("ItemsView")?.call1((slf,))
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.inner
+            .range::<str, _>((
+                std::ops::Bound::Included(prefix),
+                std::ops::Bound::Unbounded,
+            ))
+            .map(|(k, _)| k)
+            .take_while(|k| k.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    // Any key which is a prefix of value sorts between the greatest key not
+    // after value and value itself, so is a prefix of what the two share. Each
+    // step is one O(log n) descent, and strictly shortens what's searched.
+    fn longest_prefix_of(&self, value: &str) -> Option<String> {
+        let mut value = value;
+        loop {
+            let (key, _) = self
+                .inner
+                .range::<str, _>((std::ops::Bound::Unbounded, std::ops::Bound::Included(value)))
+                .next_back()?;
+            if value.starts_with(key.as_str()) {
+                return Some(key.clone());
+            }
+            let shared = value
+                .char_indices()
+                .zip(key.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(value.len().min(key.len()), |((i, _), _)| i);
+            value = &value[..shared];
+        }
+    }
+
+    fn discard(&self, key: &str) -> StringTrieMapPy {
+        StringTrieMapPy {
+            hash: OnceLock::new(),
+            inner: self.inner.remove(key),
+        }
+    }
+
+    fn insert(&self, key: String, value: Bound<'_, PyAny>) -> StringTrieMapPy {
+        StringTrieMapPy {
+            hash: OnceLock::new(),
+            inner: self.inner.insert(key, value.unbind()),
+        }
+    }
+
+    fn remove(&self, key: &str) -> PyResult<StringTrieMapPy> {
+        match self.inner.contains_key(key) {
+            true => Ok(StringTrieMapPy {
+                hash: OnceLock::new(),
+                inner: self.inner.remove(key),
+            }),
+            false => Err(PyKeyError::new_err(key.to_owned())),
+        }
+    }
+
+    #[pyo3(signature = (*maps, **kwds))]
+    fn update(
+        &self,
+        maps: &Bound<'_, PyTuple>,
+        kwds: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<StringTrieMapPy> {
+        let mut inner = self.inner.clone();
+        for value in maps {
+            let map = StringTrieMapPy::extract_bound(&value)?;
+            for (k, v) in &map.inner {
+                inner.insert_mut(k.clone(), v.clone_ref(value.py()));
+            }
+        }
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                inner.insert_mut(k.extract()?, v.extract()?);
+            }
+        }
+        Ok(StringTrieMapPy {
+            inner,
+            hash: OnceLock::new(),
+        })
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct StringTrieMapIterator {
+    inner: RedBlackTreeMapSync<String, PyObject>,
+}
+
+#[pymethods]
+impl StringTrieMapIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<String> {
+        let first = slf.inner.keys().next()?.clone();
+        slf.inner.remove_mut(&first);
+        Some(first)
+    }
+}
+
+#[pyclass(name = "ChainMap", module = "rpds", frozen, mapping)]
+struct ChainMapPy {
+    layers: ListSync<HashTrieMapSync<Key, PyObject>>,
+    // The layers flattened into one map, built on first use like the hash.
+    merged: OnceLock<HashTrieMapSync<Key, PyObject>>,
+    hash: OnceLock<isize>,
+}
+
+impl ChainMapPy {
+    fn lookup(&self, key: &Key) -> Option<&PyObject> {
+        self.layers.iter().find_map(|layer| layer.get(key))
+    }
+
+    fn merged(&self, py: Python) -> &HashTrieMapSync<Key, PyObject> {
+        self.merged.get_or_init(|| {
+            let mut layers = self.layers.iter().collect::<Vec<_>>();
+            let Some(mut inner) = layers.pop().cloned() else {
+                return HashTrieMap::new_sync();
+            };
+            for layer in layers.into_iter().rev() {
+                for (k, v) in layer {
+                    inner.insert_mut(k.clone_ref(py), v.clone_ref(py));
+                }
+            }
+            inner
+        })
+    }
+
+    fn with_first_layer(
+        &self,
+        f: impl FnOnce(&HashTrieMapSync<Key, PyObject>) -> HashTrieMapSync<Key, PyObject>,
+    ) -> ChainMapPy {
+        let first = match self.layers.first() {
+            Some(first) => f(first),
+            None => f(&HashTrieMap::new_sync()),
+        };
+        let rest = self.layers.drop_first().unwrap_or_default();
+        ChainMapPy {
+            hash: OnceLock::new(),
+            merged: OnceLock::new(),
+            layers: rest.push_front(first),
+        }
+    }
+}
+
+#[pymethods]
+impl ChainMapPy {
+    #[new]
+    #[pyo3(signature = (*maps))]
+    fn init(maps: Vec<HashTrieMapPy>) -> Self {
+        let mut layers = List::new_sync();
+        for map in maps.into_iter().rev() {
+            layers.push_front_mut(map.inner);
+        }
+        if layers.is_empty() {
+            layers.push_front_mut(HashTrieMap::new_sync());
+        }
+        ChainMapPy {
+            layers,
+            hash: OnceLock::new(),
+            merged: OnceLock::new(),
+        }
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.lookup(&key).is_some()
+    }
+
+    fn __iter__(&self, py: Python) -> KeysIterator {
+        KeysIterator {
+            inner: self.merged(py).clone(),
+        }
+    }
+
+    fn __getitem__(&self, key: Key, py: Python) -> PyResult<PyObject> {
+        match self.lookup(&key) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __len__(&self, py: Python) -> usize {
+        self.merged(py).size()
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        let contents = self
+            .layers
+            .iter()
+            .map(|layer| HashTrieMapPy::from(layer.clone()).__repr__(py));
+        format!("ChainMap({})", contents.collect::<Vec<_>>().join(", "))
+    }
+
+    fn __richcmp__<'py>(
+        &self,
+        other: &Bound<'py, PyAny>,
+        op: CompareOp,
+        py: Python<'py>,
+    ) -> PyResult<PyObject> {
+        let same = other.downcast::<ChainMapPy>().ok();
+        map_richcmp(
+            self.merged(py),
+            same.map(|map| map.get().merged(py)),
+            other,
+            op,
+            py,
+        )
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        map_hash(self.merged(py), &self.hash, py)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> PyResult<(Bound<'_, PyType>, Bound<'_, PyTuple>)> {
+        Ok((
+            ChainMapPy::type_object(slf.py()),
+            PyTuple::new(slf.py(), slf.maps())?,
+        ))
+    }
+
+    /// The layers of this chain, front (searched first) to back.
+    #[getter]
+    fn maps(&self) -> Vec<HashTrieMapPy> {
+        self.layers
+            .iter()
+            .cloned()
+            .map(HashTrieMapPy::from)
+            .collect()
+    }
+
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, key: Key, default: Option<PyObject>, py: Python) -> Option<PyObject> {
+        match self.lookup(&key) {
+            Some(value) => Some(value.clone_ref(py)),
+            None => default,
+        }
+    }
+
+    fn keys(&self, py: Python) -> KeysView {
+        KeysView {
+            inner: self.merged(py).clone(),
+        }
+    }
+
+    fn values(&self, py: Python) -> ValuesView {
+        ValuesView {
+            inner: self.merged(py).clone(),
+        }
+    }
+
+    fn items(&self, py: Python) -> ItemsView {
+        ItemsView {
+            inner: self.merged(py).clone(),
+        }
+    }
+
+    fn flatten(&self, py: Python) -> HashTrieMapPy {
+        HashTrieMapPy::from(self.merged(py).clone())
+    }
+
+    #[pyo3(signature = (map=None))]
+    fn push_layer(&self, map: Option<HashTrieMapPy>) -> ChainMapPy {
+        let layer = map.map_or_else(HashTrieMap::new_sync, |map| map.inner);
+        ChainMapPy {
+            hash: OnceLock::new(),
+            merged: OnceLock::new(),
+            layers: self.layers.push_front(layer),
+        }
+    }
+
+    fn pop_layer(&self) -> PyResult<ChainMapPy> {
+        match self.layers.drop_first() {
+            Some(layers) => Ok(ChainMapPy {
+                layers,
+                hash: OnceLock::new(),
+                merged: OnceLock::new(),
+            }),
+            None => Err(PyIndexError::new_err("pop_layer from an empty ChainMap")),
+        }
+    }
+
+    fn insert(&self, key: Key, value: Bound<'_, PyAny>) -> ChainMapPy {
+        self.with_first_layer(|first| first.insert(key, value.unbind()))
+    }
+
+    fn discard(&self, key: Key) -> ChainMapPy {
+        self.with_first_layer(|first| first.remove(&key))
+    }
+
+    fn remove(&self, key: Key) -> PyResult<ChainMapPy> {
+        match self
+            .layers
+            .first()
+            .is_some_and(|first| first.contains_key(&key))
+        {
+            true => Ok(self.with_first_layer(|first| first.remove(&key))),
+            false => Err(PyKeyError::new_err(key)),
+        }
+    }
+}
+
+#[pyclass(name = "IdentityMap", module = "rpds", frozen, mapping)]
+struct IdentityMapPy {
+    inner: HashTrieMapSync<IdentityKey, PyObject>,
+    hash: OnceLock<isize>,
+}
+
+impl<'source> FromPyObject<'source> for IdentityMapPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let mut ret = HashTrieMap::new_sync();
+        if let Ok(mapping) = ob.downcast::<PyMapping>() {
+            for each in mapping.items()?.iter() {
+                let (k, v): (IdentityKey, PyObject) = each.extract()?;
+                ret.insert_mut(k, v);
+            }
+        } else {
+            for each in ob.try_iter()? {
+                let (k, v) = each?.extract()?;
+                ret.insert_mut(k, v);
+            }
+        }
+        Ok(IdentityMapPy {
+            inner: ret,
+            hash: OnceLock::new(),
+        })
+    }
+}
+
+#[pymethods]
+impl IdentityMapPy {
+    #[new]
+    #[pyo3(signature = (value=None))]
+    fn init(value: Option<IdentityMapPy>) -> Self {
+        value.unwrap_or_else(|| IdentityMapPy {
+            hash: OnceLock::new(),
+            inner: HashTrieMap::new_sync(),
+        })
+    }
+
+    fn __contains__(&self, key: IdentityKey) -> bool {
+        self.inner.contains_key(&key)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> IdentityMapIterator {
+        IdentityMapIterator {
+            inner: slf.inner.clone(),
+        }
+    }
+
+    fn __getitem__(&self, key: IdentityKey, py: Python) -> PyResult<PyObject> {
+        match self.inner.get(&key) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key.inner)),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        let contents = self.inner.into_iter().map(|(k, v)| {
+            format!(
+                "{}: {}",
+                k.inner
+                    .call_method0(py, "__repr__")
+                    .and_then(|r| r.extract(py))
+                    .unwrap_or("<repr error>".to_owned()),
+                v.call_method0(py, "__repr__")
+                    .and_then(|r| r.extract(py))
+                    .unwrap_or("<repr error>".to_owned())
+            )
+        });
+        format!(
+            "IdentityMap({{{}}})",
+            contents.collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    fn __richcmp__<'py>(&self, other: &Self, op: CompareOp, py: Python<'py>) -> PyResult<PyObject> {
+        let eq = std::ptr::eq(self, other)
+            || (self.inner.size() == other.inner.size()
+                && self
+                    .inner
+                    .iter()
+                    .map(|(k1, v1)| (v1, other.inner.get(k1)))
+                    .map(|(v1, v2)| v2.map_or(Ok(false), |v2| elements_eq(v1, v2, py)))
+                    .find(|r| !matches!(r, Ok(true)))
+                    .unwrap_or(Ok(true))?);
+        match op {
+            CompareOp::Eq => eq
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            CompareOp::Ne => (!eq)
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            _ => Ok(py.NotImplemented()),
+        }
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        let hashes = self.inner.iter().map(|(key, val)| {
+            let mut hasher = DefaultHasher::new();
+            let val_hash = val.bind(py).hash().map_err(|_| {
+                PyTypeError::new_err(format!(
+                    "Unhashable type in IdentityMap of key {}: {}",
+                    key.inner
+                        .bind(py)
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string()),
+                    val.bind(py)
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string())
+                ))
+            })?;
+
+            key.hash(&mut hasher);
+            hasher.write_isize(val_hash);
+
+            Ok(hasher.finish() as usize)
+        });
+        unordered_hash(hashes, self.inner.size(), &self.hash)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<(PyObject, PyObject)>,)) {
+        (
+            IdentityMapPy::type_object(slf.py()),
+            (slf.inner
+                .iter()
+                .map(|(k, v)| (k.inner.clone_ref(slf.py()), v.clone_ref(slf.py())))
+                .collect(),),
+        )
+    }
+
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, key: IdentityKey, default: Option<PyObject>, py: Python) -> Option<PyObject> {
+        match self.inner.get(&key) {
+            Some(value) => Some(value.clone_ref(py)),
+            None => default,
+        }
+    }
+
+    fn keys<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let abc = PyModule::import(slf.py(), "collections.abc")?;
+        abc.getattr("KeysView")?.call1((slf,))
+    }
+
+    fn values<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let abc = PyModule::import(slf.py(), "collections.abc")?;
+        abc.getattr("ValuesView")?.call1((slf,))
+    }
+
+    fn items<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let abc = PyModule::import(slf.py(), "collections.abc")?;
+        abc.getattr("ItemsView")?.call1((slf,))
+    }
+
+    fn discard(&self, key: IdentityKey) -> IdentityMapPy {
+        IdentityMapPy {
+            hash: OnceLock::new(),
+            inner: self.inner.remove(&key),
+        }
+    }
+
+    fn insert(&self, key: IdentityKey, value: Bound<'_, PyAny>) -> IdentityMapPy {
+        IdentityMapPy {
+            hash: OnceLock::new(),
+            inner: self.inner.insert(key, value.unbind()),
+        }
+    }
+
+    fn remove(&self, key: IdentityKey) -> PyResult<IdentityMapPy> {
+        match self.inner.contains_key(&key) {
+            true => Ok(IdentityMapPy {
+                hash: OnceLock::new(),
+                inner: self.inner.remove(&key),
+            }),
+            false => Err(PyKeyError::new_err(key.inner)),
+        }
+    }
+
+    #[pyo3(signature = (*maps))]
+    fn update(&self, maps: &Bound<'_, PyTuple>) -> PyResult<IdentityMapPy> {
+        let mut inner = self.inner.clone();
+        for value in maps {
+            let map = IdentityMapPy::extract_bound(&value)?;
+            for (k, v) in &map.inner {
+                inner.insert_mut(k.clone_ref(value.py()), v.clone_ref(value.py()));
+            }
+        }
+        Ok(IdentityMapPy {
+            inner,
+            hash: OnceLock::new(),
+        })
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct IdentityMapIterator {
+    inner: HashTrieMapSync<IdentityKey, PyObject>,
+}
+
+#[pymethods]
+impl IdentityMapIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<IdentityKey> {
+        let first = slf.inner.keys().next()?.clone_ref(slf.py());
+        slf.inner.remove_mut(&first);
+        Some(first)
+    }
+}
+
+// Matches the hash Python gives to ints, so an IntSet hashes like the
+// HashTrieSet with the same elements.
+fn int_hash(value: i64) -> isize {
+    const MODULUS: i128 = (1 << 61) - 1;
+    let hash = match value {
+        0.. => value as i128 % MODULUS,
+        _ => -(-(value as i128) % MODULUS),
+    };
+    match hash {
+        -1 => -2,
+        _ => hash as isize,
+    }
+}
+
+// Elements are stored as a bitmap, one 64 bit word per chunk of 64 integers.
+#[pyclass(name = "IntSet", module = "rpds", frozen)]
+struct IntSetPy {
+    chunks: RedBlackTreeMapSync<i64, u64>,
+    size: usize,
+    hash: OnceLock<isize>,
+}
+
+impl<'source> FromPyObject<'source> for IntSetPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let mut ret = IntSetPy::new();
+        for each in ob.try_iter()? {
+            ret.insert_mut(each?.extract()?);
+        }
+        Ok(ret)
+    }
+}
+
+impl IntSetPy {
+    fn new() -> Self {
+        IntSetPy {
+            hash: OnceLock::new(),
+            chunks: RedBlackTreeMap::new_sync(),
+            size: 0,
+        }
+    }
+
+    fn from_chunks(chunks: RedBlackTreeMapSync<i64, u64>) -> Self {
+        let size = chunks.values().map(|word| word.count_ones() as usize).sum();
+        IntSetPy {
+            chunks,
+            size,
+            hash: OnceLock::new(),
+        }
+    }
+
+    fn split(value: i64) -> (i64, u64) {
+        (value.div_euclid(64), 1 << value.rem_euclid(64))
+    }
+
+    fn contains(&self, value: i64) -> bool {
+        let (chunk, bit) = IntSetPy::split(value);
+        self.chunks.get(&chunk).is_some_and(|word| word & bit != 0)
+    }
+
+    fn insert_mut(&mut self, value: i64) {
+        let (chunk, bit) = IntSetPy::split(value);
+        let word = self.chunks.get(&chunk).copied().unwrap_or(0);
+        if word & bit == 0 {
+            self.chunks.insert_mut(chunk, word | bit);
+            self.size += 1;
+        }
+    }
+
+    fn remove_mut(&mut self, value: i64) {
+        let (chunk, bit) = IntSetPy::split(value);
+        let word = self.chunks.get(&chunk).copied().unwrap_or(0);
+        if word & bit != 0 {
+            match word & !bit {
+                0 => {
+                    self.chunks.remove_mut(&chunk);
+                }
+                rest => self.chunks.insert_mut(chunk, rest),
+            }
+            self.size -= 1;
+        }
+    }
+
+    fn clone_set(&self) -> Self {
+        IntSetPy {
+            hash: OnceLock::new(),
+            chunks: self.chunks.clone(),
+            size: self.size,
+        }
+    }
+
+    // Combines other's words into self's, which is linear in the number of
+    // words rather than the number of elements.
+    fn merged_with(&self, other: &Self, f: impl Fn(u64, u64) -> u64) -> Self {
+        let mut chunks = self.chunks.clone();
+        for (chunk, word) in &other.chunks {
+            let current = chunks.get(chunk).copied().unwrap_or(0);
+            match f(current, *word) {
+                0 => {
+                    chunks.remove_mut(chunk);
+                }
+                merged => chunks.insert_mut(*chunk, merged),
+            }
+        }
+        IntSetPy::from_chunks(chunks)
+    }
+
+    /// The right-hand side of a set operator, which may be any Set of ints
+    /// (builtin ones included), or None if the operator isn't supported for it.
+    fn operand(other: &Bound<'_, PyAny>) -> PyResult<Option<IntSetPy>> {
+        if let Ok(set) = other.downcast::<IntSetPy>() {
+            return Ok(Some(set.get().clone_set()));
+        }
+        let abc = PyModule::import(other.py(), "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? {
+            return Ok(None);
+        }
+        other.extract().map(Some)
+    }
+
+    // Applies operator to self and the operand for other, if it has one.
+    fn apply_operator(
+        &self,
+        other: &Bound<'_, PyAny>,
+        operator: fn(&IntSetPy, &IntSetPy) -> IntSetPy,
+        py: Python,
+    ) -> PyResult<PyObject> {
+        let Some(other) = IntSetPy::operand(other)? else {
+            return Ok(py.NotImplemented());
+        };
+        Ok(operator(self, &other)
+            .into_pyobject(py)?
+            .into_any()
+            .unbind())
+    }
+
+    // Subset and superset comparisons, which like those of builtin sets are
+    // only supported against other Sets.
+    fn compare(&self, other: &Bound<'_, PyAny>, op: CompareOp, py: Python) -> PyResult<PyObject> {
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? {
+            return Ok(py.NotImplemented());
+        }
+        let len = other.len()?;
+        let result = match op {
+            CompareOp::Lt => len > self.size && self.is_within(other)?,
+            CompareOp::Le => len >= self.size && self.is_within(other)?,
+            CompareOp::Gt => len < self.size && self.covers(other)?,
+            CompareOp::Ge => len <= self.size && self.covers(other)?,
+            CompareOp::Eq | CompareOp::Ne => return Ok(py.NotImplemented()),
+        };
+        result
+            .into_pyobject(py)
+            .map_err(Into::into)
+            .map(BoundObject::into_any)
+            .map(BoundObject::unbind)
+    }
+
+    // Whether each of our elements is in other.
+    fn is_within(&self, other: &Bound<'_, PyAny>) -> PyResult<bool> {
+        for each in self.elements() {
+            if !other.contains(each)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Whether each of other's elements is in us.
+    fn covers(&self, other: &Bound<'_, PyAny>) -> PyResult<bool> {
+        for each in other.try_iter()? {
+            if !self.__contains__(&each?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn set_difference(&self, other: &Self) -> IntSetPy {
+        if self.chunks.size() > other.chunks.size() {
+            return self.merged_with(other, |current, word| current & !word);
+        }
+        let mut chunks = RedBlackTreeMap::new_sync();
+        for (chunk, word) in &self.chunks {
+            let rest = word & !other.chunks.get(chunk).copied().unwrap_or(0);
+            if rest != 0 {
+                chunks.insert_mut(*chunk, rest);
+            }
+        }
+        IntSetPy::from_chunks(chunks)
+    }
+
+    fn set_intersection(&self, other: &Self) -> IntSetPy {
+        let (larger, smaller) = match self.chunks.size() > other.chunks.size() {
+            true => (self, other),
+            false => (other, self),
+        };
+        let mut chunks = RedBlackTreeMap::new_sync();
+        for (chunk, word) in &smaller.chunks {
+            let both = word & larger.chunks.get(chunk).copied().unwrap_or(0);
+            if both != 0 {
+                chunks.insert_mut(*chunk, both);
+            }
+        }
+        IntSetPy::from_chunks(chunks)
+    }
+
+    fn set_symmetric_difference(&self, other: &Self) -> IntSetPy {
+        match self.chunks.size() > other.chunks.size() {
+            true => self.merged_with(other, |current, word| current ^ word),
+            false => other.merged_with(self, |current, word| current ^ word),
+        }
+    }
+
+    fn set_union(&self, other: &Self) -> IntSetPy {
+        match self.chunks.size() > other.chunks.size() {
+            true => self.merged_with(other, |current, word| current | word),
+            false => other.merged_with(self, |current, word| current | word),
+        }
+    }
+
+    fn elements(&self) -> impl Iterator<Item = i64> + '_ {
+        self.chunks.iter().flat_map(|(chunk, word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| chunk * 64 + bit)
+        })
+    }
+}
+
+#[pymethods]
+impl IntSetPy {
+    #[new]
+    #[pyo3(signature = (value=None))]
+    fn init(value: Option<IntSetPy>) -> Self {
+        value.unwrap_or_else(IntSetPy::new)
+    }
+
+    #[classmethod]
+    #[pyo3(signature = (start, stop, step=1))]
+    fn range(_cls: &Bound<'_, PyType>, start: i64, stop: i64, step: i64) -> PyResult<Self> {
+        if step == 0 {
+            return Err(PyValueError::new_err("range() arg 3 must not be zero"));
+        }
+        let mut ret = IntSetPy::new();
+        if step == 1 {
+            // whole words at a time, in i128 since the last word's end can be
+            // past i64::MAX
+            let mut value = start as i128;
+            while value < stop as i128 {
+                let (chunk, bit) = IntSetPy::split(value as i64);
+                let end = (stop as i128).min((chunk as i128 + 1) * 64);
+                let span = (end - value) as u32;
+                let word = match span {
+                    64 => u64::MAX,
+                    _ => ((1 << span) - 1) * bit,
+                };
+                ret.chunks.insert_mut(chunk, word);
+                ret.size += span as usize;
+                value = end;
+            }
+        } else {
+            let mut value = start as i128;
+            while (step > 0 && value < stop as i128) || (step < 0 && value > stop as i128) {
+                ret.insert_mut(value as i64);
+                value += step as i128;
+            }
+        }
+        Ok(ret)
+    }
+
+    fn __contains__(&self, value: &Bound<'_, PyAny>) -> bool {
+        value.extract().is_ok_and(|value| self.contains(value))
+    }
+
+    fn __and__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, IntSetPy::set_intersection, py)
+    }
+
+    fn __or__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, IntSetPy::set_union, py)
+    }
+
+    fn __sub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, IntSetPy::set_difference, py)
+    }
+
+    fn __xor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, IntSetPy::set_symmetric_difference, py)
+    }
+
+    fn __rand__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.__and__(other, py)
+    }
+
+    fn __ror__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.__or__(other, py)
+    }
+
+    fn __rsub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, |this, other| other.set_difference(this), py)
+    }
+
+    fn __rxor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.__xor__(other, py)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> IntSetIterator {
+        IntSetIterator {
+            chunks: slf.chunks.clone(),
+            current: None,
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.size
+    }
+
+    fn __repr__(&self) -> String {
+        let contents = self.elements().map(|each| each.to_string());
+        format!("IntSet({{{}}})", contents.collect::<Vec<_>>().join(", "))
+    }
+
+    fn __eq__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        if let Ok(other) = other.downcast::<IntSetPy>() {
+            return Ok(slf.chunks == other.get().chunks);
+        }
+        let abc = PyModule::import(py, "collections.abc")?;
+        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? != slf.size {
+            return Ok(false);
+        }
+        for each in other.try_iter()? {
+            if !slf.__contains__(&each?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __hash__(&self) -> PyResult<isize> {
+        let hashes = self.elements().map(|each| Ok(int_hash(each) as usize));
+        unordered_hash(hashes, self.size, &self.hash)
+    }
+
+    fn __lt__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.compare(other, CompareOp::Lt, py)
+    }
+
+    fn __le__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.compare(other, CompareOp::Le, py)
+    }
+
+    fn __gt__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.compare(other, CompareOp::Gt, py)
+    }
+
+    fn __ge__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.compare(other, CompareOp::Ge, py)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<i64>,)) {
+        (IntSetPy::type_object(slf.py()), (slf.elements().collect(),))
+    }
+
+    fn insert(&self, value: i64) -> IntSetPy {
+        let mut ret = self.clone_set();
+        ret.insert_mut(value);
+        ret
+    }
+
+    fn discard(&self, value: i64) -> IntSetPy {
+        let mut ret = self.clone_set();
+        ret.remove_mut(value);
+        ret
+    }
+
+    fn remove(&self, value: i64) -> PyResult<IntSetPy> {
+        match self.contains(value) {
+            true => Ok(self.discard(value)),
+            false => Err(PyKeyError::new_err(value)),
+        }
+    }
+
+    fn difference(&self, other: IntSetPy) -> IntSetPy {
+        self.set_difference(&other)
+    }
+
+    fn intersection(&self, other: IntSetPy) -> IntSetPy {
+        self.set_intersection(&other)
+    }
+
+    fn symmetric_difference(&self, other: IntSetPy) -> IntSetPy {
+        self.set_symmetric_difference(&other)
+    }
+
+    fn union(&self, other: IntSetPy) -> IntSetPy {
+        self.set_union(&other)
+    }
+
+    #[pyo3(signature = (*iterables))]
+    fn update(&self, iterables: Bound<'_, PyTuple>) -> PyResult<IntSetPy> {
+        let mut ret = self.clone_set();
+        for each in iterables {
+            for value in each.try_iter()? {
+                ret.insert_mut(value?.extract()?);
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct IntSetIterator {
+    chunks: RedBlackTreeMapSync<i64, u64>,
+    current: Option<(i64, u64)>,
+}
+
+#[pymethods]
+impl IntSetIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<i64> {
+        let (chunk, word) = match slf.current {
+            Some(current) => current,
+            None => {
+                let (chunk, word) = slf.chunks.first().map(|(k, v)| (*k, *v))?;
+                slf.chunks.remove_mut(&chunk);
+                (chunk, word)
+            }
+        };
+        let rest = word & (word - 1);
+        slf.current = (rest != 0).then_some((chunk, rest));
+        Some(chunk * 64 + word.trailing_zeros() as i64)
+    }
+}
+
+// Intervals are half-open, containing low but not high, and sort by low bound
+// first so queries can stop as soon as they pass the end of what they want.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Interval {
+    low: SortedKey,
+    high: SortedKey,
+}
+
+impl Interval {
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        Interval {
+            low: self.low.clone_ref(py),
+            high: self.high.clone_ref(py),
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for Interval {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let (low, high): (Bound<'_, PyAny>, Bound<'_, PyAny>) = ob.extract()?;
+        if low.gt(&high)? {
+            return Err(PyValueError::new_err(format!(
+                "interval low bound {} is greater than its high bound {}",
+                low.repr()?,
+                high.repr()?,
+            )));
+        }
+        Ok(Interval {
+            low: SortedKey {
+                inner: low.unbind(),
+                sort_key: None,
+            },
+            high: SortedKey {
+                inner: high.unbind(),
+                sort_key: None,
+            },
+        })
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Interval {
+    type Target = PyTuple;
+    type Output = Bound<'py, Self::Target>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        (self.low.inner, self.high.inner).into_pyobject(py)
+    }
+}
+
+#[pyclass(name = "IntervalMap", module = "rpds", frozen, mapping)]
+struct IntervalMapPy {
+    inner: RedBlackTreeMapSync<Interval, PyObject>,
+    hash: OnceLock<isize>,
+}
+
+impl<'source> FromPyObject<'source> for IntervalMapPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let mut ret = RedBlackTreeMap::new_sync();
+        if let Ok(mapping) = ob.downcast::<PyMapping>() {
+            for each in mapping.items()?.iter() {
+                let (k, v): (Interval, PyObject) = each.extract()?;
+                ret.insert_mut(k, v);
+                comparison_result(())?;
+            }
+        } else {
+            for each in ob.try_iter()? {
+                let (k, v): (Interval, PyObject) = each?.extract()?;
+                ret.insert_mut(k, v);
+                comparison_result(())?;
+            }
+        }
+        Ok(IntervalMapPy {
+            inner: ret,
+            hash: OnceLock::new(),
+        })
+    }
+}
+
+impl IntervalMapPy {
+    // Scans the intervals starting before (or at) `before`, which are the only
+    // candidates for a query ending there, keeping those which match.
+    //
+    // Without a per-node maximum high bound (which the tree has nowhere to
+    // keep) each candidate has to be checked, so queries are linear in the
+    // number of them, stopping at the first interval which starts too late.
+    fn matching<'py>(
+        &self,
+        before: &Bound<'py, PyAny>,
+        include_equal: bool,
+        matches: impl Fn(&Interval) -> PyResult<bool>,
+    ) -> PyResult<Vec<(Interval, PyObject)>> {
+        let py = before.py();
+        let end = SortedKey {
+            inner: before.clone().unbind(),
+            sort_key: None,
+        };
+        let mut found = Vec::new();
+        for (interval, value) in self.inner.iter() {
+            match comparison_result(interval.low.cmp(&end))? {
+                Ordering::Less => {}
+                Ordering::Equal if include_equal => {}
+                _ => break,
+            }
+            if matches(interval)? {
+                found.push((interval.clone_ref(py), value.clone_ref(py)));
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[pymethods]
+impl IntervalMapPy {
+    #[new]
+    #[pyo3(signature = (value=None))]
+    fn init(value: Option<IntervalMapPy>) -> Self {
+        value.unwrap_or_else(|| IntervalMapPy {
+            hash: OnceLock::new(),
+            inner: RedBlackTreeMap::new_sync(),
+        })
+    }
+
+    fn __contains__(&self, interval: Interval) -> PyResult<bool> {
+        comparison_result(self.inner.contains_key(&interval))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> IntervalMapIterator {
+        IntervalMapIterator {
+            inner: slf.inner.clone(),
+        }
+    }
+
+    fn __getitem__(&self, interval: Interval, py: Python) -> PyResult<PyObject> {
+        match comparison_result(self.inner.get(&interval))? {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(interval.into_pyobject(py)?.unbind())),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        let contents = self.inner.into_iter().map(|(k, v)| {
+            let repr = |obj: &PyObject| {
+                obj.call_method0(py, "__repr__")
+                    .and_then(|r| r.extract::<String>(py))
+                    .unwrap_or("<repr error>".to_owned())
+            };
+            format!(
+                "({}, {}): {}",
+                repr(&k.low.inner),
+                repr(&k.high.inner),
+                repr(v)
+            )
+        });
+        format!(
+            "IntervalMap({{{}}})",
+            contents.collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    fn __richcmp__<'py>(&self, other: &Self, op: CompareOp, py: Python<'py>) -> PyResult<PyObject> {
+        let eq = || -> PyResult<bool> {
+            if std::ptr::eq(self, other) {
+                return Ok(true);
+            }
+            if self.inner.size() != other.inner.size() {
+                return Ok(false);
+            }
+            for ((k1, v1), (k2, v2)) in self.inner.iter().zip(other.inner.iter()) {
+                if !elements_eq(&k1.low.inner, &k2.low.inner, py)?
+                    || !elements_eq(&k1.high.inner, &k2.high.inner, py)?
+                    || !elements_eq(v1, v2, py)?
+                {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+        match op {
+            CompareOp::Eq => eq()?
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            CompareOp::Ne => (!eq()?)
+                .into_pyobject(py)
+                .map_err(Into::into)
+                .map(BoundObject::into_any)
+                .map(BoundObject::unbind),
+            _ => Ok(py.NotImplemented()),
+        }
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        let hashes = self.inner.iter().map(|(key, val)| {
+            let mut hasher = DefaultHasher::new();
+            let val_bound = val.bind(py);
+
+            let unhashable = |_| {
+                PyTypeError::new_err(format!(
+                    "Unhashable type in IntervalMap of key ({}, {}): {}",
+                    key.low
+                        .inner
+                        .bind(py)
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string()),
+                    key.high
+                        .inner
+                        .bind(py)
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string()),
+                    val_bound
+                        .repr()
+                        .and_then(|r| r.extract())
+                        .unwrap_or("<repr> error".to_string())
+                ))
+            };
+            let low_hash = key.low.inner.bind(py).hash().map_err(unhashable)?;
+            let high_hash = key.high.inner.bind(py).hash().map_err(unhashable)?;
+            let val_hash = val_bound.hash().map_err(unhashable)?;
+
+            hasher.write_isize(low_hash);
+            hasher.write_isize(high_hash);
+            hasher.write_isize(val_hash);
+
+            Ok(hasher.finish() as usize)
+        });
+        unordered_hash(hashes, self.inner.size(), &self.hash)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<(Interval, PyObject)>,)) {
+        (
+            IntervalMapPy::type_object(slf.py()),
+            (slf.inner
+                .iter()
+                .map(|(k, v)| (k.clone_ref(slf.py()), v.clone_ref(slf.py())))
+                .collect(),),
+        )
+    }
+
+    #[pyo3(signature = (interval, default=None))]
+    fn get(
+        &self,
+        interval: Interval,
+        default: Option<PyObject>,
+        py: Python,
+    ) -> PyResult<Option<PyObject>> {
+        if let Some(value) = comparison_result(self.inner.get(&interval))? {
+            Ok(Some(value.clone_ref(py)))
+        } else {
+            Ok(default)
+        }
+    }
+
+    fn keys<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let abc = PyModule::import(slf.py(), "collections.abc")?;
+        abc.getattr("KeysView")?.call1((slf,))
+    }
+
+    fn values<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let abc = PyModule::import(slf.py(), "collections.abc")?;
+        abc.getattr("ValuesView")?.call1((slf,))
+    }
+
+    fn items<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let abc = PyModule::import(slf.py(), "collections.abc")?;
+        abc.getattr("ItemsView")?.call1((slf,))
+    }
+
+    /// The (interval, value) pairs whose interval contains the point.
+    ///
+    /// This checks every interval starting at or before the point.
+    fn at(&self, point: &Bound<'_, PyAny>) -> PyResult<Vec<(Interval, PyObject)>> {
+        self.matching(point, true, |interval| {
+            interval.high.inner.bind(point.py()).gt(point)
+        })
+    }
+
+    /// The (interval, value) pairs whose interval overlaps [low, high).
+    ///
+    /// This checks every interval starting before high.
+    fn overlapping(
+        &self,
+        low: &Bound<'_, PyAny>,
+        high: &Bound<'_, PyAny>,
+    ) -> PyResult<Vec<(Interval, PyObject)>> {
+        self.matching(high, false, |interval| {
+            let end = interval.high.inner.bind(low.py());
+            Ok(end.gt(low)? && end.gt(&interval.low.inner)?)
+        })
+    }
+
+    fn discard(&self, interval: Interval) -> PyResult<IntervalMapPy> {
+        match comparison_result(self.inner.contains_key(&interval))? {
+            true => Ok(IntervalMapPy {
+                hash: OnceLock::new(),
+                inner: self.inner.remove(&interval),
+            }),
+            false => Ok(IntervalMapPy {
+                hash: OnceLock::new(),
+                inner: self.inner.clone(),
+            }),
+        }
+    }
+
+    fn insert(&self, interval: Interval, value: Bound<'_, PyAny>) -> PyResult<IntervalMapPy> {
+        let inner = self.inner.insert(interval, value.unbind());
+        comparison_result(IntervalMapPy {
+            inner,
+            hash: OnceLock::new(),
+        })
+    }
+
+    fn remove(&self, interval: Interval, py: Python) -> PyResult<IntervalMapPy> {
+        match comparison_result(self.inner.contains_key(&interval))? {
+            true => Ok(IntervalMapPy {
+                hash: OnceLock::new(),
+                inner: self.inner.remove(&interval),
+            }),
+            false => Err(PyKeyError::new_err(interval.into_pyobject(py)?.unbind())),
+        }
+    }
+
+    #[pyo3(signature = (*maps))]
+    fn update(&self, maps: &Bound<'_, PyTuple>) -> PyResult<IntervalMapPy> {
+        let mut inner = self.inner.clone();
+        for value in maps {
+            let map = IntervalMapPy::extract_bound(&value)?;
+            for (k, v) in &map.inner {
+                inner.insert_mut(k.clone_ref(value.py()), v.clone_ref(value.py()));
+                comparison_result(())?;
+            }
+        }
+        Ok(IntervalMapPy {
+            inner,
+            hash: OnceLock::new(),
+        })
+    }
+}
+
+#[pyclass(module = "rpds")]
+struct IntervalMapIterator {
+    inner: RedBlackTreeMapSync<Interval, PyObject>,
+}
+
+#[pymethods]
+impl IntervalMapIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<Option<Interval>> {
+        let Some((first, _)) = slf.inner.first() else {
+            return Ok(None);
+        };
+        let first = first.clone_ref(slf.py());
+        slf.inner.remove_mut(&first);
+        comparison_result(Some(first))
+    }
+}
+
+// A check is either a type (or tuple of types) for an isinstance check, or
+// a callable which returns whether the value is acceptable.
+fn check_value(check: &Option<PyObject>, value: &Bound<'_, PyAny>, what: &str) -> PyResult<()> {
+    let Some(check) = check else {
+        return Ok(());
+    };
+    let check = check.bind(value.py());
+    let ok = match check.is_instance_of::<PyType>() || check.is_instance_of::<PyTuple>() {
+        true => value.is_instance(check)?,
+        false => check.call1((value,))?.is_truthy()?,
+    };
+    match ok {
+        true => Ok(()),
+        false => Err(PyTypeError::new_err(format!(
+            "{} {} does not satisfy {}",
+            what,
+            value.repr()?,
+            check.repr()?,
+        ))),
+    }
+}
+
+#[pyclass(name = "CheckedHashTrieMap", module = "rpds", frozen, mapping)]
+struct CheckedHashTrieMapPy {
     inner: HashTrieMapSync<Key, PyObject>,
+    key_type: Option<PyObject>,
+    value_type: Option<PyObject>,
+    hash: OnceLock<isize>,
 }
 
-impl From<HashTrieMapSync<Key, PyObject>> for HashTrieMapPy {
-    fn from(map: HashTrieMapSync<Key, PyObject>) -> Self {
-        HashTrieMapPy { inner: map }
+impl CheckedHashTrieMapPy {
+    fn as_map(&self) -> HashTrieMapPy {
+        HashTrieMapPy::from(self.inner.clone())
     }
-}
 
-impl<'source> FromPyObject<'source> for HashTrieMapPy {
-    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
-        let mut ret = HashTrieMap::new_sync();
-        if let Ok(mapping) = ob.downcast::<PyMapping>() {
-            for each in mapping.items()?.iter() {
-                let (k, v): (Key, PyObject) = each.extract()?;
-                ret.insert_mut(k, v);
-            }
-        } else {
-            for each in ob.try_iter()? {
-                let (k, v) = each?.extract()?;
-                ret.insert_mut(k, v);
-            }
+    fn derive(&self, inner: HashTrieMapSync<Key, PyObject>, py: Python) -> Self {
+        CheckedHashTrieMapPy {
+            hash: OnceLock::new(),
+            inner,
+            key_type: self.key_type.as_ref().map(|check| check.clone_ref(py)),
+            value_type: self.value_type.as_ref().map(|check| check.clone_ref(py)),
         }
-        Ok(HashTrieMapPy { inner: ret })
+    }
+
+    fn check(&self, key: &Key, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        check_value(&self.key_type, key.inner.bind(value.py()), "key")?;
+        check_value(&self.value_type, value, "value")
     }
 }
 
 #[pymethods]
-impl HashTrieMapPy {
+impl CheckedHashTrieMapPy {
     #[new]
-    #[pyo3(signature = (value=None, ** kwds))]
-    fn init(value: Option<HashTrieMapPy>, kwds: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
-        let mut map: HashTrieMapPy;
-        if let Some(value) = value {
-            map = value;
-        } else {
-            map = HashTrieMapPy {
-                inner: HashTrieMap::new_sync(),
-            };
+    #[pyo3(signature = (value=None, key_type=None, value_type=None, **kwds))]
+    fn init(
+        value: Option<HashTrieMapPy>,
+        key_type: Option<PyObject>,
+        value_type: Option<PyObject>,
+        kwds: Option<&Bound<'_, PyDict>>,
+        py: Python,
+    ) -> PyResult<Self> {
+        let map = CheckedHashTrieMapPy {
+            hash: OnceLock::new(),
+            inner: HashTrieMap::new_sync(),
+            key_type,
+            value_type,
+        };
+        let mut inner = value.map_or_else(HashTrieMap::new_sync, |value| value.inner);
+        for (k, v) in &inner {
+            map.check(k, v.bind(py))?;
         }
         if let Some(kwds) = kwds {
             for (k, v) in kwds {
-                map.inner.insert_mut(Key::extract_bound(&k)?, v.into());
+                let k = Key::extract_bound(&k)?;
+                map.check(&k, &v)?;
+                inner.insert_mut(k, v.unbind());
             }
         }
-        Ok(map)
+        Ok(CheckedHashTrieMapPy { inner, ..map })
+    }
+
+    #[getter]
+    fn key_type(&self, py: Python) -> Option<PyObject> {
+        self.key_type.as_ref().map(|check| check.clone_ref(py))
+    }
+
+    #[getter]
+    fn value_type(&self, py: Python) -> Option<PyObject> {
+        self.value_type.as_ref().map(|check| check.clone_ref(py))
     }
 
     fn __contains__(&self, key: Key) -> bool {
@@ -144,10 +8454,7 @@ impl HashTrieMapPy {
     }
 
     fn __getitem__(&self, key: Key, py: Python) -> PyResult<PyObject> {
-        match self.inner.get(&key) {
-            Some(value) => Ok(value.clone_ref(py)),
-            None => Err(PyKeyError::new_err(key)),
-        }
+        self.as_map().__getitem__(key, py)
     }
 
     fn __len__(&self) -> usize {
@@ -155,190 +8462,272 @@ impl HashTrieMapPy {
     }
 
     fn __repr__(&self, py: Python) -> String {
-        let contents = self.inner.into_iter().map(|(k, v)| {
-            format!(
-                "{}: {}",
-                k.inner
-                    .call_method0(py, "__repr__")
-                    .and_then(|r| r.extract(py))
-                    .unwrap_or("<repr error>".to_owned()),
-                v.call_method0(py, "__repr__")
-                    .and_then(|r| r.extract(py))
-                    .unwrap_or("<repr error>".to_owned())
-            )
-        });
-        format!(
-            "HashTrieMap({{{}}})",
-            contents.collect::<Vec<_>>().join(", ")
-        )
+        format!("Checked{}", self.as_map().__repr__(py))
     }
 
-    fn __richcmp__<'py>(&self, other: &Self, op: CompareOp, py: Python<'py>) -> PyResult<PyObject> {
-        match op {
-            CompareOp::Eq => (self.inner.size() == other.inner.size()
-                && self
-                    .inner
-                    .iter()
-                    .map(|(k1, v1)| (v1, other.inner.get(k1)))
-                    .map(|(v1, v2)| v1.bind(py).eq(v2))
-                    .all(|r| r.unwrap_or(false)))
-            .into_pyobject(py)
-            .map_err(Into::into)
-            .map(BoundObject::into_any)
-            .map(BoundObject::unbind),
-            CompareOp::Ne => (self.inner.size() != other.inner.size()
-                || self
-                    .inner
-                    .iter()
-                    .map(|(k1, v1)| (v1, other.inner.get(k1)))
-                    .map(|(v1, v2)| v1.bind(py).ne(v2))
-                    .all(|r| r.unwrap_or(true)))
-            .into_pyobject(py)
-            .map_err(Into::into)
-            .map(BoundObject::into_any)
-            .map(BoundObject::unbind),
-            _ => Ok(py.NotImplemented()),
+    fn __richcmp__<'py>(
+        &self,
+        other: &Bound<'py, PyAny>,
+        op: CompareOp,
+        py: Python<'py>,
+    ) -> PyResult<PyObject> {
+        if let Ok(other) = other.downcast::<CheckedHashTrieMapPy>() {
+            let other = Bound::new(py, other.get().as_map())?;
+            return self.as_map().__richcmp__(other.as_any(), op, py);
         }
+        self.as_map().__richcmp__(other, op, py)
     }
 
     fn __hash__(&self, py: Python) -> PyResult<isize> {
-        // modified from https://github.com/python/cpython/blob/d69529d31ccd1510843cfac1ab53bb8cb027541f/Objects/setobject.c#L715
+        if let Some(hash) = self.hash.get() {
+            return Ok(*hash);
+        }
+        let hash = self.as_map().__hash__(py)?;
+        Ok(*self.hash.get_or_init(|| hash))
+    }
 
-        let mut hash_val = self
+    fn __reduce__(slf: PyRef<'_, Self>) -> PyResult<(Bound<'_, PyType>, Bound<'_, PyTuple>)> {
+        let py = slf.py();
+        let items: Vec<(Key, PyObject)> = slf
             .inner
             .iter()
-            .map(|(key, val)| {
-                let mut hasher = DefaultHasher::new();
-                let val_bound = val.bind(py);
-
-                let key_hash = key.hash;
-                let val_hash = val_bound.hash().map_err(|_| {
-                    PyTypeError::new_err(format!(
-                        "Unhashable type in HashTrieMap of key {}: {}",
-                        key.inner
-                            .bind(py)
-                            .repr()
-                            .and_then(|r| r.extract())
-                            .unwrap_or("<repr> error".to_string()),
-                        val_bound
-                            .repr()
-                            .and_then(|r| r.extract())
-                            .unwrap_or("<repr> error".to_string())
-                    ))
-                })?;
-
-                hasher.write_isize(key_hash);
-                hasher.write_isize(val_hash);
-
-                Ok(hasher.finish() as usize)
-            })
-            .try_fold(0, |acc: usize, x: PyResult<usize>| {
-                PyResult::<usize>::Ok(acc ^ hash_shuffle_bits(x?))
-            })?;
+            .map(|(k, v)| (k.clone_ref(py), v.clone_ref(py)))
+            .collect();
+        Ok((
+            CheckedHashTrieMapPy::type_object(py),
+            (items, slf.key_type(py), slf.value_type(py)).into_pyobject(py)?,
+        ))
+    }
 
-        // factor in the number of entries in the collection
-        hash_val ^= self.inner.size().wrapping_add(1).wrapping_mul(1927868237);
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, key: Key, default: Option<PyObject>, py: Python) -> Option<PyObject> {
+        self.as_map().get(key, default, py)
+    }
 
-        // dispense patterns in the hash value
-        hash_val ^= (hash_val >> 11) ^ (hash_val >> 25);
-        hash_val = hash_val.wrapping_mul(69069).wrapping_add(907133923);
+    fn keys(&self) -> KeysView {
+        self.as_map().keys()
+    }
 
-        Ok(hash_val as isize)
+    fn values(&self) -> ValuesView {
+        self.as_map().values()
     }
 
-    fn __reduce__(slf: PyRef<Self>) -> (Bound<'_, PyType>, (Vec<(Key, PyObject)>,)) {
-        (
-            HashTrieMapPy::type_object(slf.py()),
-            (slf.inner
-                .iter()
-                .map(|(k, v)| (k.clone_ref(slf.py()), v.clone_ref(slf.py())))
-                .collect(),),
-        )
+    fn items(&self) -> ItemsView {
+        self.as_map().items()
     }
 
-    #[classmethod]
-    fn convert(
-        _cls: &Bound<'_, PyType>,
-        value: Bound<'_, PyAny>,
-        py: Python,
-    ) -> PyResult<PyObject> {
-        if value.is_instance_of::<HashTrieMapPy>() {
-            Ok(value.unbind())
-        } else {
-            HashTrieMapPy::extract_bound(&value)?
-                .into_pyobject(py)
-                .map(BoundObject::into_any)
-                .map(BoundObject::unbind)
+    fn discard(&self, key: Key, py: Python) -> CheckedHashTrieMapPy {
+        self.derive(self.inner.remove(&key), py)
+    }
+
+    fn insert(&self, key: Key, value: Bound<'_, PyAny>) -> PyResult<CheckedHashTrieMapPy> {
+        self.check(&key, &value)?;
+        let py = value.py();
+        Ok(self.derive(self.inner.insert(key, value.unbind()), py))
+    }
+
+    fn remove(&self, key: Key, py: Python) -> PyResult<CheckedHashTrieMapPy> {
+        match self.inner.contains_key(&key) {
+            true => Ok(self.derive(self.inner.remove(&key), py)),
+            false => Err(PyKeyError::new_err(key)),
         }
     }
 
-    #[classmethod]
-    #[pyo3(signature = (keys, val=None))]
-    fn fromkeys(
-        _cls: &Bound<'_, PyType>,
-        keys: &Bound<'_, PyAny>,
-        val: Option<&Bound<'_, PyAny>>,
-        py: Python,
-    ) -> PyResult<HashTrieMapPy> {
-        let mut inner = HashTrieMap::new_sync();
-        let none = py.None().into_bound(py);
-        let value = val.unwrap_or(&none);
-        for each in keys.try_iter()? {
-            let key = Key::extract_bound(&each?)?;
-            inner.insert_mut(key, value.clone().unbind());
+    #[pyo3(signature = (*maps, **kwds))]
+    fn update(
+        &self,
+        maps: &Bound<'_, PyTuple>,
+        kwds: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<CheckedHashTrieMapPy> {
+        let py = maps.py();
+        let mut inner = self.inner.clone();
+        for value in maps {
+            let map = HashTrieMapPy::extract_bound(&value)?;
+            for (k, v) in &map.inner {
+                self.check(k, v.bind(py))?;
+                inner.insert_mut(k.clone_ref(py), v.clone_ref(py));
+            }
+        }
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                let k = Key::extract_bound(&k)?;
+                self.check(&k, &v)?;
+                inner.insert_mut(k, v.unbind());
+            }
         }
-        Ok(HashTrieMapPy { inner })
+        Ok(self.derive(inner, py))
     }
+}
 
-    #[pyo3(signature = (key, default=None))]
-    fn get(&self, key: Key, default: Option<PyObject>, py: Python) -> Option<PyObject> {
-        if let Some(value) = self.inner.get(&key) {
-            Some(value.clone_ref(py))
-        } else {
-            default
+// A map which, like collections.defaultdict, produces factory() for missing
+// keys, though without inserting it unless asked to via add_default.
+#[pyclass(name = "DefaultHashTrieMap", module = "rpds", frozen, mapping)]
+struct DefaultHashTrieMapPy {
+    inner: HashTrieMapSync<Key, PyObject>,
+    factory: PyObject,
+    hash: OnceLock<isize>,
+}
+
+impl DefaultHashTrieMapPy {
+    fn as_map(&self) -> HashTrieMapPy {
+        HashTrieMapPy::from(self.inner.clone())
+    }
+
+    fn derive(&self, inner: HashTrieMapSync<Key, PyObject>, py: Python) -> Self {
+        DefaultHashTrieMapPy {
+            hash: OnceLock::new(),
+            inner,
+            factory: self.factory.clone_ref(py),
         }
     }
+}
 
-    fn keys(&self) -> KeysView {
-        KeysView {
-            inner: self.inner.clone(),
+#[pymethods]
+impl DefaultHashTrieMapPy {
+    #[new]
+    #[pyo3(signature = (factory, value=None, **kwds))]
+    fn init(
+        factory: &Bound<'_, PyAny>,
+        value: Option<HashTrieMapPy>,
+        kwds: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        if !factory.is_callable() {
+            return Err(PyTypeError::new_err(format!(
+                "the default factory must be callable, not {}",
+                factory.repr()?
+            )));
+        }
+        let mut inner = value.map_or_else(HashTrieMap::new_sync, |value| value.inner);
+        if let Some(kwds) = kwds {
+            for (k, v) in kwds {
+                inner.insert_mut(Key::extract_bound(&k)?, v.unbind());
+            }
         }
+        Ok(DefaultHashTrieMapPy {
+            hash: OnceLock::new(),
+            inner,
+            factory: factory.clone().unbind(),
+        })
     }
 
-    fn values(&self) -> ValuesView {
-        ValuesView {
-            inner: self.inner.clone(),
+    #[getter]
+    fn factory(&self, py: Python) -> PyObject {
+        self.factory.clone_ref(py)
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.inner.contains_key(&key)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> KeysIterator {
+        KeysIterator {
+            inner: slf.inner.clone(),
         }
     }
 
-    fn items(&self) -> ItemsView {
-        ItemsView {
-            inner: self.inner.clone(),
+    fn __getitem__(&self, key: Key, py: Python) -> PyResult<PyObject> {
+        match self.inner.get(&key) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => self.factory.call0(py),
         }
     }
 
-    fn discard(&self, key: Key) -> PyResult<HashTrieMapPy> {
-        match self.inner.contains_key(&key) {
-            true => Ok(HashTrieMapPy {
-                inner: self.inner.remove(&key),
-            }),
-            false => Ok(HashTrieMapPy {
-                inner: self.inner.clone(),
-            }),
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let contents = self.as_map().__repr__(py);
+        let contents = &contents["HashTrieMap(".len()..contents.len() - 1];
+        Ok(format!(
+            "DefaultHashTrieMap({}, {})",
+            self.factory.bind(py).repr()?,
+            contents
+        ))
+    }
+
+    fn __richcmp__<'py>(
+        &self,
+        other: &Bound<'py, PyAny>,
+        op: CompareOp,
+        py: Python<'py>,
+    ) -> PyResult<PyObject> {
+        if let Ok(other) = other.downcast::<DefaultHashTrieMapPy>() {
+            let other = Bound::new(py, other.get().as_map())?;
+            return self.as_map().__richcmp__(other.as_any(), op, py);
         }
+        self.as_map().__richcmp__(other, op, py)
     }
 
-    fn insert(&self, key: Key, value: Bound<'_, PyAny>) -> HashTrieMapPy {
-        HashTrieMapPy {
-            inner: self.inner.insert(key, value.unbind()),
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        if let Some(hash) = self.hash.get() {
+            return Ok(*hash);
         }
+        let hash = self.as_map().__hash__(py)?;
+        Ok(*self.hash.get_or_init(|| hash))
     }
 
-    fn remove(&self, key: Key) -> PyResult<HashTrieMapPy> {
+    fn __reduce__(slf: PyRef<'_, Self>) -> PyResult<(Bound<'_, PyType>, Bound<'_, PyTuple>)> {
+        let py = slf.py();
+        let items: Vec<(Key, PyObject)> = slf
+            .inner
+            .iter()
+            .map(|(k, v)| (k.clone_ref(py), v.clone_ref(py)))
+            .collect();
+        Ok((
+            DefaultHashTrieMapPy::type_object(py),
+            (slf.factory(py), items).into_pyobject(py)?,
+        ))
+    }
+
+    #[pyo3(signature = (key, *default))]
+    fn get(&self, key: Key, default: &Bound<'_, PyTuple>, py: Python) -> PyResult<PyObject> {
+        if default.len() > 1 {
+            return Err(PyTypeError::new_err(format!(
+                "get expected at most 2 arguments, got {}",
+                default.len() + 1
+            )));
+        }
+        match self.inner.get(&key) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None if default.is_empty() => self.factory.call0(py),
+            None => Ok(default.get_item(0)?.unbind()),
+        }
+    }
+
+    /// Insert the default value for key if it is missing.
+    fn add_default(&self, key: Key, py: Python) -> PyResult<DefaultHashTrieMapPy> {
+        if self.inner.contains_key(&key) {
+            return Ok(self.derive(self.inner.clone(), py));
+        }
+        let value = self.factory.call0(py)?;
+        Ok(self.derive(self.inner.insert(key, value), py))
+    }
+
+    fn keys(&self) -> KeysView {
+        self.as_map().keys()
+    }
+
+    fn values(&self) -> ValuesView {
+        self.as_map().values()
+    }
+
+    fn items(&self) -> ItemsView {
+        self.as_map().items()
+    }
+
+    fn discard(&self, key: Key, py: Python) -> DefaultHashTrieMapPy {
+        self.derive(self.inner.remove(&key), py)
+    }
+
+    fn insert(&self, key: Key, value: Bound<'_, PyAny>) -> DefaultHashTrieMapPy {
+        let py = value.py();
+        self.derive(self.inner.insert(key, value.unbind()), py)
+    }
+
+    fn remove(&self, key: Key, py: Python) -> PyResult<DefaultHashTrieMapPy> {
         match self.inner.contains_key(&key) {
-            true => Ok(HashTrieMapPy {
-                inner: self.inner.remove(&key),
-            }),
+            true => Ok(self.derive(self.inner.remove(&key), py)),
             false => Err(PyKeyError::new_err(key)),
         }
     }
@@ -348,472 +8737,415 @@ impl HashTrieMapPy {
         &self,
         maps: &Bound<'_, PyTuple>,
         kwds: Option<&Bound<'_, PyDict>>,
-    ) -> PyResult<HashTrieMapPy> {
-        let mut inner = self.inner.clone();
-        for value in maps {
-            let map = HashTrieMapPy::extract_bound(&value)?;
-            for (k, v) in &map.inner {
-                inner.insert_mut(k.clone_ref(value.py()), v.clone_ref(value.py()));
-            }
-        }
-        if let Some(kwds) = kwds {
-            for (k, v) in kwds {
-                inner.insert_mut(Key::extract_bound(&k)?, v.extract()?);
-            }
-        }
-        Ok(HashTrieMapPy { inner })
+    ) -> PyResult<DefaultHashTrieMapPy> {
+        let inner = self.as_map().update(maps, kwds)?.inner;
+        Ok(self.derive(inner, maps.py()))
     }
 }
 
-#[pyclass(module = "rpds")]
-struct KeysIterator {
-    inner: HashTrieMapSync<Key, PyObject>,
+#[pyclass(name = "CheckedHashTrieSet", module = "rpds", frozen)]
+struct CheckedHashTrieSetPy {
+    inner: HashTrieSetSync<Key>,
+    value_type: Option<PyObject>,
+    hash: OnceLock<isize>,
+}
+
+impl CheckedHashTrieSetPy {
+    fn as_set(&self) -> HashTrieSetPy {
+        HashTrieSetPy::from(self.inner.clone())
+    }
+
+    fn derive(&self, inner: HashTrieSetSync<Key>, py: Python) -> Self {
+        CheckedHashTrieSetPy {
+            hash: OnceLock::new(),
+            inner,
+            value_type: self.value_type.as_ref().map(|check| check.clone_ref(py)),
+        }
+    }
+
+    fn check_all(&self, values: &HashTrieSetSync<Key>, py: Python) -> PyResult<()> {
+        for value in values {
+            check_value(&self.value_type, value.inner.bind(py), "element")?;
+        }
+        Ok(())
+    }
 }
 
 #[pymethods]
-impl KeysIterator {
-    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
-        slf
+impl CheckedHashTrieSetPy {
+    #[new]
+    #[pyo3(signature = (value=None, value_type=None))]
+    fn init(
+        value: Option<HashTrieSetPy>,
+        value_type: Option<PyObject>,
+        py: Python,
+    ) -> PyResult<Self> {
+        let set = CheckedHashTrieSetPy {
+            hash: OnceLock::new(),
+            inner: value.map_or_else(HashTrieSet::new_sync, |value| value.inner),
+            value_type,
+        };
+        set.check_all(&set.inner, py)?;
+        Ok(set)
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<Key> {
-        let first = slf.inner.keys().next()?.clone_ref(slf.py());
-        slf.inner = slf.inner.remove(&first);
-        Some(first)
+    #[getter]
+    fn value_type(&self, py: Python) -> Option<PyObject> {
+        self.value_type.as_ref().map(|check| check.clone_ref(py))
     }
-}
 
-#[pyclass(module = "rpds")]
-struct ValuesIterator {
-    inner: HashTrieMapSync<Key, PyObject>,
-}
+    fn __contains__(&self, key: Key) -> bool {
+        self.inner.contains(&key)
+    }
 
-#[pymethods]
-impl ValuesIterator {
-    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
-        slf
+    fn __and__(&self, other: HashTrieSetPy, py: Python) -> PyResult<Self> {
+        self.intersection(other, py)
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
-        let kv = slf.inner.iter().next()?;
-        let value = kv.1.clone_ref(slf.py());
-        slf.inner = slf.inner.remove(kv.0);
-        Some(value)
+    fn __or__(&self, other: HashTrieSetPy, py: Python) -> PyResult<Self> {
+        self.union(other, py)
     }
-}
 
-#[pyclass(module = "rpds")]
-struct ItemsIterator {
-    inner: HashTrieMapSync<Key, PyObject>,
-}
+    fn __sub__(&self, other: HashTrieSetPy, py: Python) -> PyResult<Self> {
+        self.difference(other, py)
+    }
 
-#[pymethods]
-impl ItemsIterator {
-    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
-        slf
+    fn __xor__(&self, other: HashTrieSetPy, py: Python) -> PyResult<Self> {
+        self.symmetric_difference(other, py)
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<(Key, PyObject)> {
-        let kv = slf.inner.iter().next()?;
-        let key = kv.0.clone_ref(slf.py());
-        let value = kv.1.clone_ref(slf.py());
+    fn __iter__(slf: PyRef<'_, Self>) -> SetIterator {
+        SetIterator {
+            inner: slf.inner.clone(),
+        }
+    }
 
-        slf.inner = slf.inner.remove(kv.0);
+    fn __len__(&self) -> usize {
+        self.inner.size()
+    }
 
-        Some((key, value))
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!("Checked{}", self.as_set().__repr__(py)?))
     }
-}
 
-#[pyclass(module = "rpds")]
-struct KeysView {
-    inner: HashTrieMapSync<Key, PyObject>,
-}
+    fn __eq__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<bool> {
+        set_eq(slf.as_any(), &slf.get().inner, other)
+    }
 
-#[pymethods]
-impl KeysView {
-    fn __contains__(&self, key: Key) -> bool {
-        self.inner.contains_key(&key)
+    fn __hash__(&self) -> PyResult<isize> {
+        set_hash(&self.inner, &self.hash)
     }
 
-    fn __eq__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? != slf.inner.size() {
-            return Ok(false);
-        }
-        for each in other.try_iter()? {
-            if !slf.inner.contains_key(&Key::extract_bound(&each?)?) {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn __lt__(&self, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        HashTrieSetPy::__lt__(Bound::new(py, self.as_set())?.borrow(), other, py)
     }
 
-    fn __lt__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? <= slf.inner.size() {
-            return Ok(false);
-        }
+    fn __le__(&self, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        HashTrieSetPy::__le__(Bound::new(py, self.as_set())?.borrow(), other, py)
+    }
 
-        for each in slf.inner.keys() {
-            if !other.contains(each.inner.clone_ref(slf.py()))? {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn __gt__(&self, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        HashTrieSetPy::__gt__(Bound::new(py, self.as_set())?.borrow(), other, py)
     }
 
-    fn __le__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? < slf.inner.size() {
-            return Ok(false);
-        }
+    fn __ge__(&self, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
+        HashTrieSetPy::__ge__(Bound::new(py, self.as_set())?.borrow(), other, py)
+    }
 
-        for each in slf.inner.keys() {
-            if !other.contains(each.inner.clone_ref(slf.py()))? {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<Key>, Option<PyObject>)) {
+        let py = slf.py();
+        (
+            CheckedHashTrieSetPy::type_object(py),
+            (
+                slf.inner.iter().map(|e| e.clone_ref(py)).collect(),
+                slf.value_type(py),
+            ),
+        )
     }
 
-    fn __gt__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? >= slf.inner.size() {
-            return Ok(false);
-        }
-        for each in other.try_iter()? {
-            if !slf.inner.contains_key(&Key::extract_bound(&each?)?) {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn insert(&self, value: Key, py: Python) -> PyResult<CheckedHashTrieSetPy> {
+        check_value(&self.value_type, value.inner.bind(py), "element")?;
+        Ok(self.derive(self.inner.insert(value), py))
     }
 
-    fn __ge__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? > slf.inner.size() {
-            return Ok(false);
-        }
-        for each in other.try_iter()? {
-            if !slf.inner.contains_key(&Key::extract_bound(&each?)?) {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn discard(&self, value: Key, py: Python) -> CheckedHashTrieSetPy {
+        self.derive(self.inner.remove(&value), py)
     }
 
-    fn __iter__(slf: PyRef<'_, Self>) -> KeysIterator {
-        KeysIterator {
-            inner: slf.inner.clone(),
+    fn remove(&self, value: Key, py: Python) -> PyResult<CheckedHashTrieSetPy> {
+        match self.inner.contains(&value) {
+            true => Ok(self.derive(self.inner.remove(&value), py)),
+            false => Err(PyKeyError::new_err(value)),
         }
     }
 
-    fn __len__(slf: PyRef<'_, Self>) -> usize {
-        slf.inner.size()
+    fn difference(&self, other: HashTrieSetPy, py: Python) -> PyResult<CheckedHashTrieSetPy> {
+        Ok(self.derive(self.as_set().set_difference(&other).inner, py))
     }
 
-    fn __and__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<HashTrieSetPy> {
-        KeysView::intersection(slf, other)
+    fn intersection(&self, other: HashTrieSetPy, py: Python) -> PyResult<CheckedHashTrieSetPy> {
+        Ok(self.derive(self.as_set().set_intersection(&other, py).inner, py))
     }
 
-    fn __or__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<KeysView> {
-        KeysView::union(slf, other, py)
+    fn symmetric_difference(
+        &self,
+        other: HashTrieSetPy,
+        py: Python,
+    ) -> PyResult<CheckedHashTrieSetPy> {
+        self.check_all(&other.inner, py)?;
+        Ok(self.derive(self.as_set().set_symmetric_difference(&other, py).inner, py))
     }
 
-    fn __repr__(&self, py: Python) -> PyResult<String> {
-        let contents = self.inner.into_iter().map(|(k, _)| {
-            Ok(k.clone_ref(py)
-                .inner
-                .into_pyobject(py)?
-                .call_method0("__repr__")
-                .and_then(|r| r.extract())
-                .unwrap_or("<repr failed>".to_owned()))
-        });
-        let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
-        Ok(format!("keys_view({{{}}})", contents.join(", ")))
+    fn union(&self, other: HashTrieSetPy, py: Python) -> PyResult<CheckedHashTrieSetPy> {
+        self.check_all(&other.inner, py)?;
+        Ok(self.derive(self.as_set().set_union(&other, py).inner, py))
     }
 
-    fn intersection(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<HashTrieSetPy> {
-        // TODO: iterate over the shorter one if it's got a length
-        let mut inner = HashTrieSet::new_sync();
-        for each in other.try_iter()? {
-            let key = Key::extract_bound(&each?)?;
-            if slf.inner.contains_key(&key) {
-                inner.insert_mut(key);
+    #[pyo3(signature = (*iterables))]
+    fn update(&self, iterables: Bound<'_, PyTuple>) -> PyResult<CheckedHashTrieSetPy> {
+        let py = iterables.py();
+        let mut inner = self.inner.clone();
+        for each in iterables {
+            for value in each.try_iter()? {
+                let value = value?;
+                check_value(&self.value_type, &value, "element")?;
+                inner.insert_mut(Key::extract_bound(&value)?);
             }
         }
-        Ok(HashTrieSetPy { inner })
+        Ok(self.derive(inner, py))
     }
+}
 
-    fn union(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<KeysView> {
-        // There doesn't seem to be a low-effort way to get a HashTrieSet out of a map,
-        // so we just keep our map and add values we'll ignore.
-        let mut inner = slf.inner.clone();
-        for each in other.try_iter()? {
-            inner.insert_mut(Key::extract_bound(&each?)?, py.None());
+// The rpds.local variants below are backed by Rc rather than Arc, so they're
+// unsendable and raise if used from any thread other than their creator's.
+// They deliberately offer only the core of their rpds counterparts' APIs (see
+// LOCAL_DOC), with any behaviour they share implemented once above.
+
+#[pyclass(
+    name = "HashTrieMap",
+    module = "rpds.local",
+    unsendable,
+    frozen,
+    mapping
+)]
+struct LocalHashTrieMapPy {
+    inner: HashTrieMap<Key, PyObject>,
+    hash: OnceLock<isize>,
+}
+
+impl From<HashTrieMap<Key, PyObject>> for LocalHashTrieMapPy {
+    fn from(map: HashTrieMap<Key, PyObject>) -> Self {
+        LocalHashTrieMapPy {
+            inner: map,
+            hash: OnceLock::new(),
         }
-        Ok(KeysView { inner })
     }
 }
 
-#[pyclass(module = "rpds")]
-struct ValuesView {
-    inner: HashTrieMapSync<Key, PyObject>,
+impl<'source> FromPyObject<'source> for LocalHashTrieMapPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        let mut ret = HashTrieMap::new();
+        insert_items(&mut ret, ob)?;
+        Ok(LocalHashTrieMapPy::from(ret))
+    }
 }
 
 #[pymethods]
-impl ValuesView {
-    fn __iter__(slf: PyRef<'_, Self>) -> ValuesIterator {
-        ValuesIterator {
-            inner: slf.inner.clone(),
-        }
+impl LocalHashTrieMapPy {
+    #[new]
+    #[pyo3(signature = (value=None, ** kwds))]
+    fn init(value: Option<LocalHashTrieMapPy>, kwds: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let inner = value.map_or_else(HashTrieMap::new, |map| map.inner);
+        Ok(LocalHashTrieMapPy::from(map_with_kwds(inner, kwds)?))
     }
 
-    fn __len__(slf: PyRef<'_, Self>) -> usize {
-        slf.inner.size()
+    fn __contains__(&self, key: Key) -> bool {
+        self.inner.contains_key(&key)
     }
 
-    fn __repr__(&self, py: Python) -> PyResult<String> {
-        let contents = self.inner.into_iter().map(|(_, v)| {
-            Ok(v.into_pyobject(py)?
-                .call_method0("__repr__")
-                .and_then(|r| r.extract())
-                .unwrap_or("<repr failed>".to_owned()))
-        });
-        let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
-        Ok(format!("values_view([{}])", contents.join(", ")))
+    fn __iter__(slf: PyRef<'_, Self>) -> LocalKeysIterator {
+        LocalKeysIterator {
+            inner: slf.inner.clone(),
+        }
     }
-}
-
-#[pyclass(module = "rpds")]
-struct ItemsView {
-    inner: HashTrieMapSync<Key, PyObject>,
-}
-
-#[derive(FromPyObject)]
-struct ItemViewQuery(Key, PyObject);
 
-#[pymethods]
-impl ItemsView {
-    fn __contains__(slf: PyRef<'_, Self>, item: ItemViewQuery) -> PyResult<bool> {
-        if let Some(value) = slf.inner.get(&item.0) {
-            return item.1.bind(slf.py()).eq(value);
+    fn __getitem__(&self, key: Key, py: Python) -> PyResult<PyObject> {
+        match self.inner.get(&key) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key)),
         }
+    }
 
-        Ok(false)
+    fn __len__(&self) -> usize {
+        self.inner.size()
     }
 
-    fn __iter__(slf: PyRef<'_, Self>) -> ItemsIterator {
-        ItemsIterator {
-            inner: slf.inner.clone(),
-        }
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        collection_size::<(Key, PyObject), (usize, u64)>(slf.as_any(), slf.borrow().inner.size())
     }
 
-    fn __len__(slf: PyRef<'_, Self>) -> usize {
-        slf.inner.size()
+    fn __repr__(&self, py: Python) -> String {
+        map_repr(&self.inner, py)
     }
 
-    fn __eq__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? != slf.inner.size() {
-            return Ok(false);
-        }
-        for (k, v) in slf.inner.iter() {
-            if !other.contains((k.inner.clone_ref(slf.py()), v))? {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn __richcmp__<'py>(
+        &self,
+        other: &Bound<'py, PyAny>,
+        op: CompareOp,
+        py: Python<'py>,
+    ) -> PyResult<PyObject> {
+        let same = other
+            .downcast::<LocalHashTrieMapPy>()
+            .ok()
+            .map(|map| map.borrow());
+        map_richcmp(
+            &self.inner,
+            same.as_ref().map(|map| &map.inner),
+            other,
+            op,
+            py,
+        )
     }
 
-    fn __repr__(&self, py: Python) -> PyResult<String> {
-        let contents = self.inner.into_iter().map(|(k, v)| {
-            let tuple = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
-            Ok(format!("{:?}", tuple))
-        });
-        let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
-        Ok(format!("items_view([{}])", contents.join(", ")))
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        map_hash(&self.inner, &self.hash, py)
     }
 
-    fn __lt__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? <= slf.inner.size() {
-            return Ok(false);
-        }
-        for (k, v) in slf.inner.iter() {
-            let pair = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
-            // FIXME: needs to compare
-            if !other.contains(pair)? {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<(Key, PyObject)>,)) {
+        (
+            LocalHashTrieMapPy::type_object(slf.py()),
+            (slf.inner
+                .iter()
+                .map(|(k, v)| (k.clone_ref(slf.py()), v.clone_ref(slf.py())))
+                .collect(),),
+        )
     }
 
-    fn __le__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? < slf.inner.size() {
-            return Ok(false);
-        }
-        for (k, v) in slf.inner.iter() {
-            let pair = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
-            // FIXME: needs to compare
-            if !other.contains(pair)? {
-                return Ok(false);
-            }
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, key: Key, default: Option<PyObject>, py: Python) -> Option<PyObject> {
+        match self.inner.get(&key) {
+            Some(value) => Some(value.clone_ref(py)),
+            None => default,
         }
-        Ok(true)
     }
 
-    fn __gt__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? >= slf.inner.size() {
-            return Ok(false);
-        }
-        for each in other.try_iter()? {
-            let kv = each?;
-            let k = kv.get_item(0)?;
-            match slf.inner.get(&Key::extract_bound(&k)?) {
-                Some(value) => {
-                    let pair = PyTuple::new(py, [k, value.bind(py).clone()])?;
-                    if !pair.eq(kv)? {
-                        return Ok(false);
-                    }
-                }
-                None => return Ok(false),
-            }
+    fn keys(&self) -> LocalKeysView {
+        LocalKeysView {
+            inner: self.inner.clone(),
         }
-        Ok(true)
     }
 
-    fn __ge__(slf: PyRef<'_, Self>, other: &Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? > slf.inner.size() {
-            return Ok(false);
+    fn values(&self) -> LocalValuesView {
+        LocalValuesView {
+            inner: self.inner.clone(),
         }
-        for each in other.try_iter()? {
-            let kv = each?;
-            let k = kv.get_item(0)?;
-            match slf.inner.get(&Key::extract_bound(&k)?) {
-                Some(value) => {
-                    let pair = PyTuple::new(py, [k, value.bind(py).clone()])?;
-                    if !pair.eq(kv)? {
-                        return Ok(false);
-                    }
-                }
-                None => return Ok(false),
-            }
+    }
+
+    fn items(&self) -> LocalItemsView {
+        LocalItemsView {
+            inner: self.inner.clone(),
         }
-        Ok(true)
     }
 
-    fn __and__(
-        slf: PyRef<'_, Self>,
-        other: &Bound<'_, PyAny>,
-        py: Python,
-    ) -> PyResult<HashTrieSetPy> {
-        ItemsView::intersection(slf, other, py)
+    fn discard(&self, key: Key) -> LocalHashTrieMapPy {
+        LocalHashTrieMapPy::from(self.inner.remove(&key))
     }
 
-    fn __or__(
-        slf: PyRef<'_, Self>,
-        other: &Bound<'_, PyAny>,
-        py: Python,
-    ) -> PyResult<HashTrieSetPy> {
-        ItemsView::union(slf, other, py)
+    fn insert(&self, key: Key, value: Bound<'_, PyAny>) -> LocalHashTrieMapPy {
+        LocalHashTrieMapPy::from(self.inner.insert(key, value.unbind()))
     }
 
-    fn intersection(
-        slf: PyRef<'_, Self>,
-        other: &Bound<'_, PyAny>,
-        py: Python,
-    ) -> PyResult<HashTrieSetPy> {
-        // TODO: iterate over the shorter one if it's got a length
-        let mut inner = HashTrieSet::new_sync();
-        for each in other.try_iter()? {
-            let kv = each?;
-            let k = kv.get_item(0)?;
-            if let Some(value) = slf.inner.get(&Key::extract_bound(&k)?) {
-                let pair = PyTuple::new(py, [k, value.bind(py).clone()])?;
-                if pair.eq(kv)? {
-                    inner.insert_mut(Key::extract_bound(&pair)?);
-                }
-            }
+    fn remove(&self, key: Key) -> PyResult<LocalHashTrieMapPy> {
+        match self.inner.contains_key(&key) {
+            true => Ok(LocalHashTrieMapPy::from(self.inner.remove(&key))),
+            false => Err(PyKeyError::new_err(key)),
         }
-        Ok(HashTrieSetPy { inner })
     }
 
-    fn union(
-        slf: PyRef<'_, Self>,
-        other: &Bound<'_, PyAny>,
-        py: Python,
-    ) -> PyResult<HashTrieSetPy> {
-        // TODO: this is very inefficient, but again can't seem to get a HashTrieSet out of ourself
-        let mut inner = HashTrieSet::new_sync();
-        for (k, v) in slf.inner.iter() {
-            let pair = PyTuple::new(py, [k.inner.clone_ref(py), v.clone_ref(py)])?;
-            inner.insert_mut(Key::extract_bound(&pair)?);
-        }
-        for each in other.try_iter()? {
-            inner.insert_mut(Key::extract_bound(&each?)?);
-        }
-        Ok(HashTrieSetPy { inner })
+    #[pyo3(signature = (*maps, **kwds))]
+    fn update(
+        &self,
+        maps: &Bound<'_, PyTuple>,
+        kwds: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<LocalHashTrieMapPy> {
+        Ok(LocalHashTrieMapPy::from(map_updated(
+            &self.inner,
+            maps,
+            kwds,
+        )?))
     }
 }
 
-#[repr(transparent)]
-#[pyclass(name = "HashTrieSet", module = "rpds", frozen)]
-struct HashTrieSetPy {
-    inner: HashTrieSetSync<Key>,
+#[pyclass(name = "HashTrieSet", module = "rpds.local", unsendable, frozen)]
+struct LocalHashTrieSetPy {
+    inner: HashTrieSet<Key>,
+    hash: OnceLock<isize>,
 }
 
-impl<'source> FromPyObject<'source> for HashTrieSetPy {
-    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
-        let mut ret = HashTrieSet::new_sync();
-        for each in ob.try_iter()? {
-            let k: Key = each?.extract()?;
-            ret.insert_mut(k);
+impl From<HashTrieSet<Key>> for LocalHashTrieSetPy {
+    fn from(set: HashTrieSet<Key>) -> Self {
+        LocalHashTrieSetPy {
+            inner: set,
+            hash: OnceLock::new(),
         }
-        Ok(HashTrieSetPy { inner: ret })
     }
 }
 
+hash_trie_companions! {
+    pyclass(module = "rpds.local", unsendable),
+    pointer: RcK,
+    map: LocalHashTrieMapPy,
+    set: LocalHashTrieSetPy,
+    views: (LocalKeysView, LocalValuesView, LocalItemsView),
+    iterators: (LocalKeysIterator, LocalValuesIterator, LocalItemsIterator, LocalSetIterator),
+}
+
 #[pymethods]
-impl HashTrieSetPy {
+impl LocalHashTrieSetPy {
     #[new]
     #[pyo3(signature = (value=None))]
-    fn init(value: Option<HashTrieSetPy>) -> Self {
-        if let Some(value) = value {
-            value
-        } else {
-            HashTrieSetPy {
-                inner: HashTrieSet::new_sync(),
-            }
-        }
+    fn init(value: Option<LocalHashTrieSetPy>) -> Self {
+        value.unwrap_or_else(|| LocalHashTrieSetPy::from(HashTrieSet::new()))
     }
 
     fn __contains__(&self, key: Key) -> bool {
         self.inner.contains(&key)
     }
 
-    fn __and__(&self, other: &Self, py: Python) -> Self {
-        self.intersection(other, py)
+    fn __and__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::intersection, py)
     }
 
-    fn __or__(&self, other: &Self, py: Python) -> Self {
-        self.union(other, py)
+    fn __or__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::union, py)
     }
 
-    fn __sub__(&self, other: &Self) -> Self {
-        self.difference(other)
+    fn __sub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::subtracted_from, py)
     }
 
-    fn __xor__(&self, other: &Self, py: Python) -> Self {
-        self.symmetric_difference(other, py)
+    fn __xor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::symmetric_difference, py)
     }
 
-    fn __iter__(slf: PyRef<'_, Self>) -> SetIterator {
-        SetIterator {
+    fn __rand__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.__and__(other, py)
+    }
+
+    fn __ror__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.__or__(other, py)
+    }
+
+    fn __rsub__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.apply_operator(other, SetOperand::without, py)
+    }
+
+    fn __rxor__(&self, other: &Bound<'_, PyAny>, py: Python) -> PyResult<PyObject> {
+        self.__xor__(other, py)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> LocalSetIterator {
+        LocalSetIterator {
             inner: slf.inner.clone(),
         }
     }
@@ -822,583 +9154,1151 @@ impl HashTrieSetPy {
         self.inner.size()
     }
 
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        collection_size::<Key, (usize, u64)>(slf.as_any(), slf.borrow().inner.size())
+    }
+
     fn __repr__(&self, py: Python) -> PyResult<String> {
-        let contents = self.inner.into_iter().map(|k| {
-            Ok(k.clone_ref(py)
-                .into_pyobject(py)?
-                .call_method0("__repr__")
-                .and_then(|r| r.extract())
-                .unwrap_or("<repr failed>".to_owned()))
-        });
-        let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
-        Ok(format!("HashTrieSet({{{}}})", contents.join(", ")))
+        set_repr(&self.inner, py)
     }
 
-    fn __eq__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? != slf.inner.size() {
-            return Ok(false);
-        }
-        for each in other.try_iter()? {
-            if !slf.inner.contains(&Key::extract_bound(&each?)?) {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn __eq__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<bool> {
+        set_eq(slf.as_any(), &slf.borrow().inner, other)
     }
 
     fn __hash__(&self) -> PyResult<isize> {
-        // modified from https://github.com/python/cpython/blob/d69529d31ccd1510843cfac1ab53bb8cb027541f/Objects/setobject.c#L715
-
-        let mut hash_val = self
-            .inner
-            .iter()
-            .map(|k| k.hash as usize)
-            .fold(0, |acc: usize, x: usize| acc ^ hash_shuffle_bits(x));
+        set_hash(&self.inner, &self.hash)
+    }
 
-        // factor in the number of entries in the collection
-        hash_val ^= self.inner.size().wrapping_add(1).wrapping_mul(1927868237);
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<Key>,)) {
+        (
+            LocalHashTrieSetPy::type_object(slf.py()),
+            (slf.inner.iter().map(|e| e.clone_ref(slf.py())).collect(),),
+        )
+    }
 
-        // dispense patterns in the hash value
-        hash_val ^= (hash_val >> 11) ^ (hash_val >> 25);
-        hash_val = hash_val.wrapping_mul(69069).wrapping_add(907133923);
+    fn insert(&self, value: Key) -> LocalHashTrieSetPy {
+        LocalHashTrieSetPy::from(self.inner.insert(value))
+    }
 
-        Ok(hash_val as isize)
+    fn discard(&self, value: Key) -> LocalHashTrieSetPy {
+        LocalHashTrieSetPy::from(self.inner.remove(&value))
     }
 
-    fn __lt__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? <= slf.inner.size() {
-            return Ok(false);
-        }
-        for each in slf.inner.iter() {
-            if !other.contains(each.inner.clone_ref(py))? {
-                return Ok(false);
-            }
+    fn remove(&self, value: Key) -> PyResult<LocalHashTrieSetPy> {
+        match self.inner.contains(&value) {
+            true => Ok(LocalHashTrieSetPy::from(self.inner.remove(&value))),
+            false => Err(PyKeyError::new_err(value)),
         }
-        Ok(true)
     }
 
-    fn __le__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? < slf.inner.size() {
-            return Ok(false);
-        }
-        for each in slf.inner.iter() {
-            if !other.contains(each.inner.clone_ref(slf.py()))? {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn difference(&self, other: &Self) -> LocalHashTrieSetPy {
+        self.set_difference(other)
     }
 
-    fn __gt__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? >= slf.inner.size() {
-            return Ok(false);
-        }
-        for each in other.try_iter()? {
-            if !slf.inner.contains(&Key::extract_bound(&each?)?) {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn intersection(&self, other: &Self, py: Python) -> LocalHashTrieSetPy {
+        self.set_intersection(other, py)
     }
 
-    fn __ge__(slf: PyRef<'_, Self>, other: Bound<'_, PyAny>, py: Python) -> PyResult<bool> {
-        let abc = PyModule::import(py, "collections.abc")?;
-        if !other.is_instance(&abc.getattr("Set")?)? || other.len()? > slf.inner.size() {
-            return Ok(false);
-        }
-        for each in other.try_iter()? {
-            if !slf.inner.contains(&Key::extract_bound(&each?)?) {
-                return Ok(false);
-            }
-        }
-        Ok(true)
+    fn symmetric_difference(&self, other: &Self, py: Python) -> LocalHashTrieSetPy {
+        self.set_symmetric_difference(other, py)
     }
 
-    fn __reduce__(slf: PyRef<Self>) -> (Bound<'_, PyType>, (Vec<Key>,)) {
-        (
-            HashTrieSetPy::type_object(slf.py()),
-            (slf.inner.iter().map(|e| e.clone_ref(slf.py())).collect(),),
-        )
+    fn union(&self, other: &Self, py: Python) -> LocalHashTrieSetPy {
+        self.set_union(other, py)
     }
 
-    fn insert(&self, value: Key) -> HashTrieSetPy {
-        HashTrieSetPy {
-            inner: self.inner.insert(value),
-        }
+    #[pyo3(signature = (*iterables))]
+    fn update(&self, iterables: Bound<'_, PyTuple>) -> PyResult<LocalHashTrieSetPy> {
+        Ok(LocalHashTrieSetPy::from(set_updated(
+            &self.inner,
+            &iterables,
+        )?))
     }
+}
 
-    fn discard(&self, value: Key) -> PyResult<HashTrieSetPy> {
-        match self.inner.contains(&value) {
-            true => Ok(HashTrieSetPy {
-                inner: self.inner.remove(&value),
-            }),
-            false => Ok(HashTrieSetPy {
-                inner: self.inner.clone(),
-            }),
+#[pyclass(name = "List", module = "rpds.local", unsendable, frozen, sequence)]
+struct LocalListPy {
+    inner: List<PyObject>,
+    hash: OnceLock<u64>,
+}
+
+impl From<List<PyObject>> for LocalListPy {
+    fn from(elements: List<PyObject>) -> Self {
+        LocalListPy {
+            inner: elements,
+            hash: OnceLock::new(),
         }
     }
+}
 
-    fn remove(&self, value: Key) -> PyResult<HashTrieSetPy> {
-        match self.inner.contains(&value) {
-            true => Ok(HashTrieSetPy {
-                inner: self.inner.remove(&value),
-            }),
-            false => Err(PyKeyError::new_err(value)),
+impl<'source> FromPyObject<'source> for LocalListPy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        if let Ok(list) = ob.downcast::<LocalListPy>() {
+            return Ok(LocalListPy::from(list.borrow().inner.clone()));
         }
+        Ok(LocalListPy::from(list_from_iterable(ob)?))
     }
+}
 
-    fn difference(&self, other: &Self) -> HashTrieSetPy {
-        let mut inner = self.inner.clone();
-        for value in other.inner.iter() {
-            inner.remove_mut(value);
+#[pymethods]
+impl LocalListPy {
+    #[new]
+    #[pyo3(signature = (*elements))]
+    fn init(elements: &Bound<'_, PyTuple>) -> PyResult<Self> {
+        if elements.len() == 1 {
+            return elements.get_item(0)?.extract();
         }
-        HashTrieSetPy { inner }
+        Ok(LocalListPy::from(list_from_iterable(elements)?))
     }
 
-    fn intersection(&self, other: &Self, py: Python) -> HashTrieSetPy {
-        let mut inner: HashTrieSetSync<Key> = HashTrieSet::new_sync();
-        let larger: &HashTrieSetSync<Key>;
-        let iter;
-        if self.inner.size() > other.inner.size() {
-            larger = &self.inner;
-            iter = other.inner.iter();
-        } else {
-            larger = &other.inner;
-            iter = self.inner.iter();
-        }
-        for value in iter {
-            if larger.contains(value) {
-                inner.insert_mut(value.clone_ref(py));
-            }
-        }
-        HashTrieSetPy { inner }
+    fn __len__(&self) -> usize {
+        self.inner.len()
     }
 
-    fn symmetric_difference(&self, other: &Self, py: Python) -> HashTrieSetPy {
-        let mut inner: HashTrieSetSync<Key>;
-        let iter;
-        if self.inner.size() > other.inner.size() {
-            inner = self.inner.clone();
-            iter = other.inner.iter();
-        } else {
-            inner = other.inner.clone();
-            iter = self.inner.iter();
-        }
-        for value in iter {
-            if inner.contains(value) {
-                inner.remove_mut(value);
-            } else {
-                inner.insert_mut(value.clone_ref(py));
-            }
-        }
-        HashTrieSetPy { inner }
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        collection_size::<PyObject, (usize, usize)>(slf.as_any(), slf.borrow().inner.len())
     }
 
-    fn union(&self, other: &Self, py: Python) -> HashTrieSetPy {
-        let mut inner: HashTrieSetSync<Key>;
-        let iter;
-        if self.inner.size() > other.inner.size() {
-            inner = self.inner.clone();
-            iter = other.inner.iter();
-        } else {
-            inner = other.inner.clone();
-            iter = self.inner.iter();
-        }
-        for value in iter {
-            inner.insert_mut(value.clone_ref(py));
-        }
-        HashTrieSetPy { inner }
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        sequence_repr("List", &self.inner, py)
     }
 
-    #[pyo3(signature = (*iterables))]
-    fn update(&self, iterables: Bound<'_, PyTuple>) -> PyResult<HashTrieSetPy> {
-        let mut inner = self.inner.clone();
-        for each in iterables {
-            let iter = each.try_iter()?;
-            for value in iter {
-                inner.insert_mut(Key::extract_bound(&value?)?);
-            }
+    fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python<'_>) -> PyResult<PyObject> {
+        sequence_richcmp(&self.inner, &other.inner, op, py)
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<u64> {
+        sequence_hash("List", &self.inner, &self.hash, py)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> LocalListIterator {
+        LocalListIterator {
+            inner: slf.inner.clone(),
         }
-        Ok(HashTrieSetPy { inner })
+    }
+
+    fn __reversed__(&self, py: Python) -> ReverseIterator {
+        sequence_reversed(&self.inner, py)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<PyObject>,)) {
+        (
+            LocalListPy::type_object(slf.py()),
+            (slf.inner.iter().map(|e| e.clone_ref(slf.py())).collect(),),
+        )
+    }
+
+    #[getter]
+    fn first(&self) -> PyResult<&PyObject> {
+        list_first(&self.inner)
+    }
+
+    #[getter]
+    fn rest(&self) -> LocalListPy {
+        LocalListPy::from(list_rest(&self.inner))
+    }
+
+    fn push_front(&self, other: PyObject) -> LocalListPy {
+        LocalListPy::from(self.inner.push_front(other))
+    }
+
+    fn drop_first(&self) -> PyResult<LocalListPy> {
+        Ok(LocalListPy::from(list_drop_first(&self.inner)?))
     }
 }
 
-#[pyclass(module = "rpds")]
-struct SetIterator {
-    inner: HashTrieSetSync<Key>,
+#[pyclass(module = "rpds.local", unsendable)]
+struct LocalListIterator {
+    inner: List<PyObject>,
 }
 
 #[pymethods]
-impl SetIterator {
+impl LocalListIterator {
     fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<Key> {
-        let first = slf.inner.iter().next()?.clone_ref(slf.py());
-        slf.inner = slf.inner.remove(&first);
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
+        let first = slf.inner.first()?.clone_ref(slf.py());
+        slf.inner.drop_first_mut();
         Some(first)
     }
 }
 
-#[repr(transparent)]
-#[pyclass(name = "List", module = "rpds", frozen, sequence)]
-struct ListPy {
-    inner: ListSync<PyObject>,
+#[pyclass(name = "Queue", module = "rpds.local", unsendable, frozen, sequence)]
+struct LocalQueuePy {
+    inner: Queue<PyObject>,
+    hash: OnceLock<u64>,
 }
 
-impl From<ListSync<PyObject>> for ListPy {
-    fn from(elements: ListSync<PyObject>) -> Self {
-        ListPy { inner: elements }
+impl From<Queue<PyObject>> for LocalQueuePy {
+    fn from(elements: Queue<PyObject>) -> Self {
+        LocalQueuePy {
+            inner: elements,
+            hash: OnceLock::new(),
+        }
     }
 }
 
-impl<'source> FromPyObject<'source> for ListPy {
+impl<'source> FromPyObject<'source> for LocalQueuePy {
     fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
-        let mut ret = List::new_sync();
-        let reversed = PyModule::import(ob.py(), "builtins")?.getattr("reversed")?;
-        let rob: Bound<'_, PyIterator> = reversed.call1((ob,))?.try_iter()?;
-        for each in rob {
-            ret.push_front_mut(each?.extract()?);
-        }
-        Ok(ListPy { inner: ret })
+        Ok(LocalQueuePy::from(queue_from_iterable(ob)?))
     }
 }
 
 #[pymethods]
-impl ListPy {
+impl LocalQueuePy {
     #[new]
     #[pyo3(signature = (*elements))]
     fn init(elements: &Bound<'_, PyTuple>) -> PyResult<Self> {
-        let mut ret: ListPy;
-        if elements.len() == 1 {
-            ret = elements.get_item(0)?.extract()?;
-        } else {
-            ret = ListPy {
-                inner: List::new_sync(),
-            };
-            if elements.len() > 1 {
-                for each in (0..elements.len()).rev() {
-                    ret.inner
-                        .push_front_mut(elements.get_item(each)?.extract()?);
-                }
-            }
-        }
-        Ok(ret)
-    }
-
-    fn __len__(&self) -> usize {
-        self.inner.len()
-    }
-
-    fn __repr__(&self, py: Python) -> PyResult<String> {
-        let contents = self.inner.into_iter().map(|k| {
-            Ok(k.into_pyobject(py)?
-                .call_method0("__repr__")
-                .and_then(|r| r.extract())
-                .unwrap_or("<repr failed>".to_owned()))
-        });
-        let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
-        Ok(format!("List([{}])", contents.join(", ")))
-    }
-
-    fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python<'_>) -> PyResult<PyObject> {
-        match op {
-            CompareOp::Eq => (self.inner.len() == other.inner.len()
-                && self
-                    .inner
-                    .iter()
-                    .zip(other.inner.iter())
-                    .map(|(e1, e2)| e1.bind(py).eq(e2))
-                    .all(|r| r.unwrap_or(false)))
-            .into_pyobject(py)
-            .map_err(Into::into)
-            .map(BoundObject::into_any)
-            .map(BoundObject::unbind),
-            CompareOp::Ne => (self.inner.len() != other.inner.len()
-                || self
-                    .inner
-                    .iter()
-                    .zip(other.inner.iter())
-                    .map(|(e1, e2)| e1.bind(py).ne(e2))
-                    .any(|r| r.unwrap_or(true)))
-            .into_pyobject(py)
-            .map_err(Into::into)
-            .map(BoundObject::into_any)
-            .map(BoundObject::unbind),
-            _ => Ok(py.NotImplemented()),
+        if elements.len() == 1 {
+            return elements.get_item(0)?.extract();
         }
+        Ok(LocalQueuePy::from(queue_from_iterable(elements)?))
     }
 
-    fn __hash__(&self, py: Python) -> PyResult<u64> {
-        let mut hasher = DefaultHasher::new();
+    fn __eq__(&self, other: &Self, py: Python<'_>) -> PyResult<bool> {
+        sequences_eq(&self.inner, &other.inner, py)
+    }
 
-        self.inner
-            .iter()
-            .enumerate()
-            .try_for_each(|(index, each)| {
-                each.bind(py)
-                    .hash()
-                    .map_err(|_| {
-                        PyTypeError::new_err(format!(
-                            "Unhashable type at {} element in List: {}",
-                            index,
-                            each.bind(py)
-                                .repr()
-                                .and_then(|r| r.extract())
-                                .unwrap_or("<repr> error".to_string())
-                        ))
-                    })
-                    .map(|x| hasher.write_isize(x))
-            })?;
+    fn __hash__(&self, py: Python<'_>) -> PyResult<u64> {
+        sequence_hash("Queue", &self.inner, &self.hash, py)
+    }
 
-        Ok(hasher.finish())
+    fn __ne__(&self, other: &Self, py: Python<'_>) -> PyResult<bool> {
+        sequences_ne(&self.inner, &other.inner, py)
     }
 
-    fn __iter__(slf: PyRef<'_, Self>) -> ListIterator {
-        ListIterator {
+    fn __contains__(&self, value: PyObject, py: Python) -> PyResult<bool> {
+        sequence_contains(&self.inner, &value, py)
+    }
+
+    fn __getitem__(&self, index: isize, py: Python) -> PyResult<PyObject> {
+        queue_item(&self.inner, index, py)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> LocalQueueIterator {
+        LocalQueueIterator {
             inner: slf.inner.clone(),
         }
     }
 
-    fn __reversed__(&self) -> ListPy {
-        ListPy {
-            inner: self.inner.reverse(),
-        }
+    fn __reversed__(&self, py: Python) -> ReverseIterator {
+        sequence_reversed(&self.inner, py)
     }
 
-    fn __reduce__(slf: PyRef<Self>) -> (Bound<'_, PyType>, (Vec<PyObject>,)) {
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        collection_size::<PyObject, (usize, usize)>(slf.as_any(), slf.borrow().inner.len())
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        sequence_repr("Queue", &self.inner, py)
+    }
+
+    fn __reduce__(slf: PyRef<'_, Self>) -> (Bound<'_, PyType>, (Vec<PyObject>,)) {
         (
-            ListPy::type_object(slf.py()),
+            LocalQueuePy::type_object(slf.py()),
             (slf.inner.iter().map(|e| e.clone_ref(slf.py())).collect(),),
         )
     }
 
     #[getter]
-    fn first(&self) -> PyResult<&PyObject> {
-        self.inner
-            .first()
-            .ok_or_else(|| PyIndexError::new_err("empty list has no first element"))
+    fn peek(&self, py: Python) -> PyResult<PyObject> {
+        queue_peek(&self.inner, py)
     }
 
     #[getter]
-    fn rest(&self) -> ListPy {
-        let mut inner = self.inner.clone();
-        inner.drop_first_mut();
-        ListPy { inner }
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
     }
 
-    fn push_front(&self, other: PyObject) -> ListPy {
-        ListPy {
-            inner: self.inner.push_front(other),
-        }
+    fn enqueue(&self, value: Bound<'_, PyAny>) -> Self {
+        LocalQueuePy::from(self.inner.enqueue(value.into()))
     }
 
-    fn drop_first(&self) -> PyResult<ListPy> {
-        if let Some(inner) = self.inner.drop_first() {
-            Ok(ListPy { inner })
-        } else {
-            Err(PyIndexError::new_err("empty list has no first element"))
-        }
+    fn dequeue(&self) -> PyResult<LocalQueuePy> {
+        Ok(LocalQueuePy::from(queue_dequeue(&self.inner)?))
     }
 }
 
-#[pyclass(module = "rpds")]
-struct ListIterator {
-    inner: ListSync<PyObject>,
+#[pyclass(module = "rpds.local", unsendable)]
+struct LocalQueueIterator {
+    inner: Queue<PyObject>,
 }
 
 #[pymethods]
-impl ListIterator {
+impl LocalQueueIterator {
     fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
 
     fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
-        let first_op = slf.inner.first()?;
-        let first = first_op.clone_ref(slf.py());
+        let first = slf.inner.peek()?.clone_ref(slf.py());
+        slf.inner.dequeue_mut();
+        Some(first)
+    }
+}
 
-        slf.inner = slf.inner.drop_first()?;
+// The size of a reference counted allocation of a T, used to approximate the
+// memory used by the nodes of our collections (but not the objects in them).
+// Each element is counted as one allocation for itself and one for the node
+// holding it, ignoring the comparatively few branch nodes of the tries.
+fn shared_size<T>() -> usize {
+    2 * std::mem::size_of::<usize>() + std::mem::size_of::<T>()
+}
 
-        Some(first)
+fn pointer_segments(pointer: &str) -> PyResult<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
     }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(PyValueError::new_err(format!(
+            "JSON pointers must be empty or start with '/', got {:?}",
+            pointer
+        )));
+    };
+    rest.split('/')
+        .map(|segment| {
+            let mut unescaped = String::with_capacity(segment.len());
+            let mut chars = segment.chars();
+            while let Some(c) = chars.next() {
+                if c != '~' {
+                    unescaped.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('0') => unescaped.push('~'),
+                    Some('1') => unescaped.push('/'),
+                    _ => {
+                        return Err(PyValueError::new_err(format!(
+                            "invalid escape in JSON pointer {:?}",
+                            pointer
+                        )))
+                    }
+                }
+            }
+            Ok(unescaped)
+        })
+        .collect()
 }
 
-#[pyclass(module = "rpds")]
-struct QueueIterator {
-    inner: QueueSync<PyObject>,
+// RFC 6901 array indices are plain decimal with no leading zeros (and "-"
+// refers to the nonexistent element past the end).
+fn pointer_index(segment: &str) -> Option<usize> {
+    if segment.is_empty()
+        || (segment.len() > 1 && segment.starts_with('0'))
+        || !segment.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    segment.parse().ok()
 }
 
-#[pymethods]
-impl QueueIterator {
-    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
-        slf
+// The element of one of our sequences at the index chosen (given its length)
+// by index_for, or None if value isn't a List or Vector.
+fn indexed_child<'py>(
+    value: &Bound<'py, PyAny>,
+    index_for: impl FnOnce(usize) -> Option<usize>,
+) -> Option<Option<Bound<'py, PyAny>>> {
+    let py = value.py();
+    if let Ok(list) = value.downcast::<ListPy>() {
+        let inner = &list.get().inner;
+        let found = index_for(inner.len()).and_then(|index| inner.iter().nth(index));
+        return Some(found.map(|found| found.bind(py).clone()));
     }
+    if let Ok(vector) = value.downcast::<VectorPy>() {
+        let inner = &vector.get().inner;
+        let found = index_for(inner.len()).and_then(|index| inner.get(index));
+        return Some(found.map(|found| found.bind(py).clone()));
+    }
+    None
+}
 
-    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyObject> {
-        let first_op = slf.inner.peek()?;
-        let first = first_op.clone_ref(slf.py());
-        slf.inner = slf.inner.dequeue()?;
-        Some(first)
+fn resolve_segment<'py>(value: &Bound<'py, PyAny>, segment: &str) -> PyResult<Bound<'py, PyAny>> {
+    let py = value.py();
+    let out_of_range =
+        || PyIndexError::new_err(format!("{:?} is not a valid index into {}", segment, value));
+    if let Ok(map) = value.downcast::<HashTrieMapPy>() {
+        let key = Key::extract_bound(PyString::new(py, segment).as_any())?;
+        return match map.get().inner.get(&key) {
+            Some(found) => Ok(found.bind(py).clone()),
+            None => Err(PyKeyError::new_err(key)),
+        };
+    }
+    if let Some(found) = indexed_child(value, |_| pointer_index(segment)) {
+        return found.ok_or_else(out_of_range);
+    }
+    if value.downcast::<PyMapping>().is_ok() {
+        return value.get_item(segment);
+    }
+    if !value.is_instance_of::<PyString>() {
+        if let Ok(sequence) = value.downcast::<PySequence>() {
+            let index = pointer_index(segment).ok_or_else(out_of_range)?;
+            return sequence.get_item(index).map_err(|_| out_of_range());
+        }
     }
+    Err(PyTypeError::new_err(format!(
+        "cannot resolve {:?} within {}",
+        segment,
+        value.repr()?
+    )))
 }
 
-#[repr(transparent)]
-#[pyclass(name = "Queue", module = "rpds", frozen, sequence)]
-struct QueuePy {
-    inner: QueueSync<PyObject>,
+fn resolve_pointer_in<'py>(
+    value: &Bound<'py, PyAny>,
+    pointer: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    let mut current = value.clone();
+    for segment in pointer_segments(pointer)? {
+        current = resolve_segment(&current, &segment)?;
+    }
+    Ok(current)
 }
 
-impl From<QueueSync<PyObject>> for QueuePy {
-    fn from(elements: QueueSync<PyObject>) -> Self {
-        QueuePy { inner: elements }
+#[pyfunction]
+fn resolve_pointer<'py>(value: &Bound<'py, PyAny>, pointer: &str) -> PyResult<Bound<'py, PyAny>> {
+    resolve_pointer_in(value, pointer)
+}
+
+// Python style (possibly negative) indices into a sequence of length len.
+fn path_index(segment: &Bound<'_, PyAny>, len: usize) -> Option<usize> {
+    let index: isize = segment.extract().ok()?;
+    let index = match index < 0 {
+        true => index.checked_add_unsigned(len)?,
+        false => index,
+    };
+    usize::try_from(index).ok().filter(|index| *index < len)
+}
+
+// The child of a value at one step along a path of keys and indices, or None
+// if there isn't one.
+fn path_child<'py>(
+    value: &Bound<'py, PyAny>,
+    segment: &Bound<'py, PyAny>,
+) -> PyResult<Option<Bound<'py, PyAny>>> {
+    let py = value.py();
+    if let Ok(map) = value.downcast::<HashTrieMapPy>() {
+        let key = Key::extract_bound(segment)?;
+        return Ok(map
+            .get()
+            .inner
+            .get(&key)
+            .map(|found| found.bind(py).clone()));
+    }
+    if let Some(found) = indexed_child(value, |len| path_index(segment, len)) {
+        return Ok(found);
     }
+    if value.downcast::<PyMapping>().is_ok() || value.downcast::<PySequence>().is_ok() {
+        return match value.get_item(segment) {
+            Ok(found) => Ok(Some(found)),
+            Err(err) if err.is_instance_of::<PyLookupError>(py) => Ok(None),
+            Err(err) => Err(err),
+        };
+    }
+    Ok(None)
 }
 
-impl<'source> FromPyObject<'source> for QueuePy {
-    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
-        let mut ret = Queue::new_sync();
-        for each in ob.try_iter()? {
-            ret.enqueue_mut(each?.extract()?);
+// A copy of list with the element at index replaced by value, or removed if
+// there is none, sharing everything after it.
+fn list_replaced(
+    inner: &ListSync<PyObject>,
+    index: usize,
+    value: Option<PyObject>,
+    py: Python,
+) -> ListSync<PyObject> {
+    let mut tail = inner.clone();
+    for _ in 0..=index {
+        tail.drop_first_mut();
+    }
+    if let Some(value) = value {
+        tail.push_front_mut(value);
+    }
+    let prefix: Vec<_> = inner.iter().take(index).collect();
+    for each in prefix.into_iter().rev() {
+        tail.push_front_mut(each.clone_ref(py));
+    }
+    tail
+}
+
+// A copy of container with its child at segment set to value, where a missing
+// container becomes a new map.
+fn path_assoc(
+    container: Option<&Bound<'_, PyAny>>,
+    segment: &Bound<'_, PyAny>,
+    value: PyObject,
+) -> PyResult<PyObject> {
+    let py = segment.py();
+    let out_of_range = || PyIndexError::new_err(format!("{} is not a valid index", segment));
+    let Some(container) = container else {
+        let inner = HashTrieMapSync::new_sync().insert(Key::extract_bound(segment)?, value);
+        return Ok(Py::new(py, HashTrieMapPy::from(inner))?.into_any());
+    };
+    if let Ok(map) = container.downcast::<HashTrieMapPy>() {
+        let inner = map.get().inner.insert(Key::extract_bound(segment)?, value);
+        return Ok(Py::new(py, HashTrieMapPy::from(inner))?.into_any());
+    }
+    if let Ok(list) = container.downcast::<ListPy>() {
+        let inner = &list.get().inner;
+        let index = path_index(segment, inner.len()).ok_or_else(out_of_range)?;
+        let inner = list_replaced(inner, index, Some(value), py);
+        return Ok(Py::new(py, ListPy::from(inner))?.into_any());
+    }
+    if let Ok(vector) = container.downcast::<VectorPy>() {
+        let inner = &vector.get().inner;
+        let index = path_index(segment, inner.len()).ok_or_else(out_of_range)?;
+        let inner = inner.set(index, value).expect("index is in range");
+        return Ok(Py::new(py, VectorPy::from(inner))?.into_any());
+    }
+    Err(PyTypeError::new_err(format!(
+        "cannot set {} within {}",
+        segment.repr()?,
+        container.repr()?
+    )))
+}
+
+fn set_in_value(
+    current: Option<&Bound<'_, PyAny>>,
+    segments: &[Bound<'_, PyAny>],
+    value: PyObject,
+) -> PyResult<PyObject> {
+    let [first, rest @ ..] = segments else {
+        return Ok(value);
+    };
+    let child = match current {
+        Some(current) => path_child(current, first)?,
+        None => None,
+    };
+    let child = set_in_value(child.as_ref(), rest, value)?;
+    path_assoc(current, first, child)
+}
+
+// A copy of container with its child at segment removed.
+fn path_dissoc(container: &Bound<'_, PyAny>, segment: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let py = segment.py();
+    let out_of_range = || PyIndexError::new_err(format!("{} is not a valid index", segment));
+    if let Ok(map) = container.downcast::<HashTrieMapPy>() {
+        let inner = &map.get().inner;
+        let key = Key::extract_bound(segment)?;
+        if !inner.contains_key(&key) {
+            return Err(PyKeyError::new_err(key));
+        }
+        let inner = inner.remove(&key);
+        return Ok(Py::new(py, HashTrieMapPy::from(inner))?.into_any());
+    }
+    if let Ok(list) = container.downcast::<ListPy>() {
+        let inner = &list.get().inner;
+        let index = path_index(segment, inner.len()).ok_or_else(out_of_range)?;
+        let inner = list_replaced(inner, index, None, py);
+        return Ok(Py::new(py, ListPy::from(inner))?.into_any());
+    }
+    if let Ok(vector) = container.downcast::<VectorPy>() {
+        let inner = &vector.get().inner;
+        let index = path_index(segment, inner.len()).ok_or_else(out_of_range)?;
+        let mut remaining = inner.clone();
+        for _ in index..inner.len() {
+            remaining.drop_last_mut();
+        }
+        for each in inner.iter().skip(index + 1) {
+            remaining.push_back_mut(each.clone_ref(py));
         }
-        Ok(QueuePy { inner: ret })
+        return Ok(Py::new(py, VectorPy::from(remaining))?.into_any());
     }
+    Err(PyTypeError::new_err(format!(
+        "cannot delete {} within {}",
+        segment.repr()?,
+        container.repr()?
+    )))
 }
 
-#[pymethods]
-impl QueuePy {
-    #[new]
-    #[pyo3(signature = (*elements))]
-    fn init(elements: &Bound<'_, PyTuple>, py: Python<'_>) -> PyResult<Self> {
-        let mut ret: QueuePy;
-        if elements.len() == 1 {
-            ret = elements.get_item(0)?.extract()?;
-        } else {
-            ret = QueuePy {
-                inner: Queue::new_sync(),
+fn delete_in_value(
+    current: &Bound<'_, PyAny>,
+    segments: &[Bound<'_, PyAny>],
+) -> PyResult<PyObject> {
+    match segments {
+        [] => unreachable!("delete_in() checks for an empty path"),
+        [last] => path_dissoc(current, last),
+        [first, rest @ ..] => {
+            let Some(child) = path_child(current, first)? else {
+                return Err(PyKeyError::new_err(first.clone().unbind()));
             };
-            if elements.len() > 1 {
-                for each in elements {
-                    ret.inner.enqueue_mut(each.into_pyobject(py)?.unbind());
-                }
-            }
+            let child = delete_in_value(&child, rest)?;
+            path_assoc(Some(current), first, child)
         }
-        Ok(ret)
     }
+}
 
-    fn __eq__(&self, other: &Self, py: Python<'_>) -> bool {
-        (self.inner.len() == other.inner.len())
-            && self
-                .inner
-                .iter()
-                .zip(other.inner.iter())
-                .map(|(e1, e2)| e1.bind(py).eq(e2))
-                .all(|r| r.unwrap_or(false))
+enum ChildPatch {
+    Add(PyObject),
+    Replace(PyObject),
+    Remove,
+}
+
+fn patch_child(
+    container: &Bound<'_, PyAny>,
+    segment: &str,
+    patch: ChildPatch,
+) -> PyResult<PyObject> {
+    let py = container.py();
+    if let Ok(map) = container.downcast::<HashTrieMapPy>() {
+        let inner = &map.get().inner;
+        let key = Key::extract_bound(PyString::new(py, segment).as_any())?;
+        let inner = match patch {
+            ChildPatch::Add(value) => inner.insert(key, value),
+            _ if !inner.contains_key(&key) => return Err(PyKeyError::new_err(key)),
+            ChildPatch::Replace(value) => inner.insert(key, value),
+            ChildPatch::Remove => inner.remove(&key),
+        };
+        return Ok(Py::new(py, HashTrieMapPy::from(inner))?.into_any());
     }
+    let index_for = |patch: &ChildPatch, len: usize| {
+        let (index, limit) = match patch {
+            ChildPatch::Add(_) if segment == "-" => (Some(len), len + 1),
+            ChildPatch::Add(_) => (pointer_index(segment), len + 1),
+            _ => (pointer_index(segment), len),
+        };
+        index.filter(|index| *index < limit).ok_or_else(|| {
+            PyIndexError::new_err(format!(
+                "{:?} is not a valid index into {}",
+                segment, container
+            ))
+        })
+    };
+    // Additions shift everything from index on, the others everything after it.
+    let skip = |patch: &ChildPatch, index: usize| match patch {
+        ChildPatch::Add(_) => index,
+        _ => index + 1,
+    };
+    if let Ok(list) = container.downcast::<ListPy>() {
+        let inner = &list.get().inner;
+        let index = index_for(&patch, inner.len())?;
+        let mut tail = inner.clone();
+        for _ in 0..skip(&patch, index) {
+            tail.drop_first_mut();
+        }
+        if let ChildPatch::Add(value) | ChildPatch::Replace(value) = patch {
+            tail.push_front_mut(value);
+        }
+        let prefix: Vec<_> = inner.iter().take(index).collect();
+        for each in prefix.into_iter().rev() {
+            tail.push_front_mut(each.clone_ref(py));
+        }
+        return Ok(Py::new(py, ListPy::from(tail))?.into_any());
+    }
+    if let Ok(vector) = container.downcast::<VectorPy>() {
+        let inner = &vector.get().inner;
+        let index = index_for(&patch, inner.len())?;
+        if let ChildPatch::Replace(value) = patch {
+            let inner = inner.set(index, value).expect("index is in range");
+            return Ok(Py::new(py, VectorPy::from(inner))?.into_any());
+        }
+        let mut patched = inner.clone();
+        for _ in index..inner.len() {
+            patched.drop_last_mut();
+        }
+        let rest = inner.iter().skip(skip(&patch, index));
+        if let ChildPatch::Add(value) = patch {
+            patched.push_back_mut(value);
+        }
+        for each in rest {
+            patched.push_back_mut(each.clone_ref(py));
+        }
+        return Ok(Py::new(py, VectorPy::from(patched))?.into_any());
+    }
+    // Anything else resolve_segment can step into is patched in a copy (as a
+    // dict or list), since patching never changes a document in place.
+    if let Ok(mapping) = container.downcast::<PyMapping>() {
+        let patched = PyDict::new(py);
+        patched.update(mapping)?;
+        match patch {
+            ChildPatch::Add(value) => patched.set_item(segment, value)?,
+            _ if !patched.contains(segment)? => {
+                return Err(PyKeyError::new_err(segment.to_owned()))
+            }
+            ChildPatch::Replace(value) => patched.set_item(segment, value)?,
+            ChildPatch::Remove => patched.del_item(segment)?,
+        }
+        return Ok(patched.into_any().unbind());
+    }
+    if !container.is_instance_of::<PyString>() {
+        if let Ok(sequence) = container.downcast::<PySequence>() {
+            let patched = sequence.to_list()?;
+            let index = index_for(&patch, patched.len())?;
+            match patch {
+                ChildPatch::Add(value) => patched.insert(index, value)?,
+                ChildPatch::Replace(value) => patched.set_item(index, value)?,
+                ChildPatch::Remove => patched.del_item(index)?,
+            }
+            return Ok(patched.into_any().unbind());
+        }
+    }
+    Err(PyTypeError::new_err(format!(
+        "cannot patch {:?} within {}",
+        segment,
+        container.repr()?
+    )))
+}
 
-    fn __hash__(&self, py: Python<'_>) -> PyResult<u64> {
-        let mut hasher = DefaultHasher::new();
+fn patch_in<'py>(
+    document: &Bound<'py, PyAny>,
+    segments: &[String],
+    patch: ChildPatch,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = document.py();
+    let patched = match segments {
+        [] => match patch {
+            ChildPatch::Add(value) | ChildPatch::Replace(value) => value,
+            ChildPatch::Remove => {
+                return Err(PyValueError::new_err(
+                    "cannot remove the root of a document",
+                ))
+            }
+        },
+        [last] => patch_child(document, last, patch)?,
+        [first, rest @ ..] => {
+            let child = resolve_segment(document, first)?;
+            let child = patch_in(&child, rest, patch)?.unbind();
+            patch_child(document, first, ChildPatch::Replace(child))?
+        }
+    };
+    Ok(patched.into_bound(py))
+}
 
-        self.inner
-            .iter()
-            .enumerate()
-            .try_for_each(|(index, each)| {
-                each.bind(py)
-                    .hash()
-                    .map_err(|_| {
-                        PyTypeError::new_err(format!(
-                            "Unhashable type at {} element in Queue: {}",
-                            index,
-                            each.bind(py)
-                                .repr()
-                                .and_then(|r| r.extract())
-                                .unwrap_or("<repr> error".to_string())
-                        ))
-                    })
-                    .map(|x| hasher.write_isize(x))
-            })?;
+fn apply_json_patch_op<'py>(
+    document: &Bound<'py, PyAny>,
+    operation: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let op: String = operation.get_item("op")?.extract()?;
+    let path: String = operation.get_item("path")?.extract()?;
+    let segments = pointer_segments(&path)?;
+    match op.as_str() {
+        "add" => {
+            let value = operation.get_item("value")?.unbind();
+            patch_in(document, &segments, ChildPatch::Add(value))
+        }
+        "remove" => patch_in(document, &segments, ChildPatch::Remove),
+        "replace" => {
+            let value = operation.get_item("value")?.unbind();
+            patch_in(document, &segments, ChildPatch::Replace(value))
+        }
+        "move" | "copy" => {
+            let from: String = operation.get_item("from")?.extract()?;
+            let from_segments = pointer_segments(&from)?;
+            let value = resolve_pointer_in(document, &from)?.unbind();
+            if op == "copy" {
+                return patch_in(document, &segments, ChildPatch::Add(value));
+            }
+            if segments.len() > from_segments.len() && segments.starts_with(&from_segments) {
+                return Err(PyValueError::new_err(format!(
+                    "cannot move {:?} into its own child {:?}",
+                    from, path
+                )));
+            }
+            let removed = patch_in(document, &from_segments, ChildPatch::Remove)?;
+            patch_in(&removed, &segments, ChildPatch::Add(value))
+        }
+        "test" => {
+            let value = operation.get_item("value")?;
+            if !resolve_pointer_in(document, &path)?.eq(value)? {
+                return Err(PyValueError::new_err(format!("test failed at {:?}", path)));
+            }
+            Ok(document.clone())
+        }
+        _ => Err(PyValueError::new_err(format!(
+            "unknown JSON patch operation {:?}",
+            op
+        ))),
+    }
+}
+
+fn json_patch_op<'py>(
+    py: Python<'py>,
+    op: &str,
+    path: &str,
+    value: Option<&Bound<'py, PyAny>>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let operation = PyDict::new(py);
+    operation.set_item("op", op)?;
+    operation.set_item("path", path)?;
+    if let Some(value) = value {
+        operation.set_item("value", value)?;
+    }
+    Ok(operation)
+}
 
-        Ok(hasher.finish())
+// The entries of one of the maps make_json_patch can diff key by key.
+fn patchable_entries<'py>(
+    value: &Bound<'py, PyAny>,
+) -> Option<Vec<(Bound<'py, PyAny>, Bound<'py, PyAny>)>> {
+    let py = value.py();
+    if let Ok(map) = value.downcast::<HashTrieMapPy>() {
+        let entries = map.get().inner.iter();
+        return Some(
+            entries
+                .map(|(k, v)| (k.inner.bind(py).clone(), v.bind(py).clone()))
+                .collect(),
+        );
+    }
+    if let Ok(dict) = value.downcast::<PyDict>() {
+        return Some(dict.iter().collect());
     }
+    None
+}
 
-    fn __ne__(&self, other: &Self, py: Python<'_>) -> bool {
-        (self.inner.len() != other.inner.len())
-            || self
+// The elements of one of the sequences make_json_patch can diff index by index.
+fn patchable_elements<'py>(value: &Bound<'py, PyAny>) -> Option<Vec<Bound<'py, PyAny>>> {
+    let py = value.py();
+    if let Ok(list) = value.downcast::<ListPy>() {
+        return Some(
+            list.get()
+                .inner
+                .iter()
+                .map(|v| v.bind(py).clone())
+                .collect(),
+        );
+    }
+    if let Ok(vector) = value.downcast::<VectorPy>() {
+        return Some(
+            vector
+                .get()
                 .inner
                 .iter()
-                .zip(other.inner.iter())
-                .map(|(e1, e2)| e1.bind(py).ne(e2))
-                .any(|r| r.unwrap_or(true))
+                .map(|v| v.bind(py).clone())
+                .collect(),
+        );
     }
+    if let Ok(list) = value.downcast::<PyList>() {
+        return Some(list.iter().collect());
+    }
+    None
+}
 
-    fn __iter__(slf: PyRef<'_, Self>) -> QueueIterator {
-        QueueIterator {
-            inner: slf.inner.clone(),
+// Containers are only diffed against others of the same type, so that applying
+// the patch gives back the same types, and anything else is replaced whole.
+fn make_json_patch_into<'py>(
+    old: &Bound<'py, PyAny>,
+    new: &Bound<'py, PyAny>,
+    path: &str,
+    ops: &mut Vec<Bound<'py, PyDict>>,
+) -> PyResult<()> {
+    let py = old.py();
+    if old.is(new) {
+        return Ok(());
+    }
+    let child_path =
+        |segment: &str| format!("{}/{}", path, segment.replace('~', "~0").replace('/', "~1"));
+    let replace = |ops: &mut Vec<Bound<'py, PyDict>>| -> PyResult<()> {
+        if !old.eq(new)? {
+            ops.push(json_patch_op(py, "replace", path, Some(new))?);
         }
+        Ok(())
+    };
+    if !old.get_type().is(&new.get_type()) {
+        return replace(ops);
     }
-
-    fn __len__(&self) -> usize {
-        self.inner.len()
+    if let (Some(old), Some(new)) = (patchable_entries(old), patchable_entries(new)) {
+        // Only maps keyed by strings can be patched key by key, since other
+        // keys have no JSON pointer.
+        let keyed = |entries: Vec<(Bound<'py, PyAny>, Bound<'py, PyAny>)>| {
+            let mut keyed = Vec::with_capacity(entries.len());
+            for (k, v) in entries {
+                match k.downcast::<PyString>() {
+                    Ok(k) => keyed.push((k.to_cow()?.into_owned(), v)),
+                    Err(_) => return Ok(None),
+                }
+            }
+            PyResult::Ok(Some(keyed))
+        };
+        let (Some(old), Some(new)) = (keyed(old)?, keyed(new)?) else {
+            return replace(ops);
+        };
+        let lookup: HashMap<&str, &Bound<'py, PyAny>> =
+            new.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        let old_keys: BTreeSet<&str> = old.iter().map(|(k, _)| k.as_str()).collect();
+        for (k, v) in &old {
+            let child = child_path(k);
+            match lookup.get(k.as_str()) {
+                Some(w) => make_json_patch_into(v, w, &child, ops)?,
+                None => ops.push(json_patch_op(py, "remove", &child, None)?),
+            }
+        }
+        for (k, v) in &new {
+            if !old_keys.contains(k.as_str()) {
+                ops.push(json_patch_op(py, "add", &child_path(k), Some(v))?);
+            }
+        }
+        return Ok(());
+    }
+    if let (Some(old), Some(new)) = (patchable_elements(old), patchable_elements(new)) {
+        let common = old.len().min(new.len());
+        for index in 0..common {
+            let child = child_path(&index.to_string());
+            make_json_patch_into(&old[index], &new[index], &child, ops)?;
+        }
+        for (index, value) in new.iter().enumerate().skip(common) {
+            let child = child_path(&index.to_string());
+            ops.push(json_patch_op(py, "add", &child, Some(value))?);
+        }
+        for index in (common..old.len()).rev() {
+            let child = child_path(&index.to_string());
+            ops.push(json_patch_op(py, "remove", &child, None)?);
+        }
+        return Ok(());
     }
+    replace(ops)
+}
 
-    fn __repr__(&self, py: Python) -> PyResult<String> {
-        let contents = self.inner.into_iter().map(|k| {
-            Ok(k.into_pyobject(py)?
-                .call_method0("__repr__")
-                .and_then(|r| r.extract())
-                .unwrap_or("<repr failed>".to_owned()))
-        });
-        let contents = contents.collect::<Result<Vec<_>, PyErr>>()?;
-        Ok(format!("Queue([{}])", contents.join(", ")))
+#[pyfunction]
+fn apply_json_patch<'py>(
+    document: &Bound<'py, PyAny>,
+    patch: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let mut document = document.clone();
+    for operation in patch.try_iter()? {
+        document = apply_json_patch_op(&document, &operation?)?;
     }
+    Ok(document)
+}
 
-    #[getter]
-    fn peek(&self, py: Python) -> PyResult<PyObject> {
-        if let Some(peeked) = self.inner.peek() {
-            Ok(peeked.clone_ref(py))
-        } else {
-            Err(PyIndexError::new_err("peeked an empty queue"))
+#[pyfunction]
+fn make_json_patch<'py>(
+    old: &Bound<'py, PyAny>,
+    new: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyList>> {
+    let mut ops = Vec::new();
+    make_json_patch_into(old, new, "", &mut ops)?;
+    PyList::new(old.py(), ops)
+}
+
+enum ListStrategy {
+    Replace,
+    Concatenate,
+}
+
+impl<'source> FromPyObject<'source> for ListStrategy {
+    fn extract_bound(ob: &Bound<'source, PyAny>) -> PyResult<Self> {
+        match ob.extract::<&str>()? {
+            "replace" => Ok(ListStrategy::Replace),
+            "concatenate" => Ok(ListStrategy::Concatenate),
+            _ => Err(PyValueError::new_err(format!(
+                "list_strategy must be 'replace' or 'concatenate', not {}",
+                ob.repr()?
+            ))),
         }
     }
+}
 
-    #[getter]
-    fn is_empty(&self) -> bool {
-        self.inner.is_empty()
+// Recursively converts mappings, lists and sets into their persistent
+// counterparts, leaving anything else (including tuples) as is.
+fn convert_deep(value: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let py = value.py();
+    if value.is_instance_of::<HashTrieMapPy>() || value.downcast::<PyMapping>().is_ok() {
+        return Ok(Py::new(py, HashTrieMapPy::from(convert_deep_map(value)?))?.into_any());
     }
+    if value.is_instance_of::<PyList>() || value.is_instance_of::<ListPy>() {
+        return Ok(Py::new(py, ListPy::from(convert_deep_list(value)?))?.into_any());
+    }
+    if value.is_instance_of::<PySet>()
+        || value.is_instance_of::<PyFrozenSet>()
+        || value.is_instance_of::<HashTrieSetPy>()
+    {
+        return Ok(Py::new(py, HashTrieSetPy::from(convert_deep_set(value)?))?.into_any());
+    }
+    Ok(value.clone().unbind())
+}
 
-    fn enqueue(&self, value: Bound<'_, PyAny>) -> Self {
-        QueuePy {
-            inner: self.inner.enqueue(value.into()),
+// The contents of a map converted by convert_deep, from anything the map
+// constructor accepts, so that convert(deep=True) is a single traversal.
+fn convert_deep_map(value: &Bound<'_, PyAny>) -> PyResult<HashTrieMapSync<Key, PyObject>> {
+    let py = value.py();
+    let mut inner = HashTrieMap::new_sync();
+    if let Ok(map) = value.downcast::<HashTrieMapPy>() {
+        for (k, v) in map.get().inner.iter() {
+            inner.insert_mut(k.clone_ref(py), convert_deep(v.bind(py))?);
         }
+        return Ok(inner);
     }
+    each_item(value, |k, v| {
+        inner.insert_mut(k, convert_deep(v.bind(py))?);
+        Ok(())
+    })?;
+    Ok(inner)
+}
 
-    fn dequeue(&self) -> PyResult<QueuePy> {
-        if let Some(inner) = self.inner.dequeue() {
-            Ok(QueuePy { inner })
-        } else {
-            Err(PyIndexError::new_err("dequeued an empty queue"))
+fn convert_deep_list(value: &Bound<'_, PyAny>) -> PyResult<ListSync<PyObject>> {
+    let elements = value
+        .try_iter()?
+        .map(|each| convert_deep(&each?))
+        .collect::<PyResult<Vec<_>>>()?;
+    let mut inner = List::new_sync();
+    for each in elements.into_iter().rev() {
+        inner.push_front_mut(each);
+    }
+    Ok(inner)
+}
+
+fn convert_deep_set(value: &Bound<'_, PyAny>) -> PyResult<HashTrieSetSync<Key>> {
+    let py = value.py();
+    let mut inner = HashTrieSet::new_sync();
+    for each in value.try_iter()? {
+        inner.insert_mut(Key::extract_bound(convert_deep(&each?)?.bind(py))?);
+    }
+    Ok(inner)
+}
+
+fn deep_merge_into(
+    base: &mut HashTrieMapSync<Key, PyObject>,
+    other: &HashTrieMapSync<Key, PyObject>,
+    list_strategy: &ListStrategy,
+    on_conflict: Option<&Bound<'_, PyAny>>,
+    py: Python<'_>,
+) -> PyResult<()> {
+    for (k, v) in other.iter() {
+        let merged = match base.get(k) {
+            Some(existing) if existing.is(v) => continue,
+            Some(existing) => {
+                deep_merge_values(existing.bind(py), v.bind(py), list_strategy, on_conflict)?
+            }
+            None => v.clone_ref(py),
+        };
+        base.insert_mut(k.clone_ref(py), merged);
+    }
+    Ok(())
+}
+
+fn deep_merge_values(
+    old: &Bound<'_, PyAny>,
+    new: &Bound<'_, PyAny>,
+    list_strategy: &ListStrategy,
+    on_conflict: Option<&Bound<'_, PyAny>>,
+) -> PyResult<PyObject> {
+    let py = old.py();
+    // Any two mappings merge, with plain ones (e.g. dicts) becoming HashTrieMaps.
+    let is_mapping = |each: &Bound<'_, PyAny>| {
+        each.is_instance_of::<HashTrieMapPy>() || each.downcast::<PyMapping>().is_ok()
+    };
+    if is_mapping(old) && is_mapping(new) {
+        let mut inner = HashTrieMapPy::extract_bound(old)?.inner;
+        let other = HashTrieMapPy::extract_bound(new)?;
+        deep_merge_into(&mut inner, &other.inner, list_strategy, on_conflict, py)?;
+        return Ok(Py::new(py, HashTrieMapPy::from(inner))?.into_any());
+    }
+    if let ListStrategy::Concatenate = list_strategy {
+        if let (Ok(old), Ok(new)) = (old.downcast::<ListPy>(), new.downcast::<ListPy>()) {
+            let mut inner = new.get().inner.clone();
+            let front: Vec<_> = old.get().inner.iter().collect();
+            for each in front.into_iter().rev() {
+                inner.push_front_mut(each.clone_ref(py));
+            }
+            return Ok(Py::new(py, ListPy::from(inner))?.into_any());
+        }
+        if old.is_instance_of::<PyList>() && new.is_instance_of::<PyList>() {
+            return Ok(old.add(new)?.unbind());
         }
     }
+    match on_conflict {
+        Some(f) => Ok(f.call1((old, new))?.unbind()),
+        None => Ok(new.clone().unbind()),
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (*maps, list_strategy=ListStrategy::Replace))]
+fn deep_merge(
+    maps: &Bound<'_, PyTuple>,
+    list_strategy: ListStrategy,
+    py: Python,
+) -> PyResult<HashTrieMapPy> {
+    let mut inner = HashTrieMap::new_sync();
+    for each in maps {
+        let map = HashTrieMapPy::extract_bound(&each)?;
+        deep_merge_into(&mut inner, &map.inner, &list_strategy, None, py)?;
+    }
+    Ok(HashTrieMapPy::from(inner))
 }
 
+const LOCAL_DOC: &str = "\
+Single-threaded variants of HashTrieMap, HashTrieSet, List and Queue.
+
+These are backed by non-atomic reference counting, which makes them faster to
+work with, but means each may only be used from the thread which created it.
+
+They support the same protocols as their rpds counterparts (containment,
+iteration, indexing, comparison, hashing and pickling) along with their basic
+persistent operations (insert, discard, remove, update, push_front, enqueue,
+and so on), but not their other methods, such as convert, mutate, fromkeys or
+the | operator of HashTrieMap. Convert to the rpds types to use those.
+";
+
 #[pymodule(gil_used = false)]
 #[pyo3(name = "rpds")]
 fn rpds_py(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<HashTrieMapPy>()?;
     m.add_class::<HashTrieSetPy>()?;
     m.add_class::<ListPy>()?;
+    m.add_class::<ListZipperPy>()?;
     m.add_class::<QueuePy>()?;
+    m.add_class::<VectorPy>()?;
+    m.add_class::<SortedMapPy>()?;
+    m.add_class::<SortedSetPy>()?;
+    m.add_class::<BagPy>()?;
+    m.add_class::<OrderedMapPy>()?;
+    m.add_class::<RecordPy>()?;
+    m.add_class::<FieldPy>()?;
+    m.add("InvariantError", py.get_type::<InvariantError>())?;
+    m.add_class::<StringTrieMapPy>()?;
+    m.add_class::<ChainMapPy>()?;
+    m.add_class::<IdentityMapPy>()?;
+    m.add_class::<IntSetPy>()?;
+    m.add_class::<IntervalMapPy>()?;
+    m.add_class::<CheckedHashTrieMapPy>()?;
+    m.add_class::<CheckedHashTrieSetPy>()?;
+    m.add_class::<DefaultHashTrieMapPy>()?;
+
+    let local = PyModule::new(py, "local")?;
+    local.setattr("__doc__", LOCAL_DOC)?;
+    local.add_class::<LocalHashTrieMapPy>()?;
+    local.add_class::<LocalHashTrieSetPy>()?;
+    local.add_class::<LocalListPy>()?;
+    local.add_class::<LocalQueuePy>()?;
+    m.add_submodule(&local)?;
+    // so that `import rpds.local` works, not just `rpds.local` attribute access
+    PyModule::import(py, "sys")?
+        .getattr("modules")?
+        .set_item("rpds.local", &local)?;
+
+    m.add_function(wrap_pyfunction!(deep_merge, m)?)?;
+    m.add_function(wrap_pyfunction!(resolve_pointer, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_json_patch, m)?)?;
+    m.add_function(wrap_pyfunction!(make_json_patch, m)?)?;
 
     PyMapping::register::<HashTrieMapPy>(py)?;
+    PyMapping::register::<SortedMapPy>(py)?;
+    PyMapping::register::<OrderedMapPy>(py)?;
+    PyMapping::register::<StringTrieMapPy>(py)?;
+    PyMapping::register::<ChainMapPy>(py)?;
+    PyMapping::register::<IdentityMapPy>(py)?;
+    PyMapping::register::<IntervalMapPy>(py)?;
+    PyMapping::register::<CheckedHashTrieMapPy>(py)?;
+    PyMapping::register::<DefaultHashTrieMapPy>(py)?;
+    PyMapping::register::<LocalHashTrieMapPy>(py)?;
 
     let abc = PyModule::import(py, "collections.abc")?;
 
     abc.getattr("Set")?
         .call_method1("register", (HashTrieSetPy::type_object(py),))?;
+    abc.getattr("Set")?
+        .call_method1("register", (SortedSetPy::type_object(py),))?;
+    abc.getattr("Set")?
+        .call_method1("register", (IntSetPy::type_object(py),))?;
+    abc.getattr("Set")?
+        .call_method1("register", (CheckedHashTrieSetPy::type_object(py),))?;
+    abc.getattr("Set")?
+        .call_method1("register", (LocalHashTrieSetPy::type_object(py),))?;
 
     abc.getattr("MappingView")?
         .call_method1("register", (KeysView::type_object(py),))?;
@@ -1407,6 +10307,20 @@ fn rpds_py(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     abc.getattr("MappingView")?
         .call_method1("register", (ItemsView::type_object(py),))?;
 
+    abc.getattr("MappingView")?
+        .call_method1("register", (SortedKeysView::type_object(py),))?;
+    abc.getattr("MappingView")?
+        .call_method1("register", (SortedValuesView::type_object(py),))?;
+    abc.getattr("MappingView")?
+        .call_method1("register", (SortedItemsView::type_object(py),))?;
+
+    abc.getattr("MappingView")?
+        .call_method1("register", (OrderedKeysView::type_object(py),))?;
+    abc.getattr("MappingView")?
+        .call_method1("register", (OrderedValuesView::type_object(py),))?;
+    abc.getattr("MappingView")?
+        .call_method1("register", (OrderedItemsView::type_object(py),))?;
+
     abc.getattr("KeysView")?
         .call_method1("register", (KeysView::type_object(py),))?;
     abc.getattr("ValuesView")?
@@ -1414,5 +10328,18 @@ fn rpds_py(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     abc.getattr("ItemsView")?
         .call_method1("register", (ItemsView::type_object(py),))?;
 
+    abc.getattr("MappingView")?
+        .call_method1("register", (LocalKeysView::type_object(py),))?;
+    abc.getattr("MappingView")?
+        .call_method1("register", (LocalValuesView::type_object(py),))?;
+    abc.getattr("MappingView")?
+        .call_method1("register", (LocalItemsView::type_object(py),))?;
+    abc.getattr("KeysView")?
+        .call_method1("register", (LocalKeysView::type_object(py),))?;
+    abc.getattr("ValuesView")?
+        .call_method1("register", (LocalValuesView::type_object(py),))?;
+    abc.getattr("ItemsView")?
+        .call_method1("register", (LocalItemsView::type_object(py),))?;
+
     Ok(())
 }
@@ -0,0 +1,83 @@
+import pickle
+
+import pytest
+
+from rpds import Bag
+
+
+def test_count():
+    b = Bag([1, 2, 1, 1])
+    assert b.count(1) == 3
+    assert b.count(2) == 1
+    assert b.count(3) == 0
+
+
+def test_len_counts_multiplicities():
+    assert len(Bag([1, 2, 1])) == 3
+    assert len(Bag()) == 0
+
+
+def test_contains():
+    b = Bag(["a", "a"])
+    assert "a" in b
+    assert "b" not in b
+
+
+def test_add():
+    b = Bag([1])
+    assert b.add(1) == Bag([1, 1])
+    assert b.add(2) == Bag([1, 2])
+    assert b == Bag([1])
+
+
+def test_remove():
+    b = Bag([1, 1, 2])
+    assert b.remove(1) == Bag([1, 2])
+    assert b.remove(2) == Bag([1, 1])
+    assert 2 not in b.remove(2)
+    with pytest.raises(KeyError):
+        b.remove(3)
+
+
+def test_iteration_repeats_elements():
+    assert sorted(Bag([3, 1, 3, 2, 3])) == [1, 2, 3, 3, 3]
+    assert list(Bag()) == []
+
+
+def test_equality():
+    assert Bag([1, 2, 1]) == Bag([1, 1, 2])
+    assert Bag([1, 2]) != Bag([1, 1, 2])
+    assert Bag([1]) != Bag([2])
+
+
+def test_hashing():
+    assert hash(Bag([1, 2, 1])) == hash(Bag([2, 1, 1]))
+    assert hash(Bag([1, 1])) != hash(Bag([1]))
+
+
+def test_repr():
+    assert repr(Bag()) == "Bag([])"
+    assert repr(Bag(["a", "a"])) == "Bag(['a', 'a'])"
+
+
+def test_pickle():
+    b = Bag([1, 1, 2])
+    assert pickle.loads(pickle.dumps(b)) == b
+
+
+def test_mutate():
+    original = Bag([1])
+    assert original.mutate(lambda e: e.add(1)) == Bag([1, 1])
+    with original.mutate() as evolver:
+        evolver.add(2)
+        evolver.add(2)
+        evolver.remove(1)
+        assert evolver.count(2) == 2
+        assert 1 not in evolver
+        assert len(evolver) == 2
+    assert evolver.result == Bag([2, 2])
+    assert original == Bag([1])
+    with pytest.raises(ValueError):
+        evolver.add(3)
+    with pytest.raises(KeyError):
+        Bag().mutate(lambda e: e.remove(1))
@@ -0,0 +1,123 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import ChainMap, HashTrieMap
+
+
+def test_instance_of_map():
+    assert isinstance(ChainMap(), abc.Mapping)
+
+
+def test_lookup_is_front_to_back():
+    chain = ChainMap(HashTrieMap(a=1), HashTrieMap(a=2, b=3))
+    assert chain["a"] == 1
+    assert chain["b"] == 3
+    assert chain.get("c") is None
+    assert chain.get("c", 4) == 4
+    assert "b" in chain
+    assert "c" not in chain
+
+
+def test_missing_key():
+    with pytest.raises(KeyError):
+        ChainMap(HashTrieMap(a=1))["b"]
+
+
+def test_len_and_iter_count_distinct_keys():
+    chain = ChainMap(HashTrieMap(a=1), HashTrieMap(a=2, b=3))
+    assert len(chain) == 2
+    assert sorted(chain) == ["a", "b"]
+    assert dict(chain.items()) == {"a": 1, "b": 3}
+    assert sorted(chain.keys()) == ["a", "b"]
+    assert sorted(chain.values()) == [1, 3]
+
+
+def test_accepts_plain_mappings():
+    chain = ChainMap({"a": 1}, {"b": 2})
+    assert chain.maps == [HashTrieMap(a=1), HashTrieMap(b=2)]
+
+
+def test_empty_starts_with_one_layer():
+    assert ChainMap().maps == [HashTrieMap()]
+
+
+def test_push_and_pop_layer():
+    base = ChainMap(HashTrieMap(a=1))
+    scoped = base.push_layer(HashTrieMap(a=2))
+    assert scoped["a"] == 2
+    assert scoped.pop_layer() == base
+    assert base.push_layer().maps == [HashTrieMap(), HashTrieMap(a=1)]
+    assert base["a"] == 1
+
+
+def test_pop_layer_empty():
+    with pytest.raises(IndexError):
+        ChainMap().pop_layer().pop_layer()
+
+
+def test_flatten():
+    chain = ChainMap(HashTrieMap(a=1), HashTrieMap(a=2, b=3))
+    assert chain.flatten() == HashTrieMap(a=1, b=3)
+
+
+def test_insert_and_remove_touch_the_first_layer():
+    chain = ChainMap(HashTrieMap(a=1), HashTrieMap(b=2))
+    assert chain.insert("b", 3).maps == [HashTrieMap(a=1, b=3), HashTrieMap(b=2)]
+    assert chain.remove("a").maps == [HashTrieMap(), HashTrieMap(b=2)]
+    assert chain.discard("b") == chain
+    with pytest.raises(KeyError):
+        chain.remove("b")
+
+
+def test_insert_without_layers():
+    assert ChainMap().pop_layer().insert("a", 1).maps == [HashTrieMap(a=1)]
+
+
+def test_eq_and_hash():
+    one = ChainMap(HashTrieMap(a=1), HashTrieMap(b=2))
+    two = ChainMap(HashTrieMap(a=1, b=2))
+    assert one == two
+    assert hash(one) == hash(two)
+    assert one != ChainMap(HashTrieMap(a=2))
+
+
+def test_eq_other_mappings():
+    chain = ChainMap(HashTrieMap({1: 2}), HashTrieMap({1: 3, 4: 5}))
+    assert chain == {1: 2, 4: 5}
+    assert {1: 2, 4: 5} == chain
+    assert chain == HashTrieMap({1: 2, 4: 5})
+    assert chain != {1: 3, 4: 5}
+
+
+def test_merged_contents_follow_changes():
+    chain = ChainMap(HashTrieMap(a=1), HashTrieMap(a=2, b=3))
+    assert len(chain) == 2
+    assert sorted(chain) == ["a", "b"]
+    assert dict(chain.items()) == {"a": 1, "b": 3}
+    assert len(chain.insert("c", 4)) == 3
+
+
+def test_repr():
+    chain = ChainMap(HashTrieMap(a=1), HashTrieMap())
+    assert repr(chain) == "ChainMap(HashTrieMap({'a': 1}), HashTrieMap({}))"
+
+
+def test_pickle():
+    chain = ChainMap(HashTrieMap(a=1), HashTrieMap(a=2, b=3))
+    unpickled = pickle.loads(pickle.dumps(chain))
+    assert unpickled.maps == chain.maps
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = ChainMap(HashTrieMap(a=Counted()), HashTrieMap(b=1))
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
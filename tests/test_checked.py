@@ -0,0 +1,152 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import CheckedHashTrieMap, CheckedHashTrieSet, HashTrieMap, HashTrieSet
+
+
+def test_map_instance_of_mapping():
+    assert isinstance(CheckedHashTrieMap(), abc.Mapping)
+
+
+def test_set_instance_of_set():
+    assert isinstance(CheckedHashTrieSet(), abc.Set)
+
+
+def test_map_checks_key_and_value_types():
+    m = CheckedHashTrieMap({"a": 1}, key_type=str, value_type=int)
+    assert m.insert("b", 2)["b"] == 2
+    with pytest.raises(TypeError):
+        m.insert(3, 2)
+    with pytest.raises(TypeError):
+        m.insert("b", "c")
+
+
+def test_map_checks_on_construction():
+    with pytest.raises(TypeError):
+        CheckedHashTrieMap({"a": "b"}, value_type=int)
+    with pytest.raises(TypeError):
+        CheckedHashTrieMap(value_type=int, a="b")
+
+
+def test_map_tuple_of_types():
+    m = CheckedHashTrieMap(value_type=(int, float))
+    assert m.insert("a", 1.5).insert("b", 2) == HashTrieMap(a=1.5, b=2)
+    with pytest.raises(TypeError):
+        m.insert("c", "d")
+
+
+def test_map_validator_callable():
+    m = CheckedHashTrieMap(value_type=lambda value: value > 0)
+    assert m.insert("a", 1)["a"] == 1
+    with pytest.raises(TypeError):
+        m.insert("a", -1)
+
+
+def test_map_update_checks():
+    m = CheckedHashTrieMap(key_type=str)
+    assert m.update({"a": 1}, b=2) == HashTrieMap(a=1, b=2)
+    with pytest.raises(TypeError):
+        m.update({1: 1})
+
+
+def test_map_derived_maps_stay_checked():
+    m = CheckedHashTrieMap({"a": 1}, value_type=int).remove("a").discard("x")
+    assert m.value_type is int
+    with pytest.raises(TypeError):
+        m.insert("a", "b")
+
+
+def test_map_behaves_like_a_map():
+    m = CheckedHashTrieMap({"a": 1, "b": 2}, value_type=int)
+    assert m["a"] == 1
+    assert m.get("c", 3) == 3
+    assert "b" in m
+    assert len(m) == 2
+    assert sorted(m) == ["a", "b"]
+    assert sorted(m.items()) == [("a", 1), ("b", 2)]
+    assert m == HashTrieMap(a=1, b=2)
+    assert hash(m) == hash(HashTrieMap(a=1, b=2))
+    with pytest.raises(KeyError):
+        m.remove("c")
+
+
+def test_map_repr():
+    assert repr(CheckedHashTrieMap(a=1)) == "CheckedHashTrieMap({'a': 1})"
+
+
+def test_map_pickle():
+    m = CheckedHashTrieMap({"a": 1}, key_type=str, value_type=int)
+    unpickled = pickle.loads(pickle.dumps(m))
+    assert unpickled == m
+    assert unpickled.key_type is str
+    with pytest.raises(TypeError):
+        unpickled.insert("b", "c")
+
+
+def test_set_checks_elements():
+    s = CheckedHashTrieSet([1, 2], value_type=int)
+    assert s.insert(3) == {1, 2, 3}
+    with pytest.raises(TypeError):
+        s.insert("a")
+    with pytest.raises(TypeError):
+        CheckedHashTrieSet(["a"], value_type=int)
+    with pytest.raises(TypeError):
+        s.update([4], ["a"])
+
+
+def test_set_validator_callable():
+    s = CheckedHashTrieSet(value_type=lambda value: value % 2 == 0)
+    assert s.insert(2) == {2}
+    with pytest.raises(TypeError):
+        s.insert(3)
+
+
+def test_set_algebra_checks_new_elements():
+    s = CheckedHashTrieSet([1, 2], value_type=int)
+    assert s | HashTrieSet([3]) == {1, 2, 3}
+    assert s & {2, "a"} == {2}
+    assert s - {"a", 1} == {2}
+    with pytest.raises(TypeError):
+        s | {"a"}
+    with pytest.raises(TypeError):
+        s ^ {"a"}
+    assert (s | {3}).value_type is int
+
+
+def test_set_behaves_like_a_set():
+    s = CheckedHashTrieSet([1, 2], value_type=int)
+    assert 1 in s
+    assert len(s) == 2
+    assert s == HashTrieSet([1, 2])
+    assert s < {1, 2, 3}
+    assert s >= {1}
+    assert hash(s) == hash(HashTrieSet([1, 2]))
+    assert s.discard(1).remove(2) == set()
+    with pytest.raises(KeyError):
+        s.remove(3)
+
+
+def test_set_repr():
+    assert repr(CheckedHashTrieSet([1])) == "CheckedHashTrieSet({1})"
+
+
+def test_set_pickle():
+    s = CheckedHashTrieSet([1], value_type=int)
+    unpickled = pickle.loads(pickle.dumps(s))
+    assert unpickled == s
+    assert unpickled.value_type is int
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = CheckedHashTrieMap(a=Counted())
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
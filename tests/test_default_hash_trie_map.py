@@ -0,0 +1,104 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import DefaultHashTrieMap, HashTrieMap
+
+
+def test_missing_keys_produce_defaults():
+    m = DefaultHashTrieMap(list, a=[1])
+    assert m["a"] == [1]
+    assert m["b"] == []
+    assert m["b"] is not m["b"]
+    assert "b" not in m
+    assert len(m) == 1
+
+
+def test_get():
+    m = DefaultHashTrieMap(int, {"a": 1})
+    assert m.get("a") == 1
+    assert m.get("b") == 0
+    assert m.get("b", None) is None
+    assert m.get("b", 7) == 7
+
+
+def test_add_default():
+    m = DefaultHashTrieMap(HashTrieMap)
+    added = m.add_default("a")
+    assert added == {"a": HashTrieMap()}
+    assert added.add_default("a") == added
+    assert m == {}
+    assert added.factory is HashTrieMap
+
+
+def test_accumulation():
+    m = DefaultHashTrieMap(int)
+    for word in ["a", "b", "a"]:
+        m = m.insert(word, m[word] + 1)
+    assert m == HashTrieMap(a=2, b=1)
+    assert isinstance(m, DefaultHashTrieMap)
+
+
+def test_with_default():
+    m = HashTrieMap(a=1).with_default(int)
+    assert isinstance(m, DefaultHashTrieMap)
+    assert m["a"] == 1
+    assert m["b"] == 0
+
+
+def test_derived_maps_keep_the_factory():
+    m = DefaultHashTrieMap(list, a=1)
+    for derived in [
+        m.insert("b", 2),
+        m.remove("a"),
+        m.discard("c"),
+        m.update({"c": 3}),
+    ]:
+        assert isinstance(derived, DefaultHashTrieMap)
+        assert derived["missing"] == []
+
+
+def test_factory_must_be_callable():
+    with pytest.raises(TypeError):
+        DefaultHashTrieMap(3)
+
+
+def test_is_a_mapping():
+    m = DefaultHashTrieMap(int, a=1)
+    assert isinstance(m, abc.Mapping)
+    assert dict(m.items()) == {"a": 1}
+    assert list(m) == ["a"]
+
+
+def test_equality_and_hash():
+    assert DefaultHashTrieMap(int, a=1) == DefaultHashTrieMap(list, a=1)
+    assert DefaultHashTrieMap(int, a=1) == HashTrieMap(a=1)
+    assert DefaultHashTrieMap(int, a=1) != HashTrieMap(a=2)
+    assert hash(DefaultHashTrieMap(int, a=1)) == hash(HashTrieMap(a=1))
+
+
+def test_repr():
+    assert repr(DefaultHashTrieMap(int, a=1)) == (
+        "DefaultHashTrieMap(<class 'int'>, {'a': 1})"
+    )
+
+
+def test_pickle():
+    m = DefaultHashTrieMap(list, a=[1])
+    unpickled = pickle.loads(pickle.dumps(m))
+    assert unpickled == m
+    assert unpickled.factory is list
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = DefaultHashTrieMap(int, a=Counted())
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
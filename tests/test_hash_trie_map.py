@@ -28,13 +28,23 @@ Pre-modification, these were MIT licensed, and are copyright:
 """
 
 from collections import abc
-from operator import methodcaller
+from operator import add, methodcaller
 import pickle
+import sys
 import sysconfig
 
 import pytest
 
-from rpds import HashTrieMap
+from rpds import (
+    HashTrieMap,
+    HashTrieSet,
+    List,
+    Vector,
+    apply_json_patch,
+    deep_merge,
+    make_json_patch,
+    resolve_pointer,
+)
 
 # see https://github.com/python/cpython/issues/127065,
 # remove this when the CPython bug is fixed in a released version
@@ -204,14 +214,16 @@ class HashabilityControlled:
 def test_map_does_not_hash_values_on_second_hash_invocation():
     hashable = HashabilityControlled()
     x = HashTrieMap(dict(el=hashable))
-    hash(x)
+    first = hash(x)
 
     hashable.hashable = False
+    assert hash(x) == first
+
     with pytest.raises(
         TypeError,
         match=r"Unhashable type in HashTrieMap of key 'el'",
     ):
-        hash(x)
+        hash(HashTrieMap(dict(el=hashable)))
 
 
 def test_equal():
@@ -236,6 +248,26 @@ def test_equal_with_different_insertion_order():
     assert not (y != x)
 
 
+def test_equal_to_other_mappings_does_not_compare_identical_values():
+    nan = float("nan")
+    assert HashTrieMap({1: nan}) == HashTrieMap({1: nan})
+    assert HashTrieMap({1: nan}) == {1: nan}
+    assert not HashTrieMap({1: nan}) != {1: nan}
+
+
+def test_equal_propagates_equality_errors():
+    class Incomparable:
+        def __eq__(self, other):
+            raise ZeroDivisionError()
+
+        __hash__ = object.__hash__
+
+    with pytest.raises(ZeroDivisionError):
+        HashTrieMap({1: Incomparable()}) == HashTrieMap({1: Incomparable()})
+    with pytest.raises(ZeroDivisionError):
+        HashTrieMap({1: Incomparable()}) == {1: Incomparable()}
+
+
 def test_not_equal():
     x = HashTrieMap(a=1, b=2, c=3)
     y = HashTrieMap(a=1, b=2)
@@ -365,6 +397,22 @@ def test_pickle():
     ) == HashTrieMap([(1, 2), (3, 4)])
 
 
+@pytest.mark.parametrize("protocol", range(pickle.HIGHEST_PROTOCOL + 1))
+def test_pickle_protocols(protocol):
+    m = HashTrieMap((i, str(i)) for i in range(1000))
+    assert pickle.loads(pickle.dumps(m, protocol=protocol)) == m
+    assert pickle.loads(pickle.dumps(HashTrieMap(), protocol=protocol)) == {}
+
+
+def test_unpickle_list_of_pairs():
+    # the format older versions pickled as, which should keep loading
+    assert pickle.loads(
+        b"\x80\x04\x95%\x00\x00\x00\x00\x00\x00\x00\x8c\x04rpds"
+        b"\x94\x8c\x0bHashTrieMap\x94\x93\x94]\x94K\x01K\x02\x86\x94a"
+        b"\x85\x94R\x94.",
+    ) == HashTrieMap({1: 2})
+
+
 def test_get():
     m1 = HashTrieMap({"foo": "bar"})
     assert m1.get("foo") == "bar"
@@ -567,3 +615,969 @@ def test_update_with_iterable_of_kvs():
             "5": 6,
         },
     )
+
+
+def test_flatten():
+    m = HashTrieMap({"a": HashTrieMap({"b": 1, "c": {"d": 2}}), "e": 3})
+    assert m.flatten() == HashTrieMap({"a.b": 1, "a.c.d": 2, "e": 3})
+
+
+def test_flatten_separator():
+    m = HashTrieMap({"a": HashTrieMap({"b": 1})})
+    assert m.flatten(sep="/") == HashTrieMap({"a/b": 1})
+
+
+def test_flatten_keeps_empty_nested_maps():
+    m = HashTrieMap({"a": HashTrieMap(), 1: {2: 3}})
+    assert m.flatten() == HashTrieMap({"a": HashTrieMap(), "1.2": 3})
+
+
+def test_flatten_duplicate_keys():
+    m = HashTrieMap({"a.b": 1, "a": {"b": 2}})
+    with pytest.raises(ValueError, match="a.b"):
+        m.flatten()
+
+
+def test_unflatten():
+    m = HashTrieMap({"a.b": 1, "a.c.d": 2, "e": 3, 4: 5})
+    assert m.unflatten() == HashTrieMap(
+        {
+            "a": HashTrieMap({"b": 1, "c": HashTrieMap({"d": 2})}),
+            "e": 3,
+            4: 5,
+        },
+    )
+
+
+def test_unflatten_round_trips_flatten():
+    m = HashTrieMap({"a": HashTrieMap({"b": 1, "c": HashTrieMap()}), "d": 2})
+    assert m.flatten(sep="/").unflatten(sep="/") == m
+
+
+def test_unflatten_conflict():
+    with pytest.raises(ValueError):
+        HashTrieMap({"a": 1, "a.b": 2}).unflatten()
+
+
+def test_deep_merge():
+    base = HashTrieMap({"a": HashTrieMap({"b": 1, "c": 2}), "d": 3})
+    override = HashTrieMap({"a": HashTrieMap({"c": 4}), "e": 5})
+    assert deep_merge(base, override) == HashTrieMap(
+        {"a": HashTrieMap({"b": 1, "c": 4}), "d": 3, "e": 5},
+    )
+
+
+def test_deep_merge_plain_mappings():
+    merged = deep_merge(HashTrieMap(a={"b": 1}), HashTrieMap(a={"c": 2}))
+    assert merged == HashTrieMap(a=HashTrieMap(b=1, c=2))
+    assert isinstance(merged["a"], HashTrieMap)
+
+    mixed = deep_merge({"a": HashTrieMap(b=1)}, {"a": {"c": {"d": 3}}})
+    assert mixed == HashTrieMap(a=HashTrieMap(b=1, c={"d": 3}))
+
+
+def test_deep_merge_list_strategy():
+    first, second = {"a": List([1, 2])}, {"a": List([3])}
+    assert deep_merge(first, second) == HashTrieMap({"a": List([3])})
+    assert deep_merge(
+        first,
+        second,
+        list_strategy="concatenate",
+    ) == HashTrieMap({"a": List([1, 2, 3])})
+    with pytest.raises(ValueError):
+        deep_merge(first, second, list_strategy="zip")
+
+
+def test_resolve_pointer():
+    doc = HashTrieMap({"a": HashTrieMap({"b": List([1, 2, {"c": 3}])})})
+    assert resolve_pointer(doc, "") is doc
+    assert resolve_pointer(doc, "/a/b/1") == 2
+    assert resolve_pointer(doc, "/a/b/2/c") == 3
+    assert doc.resolve_pointer("/a/b/0") == 1
+
+
+def test_resolve_pointer_into_vectors():
+    doc = HashTrieMap({"a": Vector([1, HashTrieMap({"b": 2})])})
+    assert doc.resolve_pointer("/a/0") == 1
+    assert doc.resolve_pointer("/a/1/b") == 2
+    with pytest.raises(IndexError):
+        doc.resolve_pointer("/a/2")
+    with pytest.raises(IndexError):
+        doc.resolve_pointer("/a/-1")
+
+
+def test_resolve_pointer_unescapes():
+    doc = HashTrieMap({"a/b": 1, "c~d": 2, "~1": 3})
+    assert doc.resolve_pointer("/a~1b") == 1
+    assert doc.resolve_pointer("/c~0d") == 2
+    assert doc.resolve_pointer("/~01") == 3
+
+
+def test_resolve_pointer_nowhere():
+    doc = HashTrieMap({"a": List([1])})
+    with pytest.raises(KeyError):
+        doc.resolve_pointer("/b")
+    with pytest.raises(IndexError):
+        doc.resolve_pointer("/a/1")
+    with pytest.raises(IndexError):
+        doc.resolve_pointer("/a/01")
+    with pytest.raises(ValueError):
+        doc.resolve_pointer("a")
+
+
+def test_apply_json_patch():
+    doc = HashTrieMap({"a": HashTrieMap({"b": List([1, 2])}), "c": 3})
+    patched = apply_json_patch(
+        doc,
+        [
+            {"op": "add", "path": "/a/b/1", "value": 7},
+            {"op": "add", "path": "/a/b/-", "value": 8},
+            {"op": "remove", "path": "/c"},
+            {"op": "replace", "path": "/a/b/0", "value": 0},
+            {"op": "copy", "from": "/a/b", "path": "/d"},
+            {"op": "move", "from": "/d", "path": "/e"},
+            {"op": "test", "path": "/e/3", "value": 8},
+        ],
+    )
+    assert patched == HashTrieMap(
+        {"a": HashTrieMap({"b": List([0, 7, 2, 8])}), "e": List([0, 7, 2, 8])},
+    )
+    assert doc == HashTrieMap({"a": HashTrieMap({"b": List([1, 2])}), "c": 3})
+
+
+def test_apply_json_patch_failures():
+    doc = HashTrieMap({"a": List([1])})
+    with pytest.raises(ValueError):
+        apply_json_patch(doc, [{"op": "test", "path": "/a/0", "value": 2}])
+    with pytest.raises(KeyError):
+        apply_json_patch(doc, [{"op": "replace", "path": "/b", "value": 2}])
+    with pytest.raises(IndexError):
+        apply_json_patch(doc, [{"op": "remove", "path": "/a/1"}])
+    with pytest.raises(ValueError):
+        apply_json_patch(doc, [{"op": "move", "from": "/a", "path": "/a/0"}])
+
+
+def test_make_json_patch_round_trips():
+    old = HashTrieMap({"a": HashTrieMap({"b": List([1, 2, 3])}), "c/d": 4})
+    new = HashTrieMap({"a": HashTrieMap({"b": List([1, 5])}), "e": 6})
+    patch = make_json_patch(old, new)
+    assert {"op": "replace", "path": "/a/b/1", "value": 5} in patch
+    assert {"op": "remove", "path": "/c~1d"} in patch
+    assert apply_json_patch(old, patch) == new
+    assert make_json_patch(new, new) == []
+
+
+def test_make_json_patch_replaces_maps_with_non_string_keys():
+    old = HashTrieMap({"a": HashTrieMap({1: "x", 2: "y"})})
+    new = HashTrieMap({"a": HashTrieMap({1: "x", 2: "z"})})
+    patch = make_json_patch(old, new)
+    assert patch == [{"op": "replace", "path": "/a", "value": new["a"]}]
+    assert apply_json_patch(old, patch) == new
+    assert make_json_patch(old, HashTrieMap(old)) == []
+
+
+def test_apply_json_patch_to_vectors():
+    doc = HashTrieMap({"a": Vector([1, HashTrieMap({"b": 2}), 3])})
+    patched = apply_json_patch(
+        doc,
+        [
+            {"op": "add", "path": "/a/0", "value": 0},
+            {"op": "add", "path": "/a/-", "value": 4},
+            {"op": "replace", "path": "/a/2/b", "value": 5},
+            {"op": "remove", "path": "/a/3"},
+        ],
+    )
+    assert patched == HashTrieMap({"a": Vector([0, 1, HashTrieMap({"b": 5}), 4])})
+    assert doc == HashTrieMap({"a": Vector([1, HashTrieMap({"b": 2}), 3])})
+    with pytest.raises(IndexError):
+        apply_json_patch(doc, [{"op": "remove", "path": "/a/3"}])
+
+
+def test_make_json_patch_round_trips_vectors():
+    old = HashTrieMap({"a": Vector([1, HashTrieMap({"b": 2}), 3])})
+    new = HashTrieMap({"a": Vector([1, HashTrieMap({"b": 5})])})
+    patch = make_json_patch(old, new)
+    assert patch == [
+        {"op": "replace", "path": "/a/1/b", "value": 5},
+        {"op": "remove", "path": "/a/2"},
+    ]
+    assert apply_json_patch(old, patch) == new
+
+
+def test_make_json_patch_round_trips_plain_containers():
+    old = HashTrieMap({"a": {"b": [1, {"c": 2}], "d": 3}})
+    new = HashTrieMap({"a": {"b": [1, {"c": 4}, 5], "e": 6}})
+    patch = make_json_patch(old, new)
+    assert {"op": "replace", "path": "/a/b/1/c", "value": 4} in patch
+    assert {"op": "remove", "path": "/a/d"} in patch
+    patched = apply_json_patch(old, patch)
+    assert patched == new
+    assert type(patched["a"]) is dict
+    assert old == HashTrieMap({"a": {"b": [1, {"c": 2}], "d": 3}})
+
+
+def test_apply_json_patch_within_plain_containers():
+    doc = HashTrieMap({"a": {"b": [1, 2]}})
+    assert resolve_pointer(doc, "/a/b/1") == 2
+    patched = apply_json_patch(
+        doc,
+        [
+            {"op": "add", "path": "/a/b/-", "value": 3},
+            {"op": "replace", "path": "/a/b/0", "value": 0},
+            {"op": "remove", "path": "/a/b/1"},
+            {"op": "add", "path": "/a/c", "value": 4},
+        ],
+    )
+    assert patched == HashTrieMap({"a": {"b": [0, 3], "c": 4}})
+    assert doc == HashTrieMap({"a": {"b": [1, 2]}})
+    with pytest.raises(KeyError):
+        apply_json_patch(doc, [{"op": "remove", "path": "/a/z"}])
+    with pytest.raises(IndexError):
+        apply_json_patch(doc, [{"op": "remove", "path": "/a/b/2"}])
+
+
+def test_json_patch_methods():
+    old = HashTrieMap({"a": HashTrieMap({"b": List([1, 2])}), "c": 3})
+    new = HashTrieMap({"a": HashTrieMap({"b": List([1])}), "d": 4})
+    patch = old.to_json_patch(new)
+    assert patch == make_json_patch(old, new)
+    assert old.apply_json_patch(patch) == new
+    assert old.apply_json_patch([]) is old
+
+
+def test_evolver():
+    original = HashTrieMap(a=1, b=2)
+    evolver = original.evolver()
+    evolver["c"] = 3
+    evolver["a"] = 4
+    del evolver["b"]
+    assert evolver["a"] == 4
+    assert "c" in evolver
+    assert "b" not in evolver
+    assert len(evolver) == 2
+    assert evolver.persistent() == HashTrieMap(a=4, c=3)
+    assert original == HashTrieMap(a=1, b=2)
+
+
+def test_evolver_persistent_is_a_snapshot():
+    evolver = HashTrieMap().evolver()
+    evolver["a"] = 1
+    snapshot = evolver.persistent()
+    evolver["b"] = 2
+    assert snapshot == HashTrieMap(a=1)
+    assert evolver.persistent() == HashTrieMap(a=1, b=2)
+
+
+def test_evolver_missing_key():
+    evolver = HashTrieMap().evolver()
+    with pytest.raises(KeyError):
+        evolver["a"]
+    with pytest.raises(KeyError):
+        del evolver["a"]
+
+
+def test_mutate_with_callable():
+    original = HashTrieMap(a=1)
+    result = original.mutate(lambda e: e.__setitem__("b", 2))
+    assert result == HashTrieMap(a=1, b=2)
+    assert original == HashTrieMap(a=1)
+
+
+def test_mutate_as_context_manager():
+    original = HashTrieMap(a=1)
+    with original.mutate() as evolver:
+        evolver["b"] = 2
+        del evolver["a"]
+    assert evolver.persistent() == HashTrieMap(b=2)
+    assert original == HashTrieMap(a=1)
+
+
+def test_pop():
+    m = HashTrieMap(a=1, b=2)
+    value, rest = m.pop("a")
+    assert value == 1
+    assert rest == HashTrieMap(b=2)
+    assert m == HashTrieMap(a=1, b=2)
+
+
+def test_pop_default():
+    m = HashTrieMap(a=1)
+    assert m.pop("b", None) == (None, m)
+    assert m.pop("a", None) == (1, HashTrieMap())
+
+
+def test_pop_missing():
+    with pytest.raises(KeyError):
+        HashTrieMap().pop("a")
+    with pytest.raises(TypeError):
+        HashTrieMap().pop("a", 1, 2)
+
+
+def test_popitem():
+    m = HashTrieMap(a=1, b=2)
+    key, value, rest = m.popitem()
+    assert m[key] == value
+    assert rest == m.remove(key)
+
+
+def test_popitem_drains():
+    m, seen = HashTrieMap(a=1, b=2, c=3), {}
+    while m:
+        key, value, m = m.popitem()
+        seen[key] = value
+    assert seen == {"a": 1, "b": 2, "c": 3}
+
+
+def test_popitem_empty():
+    with pytest.raises(KeyError):
+        HashTrieMap().popitem()
+
+
+def test_insert_if_missing():
+    m = HashTrieMap(a=1)
+    assert m.insert_if_missing("b", 2) == (2, HashTrieMap(a=1, b=2))
+    value, same = m.insert_if_missing("a", 3)
+    assert value == 1
+    assert same == m
+
+
+def test_or():
+    m = HashTrieMap(a=1, b=2)
+    assert m | {"b": 3, "c": 4} == HashTrieMap(a=1, b=3, c=4)
+    assert m | HashTrieMap(c=4) == HashTrieMap(a=1, b=2, c=4)
+    assert m == HashTrieMap(a=1, b=2)
+
+
+def test_ror():
+    result = {"a": 0, "c": 4} | HashTrieMap(a=1, b=2)
+    assert isinstance(result, HashTrieMap)
+    assert result == HashTrieMap(a=1, b=2, c=4)
+
+
+def test_or_non_mapping():
+    with pytest.raises(TypeError):
+        HashTrieMap() | [("a", 1)]
+    with pytest.raises(TypeError):
+        [("a", 1)] | HashTrieMap()
+
+
+def test_eq_with_other_mappings():
+    m = HashTrieMap(a=1, b=2)
+    assert m == {"a": 1, "b": 2}
+    assert {"a": 1, "b": 2} == m
+    assert m != {"a": 1}
+    assert m != {"a": 1, "b": 3}
+    assert m != {"a": 1, "c": 2}
+    assert not m == {"a": 1, "c": 2}
+    assert m != [("a", 1), ("b", 2)]
+
+
+def test_eq_with_other_mappings_propagates_errors():
+    class Boom:
+        def __eq__(self, other):
+            raise ZeroDivisionError()
+
+        __hash__ = object.__hash__
+
+    m = HashTrieMap(a=Boom())
+    with pytest.raises(ZeroDivisionError):
+        m == {"a": 1}
+    with pytest.raises(ZeroDivisionError):
+        m != {"a": 1}
+
+
+def test_update_with_iterables_of_pairs():
+    m = HashTrieMap(a=1)
+    pairs = (("b", i) for i in [2])
+    assert m.update(pairs, [["c", 3]], [("d", 4)]) == HashTrieMap(a=1, b=2, c=3, d=4)
+
+
+def test_update_with_malformed_pairs():
+    with pytest.raises(ValueError):
+        HashTrieMap().update([("a", 1, 2)])
+    with pytest.raises(TypeError):
+        HashTrieMap().update([1])
+
+
+def test_init_with_list_pairs():
+    assert HashTrieMap([["a", 1]]) == HashTrieMap(a=1)
+
+
+def test_update_with():
+    m = HashTrieMap(a=1, b=2)
+    combined = m.update_with(add, {"b": 3, "c": 4}, [("a", 10)])
+    assert combined == HashTrieMap(a=11, b=5, c=4)
+    assert m == HashTrieMap(a=1, b=2)
+
+
+def test_update_with_repeated_keys_in_one_argument():
+    m = HashTrieMap(a=[1])
+    assert m.update_with(add, [("a", [2]), ("a", [3])]) == HashTrieMap(
+        a=[1, 2, 3],
+    )
+
+
+def test_update_with_no_arguments():
+    m = HashTrieMap(a=1)
+    assert m.update_with(add) == m
+
+
+def test_update_with_error_propagates():
+    with pytest.raises(TypeError):
+        HashTrieMap(a=1).update_with(add, {"a": "x"})
+
+
+def test_merge_deep():
+    base = HashTrieMap(a=HashTrieMap(b=1, c=2), d=3)
+    layer = HashTrieMap(a=HashTrieMap(c=20, e=5), f=6)
+    assert base.merge_deep(layer) == HashTrieMap(
+        a=HashTrieMap(b=1, c=20, e=5),
+        d=3,
+        f=6,
+    )
+
+
+def test_merge_deep_replaces_non_map_values():
+    base = HashTrieMap(a=HashTrieMap(b=1))
+    assert base.merge_deep({"a": 2}) == HashTrieMap(a=2)
+
+
+def test_merge_deep_on_conflict():
+    base = HashTrieMap(a=HashTrieMap(b=1), c=2)
+    layer = HashTrieMap(a=HashTrieMap(b=10), c=20, d=4)
+    assert base.merge_deep(layer, on_conflict=add) == HashTrieMap(
+        a=HashTrieMap(b=11),
+        c=22,
+        d=4,
+    )
+
+
+def test_merge_deep_shares_unchanged_values():
+    nested = HashTrieMap(b=1)
+    merged = HashTrieMap(a=nested).merge_deep({"a": nested, "c": 2})
+    assert merged["a"] is nested
+
+
+def test_map_values():
+    m = HashTrieMap(a=1, b=2)
+    assert m.map_values(lambda v: v * 10) == HashTrieMap(a=10, b=20)
+    assert m == HashTrieMap(a=1, b=2)
+
+
+def test_map_values_empty():
+    assert HashTrieMap().map_values(str) == HashTrieMap()
+
+
+def test_map_values_error_propagates():
+    with pytest.raises(ZeroDivisionError):
+        HashTrieMap(a=0).map_values(lambda v: 1 / v)
+
+
+def test_map_keys():
+    m = HashTrieMap({"Content-Type": 1, "Accept": 2})
+    assert m.map_keys(str.lower) == HashTrieMap({"content-type": 1, "accept": 2})
+
+
+def test_map_keys_collision():
+    m = HashTrieMap({"A": 1, "a": 2})
+    with pytest.raises(ValueError):
+        m.map_keys(str.lower)
+
+
+def test_map_keys_collision_combine():
+    m = HashTrieMap({"A": 1, "a": 2, "b": 3})
+    assert m.map_keys(str.lower, combine=add) == HashTrieMap(a=3, b=3)
+
+
+def test_map_keys_unhashable_result():
+    with pytest.raises(TypeError):
+        HashTrieMap(a=1).map_keys(list)
+
+
+def test_partition():
+    m = HashTrieMap(a=1, b=2, c=3, d=4)
+    known = {"a", "c"}
+    matching, rest = m.partition(lambda k, v: k in known)
+    assert matching == HashTrieMap(a=1, c=3)
+    assert rest == HashTrieMap(b=2, d=4)
+
+
+def test_partition_by_value():
+    matching, rest = HashTrieMap(a=1, b=2).partition(lambda k, v: v % 2)
+    assert (matching, rest) == (HashTrieMap(a=1), HashTrieMap(b=2))
+
+
+def test_partition_empty():
+    assert HashTrieMap().partition(lambda k, v: True) == (
+        HashTrieMap(),
+        HashTrieMap(),
+    )
+
+
+def test_select():
+    m = HashTrieMap(a=1, b=2, c=3)
+    assert m.select(["a", "c", "missing"]) == HashTrieMap(a=1, c=3)
+    assert m.select(iter(["b"])) == HashTrieMap(b=2)
+    assert m.select([]) == HashTrieMap()
+
+
+def test_select_unhashable_key():
+    with pytest.raises(TypeError):
+        HashTrieMap(a=1).select([[]])
+
+
+def test_omit():
+    m = HashTrieMap(a=1, b=2, c=3)
+    assert m.omit(["a", "c", "missing"]) == HashTrieMap(b=2)
+    assert m.omit(iter([])) == m
+    assert m == HashTrieMap(a=1, b=2, c=3)
+
+
+def test_get_in():
+    m = HashTrieMap(
+        a=HashTrieMap(b=List([HashTrieMap(c=1), 2])),
+        d=Vector([3, HashTrieMap(e=4)]),
+        f={"g": [5]},
+    )
+    assert m.get_in(["a", "b", 0, "c"]) == 1
+    assert m.get_in(["a", "b", -1]) == 2
+    assert m.get_in(["d", 1, "e"]) == 4
+    assert m.get_in(("f", "g", 0)) == 5
+    assert m.get_in([]) is m
+
+
+def test_get_in_missing():
+    m = HashTrieMap(a=HashTrieMap(b=List([1])), c=3)
+    assert m.get_in(["missing"]) is None
+    assert m.get_in(["a", "b", 1], default="nope") == "nope"
+    assert m.get_in(["a", "b", "x"], default="nope") == "nope"
+    assert m.get_in(["c", "d"], default="nope") == "nope"
+
+
+def test_set_in():
+    m = HashTrieMap(a=HashTrieMap(b=1, c=2), d=List([0, HashTrieMap(e=3)]))
+    assert m.set_in(["a", "b"], 10) == HashTrieMap(
+        a=HashTrieMap(b=10, c=2),
+        d=List([0, HashTrieMap(e=3)]),
+    )
+    assert m.set_in(["d", -1, "e"], 30)["d"] == List([0, HashTrieMap(e=30)])
+    assert m == HashTrieMap(a=HashTrieMap(b=1, c=2), d=List([0, HashTrieMap(e=3)]))
+
+
+def test_set_in_creates_intermediate_maps():
+    m = HashTrieMap(a=1)
+    assert m.set_in(["b", "c", "d"], 2) == HashTrieMap(
+        a=1,
+        b=HashTrieMap(c=HashTrieMap(d=2)),
+    )
+
+
+def test_set_in_vector():
+    m = HashTrieMap(a=Vector([1, 2, 3]))
+    assert m.set_in(["a", 1], 20) == HashTrieMap(a=Vector([1, 20, 3]))
+
+
+def test_set_in_shares_siblings():
+    sibling = HashTrieMap(x=1)
+    m = HashTrieMap(a=HashTrieMap(b=1), s=sibling)
+    assert m.set_in(["a", "b"], 2)["s"] is sibling
+
+
+def test_set_in_invalid():
+    m = HashTrieMap(a=List([1]), b=2)
+    with pytest.raises(IndexError):
+        m.set_in(["a", 5], 0)
+    with pytest.raises(TypeError):
+        m.set_in(["b", "c"], 0)
+    with pytest.raises(ValueError):
+        m.set_in([], 0)
+
+
+def test_delete_in():
+    sibling = HashTrieMap(x=1)
+    m = HashTrieMap(a=HashTrieMap(b=1, c=2), s=sibling)
+    deleted = m.delete_in(["a", "b"])
+    assert deleted == HashTrieMap(a=HashTrieMap(c=2), s=sibling)
+    assert deleted["s"] is sibling
+    assert m.delete_in(["s"]) == HashTrieMap(a=HashTrieMap(b=1, c=2))
+
+
+def test_delete_in_sequences():
+    m = HashTrieMap(a=List([1, 2, 3]), b=Vector([4, 5, 6]))
+    assert m.delete_in(["a", 1])["a"] == List([1, 3])
+    assert m.delete_in(["b", 0])["b"] == Vector([5, 6])
+    assert m.delete_in(["b", -1])["b"] == Vector([4, 5])
+
+
+def test_delete_in_missing():
+    m = HashTrieMap(a=HashTrieMap(b=1), c=List([]))
+    with pytest.raises(KeyError):
+        m.delete_in(["a", "missing"])
+    with pytest.raises(KeyError):
+        m.delete_in(["missing", "b"])
+    with pytest.raises(IndexError):
+        m.delete_in(["c", 0])
+    with pytest.raises(ValueError):
+        m.delete_in([])
+
+
+def test_try_remove():
+    m = HashTrieMap(a=1, b=2)
+    assert m.try_remove("a") == (HashTrieMap(b=2), True)
+    assert m.try_remove("missing") == (m, False)
+
+
+def test_remove_many():
+    m = HashTrieMap(a=1, b=2, c=3)
+    assert m.remove_many(["a", "c"]) == HashTrieMap(b=2)
+    assert m.remove_many(iter([])) == m
+
+
+def test_remove_many_missing():
+    with pytest.raises(KeyError):
+        HashTrieMap(a=1).remove_many(["a", "b"])
+
+
+def test_insert_many():
+    m = HashTrieMap(a=1)
+    pairs = ((k, len(k)) for k in ["bb", "ccc"])
+    assert m.insert_many(pairs) == HashTrieMap(a=1, bb=2, ccc=3)
+    assert m.insert_many([("a", 2), ("a", 3)]) == HashTrieMap(a=3)
+
+
+def test_insert_many_does_not_accept_mappings_as_pairs():
+    with pytest.raises(TypeError):
+        HashTrieMap().insert_many({"a": 1})
+
+
+def test_fromkeys_factory():
+    m = HashTrieMap.fromkeys(["a", "b"], list, factory=True)
+    assert m == HashTrieMap(a=[], b=[])
+    assert m["a"] is not m["b"]
+
+
+def test_fromkeys_factory_not_callable():
+    with pytest.raises(TypeError):
+        HashTrieMap.fromkeys(["a"], 1, factory=True)
+
+
+def test_keys_set():
+    m = HashTrieMap(a=1, b=2)
+    keys = m.keys_set()
+    assert isinstance(keys, HashTrieSet)
+    assert keys == HashTrieSet(["a", "b"])
+    assert HashTrieMap().keys_set() == HashTrieSet()
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    m = HashTrieMap(a=Counted())
+    assert hash(m) == hash(m)
+    assert len(calls) == 1
+
+
+def test_convert_deep():
+    converted = HashTrieMap.convert(
+        {"a": {"b": [1, {"c": 2}]}, "d": {3, 4}, "e": (5, [6])},
+        deep=True,
+    )
+    assert converted == HashTrieMap(
+        a=HashTrieMap(b=List([1, HashTrieMap(c=2)])),
+        d=HashTrieSet([3, 4]),
+        e=(5, [6]),
+    )
+    assert isinstance(converted["a"], HashTrieMap)
+    assert isinstance(converted["a"]["b"], List)
+    assert isinstance(converted["d"], HashTrieSet)
+
+
+def test_convert_deep_converts_within_existing_maps():
+    m = HashTrieMap(a=[1])
+    assert HashTrieMap.convert(m) is m
+    assert isinstance(HashTrieMap.convert(m, deep=True)["a"], List)
+
+
+def test_convert_deep_from_pairs():
+    pairs = (pair for pair in [("a", {"b": [1]}), ("c", 2)])
+    converted = HashTrieMap.convert(pairs, deep=True)
+    assert converted == HashTrieMap(a=HashTrieMap(b=List([1])), c=2)
+    assert isinstance(converted["a"], HashTrieMap)
+
+
+def test_sizeof_grows_with_size():
+    empty = sys.getsizeof(HashTrieMap())
+    assert sys.getsizeof(HashTrieMap(a=1)) > empty
+    assert sys.getsizeof(HashTrieMap.fromkeys(range(100))) > sys.getsizeof(
+        HashTrieMap(a=1),
+    )
+
+
+def test_ne_with_one_differing_value():
+    assert HashTrieMap(a=1, b=2) != HashTrieMap(a=1, b=3)
+    assert not HashTrieMap(a=1, b=2) != HashTrieMap(a=1, b=2)
+
+
+def test_equality_does_not_compare_identical_values():
+    class NeverEqual:
+        def __eq__(self, other):
+            raise AssertionError("compared!")
+
+        __hash__ = object.__hash__
+
+    value = NeverEqual()
+    m = HashTrieMap(a=value, b=1)
+    assert m == m
+    assert m == m.insert("b", 1)
+    assert m == HashTrieMap(a=value, b=1)
+    assert not m != HashTrieMap(a=value, b=1)
+    assert HashTrieMap({value: 1}) == HashTrieMap({value: 1})
+
+
+def test_sorted_items():
+    m = HashTrieMap(b=1, c=3, a=2)
+    assert m.sorted_items() == [("a", 2), ("b", 1), ("c", 3)]
+    assert m.sorted_items(reverse=True) == [("c", 3), ("b", 1), ("a", 2)]
+
+
+def test_sorted_keys():
+    m = HashTrieMap.fromkeys(["bb", "a", "ccc"])
+    assert m.sorted_keys() == ["a", "bb", "ccc"]
+    assert m.sorted_keys(key=len, reverse=True) == ["ccc", "bb", "a"]
+
+
+def test_sorted_is_stable():
+    m = HashTrieMap.fromkeys(range(20))
+    assert m.sorted_keys(key=lambda k: k % 2) == sorted(m, key=lambda k: k % 2)
+    assert m.sorted_keys(key=lambda k: k % 2, reverse=True) == sorted(
+        m,
+        key=lambda k: k % 2,
+        reverse=True,
+    )
+
+
+def test_sorted_items_incomparable_keys():
+    with pytest.raises(TypeError):
+        HashTrieMap({1: 1, "a": 2}).sorted_items()
+
+
+def test_invert():
+    assert HashTrieMap(a=1, b=2).invert() == HashTrieMap({1: "a", 2: "b"})
+    assert HashTrieMap().invert() == HashTrieMap()
+
+
+def test_invert_duplicate_values():
+    with pytest.raises(ValueError):
+        HashTrieMap(a=1, b=1).invert()
+
+
+def test_invert_collect():
+    inverted = HashTrieMap(a=1, b=1, c=2).invert(collect=True)
+    assert inverted == HashTrieMap(
+        {1: HashTrieSet(["a", "b"]), 2: HashTrieSet(["c"])},
+    )
+
+
+def test_invert_unhashable_values():
+    with pytest.raises(TypeError):
+        HashTrieMap(a=[]).invert()
+
+
+def test_reduce():
+    m = HashTrieMap(a=1, bb=2, ccc=3)
+    assert m.reduce(lambda acc, k, v: acc + len(k) * v, 0) == 14
+    assert HashTrieMap().reduce(lambda acc, k, v: acc + v, "initial") == "initial"
+
+
+def test_group_by():
+    groups = HashTrieMap.group_by(len, ["a", "bb", "c", "dd", "eee", "f"])
+    assert groups == HashTrieMap(
+        {
+            1: List(["a", "c", "f"]),
+            2: List(["bb", "dd"]),
+            3: List(["eee"]),
+        },
+    )
+
+
+def test_group_by_empty():
+    assert HashTrieMap.group_by(len, iter([])) == HashTrieMap()
+
+
+def test_group_by_unhashable_key():
+    with pytest.raises(TypeError):
+        HashTrieMap.group_by(lambda each: [each], [1, 2])
+
+
+def test_keys_difference_and_symmetric_difference():
+    keys = HashTrieMap({1: 2, 3: 4}).keys()
+
+    assert keys - {3, 5} == HashTrieSet([1])
+    assert keys - [1] == HashTrieSet([3])
+    assert keys ^ {3, 5} == HashTrieSet([1, 5])
+    assert keys ^ iter([1]) == HashTrieSet([3])
+    assert isinstance(keys - {3}, HashTrieSet)
+
+
+def test_keys_reflected_operators():
+    keys = HashTrieMap({1: 2, 3: 4}).keys()
+
+    assert {1, 5} & keys == HashTrieSet([1])
+    assert {5} | keys == HashTrieSet([1, 3, 5])
+    assert {1, 5} - keys == HashTrieSet([5])
+    assert frozenset([3, 5]) ^ keys == HashTrieSet([1, 5])
+    assert isinstance({5} - keys, HashTrieSet)
+
+
+def test_keys_operators_with_other_keys_and_sets():
+    small = HashTrieMap.fromkeys([2, 3]).keys()
+    large = HashTrieMap.fromkeys(range(1, 6)).keys()
+    s = HashTrieSet(range(4))
+
+    assert small | large == HashTrieSet(range(1, 6))
+    assert small - large == HashTrieSet()
+    assert large - small == HashTrieSet([1, 4, 5])
+    assert small ^ large == HashTrieSet([1, 4, 5])
+    assert large - s == HashTrieSet([4, 5])
+    assert s - large == HashTrieSet([0])
+    assert s - small == HashTrieSet([0, 1])
+    assert large ^ s == HashTrieSet([0, 4, 5])
+    assert s | small == s
+    assert set(range(4)) - small == HashTrieSet([0, 1])
+    assert {0, 9} | small == HashTrieSet([0, 2, 3, 9])
+
+
+def test_keys_operators_reject_non_iterables():
+    keys = HashTrieMap({1: 2}).keys()
+
+    with pytest.raises(TypeError):
+        keys - 1
+    with pytest.raises(TypeError):
+        1 ^ keys
+
+
+def test_keys_isdisjoint():
+    keys = HashTrieMap({1: 2, 3: 4}).keys()
+
+    assert keys.isdisjoint({5, 6, 7})
+    assert not keys.isdisjoint({3})
+    assert keys.isdisjoint([5])
+    assert not keys.isdisjoint(iter([5, 1]))
+    assert keys.isdisjoint(HashTrieSet([5]))
+    assert not keys.isdisjoint(HashTrieSet([1, 5, 6]))
+    assert keys.isdisjoint(HashTrieMap({5: 6}).keys())
+    assert HashTrieMap().keys().isdisjoint({1})
+
+
+def test_items_difference_and_symmetric_difference():
+    items = HashTrieMap({1: 2, 3: 4}).items()
+
+    assert items - {(1, 2)} == HashTrieSet([(3, 4)])
+    assert items - [(1, 7)] == HashTrieSet([(1, 2), (3, 4)])
+    assert items ^ {(1, 2), (5, 6)} == HashTrieSet([(3, 4), (5, 6)])
+
+    assert items.difference([(3, 4)]) == HashTrieSet([(1, 2)])
+    assert items.symmetric_difference({3: 4}.items()) == HashTrieSet([(1, 2)])
+
+
+def test_items_compares_values():
+    items = HashTrieMap({1: 2}).items()
+
+    assert items - {(1, 2.0)} == HashTrieSet()
+    assert items - {(1, 3)} == HashTrieSet([(1, 2)])
+
+
+def test_items_reflected_operators():
+    items = HashTrieMap({1: 2, 3: 4}).items()
+
+    assert {(1, 2), (5, 6)} & items == HashTrieSet([(1, 2)])
+    assert {(5, 6)} | items == HashTrieSet([(1, 2), (3, 4), (5, 6)])
+    assert {(1, 2), (5, 6)} - items == HashTrieSet([(5, 6)])
+    assert frozenset([(1, 2)]) ^ items == HashTrieSet([(3, 4)])
+
+
+def test_items_operators_reject_non_iterables():
+    items = HashTrieMap({1: 2}).items()
+
+    with pytest.raises(TypeError):
+        items - 1
+    with pytest.raises(TypeError):
+        1 ^ items
+    with pytest.raises(TypeError):
+        items.difference(1)
+
+
+@pytest.mark.parametrize(
+    "view",
+    [pytest.param(methodcaller(p), id=p) for p in ["keys", "values", "items"]],
+)
+def test_views_mapping(view):
+    m = HashTrieMap(a=1, b=2)
+    mapping = view(m).mapping
+    assert isinstance(mapping, HashTrieMap)
+    assert mapping == m
+
+
+@pytest.mark.parametrize(
+    "view",
+    [pytest.param(methodcaller(p), id=p) for p in ["keys", "values", "items"]],
+)
+def test_pickle_views(view):
+    m = HashTrieMap(a=1, b=2)
+    unpickled = pickle.loads(pickle.dumps(view(m)))
+    assert type(unpickled) is type(view(m))
+    assert sorted(unpickled) == sorted(view(m))
+
+
+@pytest.mark.parametrize(
+    "view",
+    [pytest.param(methodcaller(p), id=p) for p in ["keys", "values", "items"]],
+)
+def test_pickle_view_iterators(view):
+    m = HashTrieMap(a=1, b=2, c=3)
+    it = iter(view(m))
+    first = next(it)
+    unpickled = pickle.loads(pickle.dumps(it))
+    assert sorted([first, *unpickled]) == sorted(view(m))
+    assert sorted([first, *it]) == sorted(view(m))
+
+
+def test_keys_union_is_a_set():
+    keys = HashTrieMap({1: 2}).keys()
+
+    assert keys.union([3]) == HashTrieSet([1, 3])
+    assert isinstance(keys.union([3]), HashTrieSet)
+    assert keys | {3} == HashTrieSet([1, 3])
+    assert isinstance(keys | {3}, HashTrieSet)
+    assert keys | HashTrieSet([1]) == HashTrieSet([1])
+
+
+def test_mutate_finishes_evolver_on_exit():
+    with HashTrieMap(a=1).mutate() as evolver:
+        evolver["b"] = 2
+    assert evolver.result == HashTrieMap(a=1, b=2)
+    with pytest.raises(ValueError):
+        evolver["c"] = 3
+    with pytest.raises(ValueError):
+        del evolver["a"]
+    assert evolver.result == HashTrieMap(a=1, b=2)
+
+
+def test_mutate_stays_open_after_an_exception():
+    with pytest.raises(ZeroDivisionError):
+        with HashTrieMap().mutate() as evolver:
+            1 / 0
+    evolver["a"] = 1
+    assert evolver.result == HashTrieMap(a=1)
+
+
+def test_mutate_with_callable_finishes_evolver():
+    evolvers = []
+    HashTrieMap().mutate(evolvers.append)
+    with pytest.raises(ValueError):
+        evolvers[0]["a"] = 1
@@ -29,10 +29,11 @@ Pre-modification, these were MIT licensed, and are copyright:
 
 from collections import abc
 import pickle
+import sys
 
 import pytest
 
-from rpds import HashTrieSet
+from rpds import HashTrieMap, HashTrieSet
 
 
 def test_key_is_tuple():
@@ -218,3 +219,247 @@ def test_lt_le_gt_ge():
     assert HashTrieSet({}) <= {1}
     assert HashTrieSet({1}) > set()
     assert HashTrieSet({1}) >= set()
+
+
+def test_evolver():
+    original = HashTrieSet([1, 2])
+    evolver = original.evolver()
+    evolver.add(3)
+    evolver.discard(1)
+    evolver.discard(17)
+    evolver.remove(2)
+    assert 3 in evolver
+    assert len(evolver) == 1
+    assert evolver.persistent() == HashTrieSet([3])
+    assert original == HashTrieSet([1, 2])
+    with pytest.raises(KeyError):
+        evolver.remove(17)
+
+
+def test_mutate():
+    assert HashTrieSet([1]).mutate(lambda e: e.add(2)) == HashTrieSet([1, 2])
+    with HashTrieSet([1]).mutate() as evolver:
+        evolver.add(2)
+    assert evolver.persistent() == HashTrieSet([1, 2])
+
+
+def test_convert():
+    existing = HashTrieSet([1])
+    assert HashTrieSet.convert(existing) is existing
+    assert HashTrieSet.convert([1, 1, 2]) == HashTrieSet([1, 2])
+
+
+def test_convert_deep():
+    converted = HashTrieSet.convert([frozenset([1]), 2], deep=True)
+    assert converted == HashTrieSet([HashTrieSet([1]), 2])
+
+
+def test_sizeof_grows_with_size():
+    empty = sys.getsizeof(HashTrieSet())
+    assert sys.getsizeof(HashTrieSet([1])) > empty
+    assert sys.getsizeof(HashTrieSet(range(100))) > sys.getsizeof(HashTrieSet([1]))
+
+
+def test_named_set_operations_accept_iterables():
+    s = HashTrieSet([1, 2, 3])
+
+    assert s.union([3, 4]) == HashTrieSet([1, 2, 3, 4])
+    assert s.intersection(iter([2, 3, 4])) == HashTrieSet([2, 3])
+    assert s.difference({1, 5}) == HashTrieSet([2, 3])
+    assert s.symmetric_difference((3, 4)) == HashTrieSet([1, 2, 4])
+
+
+def test_named_set_operations_accept_multiple_arguments():
+    s = HashTrieSet([1, 2, 3, 4])
+
+    assert s.union([5], HashTrieSet([6])) == HashTrieSet([1, 2, 3, 4, 5, 6])
+    assert s.intersection([1, 2, 3], {2, 3, 4}) == HashTrieSet([2, 3])
+    assert s.difference([1], [4]) == HashTrieSet([2, 3])
+    assert s.symmetric_difference([1, 5], [5, 6]) == HashTrieSet([2, 3, 4, 6])
+
+
+def test_named_set_operations_with_no_arguments():
+    s = HashTrieSet([1, 2, 3])
+
+    assert s.union() == s
+    assert s.intersection() == s
+    assert s.difference() == s
+    assert s.symmetric_difference() == s
+
+
+def test_set_operators_with_builtin_sets():
+    s = HashTrieSet([1, 2, 3])
+
+    assert s | {4} == HashTrieSet([1, 2, 3, 4])
+    assert s & frozenset([2, 3, 4]) == HashTrieSet([2, 3])
+    assert s - {1} == HashTrieSet([2, 3])
+    assert s ^ frozenset([3, 4]) == HashTrieSet([1, 2, 4])
+
+    assert isinstance(s | {4}, HashTrieSet)
+
+
+def test_set_operators_with_other_sets():
+    s = HashTrieSet(["a", "b"])
+    keys = {"b": 1, "c": 2}.keys()
+
+    assert s | keys == HashTrieSet(["a", "b", "c"])
+    assert s & keys == HashTrieSet(["b"])
+
+
+def test_set_operators_reject_non_sets():
+    s = HashTrieSet([1, 2, 3])
+
+    with pytest.raises(TypeError):
+        s | [4]
+    with pytest.raises(TypeError):
+        s & (1, 2)
+    with pytest.raises(TypeError):
+        s - "abc"
+    with pytest.raises(TypeError):
+        s ^ 12
+
+
+def test_reflected_set_operators_with_builtin_sets():
+    s = HashTrieSet([1, 2, 3])
+
+    assert {4} | s == HashTrieSet([1, 2, 3, 4])
+    assert frozenset([2, 3, 4]) & s == HashTrieSet([2, 3])
+    assert {1, 2, 5} - s == HashTrieSet([5])
+    assert frozenset([3, 4]) ^ s == HashTrieSet([1, 2, 4])
+
+    assert isinstance({1, 2} - s, HashTrieSet)
+
+
+def test_reflected_set_operators_reject_non_sets():
+    s = HashTrieSet([1, 2, 3])
+
+    with pytest.raises(TypeError):
+        [4] | s
+    with pytest.raises(TypeError):
+        "abc" - s
+
+
+def test_map():
+    s = HashTrieSet([1, 2, 3])
+    assert s.map(lambda each: each * 10) == HashTrieSet([10, 20, 30])
+
+
+def test_map_deduplicates():
+    s = HashTrieSet([-2, -1, 0, 1, 2])
+    assert s.map(abs) == HashTrieSet([0, 1, 2])
+
+
+def test_map_unhashable_result():
+    with pytest.raises(TypeError):
+        HashTrieSet([1]).map(lambda each: [each])
+
+
+def test_filter():
+    s = HashTrieSet(range(10))
+    assert s.filter(lambda each: each % 3 == 0) == HashTrieSet([0, 3, 6, 9])
+
+
+def test_filter_nothing_matches():
+    assert HashTrieSet([1, 2, 3]).filter(lambda each: False) == HashTrieSet()
+
+
+def test_partition():
+    evens, odds = HashTrieSet(range(6)).partition(lambda each: each % 2 == 0)
+    assert evens == HashTrieSet([0, 2, 4])
+    assert odds == HashTrieSet([1, 3, 5])
+
+
+def test_partition_empty():
+    assert HashTrieSet().partition(bool) == (HashTrieSet(), HashTrieSet())
+
+
+def test_remove_many():
+    s = HashTrieSet(range(5))
+    assert s.remove_many(iter([0, 2, 4])) == HashTrieSet([1, 3])
+    assert s == HashTrieSet(range(5))
+
+
+def test_remove_many_missing_element():
+    with pytest.raises(KeyError, match="7"):
+        HashTrieSet([1, 2]).remove_many([1, 7])
+
+
+def test_to_frozenset():
+    s = HashTrieSet([1, "two", (3,)])
+    converted = s.to_frozenset()
+    assert type(converted) is frozenset
+    assert converted == frozenset([1, "two", (3,)])
+
+
+def test_to_frozenset_empty():
+    assert HashTrieSet().to_frozenset() == frozenset()
+
+
+@pytest.mark.parametrize("kind", [set, frozenset])
+def test_from_builtin_set(kind):
+    s = HashTrieSet(kind([1, 2, 3]))
+    assert s == HashTrieSet([1, 2, 3])
+    assert set(s) == {1, 2, 3}
+
+
+def test_set_operators_with_map_keys():
+    s = HashTrieSet(["a", "b"])
+    keys = HashTrieMap(b=1, c=2).keys()
+
+    assert s & keys == HashTrieSet(["b"])
+    assert s | keys == HashTrieSet(["a", "b", "c"])
+    assert s - keys == HashTrieSet(["a"])
+    assert s ^ keys == HashTrieSet(["a", "c"])
+
+    assert keys & s == HashTrieSet(["b"])
+    assert keys | s == {"a", "b", "c"}
+    assert s.__rsub__(keys) == HashTrieSet(["c"])
+
+
+def test_set_operators_with_larger_and_smaller_map_keys():
+    small, large = HashTrieSet([1, 2]), HashTrieSet(range(10))
+    small_keys = HashTrieMap.fromkeys([2, 3]).keys()
+    large_keys = HashTrieMap.fromkeys(range(1, 20)).keys()
+
+    assert small & large_keys == HashTrieSet([1, 2])
+    assert large & small_keys == HashTrieSet([2, 3])
+    assert small - large_keys == HashTrieSet()
+    assert large - small_keys == HashTrieSet([0, 1, *range(4, 10)])
+    assert small ^ large_keys == HashTrieSet(range(3, 20))
+    assert large | small_keys == large
+
+
+def test_set_operators_with_map_keys_do_not_rehash():
+    class HashCounter:
+        hashes = 0
+
+        def __init__(self, value):
+            self.value = value
+
+        def __hash__(self):
+            HashCounter.hashes += 1
+            return self.value
+
+    first, second = HashCounter(1), HashCounter(2)
+    s = HashTrieSet([first])
+    keys = HashTrieMap({first: 1, second: 2}).keys()
+
+    HashCounter.hashes = 0
+    results = s & keys, keys & s, s | keys, keys | s
+    assert HashCounter.hashes == 0
+
+    assert results[:3] == (s, s, HashTrieSet([first, second]))
+    assert set(results[3]) == {first, second}
+
+
+def test_mutate_finishes_evolver_on_exit():
+    with HashTrieSet([1]).mutate() as evolver:
+        evolver.add(2)
+    assert evolver.result == HashTrieSet([1, 2])
+    with pytest.raises(ValueError):
+        evolver.add(3)
+    with pytest.raises(ValueError):
+        evolver.discard(1)
+    with pytest.raises(ValueError):
+        evolver.remove(1)
+    assert evolver.result == HashTrieSet([1, 2])
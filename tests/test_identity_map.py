@@ -0,0 +1,91 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import IdentityMap
+
+
+def test_instance_of_map():
+    assert isinstance(IdentityMap(), abc.Mapping)
+
+
+def test_unhashable_keys():
+    key, other = [1, 2], {"a": 1}
+    m = IdentityMap([(key, "list"), (other, "dict")])
+    assert m[key] == "list"
+    assert m[other] == "dict"
+    assert len(m) == 2
+
+
+def test_equal_but_distinct_keys_are_different():
+    key = [1, 2]
+    m = IdentityMap().insert(key, 1)
+    assert key in m
+    assert [1, 2] not in m
+    assert m.get([1, 2]) is None
+    with pytest.raises(KeyError):
+        m[[1, 2]]
+
+
+def test_insert_overwrites_same_object():
+    key = []
+    m = IdentityMap().insert(key, 1).insert(key, 2)
+    assert len(m) == 1
+    assert m[key] == 2
+
+
+def test_remove_and_discard():
+    key = []
+    m = IdentityMap().insert(key, 1)
+    assert m.remove(key) == IdentityMap()
+    assert m.discard([]) == m
+    with pytest.raises(KeyError):
+        m.remove([])
+
+
+def test_iter_keys_values_items():
+    one, two = [], []
+    m = IdentityMap([(one, 1), (two, 2)])
+    assert sorted(id(each) for each in m) == sorted([id(one), id(two)])
+    assert sorted(m.values()) == [1, 2]
+    assert (one, 1) in m.items()
+    assert two in m.keys()
+
+
+def test_update():
+    one, two = [], []
+    m = IdentityMap([(one, 1)]).update([(two, 2)], IdentityMap([(one, 3)]))
+    assert m[one] == 3
+    assert m[two] == 2
+
+
+def test_eq_and_hash():
+    key = []
+    assert IdentityMap([(key, 1)]) == IdentityMap([(key, 1)])
+    assert IdentityMap([(key, 1)]) != IdentityMap([([], 1)])
+    assert hash(IdentityMap([(key, 1)])) == hash(IdentityMap([(key, 1)]))
+
+
+def test_repr():
+    assert repr(IdentityMap([([1], 2)])) == "IdentityMap({[1]: 2})"
+
+
+def test_pickle_preserves_shared_keys():
+    key = []
+    m = IdentityMap([(key, 1)])
+    unpickled = pickle.loads(pickle.dumps((key, m)))
+    assert unpickled[1][unpickled[0]] == 1
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = IdentityMap([(1, Counted())])
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
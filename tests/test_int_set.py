@@ -0,0 +1,148 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import HashTrieSet, IntSet
+
+
+def test_instance_of_set():
+    assert isinstance(IntSet(), abc.Set)
+
+
+def test_contains():
+    s = IntSet([1, -3, 200])
+    assert 1 in s
+    assert -3 in s
+    assert 200 in s
+    assert 2 not in s
+    assert "foo" not in s
+
+
+def test_non_integers():
+    with pytest.raises(TypeError):
+        IntSet(["foo"])
+    with pytest.raises(TypeError):
+        IntSet().insert(1.5)
+
+
+def test_iterates_in_order():
+    assert list(IntSet([300, -70, 5, 64, 63, -1])) == [-70, -1, 5, 63, 64, 300]
+
+
+def test_len():
+    assert len(IntSet()) == 0
+    assert len(IntSet([1, 1, 2, 130])) == 3
+
+
+@pytest.mark.parametrize(
+    "args",
+    [
+        (0, 10_000),
+        (-100, 100),
+        (3, 64),
+        (64, 128),
+        (5, 5),
+        (10, 0),
+        (0, 100, 7),
+        (100, -50, -3),
+        (-70, 3),
+        (2**63 - 10, 2**63 - 1),
+        (2**63 - 130, 2**63 - 1),
+        (-(2**63), -(2**63) + 70),
+    ],
+)
+def test_range(args):
+    assert list(IntSet.range(*args)) == sorted(range(*args))
+    assert len(IntSet.range(*args)) == len(range(*args))
+
+
+def test_range_zero_step():
+    with pytest.raises(ValueError):
+        IntSet.range(0, 10, 0)
+
+
+def test_insert_discard_remove():
+    s = IntSet([1, 2])
+    assert s.insert(3) == IntSet([1, 2, 3])
+    assert s.insert(1) == s
+    assert s.discard(1) == IntSet([2])
+    assert s.discard(7) == s
+    assert s.remove(2) == IntSet([1])
+    with pytest.raises(KeyError):
+        s.remove(7)
+
+
+def test_set_algebra():
+    one, two = IntSet.range(0, 200), IntSet.range(100, 300)
+    assert one | two == IntSet.range(0, 300)
+    assert one & two == IntSet.range(100, 200)
+    assert one - two == IntSet.range(0, 100)
+    assert two - one == IntSet.range(200, 300)
+    assert one ^ two == IntSet.range(0, 100) | IntSet.range(200, 300)
+    assert len(one ^ two) == 200
+
+
+def test_set_algebra_with_other_sets():
+    s = IntSet([1, 2])
+    assert s | {3} == IntSet([1, 2, 3])
+    assert s & frozenset([2, 3]) == IntSet([2])
+    assert s - HashTrieSet([1]) == IntSet([2])
+    assert s ^ {2, 3} == IntSet([1, 3])
+    assert {3} | s == IntSet([1, 2, 3])
+    assert {2, 3} & s == IntSet([2])
+    assert {2, 3} - s == IntSet([3])
+    assert {2, 3} ^ s == IntSet([1, 3])
+    assert isinstance({3} - s, IntSet)
+
+
+def test_set_algebra_methods_accept_iterables():
+    s = IntSet([1, 2])
+    assert s.union([3]) == IntSet([1, 2, 3])
+    assert s.difference(range(2)) == IntSet([2])
+
+
+def test_set_algebra_rejects_non_sets():
+    with pytest.raises(TypeError):
+        IntSet([1]) | [2]
+    with pytest.raises(TypeError):
+        [2] - IntSet([1])
+
+
+def test_update():
+    assert IntSet([1]).update([2], range(3, 5)) == IntSet([1, 2, 3, 4])
+
+
+def test_comparisons():
+    assert IntSet([1, 2]) == {1, 2}
+    assert IntSet([1, 2]) == HashTrieSet([1, 2])
+    assert IntSet([1, 2]) != {1, 3}
+    assert IntSet([1]) < IntSet([1, 2])
+    assert IntSet([1]) <= {1}
+    assert IntSet([1, 2]) > IntSet([2])
+    assert IntSet([1, 2]) >= {1, 2}
+    assert not IntSet([1, 2]) < {1, 2}
+    assert not IntSet([1, 3]) <= {1, 2, 4}
+
+
+def test_comparisons_with_non_sets():
+    assert IntSet([1]).__lt__([1, 2]) is NotImplemented
+    assert IntSet([1]).__ge__(1) is NotImplemented
+    with pytest.raises(TypeError):
+        IntSet([1]) < [1, 2]
+
+
+def test_hash_matches_hash_trie_set():
+    values = [0, 1, -1, -2, 2**62, -(2**63)]
+    assert hash(IntSet(values)) == hash(HashTrieSet(values))
+    assert hash(IntSet([1, 2])) == hash(IntSet([2, 1]))
+
+
+def test_repr():
+    assert repr(IntSet()) == "IntSet({})"
+    assert repr(IntSet([3, 1])) == "IntSet({1, 3})"
+
+
+def test_pickle():
+    s = IntSet.range(-5, 500, 3)
+    assert pickle.loads(pickle.dumps(s)) == s
@@ -0,0 +1,159 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import IntervalMap
+
+
+def test_instance_of_map():
+    assert isinstance(IntervalMap(), abc.Mapping)
+
+
+def test_exact_interval_lookup():
+    m = IntervalMap({(0, 5): "a", (3, 8): "b"})
+    assert m[0, 5] == "a"
+    assert m.get((3, 8)) == "b"
+    assert m.get((0, 1)) is None
+    assert (0, 5) in m
+    assert (0, 6) not in m
+    with pytest.raises(KeyError):
+        m[1, 2]
+
+
+def test_invalid_interval():
+    with pytest.raises(ValueError):
+        IntervalMap().insert((5, 1), "x")
+    with pytest.raises(TypeError):
+        IntervalMap({5: "x"})
+
+
+def test_at():
+    m = IntervalMap({(0, 5): "a", (3, 8): "b", (10, 12): "c"})
+    assert m.at(4) == [((0, 5), "a"), ((3, 8), "b")]
+    assert m.at(0) == [((0, 5), "a")]
+    assert m.at(5) == [((3, 8), "b")]
+    assert m.at(9) == []
+    assert m.at(11) == [((10, 12), "c")]
+
+
+def test_overlapping():
+    m = IntervalMap({(0, 5): "a", (3, 8): "b", (10, 12): "c"})
+    assert m.overlapping(4, 11) == [((0, 5), "a"), ((3, 8), "b"), ((10, 12), "c")]
+    assert m.overlapping(5, 10) == [((3, 8), "b")]
+    assert m.overlapping(8, 10) == []
+    assert m.overlapping(-5, 0) == []
+
+
+def test_queries_at_shared_low_bounds():
+    m = IntervalMap({(3, 4): "a", (3, 9): "b", (3, 3): "c", (4, 6): "d"})
+    assert m.at(3) == [((3, 4), "a"), ((3, 9), "b")]
+    assert m.at(4) == [((3, 9), "b"), ((4, 6), "d")]
+    assert m.overlapping(0, 3) == []
+    assert m.overlapping(0, 4) == [((3, 4), "a"), ((3, 9), "b")]
+    assert m[3, 9] == "b"
+    assert (3, 5) not in m
+    assert m.remove((3, 4)).at(3) == [((3, 9), "b")]
+
+
+def test_queries_with_incomparable_points():
+    m = IntervalMap({(0, 5): "a", (3, 8): "b"})
+    with pytest.raises(TypeError):
+        m.at("x")
+    with pytest.raises(TypeError):
+        m.overlapping(0, "x")
+
+
+def test_empty_intervals_contain_nothing():
+    m = IntervalMap({(3, 3): "empty"})
+    assert m.at(3) == []
+    assert m.overlapping(0, 10) == []
+    assert m[3, 3] == "empty"
+
+
+def test_non_numeric_bounds():
+    m = IntervalMap({("a", "m"): 1, ("k", "z"): 2})
+    assert m.at("l") == [(("a", "m"), 1), (("k", "z"), 2)]
+
+
+def test_iterates_in_order():
+    m = IntervalMap({(3, 8): "b", (0, 5): "a", (0, 2): "z"})
+    assert list(m) == [(0, 2), (0, 5), (3, 8)]
+    assert list(m.values()) == ["z", "a", "b"]
+    assert len(m) == 3
+
+
+def test_insert_remove_discard():
+    m = IntervalMap().insert((0, 1), "a")
+    assert m == IntervalMap({(0, 1): "a"})
+    assert m.remove((0, 1)) == IntervalMap()
+    assert m.discard((5, 6)) == m
+    with pytest.raises(KeyError):
+        m.remove((5, 6))
+
+
+def test_persistence():
+    m = IntervalMap({(0, 5): "a"})
+    m.insert((1, 2), "b")
+    assert m.at(1) == [((0, 5), "a")]
+
+
+def test_update():
+    m = IntervalMap({(0, 5): "a"}).update({(0, 5): "b"}, [((6, 7), "c")])
+    assert m == IntervalMap({(0, 5): "b", (6, 7): "c"})
+
+
+def test_incomparable_bounds():
+    with pytest.raises(TypeError):
+        IntervalMap({(0, 5): "a"}).insert(("a", "b"), "c")
+
+
+def test_hash():
+    assert hash(IntervalMap({(0, 5): "a"})) == hash(IntervalMap({(0, 5): "a"}))
+
+
+def test_repr():
+    assert repr(IntervalMap({(0, 5): "a"})) == "IntervalMap({(0, 5): 'a'})"
+
+
+def test_pickle():
+    m = IntervalMap({(0, 5): "a", (3, 8): "b"})
+    assert pickle.loads(pickle.dumps(m)) == m
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = IntervalMap({(0, 5): Counted()})
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
+
+
+def test_equality_does_not_compare_identical_values():
+    class NeverEqual:
+        def __eq__(self, other):
+            raise AssertionError("compared!")
+
+        __hash__ = object.__hash__
+
+    value = NeverEqual()
+    m = IntervalMap({(0, 5): value, (3, 8): "b"})
+    assert m == m
+    assert m == IntervalMap({(0, 5): value, (3, 8): "b"})
+    assert not m != IntervalMap({(0, 5): value, (3, 8): "b"})
+
+
+def test_equality_propagates_errors():
+    class Boom:
+        def __eq__(self, other):
+            raise ZeroDivisionError()
+
+        __hash__ = object.__hash__
+
+    with pytest.raises(ZeroDivisionError):
+        IntervalMap({(0, 5): Boom()}) == IntervalMap({(0, 5): 1})
@@ -28,10 +28,11 @@ Pre-modification, these were MIT licensed, and are copyright:
 """
 
 import pickle
+import sys
 
 import pytest
 
-from rpds import List
+from rpds import HashTrieMap, List
 
 
 def test_literalish_works():
@@ -82,9 +83,9 @@ def test_rest_return_self_on_empty_list():
 
 
 def test_reverse():
-    assert reversed(List([1, 2, 3])) == List([3, 2, 1])
+    assert List(reversed(List([1, 2, 3]))) == List([3, 2, 1])
 
-    assert reversed(List()) == List()
+    assert List(reversed(List())) == List()
 
 
 def test_inequality():
@@ -158,3 +159,287 @@ def test_more_eq():
 
 def test_pickle():
     assert pickle.loads(pickle.dumps(List([1, 2, 3, 4]))) == List([1, 2, 3, 4])
+
+
+def test_resolve_pointer():
+    assert List([0, {"a": List([1, 2])}]).resolve_pointer("/1/a/1") == 2
+
+
+def test_builder():
+    builder = List.builder()
+    for each in range(5):
+        builder.append(each)
+    assert len(builder) == 5
+    assert builder.build() == List(range(5))
+
+
+def test_builder_build_is_a_snapshot():
+    builder = List.builder()
+    builder.append(1)
+    first = builder.build()
+    builder.append(2)
+    assert first == List([1])
+    assert builder.build() == List([1, 2])
+
+
+def test_mutate():
+    original = List([1, 2])
+    assert original.mutate(lambda b: b.append(3)) == List([1, 2, 3])
+    with original.mutate() as builder:
+        builder.append(3)
+    assert builder.build() == List([1, 2, 3])
+    assert original == List([1, 2])
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    items = List([Counted(), Counted()])
+    assert hash(items) == hash(items)
+    assert len(calls) == 2
+
+
+def test_convert():
+    existing = List([1])
+    assert List.convert(existing) is existing
+    assert List.convert([1, 2]) == List([1, 2])
+
+
+def test_convert_deep():
+    converted = List.convert([{"a": [1]}, [2]], deep=True)
+    assert converted == List([HashTrieMap(a=List([1])), List([2])])
+    assert isinstance(converted.first, HashTrieMap)
+    assert List.convert(iter([[1]]), deep=True) == List([List([1])])
+
+
+def test_sizeof_grows_with_size():
+    empty = sys.getsizeof(List())
+    assert sys.getsizeof(List([1])) > empty
+    assert sys.getsizeof(List(range(100))) > sys.getsizeof(List([1]))
+
+
+def test_equality_does_not_compare_identical_elements():
+    nan = float("nan")
+    assert List([nan, 1]) == List([nan, 1])
+    assert not List([nan, 1]) != List([nan, 1])
+    assert List([float("nan")]) != List([float("nan")])
+
+
+def test_equality_propagates_equality_errors():
+    class Incomparable:
+        def __eq__(self, other):
+            raise ZeroDivisionError()
+
+        def __ne__(self, other):
+            raise ZeroDivisionError()
+
+    with pytest.raises(ZeroDivisionError):
+        List([Incomparable()]) == List([Incomparable()])
+    with pytest.raises(ZeroDivisionError):
+        List([Incomparable()]) != List([Incomparable()])
+
+
+def test_index():
+    ls = List(["a", "b", "c", "b"])
+    assert ls.index("b") == 1
+    assert ls.index("b", 2) == 3
+    assert ls.index("a", -4, -2) == 0
+
+
+def test_index_missing():
+    with pytest.raises(ValueError):
+        List([1, 2, 3]).index(4)
+    with pytest.raises(ValueError):
+        List([1, 2, 3]).index(1, 1)
+    with pytest.raises(ValueError):
+        List([1, 2, 3]).index(3, 0, 2)
+
+
+def test_count():
+    ls = List([1, 2, 1, 1.0, 3])
+    assert ls.count(1) == 3
+    assert ls.count(4) == 0
+
+
+def test_push_back():
+    ls = List([1, 2])
+    assert ls.push_back(3) == List([1, 2, 3])
+    assert ls == List([1, 2])
+
+
+def test_push_back_empty():
+    assert List().push_back(1) == List([1])
+
+
+def test_extend():
+    ls = List([1, 2])
+    assert ls.extend(iter([3, 4])) == List([1, 2, 3, 4])
+    assert ls.extend([]) == ls
+    assert ls == List([1, 2])
+
+
+def test_extend_front():
+    ls = List([3, 4])
+    assert ls.extend_front(iter([1, 2])) == List([1, 2, 3, 4])
+    assert List().extend_front([1]) == List([1])
+    assert ls == List([3, 4])
+
+
+def test_insert():
+    ls = List([1, 2, 3])
+    assert ls.insert(0, 0) == List([0, 1, 2, 3])
+    assert ls.insert(2, 2.5) == List([1, 2, 2.5, 3])
+    assert ls.insert(-1, 2.5) == List([1, 2, 2.5, 3])
+    assert ls.insert(10, 4) == List([1, 2, 3, 4])
+    assert ls.insert(-10, 0) == List([0, 1, 2, 3])
+    assert ls == List([1, 2, 3])
+
+
+def test_delete():
+    ls = List([1, 2, 3])
+    assert ls.delete(0) == List([2, 3])
+    assert ls.delete(1) == List([1, 3])
+    assert ls.delete(-1) == List([1, 2])
+    assert ls == List([1, 2, 3])
+
+
+def test_delete_out_of_range():
+    with pytest.raises(IndexError):
+        List([1, 2, 3]).delete(3)
+    with pytest.raises(IndexError):
+        List([1, 2, 3]).delete(-4)
+    with pytest.raises(IndexError):
+        List().delete(0)
+
+
+def test_remove():
+    ls = List([1, 2, 3, 2])
+    assert ls.remove(2) == List([1, 3, 2])
+    assert ls == List([1, 2, 3, 2])
+
+
+def test_remove_missing():
+    with pytest.raises(ValueError):
+        List([1, 2, 3]).remove(4)
+
+
+def test_sort():
+    ls = List([3, 1, 2])
+    assert ls.sort() == List([1, 2, 3])
+    assert ls.sort(reverse=True) == List([3, 2, 1])
+    assert ls == List([3, 1, 2])
+
+
+def test_sort_key_is_stable():
+    ls = List(["bb", "a", "cc", "d"])
+    assert ls.sort(key=len) == List(["a", "d", "bb", "cc"])
+    assert ls.sort(key=len, reverse=True) == List(["bb", "cc", "a", "d"])
+
+
+def test_sort_incomparable():
+    with pytest.raises(TypeError):
+        List([1, "two", 3]).sort()
+
+
+def test_take():
+    ls = List([1, 2, 3])
+    assert ls.take(2) == List([1, 2])
+    assert ls.take(0) == List()
+    assert ls.take(10) == ls
+
+
+def test_drop():
+    ls = List([1, 2, 3])
+    assert ls.drop(2) == List([3])
+    assert ls.drop(0) == ls
+    assert ls.drop(10) == List()
+
+
+def test_split_at():
+    ls = List([1, 2, 3])
+    assert ls.split_at(1) == (List([1]), List([2, 3]))
+    assert ls.split_at(0) == (List(), ls)
+    assert ls.split_at(3) == (ls, List())
+
+
+def test_negative_take_or_drop():
+    with pytest.raises(OverflowError):
+        List([1, 2, 3]).take(-1)
+    with pytest.raises(OverflowError):
+        List([1, 2, 3]).drop(-1)
+
+
+def test_zip():
+    ls = List([1, 2, 3])
+    assert ls.zip(List("abc")) == List([(1, "a"), (2, "b"), (3, "c")])
+
+
+def test_zip_stops_at_shorter():
+    assert List([1, 2, 3]).zip("ab") == List([(1, "a"), (2, "b")])
+    assert List([1]).zip(iter("abc")) == List([(1, "a")])
+    assert List().zip([1, 2]) == List()
+
+
+def test_map():
+    assert List([1, 2, 3]).map(lambda each: each * 10) == List([10, 20, 30])
+    assert List().map(str) == List()
+
+
+def test_filter():
+    ls = List(range(7))
+    assert ls.filter(lambda each: each % 3 == 0) == List([0, 3, 6])
+    assert ls.filter(lambda each: False) == List()
+
+
+def test_reduce():
+    ls = List(["a", "b", "c"])
+    assert ls.reduce(lambda acc, each: acc + each, ">") == ">abc"
+    assert List().reduce(lambda acc, each: acc + each, 0) == 0
+
+
+def test_from_generator():
+    assert List(each * 2 for each in range(4)) == List([0, 2, 4, 6])
+
+
+def test_from_non_reversible_iterable():
+    assert List(iter([1, 2, 3])) == List([1, 2, 3])
+    assert List({1}) == List([1])
+    assert List(HashTrieMap(a=1)) == List(["a"])
+
+
+def test_reversed_is_an_iterator():
+    it = reversed(List([1, 2, 3]))
+    assert iter(it) is it
+    assert next(it) == 3
+    assert list(it) == [2, 1]
+
+
+def test_unique():
+    ls = List([3, 1, 3, 2, 1])
+    assert ls.unique() == List([3, 1, 2])
+    assert List().unique() == List()
+
+
+def test_unique_keeps_first_occurrence():
+    first, second = tuple([1]), tuple([1])
+    (only,) = List([first, second]).unique()
+    assert only is first
+
+
+def test_unique_unhashable():
+    with pytest.raises(TypeError):
+        List([[1], [1]]).unique()
+
+
+def test_mutate_finishes_builder_on_exit():
+    with List([1]).mutate() as builder:
+        builder.append(2)
+    assert builder.result == List([1, 2])
+    with pytest.raises(ValueError):
+        builder.append(3)
+    assert builder.build() == List([1, 2])
@@ -0,0 +1,73 @@
+import pytest
+
+from rpds import List, ListZipper
+
+
+def test_starts_focused_on_first_element():
+    zipper = ListZipper(List([1, 2, 3]))
+    assert zipper.focus == 1
+    assert zipper.index == 0
+    assert len(zipper) == 3
+
+
+def test_accepts_iterables():
+    assert ListZipper([1, 2]).to_list() == List([1, 2])
+
+
+def test_move_left_and_right():
+    zipper = ListZipper(List([1, 2, 3])).right().right()
+    assert zipper.focus == 3
+    assert zipper.index == 2
+    assert zipper.left().focus == 2
+    with pytest.raises(IndexError):
+        zipper.right()
+    with pytest.raises(IndexError):
+        ListZipper(List([1])).left()
+
+
+def test_set():
+    zipper = ListZipper(List([1, 2, 3])).right().set(5)
+    assert zipper.focus == 5
+    assert zipper.to_list() == List([1, 5, 3])
+
+
+def test_insert():
+    zipper = ListZipper(List([1, 2, 3])).right().insert(7)
+    assert zipper.focus == 7
+    assert zipper.to_list() == List([1, 7, 2, 3])
+    assert ListZipper().insert(1).to_list() == List([1])
+
+
+def test_delete():
+    zipper = ListZipper(List([1, 2, 3])).right()
+    assert zipper.delete().focus == 3
+    assert zipper.delete().to_list() == List([1, 3])
+    at_end = zipper.right().delete()
+    assert at_end.focus == 2
+    assert at_end.to_list() == List([1, 2])
+    assert len(ListZipper(List([1])).delete()) == 0
+
+
+def test_empty():
+    zipper = ListZipper()
+    with pytest.raises(IndexError):
+        zipper.focus
+    with pytest.raises(IndexError):
+        zipper.set(1)
+    with pytest.raises(IndexError):
+        zipper.delete()
+    with pytest.raises(IndexError):
+        zipper.right()
+    assert zipper.to_list() == List()
+
+
+def test_edits_are_persistent():
+    original = List([1, 2, 3])
+    zipper = ListZipper(original).right()
+    zipper.set(9)
+    assert zipper.to_list() == original
+
+
+def test_repr():
+    zipper = ListZipper(List([1, 2])).right()
+    assert repr(zipper) == "ListZipper(List([1, 2]), index=1)"
@@ -0,0 +1,148 @@
+from collections import abc
+import gc
+import pickle
+import threading
+
+import pytest
+
+import rpds
+from rpds import local
+
+
+def test_importable():
+    import rpds.local
+
+    assert rpds.local is local
+
+
+def test_hash_trie_map():
+    m = local.HashTrieMap({"a": 1}, b=2)
+    assert isinstance(m, abc.Mapping)
+    assert m["a"] == 1
+    assert m.get("c", 3) == 3
+    assert "b" in m
+    assert len(m) == 2
+    assert sorted(m) == ["a", "b"]
+    assert sorted(m.items()) == [("a", 1), ("b", 2)]
+    assert m.insert("c", 3).remove("a") == local.HashTrieMap(b=2, c=3)
+    assert m.discard("z") == m
+    assert m.update({"a": 7})["a"] == 7
+    with pytest.raises(KeyError):
+        m.remove("z")
+
+
+def test_hash_trie_map_hash_matches_sync():
+    m = local.HashTrieMap(a=1, b=2)
+    assert hash(m) == hash(rpds.HashTrieMap(a=1, b=2))
+
+
+def test_hash_trie_set():
+    s = local.HashTrieSet([1, 2])
+    assert isinstance(s, abc.Set)
+    assert s == {1, 2}
+    assert s | local.HashTrieSet([3]) == {1, 2, 3}
+    assert s & local.HashTrieSet([2, 3]) == {2}
+    assert s - local.HashTrieSet([2]) == {1}
+    assert s ^ local.HashTrieSet([2, 3]) == {1, 3}
+    assert s.insert(3).discard(1).remove(2) == {3}
+    assert s.update([5], [6]) == {1, 2, 5, 6}
+    assert hash(s) == hash(rpds.HashTrieSet([1, 2]))
+
+
+def test_list():
+    lst = local.List([1, 2, 3])
+    assert list(lst) == [1, 2, 3]
+    assert local.List(1, 2, 3) == lst
+    assert lst.first == 1
+    assert lst.rest == local.List([2, 3])
+    assert lst.push_front(0) == local.List([0, 1, 2, 3])
+    assert lst.drop_first() == local.List([2, 3])
+    assert list(reversed(lst)) == [3, 2, 1]
+    assert local.List(each for each in [1, 2]) == local.List([1, 2])
+    assert len(lst) == 3
+    assert hash(lst) == hash(rpds.List([1, 2, 3]))
+    with pytest.raises(IndexError):
+        local.List().drop_first()
+
+
+def test_queue():
+    q = local.Queue([1, 2])
+    assert list(q.enqueue(3)) == [1, 2, 3]
+    assert q.peek == 1
+    assert q.dequeue() == local.Queue([2])
+    assert not q.is_empty
+    assert local.Queue().is_empty
+    with pytest.raises(IndexError):
+        local.Queue().dequeue()
+
+
+def test_queue_sequence_protocol():
+    q = local.Queue([1, 2, 3])
+    assert 2 in q
+    assert 4 not in q
+    assert q[0] == 1
+    assert q[-1] == 3
+    assert list(reversed(q)) == [3, 2, 1]
+    with pytest.raises(IndexError):
+        q[3]
+
+
+@pytest.mark.parametrize("name", ["HashTrieMap", "HashTrieSet", "List", "Queue"])
+def test_api_is_a_subset_of_rpds(name):
+    ours = {each for each in dir(getattr(local, name)) if not each.startswith("__")}
+    assert ours <= set(dir(getattr(rpds, name)))
+
+
+@pytest.mark.parametrize(
+    "name, method",
+    [
+        ("HashTrieMap", "convert"),
+        ("HashTrieMap", "fromkeys"),
+        ("HashTrieMap", "mutate"),
+        ("HashTrieMap", "__or__"),
+        ("HashTrieSet", "convert"),
+        ("HashTrieSet", "mutate"),
+        ("List", "convert"),
+        ("List", "mutate"),
+        ("Queue", "mutate"),
+    ],
+)
+def test_extended_api_is_only_in_rpds(name, method):
+    assert hasattr(getattr(rpds, name)(), method)
+    assert not hasattr(getattr(local, name)(), method)
+
+
+@pytest.mark.parametrize(
+    "collection",
+    [
+        local.HashTrieMap(a=1),
+        local.HashTrieSet([1]),
+        local.List([1]),
+        local.Queue([1]),
+    ],
+)
+def test_pickle(collection):
+    assert pickle.loads(pickle.dumps(collection)) == collection
+
+
+def test_reprs():
+    assert repr(local.HashTrieMap(a=1)) == "HashTrieMap({'a': 1})"
+    assert repr(local.List([1])) == "List([1])"
+
+
+def test_unusable_from_other_threads():
+    m = local.HashTrieMap(a=1)
+    errors = []
+
+    def use():
+        try:
+            len(m)
+        except BaseException as error:  # unsendable classes panic
+            errors.append(error)
+
+    # don't let garbage from other tests get collected over on the thread
+    gc.collect()
+    thread = threading.Thread(target=use)
+    thread.start()
+    thread.join()
+    assert errors
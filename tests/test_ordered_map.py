@@ -0,0 +1,99 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import HashTrieMap, OrderedMap
+
+
+def test_instance_of_map():
+    assert isinstance(OrderedMap(), abc.Mapping)
+
+
+def test_preserves_insertion_order():
+    keys = [5, "a", 3, None, 9, (1, 2)]
+    m = OrderedMap((k, i) for i, k in enumerate(keys))
+    assert list(m) == keys
+    assert list(m.keys()) == keys
+    assert list(m.values()) == list(range(len(keys)))
+    assert list(m.items()) == [(k, i) for i, k in enumerate(keys)]
+
+
+def test_insert_appends_and_overwrite_keeps_position():
+    m = OrderedMap({"a": 1, "b": 2})
+    assert list(m.insert("c", 3)) == ["a", "b", "c"]
+    assert list(m.insert("a", 7).items()) == [("a", 7), ("b", 2)]
+
+
+def test_remove_and_reinsert_moves_to_end():
+    m = OrderedMap({"a": 1, "b": 2, "c": 3})
+    assert list(m.remove("a").insert("a", 1)) == ["b", "c", "a"]
+    assert m.discard("z") == m
+    with pytest.raises(KeyError):
+        m.remove("z")
+
+
+def test_update():
+    m = OrderedMap({"a": 1}).update({"b": 2}, [("a", 3)], c=4)
+    assert list(m.items()) == [("a", 3), ("b", 2), ("c", 4)]
+
+
+def test_getitem_and_get():
+    m = OrderedMap({"a": 1})
+    assert m["a"] == 1
+    assert m.get("b", 2) == 2
+    with pytest.raises(KeyError):
+        m["b"]
+
+
+def test_equality_ignores_order():
+    assert OrderedMap({"a": 1, "b": 2}) == OrderedMap({"b": 2, "a": 1})
+    assert OrderedMap({"a": 1}) != OrderedMap({"a": 2})
+    assert hash(OrderedMap({"a": 1, "b": 2})) == hash(OrderedMap({"b": 2, "a": 1}))
+
+
+def test_equality_with_other_mappings():
+    assert OrderedMap({1: 2}) == {1: 2}
+    assert {1: 2} == OrderedMap({1: 2})
+    assert OrderedMap({1: 2}) != {1: 3}
+    assert OrderedMap({1: 2}) != {2: 2}
+    assert OrderedMap({1: 2}) == HashTrieMap({1: 2})
+    assert OrderedMap({1: 2}) != [(1, 2)]
+
+
+def test_repr():
+    assert repr(OrderedMap({"b": 1, "a": 2})) == "OrderedMap({'b': 1, 'a': 2})"
+
+
+def test_pickle_preserves_order():
+    m = OrderedMap({"b": 1, "a": 2, "c": 3})
+    assert list(pickle.loads(pickle.dumps(m)).items()) == list(m.items())
+
+
+def test_mutate():
+    original = OrderedMap([("b", 1), ("a", 2)])
+    with original.mutate() as evolver:
+        evolver["c"] = 3
+        evolver["b"] = 4
+        del evolver["a"]
+        assert evolver["b"] == 4
+        assert "a" not in evolver
+        assert len(evolver) == 2
+    assert list(evolver.result.items()) == [("b", 4), ("c", 3)]
+    assert original == OrderedMap([("b", 1), ("a", 2)])
+    with pytest.raises(ValueError):
+        evolver["d"] = 5
+    assert original.mutate(lambda e: e.__delitem__("b")) == OrderedMap(a=2)
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = OrderedMap(a=Counted())
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
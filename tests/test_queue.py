@@ -27,6 +27,10 @@ Pre-modification, these were MIT licensed, and are copyright:
     OTHER DEALINGS IN THE SOFTWARE.
 """
 
+import pickle
+import random
+import sys
+
 import pytest
 
 from rpds import Queue
@@ -136,3 +140,139 @@ def test_unhashable_contents():
     q = Queue([1, {1}])
     with pytest.raises(TypeError):
         hash(q)
+
+
+def test_builder():
+    builder = Queue.builder()
+    for each in range(5):
+        builder.enqueue(each)
+    assert len(builder) == 5
+    assert builder.build() == Queue(range(5))
+
+
+def test_builder_build_is_a_snapshot():
+    builder = Queue.builder()
+    builder.enqueue(1)
+    first = builder.build()
+    builder.enqueue(2)
+    assert first == Queue([1])
+    assert builder.build() == Queue([1, 2])
+
+
+def test_mutate():
+    original = Queue([1, 2])
+    assert original.mutate(lambda b: b.enqueue(3)) == Queue([1, 2, 3])
+    with original.mutate() as builder:
+        builder.enqueue(3)
+    assert builder.build() == Queue([1, 2, 3])
+    assert original == Queue([1, 2])
+
+
+def test_sizeof_grows_with_size():
+    empty = sys.getsizeof(Queue())
+    assert sys.getsizeof(Queue([1])) > empty
+    assert sys.getsizeof(Queue(range(100))) > sys.getsizeof(Queue([1]))
+
+
+def test_index():
+    q = Queue(["a", "b", "c", "b"])
+    assert q.index("b") == 1
+    assert q.index("b", 2) == 3
+    assert q.index("c", -2) == 2
+
+
+def test_index_missing():
+    with pytest.raises(ValueError):
+        Queue([1, 2, 3]).index(4)
+    with pytest.raises(ValueError):
+        Queue([1, 2, 3]).index(3, 0, -1)
+
+
+def test_count():
+    q = Queue([1, 2, 1, 3])
+    assert q.count(1) == 2
+    assert q.count(4) == 0
+
+
+def test_contains():
+    q = Queue([1, 2, 3])
+    assert 2 in q
+    assert 2.0 in q
+    assert 4 not in q
+    assert 1 not in Queue()
+
+
+def test_contains_propagates_equality_errors():
+    class Incomparable:
+        def __eq__(self, other):
+            raise ZeroDivisionError()
+
+    with pytest.raises(ZeroDivisionError):
+        Incomparable() in Queue([1])
+
+
+def test_getitem():
+    q = Queue(["a", "b", "c"])
+    assert q[0] == "a"
+    assert q[2] == "c"
+    assert q[-1] == "c"
+    assert q[-3] == "a"
+
+
+def test_getitem_out_of_range():
+    with pytest.raises(IndexError):
+        Queue([1, 2])[2]
+    with pytest.raises(IndexError):
+        Queue([1, 2])[-3]
+    with pytest.raises(IndexError):
+        Queue()[0]
+
+
+def test_random_choice():
+    assert random.choice(Queue([1, 2, 3])) in {1, 2, 3}
+
+
+def test_peek_or():
+    assert Queue([1, 2]).peek_or(None) == 1
+    assert Queue().peek_or(None) is None
+    assert Queue().peek_or("fallback") == "fallback"
+
+
+def test_pickle():
+    assert pickle.loads(pickle.dumps(Queue([1, 2, 3, 4]))) == Queue([1, 2, 3, 4])
+
+
+def test_pickle_empty_and_single():
+    assert pickle.loads(pickle.dumps(Queue())) == Queue()
+    assert pickle.loads(pickle.dumps(Queue([[1]]))) == Queue([[1]])
+
+
+def test_reversed():
+    q = Queue([1, 2, 3])
+    it = reversed(q)
+    assert next(it) == 3
+    assert list(it) == [2, 1]
+    assert list(reversed(Queue())) == []
+    assert q == Queue([1, 2, 3])
+
+
+def test_pop():
+    q = Queue([1, 2, 3])
+    value, rest = q.pop()
+    assert value == 1
+    assert rest == Queue([2, 3])
+    assert q == Queue([1, 2, 3])
+
+
+def test_pop_empty():
+    with pytest.raises(IndexError):
+        Queue().pop()
+
+
+def test_mutate_finishes_builder_on_exit():
+    with Queue([1]).mutate() as builder:
+        builder.enqueue(2)
+    assert builder.result == Queue([1, 2])
+    with pytest.raises(ValueError):
+        builder.enqueue(3)
+    assert builder.build() == Queue([1, 2])
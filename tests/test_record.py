@@ -0,0 +1,252 @@
+import copy
+import json
+import pickle
+import sys
+
+import pytest
+
+from rpds import Field, HashTrieMap, HashTrieSet, InvariantError, List, Record
+
+
+class Point(Record):
+    _fields = ("x", "y")
+
+
+class OtherPoint(Record):
+    _fields = ("x", "y")
+
+
+def test_attribute_access():
+    p = Point(x=1, y=2)
+    assert p.x == 1
+    assert p.y == 2
+    with pytest.raises(AttributeError):
+        p.z
+
+
+def test_set_returns_new_record():
+    p = Point(x=1, y=2)
+    q = p.set("x", 3)
+    assert type(q) is Point
+    assert q == Point(x=3, y=2)
+    assert p == Point(x=1, y=2)
+    with pytest.raises(AttributeError):
+        p.set("z", 3)
+
+
+def test_immutable():
+    p = Point(x=1, y=2)
+    with pytest.raises(AttributeError):
+        p.x = 3
+    with pytest.raises(AttributeError):
+        del p.x
+
+
+def test_constructor_validates_fields():
+    with pytest.raises(TypeError):
+        Point(x=1)
+    with pytest.raises(TypeError):
+        Point(x=1, y=2, z=3)
+    with pytest.raises(TypeError):
+        Point(1, 2)
+
+
+def test_equality_and_hashing():
+    assert Point(x=1, y=2) == Point(x=1, y=2)
+    assert Point(x=1, y=2) != Point(x=1, y=3)
+    assert Point(x=1, y=2) != OtherPoint(x=1, y=2)
+    assert hash(Point(x=1, y=2)) == hash(Point(y=2, x=1))
+    assert len({Point(x=1, y=2), Point(x=1, y=2)}) == 1
+
+
+def test_repr():
+    assert repr(Point(y=2, x=1)) == "Point(x=1, y=2)"
+
+
+def test_pickle():
+    p = Point(x=1, y=[2])
+    assert pickle.loads(pickle.dumps(p)) == p
+
+
+class Account(Record):
+    _fields = {
+        "owner": Field(type=str),
+        "balance": Field(type=int, invariant=lambda b: b >= 0, default=0),
+        "note": Field(default=None),
+        "extra": None,
+    }
+
+
+def test_field_defaults():
+    account = Account(owner="alice", extra=1)
+    assert account.balance == 0
+    assert account.note is None
+    assert account == Account(owner="alice", balance=0, note=None, extra=1)
+    with pytest.raises(TypeError):
+        Account(balance=3, extra=1)
+
+
+def test_field_type_validation():
+    with pytest.raises(InvariantError) as e:
+        Account(owner=12, extra=1)
+    assert e.value.field == "owner"
+    assert e.value.value == 12
+
+
+def test_field_invariant():
+    with pytest.raises(InvariantError) as e:
+        Account(owner="alice", balance=-1, extra=1)
+    assert e.value.field == "balance"
+    assert e.value.value == -1
+
+
+def test_field_validation_on_set():
+    account = Account(owner="alice", extra=1)
+    assert account.set("balance", 10).balance == 10
+    assert account.set("extra", "anything").extra == "anything"
+    with pytest.raises(InvariantError):
+        account.set("balance", -10)
+    with pytest.raises(InvariantError):
+        account.set("owner", None)
+
+
+def test_invariant_error_is_a_value_error():
+    assert issubclass(InvariantError, ValueError)
+
+
+def test_field_rejects_unknown_options():
+    with pytest.raises(TypeError):
+        Field(defualt=1)
+
+
+def test_pickle_with_field_specs():
+    account = Account(owner="alice", balance=5, extra=1)
+    assert pickle.loads(pickle.dumps(account)) == account
+
+
+def test_evolve():
+    p = Point(x=1, y=2)
+    q = p.evolve(y=5)
+    assert type(q) is Point
+    assert q == Point(x=1, y=5)
+    assert p == Point(x=1, y=2)
+    assert p.evolve() == p
+    assert p.evolve(x=3, y=4) == Point(x=3, y=4)
+
+
+def test_evolve_unknown_field():
+    with pytest.raises(TypeError):
+        Point(x=1, y=2).evolve(z=3)
+
+
+def test_evolve_validates():
+    account = Account(owner="alice", extra=1)
+    assert account.evolve(balance=3, note="hi").balance == 3
+    with pytest.raises(InvariantError):
+        account.evolve(balance=-3)
+
+
+def test_replace():
+    assert Point(x=1, y=2).__replace__(x=3) == Point(x=3, y=2)
+
+
+@pytest.mark.skipif(sys.version_info < (3, 13), reason="copy.replace is new")
+def test_copy_replace():
+    assert copy.replace(Point(x=1, y=2), x=3) == Point(x=3, y=2)
+
+
+class Line(Record):
+    _fields = {
+        "start": Field(type=Point),
+        "end": Field(type=Point),
+        "tags": Field(type=HashTrieSet),
+        "meta": Field(type=HashTrieMap, default=HashTrieMap()),
+        "points": None,
+    }
+
+
+def test_to_dict():
+    assert Point(x=1, y=2).to_dict() == {"x": 1, "y": 2}
+
+
+def test_to_dict_nested():
+    line = Line(
+        start=Point(x=0, y=0),
+        end=Point(x=1, y=1),
+        tags=HashTrieSet(["a"]),
+        meta=HashTrieMap(color=List([1, 2])),
+        points=List([Point(x=2, y=3)]),
+    )
+    assert line.to_dict() == {
+        "start": {"x": 0, "y": 0},
+        "end": {"x": 1, "y": 1},
+        "tags": {"a"},
+        "meta": {"color": [1, 2]},
+        "points": [{"x": 2, "y": 3}],
+    }
+    assert type(line.to_dict()["meta"]) is dict
+    assert type(line.to_dict()["meta"]["color"]) is list
+
+
+def test_from_dict():
+    assert Point.from_dict({"x": 1, "y": 2}) == Point(x=1, y=2)
+    with pytest.raises(TypeError):
+        Point.from_dict({"x": 1})
+    with pytest.raises(TypeError):
+        Point.from_dict({"x": 1, "y": 2, "z": 3})
+
+
+def test_from_dict_nested():
+    line = Line.from_dict(
+        {
+            "start": {"x": 0, "y": 0},
+            "end": Point(x=1, y=1),
+            "tags": {"a"},
+            "meta": {"color": "red"},
+            "points": None,
+        },
+    )
+    assert line.start == Point(x=0, y=0)
+    assert line.end == Point(x=1, y=1)
+    assert line.tags == HashTrieSet(["a"])
+    assert line.meta == HashTrieMap(color="red")
+    assert line.points is None
+
+
+def test_from_dict_still_validates():
+    with pytest.raises(InvariantError):
+        Account.from_dict({"owner": 12, "extra": 1})
+
+
+def test_json_round_trip():
+    p = Point(x=1, y=[1, 2])
+    dumped = json.dumps(p, default=Record.to_dict)
+    assert json.loads(dumped) == {"x": 1, "y": [1, 2]}
+    assert Point.from_dict(json.loads(dumped)) == p
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = Point(x=Counted(), y=2)
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
+
+
+def test_equality_does_not_compare_identical_values():
+    class NeverEqual:
+        def __eq__(self, other):
+            raise AssertionError("compared!")
+
+        __hash__ = object.__hash__
+
+    value = NeverEqual()
+    p = Point(x=value, y=2)
+    assert p == p
+    assert p == Point(x=value, y=2)
+    assert not p != Point(x=value, y=2)
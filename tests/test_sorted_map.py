@@ -0,0 +1,406 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import SortedMap
+
+
+def test_instance_of_map():
+    assert isinstance(SortedMap(), abc.Mapping)
+
+
+def test_literalish_works():
+    assert SortedMap(a=1, b=2) == SortedMap({"a": 1, "b": 2})
+    assert SortedMap([(2, "b"), (1, "a")]) == SortedMap({1: "a", 2: "b"})
+
+
+def test_ordered_iteration():
+    m = SortedMap({k: str(k) for k in [5, 3, 9, 1, 7]})
+    assert list(m) == [1, 3, 5, 7, 9]
+    assert list(m.keys()) == [1, 3, 5, 7, 9]
+    assert list(m.values()) == ["1", "3", "5", "7", "9"]
+    assert list(m.items()) == [(1, "1"), (3, "3"), (5, "5"), (7, "7"), (9, "9")]
+
+
+def test_getitem_and_get():
+    m = SortedMap({"a": 1})
+    assert m["a"] == 1
+    assert m.get("a") == 1
+    assert m.get("b") is None
+    assert m.get("b", 2) == 2
+    with pytest.raises(KeyError):
+        m["b"]
+
+
+def test_insert_remove_discard():
+    m = SortedMap({1: "a"})
+    assert m.insert(0, "z") == SortedMap({0: "z", 1: "a"})
+    assert m.remove(1) == SortedMap()
+    assert m.discard(2) == m
+    with pytest.raises(KeyError):
+        m.remove(2)
+
+
+def test_equality_with_different_key_functions():
+    one = SortedMap({1: "a", 2: "b"})
+    two = SortedMap({1: "a", 2: "b"}, key=lambda k: -k)
+    assert one == two
+    assert not one != two
+    assert hash(one) == hash(two)
+    assert one != SortedMap({1: "a", 2: "c"}, key=lambda k: -k)
+    assert one != SortedMap({1: "a", "b": 2}, key=str)
+
+
+def test_update():
+    m = SortedMap({"a": 1, "b": 2})
+    assert m.update({"b": 3}, [("c", 4)], d=5) == SortedMap(
+        {"a": 1, "b": 3, "c": 4, "d": 5},
+    )
+
+
+
+def test_update_with_key_function():
+    m = SortedMap({"a": 1, "B": 2}, key=str.lower)
+    updated = m.update([("b", 3)], {"C": 4})
+    assert list(updated.items()) == [("a", 1), ("b", 3), ("C", 4)]
+    assert updated.key is str.lower
+
+
+def test_contains_and_len():
+    m = SortedMap({1: "a", 2: "b"})
+    assert 1 in m
+    assert 3 not in m
+    assert len(m) == 2
+    assert (1, "a") in m.items()
+    assert 2 in m.keys()
+
+
+def test_incomparable_keys():
+    with pytest.raises(TypeError):
+        SortedMap({1: "a", "b": 2})
+    with pytest.raises(TypeError):
+        SortedMap({1: "a"}).insert("b", 2)
+    with pytest.raises(TypeError):
+        "b" in SortedMap({1: "a"})
+
+
+def test_repr():
+    assert repr(SortedMap({2: "b", 1: "a"})) == "SortedMap({1: 'a', 2: 'b'})"
+    assert repr(SortedMap({2: "b", 1: "a"}).keys()) == "sorted_keys_view([1, 2])"
+
+
+def test_inequality():
+    assert SortedMap({1: 2}) != SortedMap({1: 3})
+    assert SortedMap({1: 2}) != SortedMap({1: 2, 3: 4})
+    assert not (SortedMap({1: 2}) != SortedMap({1: 2}))
+
+
+def test_hashing():
+    assert hash(SortedMap({1: 2, 3: 4})) == hash(SortedMap({3: 4, 1: 2}))
+    assert hash(SortedMap({1: 2})) != hash(SortedMap({1: 3}))
+
+
+def test_pickle():
+    m = SortedMap({1: 2, 3: 4})
+    assert pickle.loads(pickle.dumps(m)) == m
+
+
+def test_irange():
+    m = SortedMap({k: str(k) for k in [5, 3, 9, 1, 7]})
+    assert list(m.irange()) == [1, 3, 5, 7, 9]
+    assert list(m.irange(3, 7)) == [3, 5, 7]
+    assert list(m.irange(min=4)) == [5, 7, 9]
+    assert list(m.irange(max=4)) == [1, 3]
+    assert list(m.irange(2, 8, reverse=True)) == [7, 5, 3]
+
+
+def test_irange_exclusive():
+    m = SortedMap({k: str(k) for k in [1, 3, 5, 7]})
+    assert list(m.irange(3, 7, inclusive=(False, True))) == [5, 7]
+    assert list(m.irange(3, 7, inclusive=(True, False))) == [3, 5]
+    assert list(m.irange(3, 7, inclusive=(False, False))) == [5]
+
+
+def test_irange_empty():
+    m = SortedMap({1: 2, 3: 4})
+    assert list(m.irange(3, 1)) == []
+    assert list(m.irange(3, 3)) == [3]
+    assert list(m.irange(3, 3, inclusive=(False, False))) == []
+    assert list(m.irange(3, 3, inclusive=(True, False))) == []
+    assert list(SortedMap().irange(1, 2)) == []
+
+
+def test_irange_incomparable():
+    with pytest.raises(TypeError):
+        list(SortedMap({1: 2}).irange("a"))
+
+
+def test_first_and_last_item():
+    m = SortedMap({3: "c", 1: "a", 2: "b"})
+    assert m.first_item() == (1, "a")
+    assert m.last_item() == (3, "c")
+
+
+def test_first_and_last_item_empty():
+    with pytest.raises(IndexError):
+        SortedMap().first_item()
+    with pytest.raises(IndexError):
+        SortedMap().last_item()
+
+
+def test_floor_and_ceiling_key():
+    m = SortedMap({10: "a", 20: "b", 30: "c"})
+    assert m.floor_key(25) == 20
+    assert m.floor_key(20) == 20
+    assert m.floor_key(5) is None
+    assert m.ceiling_key(25) == 30
+    assert m.ceiling_key(30) == 30
+    assert m.ceiling_key(35) is None
+
+
+def test_floor_key_incomparable():
+    with pytest.raises(TypeError):
+        SortedMap({1: 2}).floor_key("a")
+
+
+def test_nth():
+    m = SortedMap({k: str(k) for k in [5, 3, 9, 1, 7]})
+    assert m.nth(0) == 1
+    assert m.nth(2) == 5
+    assert m.nth(-1) == 9
+
+
+def test_nth_out_of_range():
+    with pytest.raises(IndexError):
+        SortedMap({1: 2}).nth(1)
+    with pytest.raises(IndexError):
+        SortedMap({1: 2}).nth(-2)
+
+
+def test_index_of():
+    m = SortedMap({k: str(k) for k in [5, 3, 9, 1, 7]})
+    assert m.index_of(1) == 0
+    assert m.index_of(7) == 3
+    assert [m.index_of(m.nth(i)) for i in range(len(m))] == list(range(len(m)))
+
+
+def test_index_of_missing():
+    with pytest.raises(KeyError):
+        SortedMap({1: 2}).index_of(2)
+
+
+def test_reversed():
+    m = SortedMap({k: str(k) for k in [5, 3, 9, 1, 7]})
+    assert list(reversed(m)) == [9, 7, 5, 3, 1]
+    assert list(reversed(m.keys())) == [9, 7, 5, 3, 1]
+    assert list(reversed(m.values())) == ["9", "7", "5", "3", "1"]
+    assert list(reversed(m.items()))[:2] == [(9, "9"), (7, "7")]
+    assert list(reversed(SortedMap())) == []
+
+
+def test_key_function():
+    m = SortedMap({"b": 1, "C": 2, "a": 3}, key=str.lower)
+    assert list(m) == ["a", "b", "C"]
+    assert m.key is str.lower
+    assert SortedMap().key is None
+
+    assert "c" in m
+    assert "c" in m.keys()
+    assert ("c", 2) in m.items()
+    assert m["A"] == 3
+    assert m.get("B") == 1
+    assert m.floor_key("bb") == "b"
+    assert m.index_of("c") == 2
+    assert list(m.irange("B", "c")) == ["b", "C"]
+
+
+def test_key_function_is_kept():
+    m = SortedMap(key=str.lower).insert("b", 1).insert("A", 2)
+    assert list(m) == ["A", "b"]
+    assert m.key is str.lower
+
+    m = m.insert("a", 3).remove("B")
+    assert list(m.items()) == [("a", 3)]
+
+    assert list(m.update({"c": 1, "B": 2})) == ["a", "B", "c"]
+    assert list(SortedMap(key=str.lower, Z=1, y=2)) == ["y", "Z"]
+
+
+def test_key_function_called_once_per_key():
+    calls = []
+
+    def key(k):
+        calls.append(k)
+        return -k
+
+    m = SortedMap({i: i for i in range(100)}, key=key)
+    assert list(m) == list(reversed(range(100)))
+    assert len(calls) == 100
+
+
+def test_key_function_error():
+    def key(k):
+        raise ZeroDivisionError()
+
+    with pytest.raises(ZeroDivisionError):
+        SortedMap({1: 2}, key=key)
+    with pytest.raises(ZeroDivisionError):
+        SortedMap(key=key).insert(1, 2)
+
+
+def test_pickle_with_key_function():
+    m = SortedMap({"b": 1, "A": 2}, key=str.lower)
+    unpickled = pickle.loads(pickle.dumps(m))
+    assert unpickled == m
+    assert unpickled.key is str.lower
+
+
+def test_submap():
+    m = SortedMap((i, str(i)) for i in range(10))
+    sub = m.submap(3, 6)
+    assert isinstance(sub, SortedMap)
+    assert sub == SortedMap({3: "3", 4: "4", 5: "5", 6: "6"})
+    assert list(m.submap(3, 6, inclusive=(False, False))) == [4, 5]
+    assert list(m.submap(max=2)) == [0, 1, 2]
+    assert list(m.submap(8)) == [8, 9]
+    assert m.submap() == m
+    assert m == SortedMap((i, str(i)) for i in range(10))
+
+
+def test_submap_empty():
+    m = SortedMap({1: 1, 2: 2})
+    assert m.submap(5, 3) == SortedMap()
+    assert m.submap(1, 1, inclusive=(True, False)) == SortedMap()
+    assert SortedMap().submap(1, 2) == SortedMap()
+
+
+def test_submap_keeps_key_function():
+    m = SortedMap({"a": 1, "B": 2, "c": 3}, key=str.lower)
+    sub = m.submap("A", "b")
+    assert list(sub) == ["a", "B"]
+    assert sub.key is str.lower
+
+
+def test_merge():
+    one = SortedMap({1: "a", 2: "b", 3: "c"})
+    two = SortedMap({3: "C", 4: "D"})
+    expected = SortedMap({1: "a", 2: "b", 3: "C", 4: "D"})
+    assert one.merge(two) == expected
+    assert two.merge(one) == SortedMap({1: "a", 2: "b", 3: "c", 4: "D"})
+    assert one.merge(SortedMap()) == one
+    assert SortedMap().merge(one) == one
+
+
+@pytest.mark.parametrize(
+    "one, two",
+    [
+        ({1: 10, 2: 20, 3: 30}, {3: 3, 4: 4}),
+        ({3: 30}, {1: 1, 2: 2, 3: 3, 4: 4}),
+    ],
+)
+def test_merge_resolve(one, two):
+    calls = []
+
+    def resolve(old, new):
+        calls.append((old, new))
+        return old + new
+
+    merged = SortedMap(one).merge(SortedMap(two), resolve=resolve)
+    assert merged[3] == 33
+    assert calls == [(30, 3)]
+    assert len(merged) == len(set(one) | set(two))
+
+
+def test_merge_resolve_order_with_larger_other():
+    calls = []
+
+    def resolve(old, new):
+        calls.append((old, new))
+        return new
+
+    one = SortedMap({2: "old", 5: "old too"})
+    two = SortedMap({1: "a", 2: "new", 3: "b", 4: "c", 5: "new too"})
+    merged = one.merge(two, resolve=resolve)
+    assert calls == [("old", "new"), ("old too", "new too")]
+    assert merged == two
+
+
+def test_merge_with_key_function():
+    one = SortedMap({"a": 1, "B": 2}, key=str.lower)
+    two = SortedMap({"b": 3, "C": 4})
+    merged = one.merge(two)
+    assert list(merged.items()) == [("a", 1), ("b", 3), ("C", 4)]
+    assert merged.key is str.lower
+
+
+def test_mutate():
+    original = SortedMap({2: "b"})
+    assert original.mutate(lambda e: e.__setitem__(1, "a")) == SortedMap(
+        {1: "a", 2: "b"},
+    )
+    with original.mutate() as evolver:
+        evolver[3] = "c"
+        del evolver[2]
+        assert 3 in evolver
+        assert evolver[3] == "c"
+        assert len(evolver) == 1
+    assert evolver.result == SortedMap({3: "c"})
+    assert original == SortedMap({2: "b"})
+    with pytest.raises(ValueError):
+        evolver[4] = "d"
+    with pytest.raises(KeyError):
+        SortedMap().mutate(lambda e: e.__delitem__(1))
+
+
+def test_mutate_keeps_key_function():
+    with SortedMap({"b": 1}, key=str.lower).mutate() as evolver:
+        evolver["A"] = 2
+        assert "a" in evolver
+    assert list(evolver.result) == ["A", "b"]
+    assert evolver.result.key is str.lower
+
+
+def test_mutate_incomparable():
+    with SortedMap({1: 1}).mutate() as evolver:
+        with pytest.raises(TypeError):
+            evolver["a"] = 2
+        assert len(evolver) == 1
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = SortedMap({1: Counted()})
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
+
+
+def test_equality_does_not_compare_identical_values():
+    class NeverEqual:
+        def __eq__(self, other):
+            raise AssertionError("compared!")
+
+        __hash__ = object.__hash__
+
+    value = NeverEqual()
+    m = SortedMap({1: value, 2: 3})
+    assert m == m
+    assert m == SortedMap({1: value, 2: 3})
+    assert not m != SortedMap({1: value, 2: 3})
+
+
+def test_equality_propagates_errors():
+    class Boom:
+        def __eq__(self, other):
+            raise ZeroDivisionError()
+
+        __hash__ = object.__hash__
+
+    with pytest.raises(ZeroDivisionError):
+        SortedMap({1: Boom()}) == SortedMap({1: 2})
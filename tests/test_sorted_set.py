@@ -0,0 +1,215 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import SortedSet
+
+
+def test_instance_of_set():
+    assert isinstance(SortedSet(), abc.Set)
+
+
+def test_ordered_iteration():
+    assert list(SortedSet([5, 3, 9, 1, 7])) == [1, 3, 5, 7, 9]
+    assert list(SortedSet()) == []
+
+
+def test_first_last():
+    s = SortedSet(["b", "c", "a"])
+    assert s.first == "a"
+    assert s.last == "c"
+    with pytest.raises(IndexError):
+        SortedSet().first
+    with pytest.raises(IndexError):
+        SortedSet().last
+
+
+def test_insert_remove_discard():
+    s = SortedSet([1, 2])
+    assert s.insert(0) == SortedSet([0, 1, 2])
+    assert s.remove(1) == SortedSet([2])
+    assert s.discard(3) == s
+    with pytest.raises(KeyError):
+        s.remove(3)
+
+
+def test_set_algebra():
+    one, two = SortedSet([1, 2, 3]), SortedSet([2, 3, 4])
+    assert one | two == SortedSet([1, 2, 3, 4])
+    assert one & two == SortedSet([2, 3])
+    assert one - two == SortedSet([1])
+    assert one ^ two == SortedSet([1, 4])
+    assert list(one.union(two)) == [1, 2, 3, 4]
+
+
+def test_update():
+    assert SortedSet([1]).update([3, 2], {0}) == SortedSet([0, 1, 2, 3])
+
+
+def test_comparisons():
+    assert SortedSet([1, 2]) == {1, 2}
+    assert SortedSet([1, 2]) < {1, 2, 3}
+    assert SortedSet([1, 2]) <= {1, 2}
+    assert SortedSet([1, 2]) > {1}
+    assert SortedSet([1, 2]) >= {1, 2}
+    assert SortedSet([1, 2]) != {"a", "b"}
+
+
+def test_incomparable_elements():
+    with pytest.raises(TypeError):
+        SortedSet([1, "a"])
+    with pytest.raises(TypeError):
+        SortedSet([1]).insert("a")
+
+
+def test_repr():
+    assert repr(SortedSet()) == "SortedSet({})"
+    assert repr(SortedSet([2, 1])) == "SortedSet({1, 2})"
+
+
+def test_hashing():
+    assert hash(SortedSet([1, 2])) == hash(SortedSet([2, 1]))
+    assert len({SortedSet([1, 2]), SortedSet([2, 1])}) == 1
+
+
+def test_pickle():
+    s = SortedSet([1, 2, 3])
+    assert pickle.loads(pickle.dumps(s)) == s
+
+
+def test_reversed():
+    s = SortedSet([5, 3, 9, 1, 7])
+    assert list(reversed(s)) == [9, 7, 5, 3, 1]
+    assert list(reversed(SortedSet())) == []
+    assert list(s) == [1, 3, 5, 7, 9]
+
+
+def test_key_function():
+    s = SortedSet(["b", "C", "a"], key=str.lower)
+    assert list(s) == ["a", "b", "C"]
+    assert s.key is str.lower
+    assert SortedSet().key is None
+
+    assert "c" in s
+    assert s == {"a", "b", "C"}
+    assert s.first == "a"
+    assert s.last == "C"
+
+
+def test_key_function_is_kept():
+    s = SortedSet(key=abs).insert(-3).insert(2).insert(-1)
+    assert list(s) == [-1, 2, -3]
+    assert s.key is abs
+    assert list(s.insert(3)) == [-1, 2, 3]
+    assert list(s.remove(3)) == [-1, 2]
+    assert list(s.discard(1).update([4, -5])) == [2, -3, 4, -5]
+
+
+def test_key_function_with_other_sets():
+    s = SortedSet([-3, 2, -1], key=abs)
+    other = SortedSet([1, 3, 5])
+    assert list(s | other) == [-1, 2, -3, 5]
+    assert list(s & other) == [-1, -3]
+    assert list(s - other) == [2]
+    assert list(s ^ other) == [2, 5]
+    assert (s | other).key is abs
+
+
+def test_key_function_called_once_per_element():
+    calls = []
+
+    def key(k):
+        calls.append(k)
+        return -k
+
+    s = SortedSet(range(100), key=key)
+    assert list(s) == list(reversed(range(100)))
+    assert len(calls) == 100
+
+
+def test_pickle_with_key_function():
+    s = SortedSet(["b", "A"], key=str.lower)
+    unpickled = pickle.loads(pickle.dumps(s))
+    assert list(unpickled) == ["A", "b"]
+    assert unpickled.key is str.lower
+
+
+def test_nsmallest_nlargest():
+    s = SortedSet([50, 10, 40, 20, 30])
+    assert s.nsmallest(2) == [10, 20]
+    assert s.nlargest(2) == [50, 40]
+    assert s.nsmallest(0) == []
+    assert s.nlargest(10) == [50, 40, 30, 20, 10]
+    assert SortedSet().nsmallest(3) == []
+
+
+def test_irange():
+    s = SortedSet(range(0, 20, 2))
+    assert list(s.irange(3, 9)) == [4, 6, 8]
+    assert list(s.irange(4, 8)) == [4, 6, 8]
+    assert list(s.irange(4, 8, inclusive=(False, False))) == [6]
+    assert list(s.irange(min=14)) == [14, 16, 18]
+    assert list(s.irange(max=3)) == [0, 2]
+    assert list(s.irange(4, 8, reverse=True)) == [8, 6, 4]
+    assert list(s.irange(9, 3)) == []
+    assert list(s.irange(4, 4, inclusive=(True, False))) == []
+
+
+def test_irange_incomparable():
+    with pytest.raises(TypeError):
+        list(SortedSet([1, 2]).irange("a"))
+
+
+def test_irange_with_key_function():
+    s = SortedSet(["a", "B", "c", "D"], key=str.lower)
+    assert list(s.irange("b", "C")) == ["B", "c"]
+
+
+def test_mutate():
+    original = SortedSet([2])
+    assert original.mutate(lambda e: e.add(1)) == SortedSet([1, 2])
+    with original.mutate() as evolver:
+        evolver.add(3)
+        evolver.discard(2)
+        evolver.discard(7)
+        assert 3 in evolver
+    assert list(evolver.result) == [3]
+    assert original == SortedSet([2])
+    with pytest.raises(ValueError):
+        evolver.add(4)
+    with pytest.raises(KeyError):
+        SortedSet().mutate(lambda e: e.remove(1))
+
+
+def test_mutate_keeps_key_function():
+    with SortedSet(["b"], key=str.lower).mutate() as evolver:
+        evolver.add("A")
+    assert list(evolver.result) == ["A", "b"]
+    assert evolver.result.key is str.lower
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = SortedSet([Counted()])
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
+
+
+def test_equal_to_itself_without_comparing():
+    class Unorderable:
+        def __eq__(self, other):
+            raise AssertionError("compared!")
+
+        __lt__ = __eq__
+        __hash__ = object.__hash__
+
+    s = SortedSet([Unorderable()])
+    assert s == s
+    assert not s != s
@@ -0,0 +1,119 @@
+from collections import abc
+import pickle
+
+import pytest
+
+from rpds import HashTrieMap, StringTrieMap
+
+
+def test_instance_of_map():
+    assert isinstance(StringTrieMap(), abc.Mapping)
+
+
+def test_basic_map_operations():
+    m = StringTrieMap({"foo": 1}, bar=2)
+    assert m["foo"] == 1
+    assert m.get("bar") == 2
+    assert m.get("baz", 3) == 3
+    assert "foo" in m
+    assert 12 not in m
+    assert len(m) == 2
+    assert m.insert("baz", 3)["baz"] == 3
+    assert m.remove("foo") == StringTrieMap(bar=2)
+    assert m.discard("nope") == m
+
+
+def test_missing_key():
+    with pytest.raises(KeyError):
+        StringTrieMap()["foo"]
+    with pytest.raises(KeyError):
+        StringTrieMap().remove("foo")
+
+
+def test_non_string_keys():
+    with pytest.raises(TypeError):
+        StringTrieMap({1: 2})
+    with pytest.raises(TypeError):
+        StringTrieMap().insert(1, 2)
+
+
+def test_iterates_in_sorted_order():
+    m = StringTrieMap(b=2, a=1, ab=3)
+    assert list(m) == ["a", "ab", "b"]
+    assert list(m.keys()) == ["a", "ab", "b"]
+    assert list(m.values()) == [1, 3, 2]
+    assert list(m.items()) == [("a", 1), ("ab", 3), ("b", 2)]
+
+
+def test_keys_with_prefix():
+    m = StringTrieMap(
+        (k, None) for k in ["car", "card", "care", "cat", "dog", "ca"]
+    )
+    assert m.keys_with_prefix("car") == ["car", "card", "care"]
+    assert m.keys_with_prefix("ca") == ["ca", "car", "card", "care", "cat"]
+    assert m.keys_with_prefix("x") == []
+    assert m.keys_with_prefix("") == list(m)
+
+
+def test_longest_prefix_of():
+    m = StringTrieMap(a=1, ab=2, abcd=3)
+    assert m.longest_prefix_of("abc") == "ab"
+    assert m.longest_prefix_of("abcde") == "abcd"
+    assert m.longest_prefix_of("ab") == "ab"
+    assert m.longest_prefix_of("b") is None
+    assert StringTrieMap({"": 0}).longest_prefix_of("zzz") == ""
+
+
+def test_longest_prefix_of_non_ascii():
+    m = StringTrieMap({"é": 1, "éé": 2})
+    assert m.longest_prefix_of("ééa") == "éé"
+
+
+def test_longest_prefix_of_skips_keys_sorting_in_between():
+    m = StringTrieMap({"a": 1, "ab": 2, "abca": 3, "abcb": 4, "abd": 5})
+    assert m.longest_prefix_of("abcc") == "ab"
+    assert m.longest_prefix_of("abz") == "ab"
+    assert m.longest_prefix_of("b") is None
+    assert m.longest_prefix_of("aé") == "a"
+    assert m.longest_prefix_of("") is None
+
+
+def test_eq_and_hash():
+    assert StringTrieMap(a=1, b=2) == StringTrieMap(b=2, a=1)
+    assert StringTrieMap(a=1) != StringTrieMap(a=2)
+    assert hash(StringTrieMap(a=1, b=2)) == hash(StringTrieMap(b=2, a=1))
+
+
+def test_eq_other_mappings():
+    assert StringTrieMap({"a": 1}) == {"a": 1}
+    assert {"a": 1} == StringTrieMap({"a": 1})
+    assert StringTrieMap({"a": 1}) != {"a": 2}
+    assert StringTrieMap({"a": 1}) != {"b": 1}
+    assert StringTrieMap({"a": 1}) == HashTrieMap({"a": 1})
+
+
+def test_update():
+    m = StringTrieMap(a=1).update({"b": 2}, [("c", 3)], d=4)
+    assert m == StringTrieMap(a=1, b=2, c=3, d=4)
+
+
+def test_repr():
+    assert repr(StringTrieMap(b=2, a=1)) == "StringTrieMap({'a': 1, 'b': 2})"
+
+
+def test_pickle():
+    m = StringTrieMap(a=1, b=[2])
+    assert pickle.loads(pickle.dumps(m)) == m
+
+
+def test_hash_is_cached():
+    calls = []
+
+    class Counted:
+        def __hash__(self):
+            calls.append(self)
+            return 0
+
+    collection = StringTrieMap(a=Counted())
+    assert hash(collection) == hash(collection)
+    assert len(calls) == 1
@@ -0,0 +1,153 @@
+import pickle
+import sys
+
+import pytest
+
+from rpds import Vector
+
+
+def test_literalish_works():
+    assert Vector(1, 2, 3) == Vector([1, 2, 3])
+
+
+def test_from_generator():
+    assert Vector(i for i in range(3)) == Vector([0, 1, 2])
+
+
+def test_getitem():
+    v = Vector(range(100))
+    assert v[0] == 0
+    assert v[57] == 57
+    assert v[-1] == 99
+    with pytest.raises(IndexError):
+        v[100]
+    with pytest.raises(IndexError):
+        v[-101]
+
+
+def test_set():
+    v = Vector([1, 2, 3])
+    assert v.set(1, 7) == Vector([1, 7, 3])
+    assert v.set(-1, 7) == Vector([1, 2, 7])
+    assert v == Vector([1, 2, 3])
+    with pytest.raises(IndexError):
+        v.set(3, 7)
+
+
+def test_push_back():
+    assert Vector([1, 2]).push_back(3) == Vector([1, 2, 3])
+    assert Vector().push_back(0) == Vector([0])
+
+
+def test_drop_last():
+    assert Vector([1, 2, 3]).drop_last() == Vector([1, 2])
+    with pytest.raises(IndexError):
+        Vector().drop_last()
+
+
+def test_iteration():
+    assert list(Vector()) == []
+    assert list(Vector([1, 2, 3])) == [1, 2, 3]
+
+
+def test_len():
+    assert len(Vector([1, 2, 3])) == 3
+    assert len(Vector()) == 0
+
+
+def test_truthiness():
+    assert Vector([1])
+    assert not Vector()
+
+
+def test_repr():
+    assert repr(Vector()) == "Vector([])"
+    assert repr(Vector([1, "2"])) == "Vector([1, '2'])"
+
+
+def test_contains():
+    assert 2 in Vector([1, 2, 3])
+    assert 4 not in Vector([1, 2, 3])
+
+
+def test_reversed():
+    it = reversed(Vector([1, 2, 3]))
+    assert iter(it) is it
+    assert list(it) == [3, 2, 1]
+
+
+def test_index():
+    vec = Vector(["a", "b", "c", "b"])
+    assert vec.index("b") == 1
+    assert vec.index("b", 2) == 3
+    assert vec.index("a", -4, -2) == 0
+    with pytest.raises(ValueError):
+        vec.index("d")
+    with pytest.raises(ValueError):
+        vec.index("c", 0, 2)
+
+
+def test_count():
+    assert Vector([1, 2, 1, 1.0, 3]).count(1) == 3
+    assert Vector([1, 2]).count(4) == 0
+
+
+def test_sizeof_grows_with_size():
+    empty = sys.getsizeof(Vector())
+    assert sys.getsizeof(Vector([1])) > empty
+    assert sys.getsizeof(Vector(range(100))) > sys.getsizeof(Vector([1]))
+
+
+def test_inequality():
+    assert Vector([1, 2]) != Vector([1, 3])
+    assert Vector([1, 2]) != Vector([1, 2, 3])
+    assert not (Vector([1, 2]) != Vector([1, 2]))
+
+
+def test_hashing():
+    assert hash(Vector([1, 2])) == hash(Vector([1, 2]))
+    assert hash(Vector([1, 2])) != hash(Vector([2, 1]))
+
+
+def test_unhashable_contents():
+    with pytest.raises(TypeError):
+        hash(Vector([1, {1}]))
+
+
+def test_pickle():
+    assert pickle.loads(pickle.dumps(Vector([1, 2, 3]))) == Vector([1, 2, 3])
+
+
+def test_builder():
+    builder = Vector.builder()
+    for each in range(5):
+        builder.append(each)
+    assert len(builder) == 5
+    assert builder.build() == Vector(range(5))
+
+
+def test_builder_build_is_a_snapshot():
+    builder = Vector.builder()
+    builder.append(1)
+    first = builder.build()
+    builder.append(2)
+    assert first == Vector([1])
+    assert builder.build() == Vector([1, 2])
+
+
+def test_mutate():
+    original = Vector([1, 2])
+    assert original.mutate(lambda b: b.append(3)) == Vector([1, 2, 3])
+    with original.mutate() as builder:
+        builder.append(3)
+    assert builder.build() == Vector([1, 2, 3])
+    assert original == Vector([1, 2])
+
+
+def test_mutate_finishes_builder_on_exit():
+    with Vector([1]).mutate() as builder:
+        builder.append(2)
+    assert builder.result == Vector([1, 2])
+    with pytest.raises(ValueError):
+        builder.append(3)
+    assert builder.build() == Vector([1, 2])